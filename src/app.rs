@@ -1,23 +1,276 @@
-use crate::config::Config;
+use crate::batch::{
+    BatchFailurePolicy, BatchPriority, BatchQueue, BatchStatus, PendingJoin, SavedBatch,
+    render_rename_template,
+};
+use crate::benchmark::{
+    BENCHMARK_SAMPLE, BenchmarkMessage, BenchmarkResult, BenchmarkSort, QualityMetric,
+    benchmark_candidates, run_benchmark, sort_results,
+};
+use crate::capabilities::{drop_unusable_hardware_encoders, list_encoders};
+use crate::compare::{
+    FramePair, MediaComparison, compare_media, comparison_frame_size, extract_frame_pair,
+};
+use crate::config::{Config, OutputMode, UiTheme, config_location};
 use crate::conversion::{
-    ConversionMessage, ConversionProgress, ConversionTask, check_ffmpeg_installation,
-    generate_output_filename,
+    ConversionControl, ConversionError, ConversionMessage, ConversionProgress, ConversionTask,
+    DISK_SPACE_THRESHOLD_MB, FILMSTRIP_FRAME_SIZE, LogBuffer, StreamAction, avoid_input_collision,
+    check_ffmpeg_installation, existing_split_parts, extract_filmstrip, extract_poster_frame,
+    format_duration, generate_output_filename, is_conversion_noop, milestone_text, new_log_file,
+    next_free_name, next_free_split_name, plan_streams, strip_media_extensions,
+};
+use crate::crf_search::MAX_SEARCH_ITERATIONS;
+use crate::diagnostics::{CheckStatus, DiagnosticCheck, diagnostics_report, run_diagnostics};
+use crate::hardware::{HardwareDevice, list_hardware_devices};
+use crate::library::{
+    LibraryEntry, LibraryMessage, LibrarySort, VIDEO_EXTENSIONS, is_video_file, scan_library,
+    sort_entries,
 };
+use crate::palette::{PaletteAction, palette_entries};
 use crate::presets::{
-    AudioCodec, ConversionMode, ConversionPreset, MetadataOptions, PresetManager, VideoCodec,
-    VideoFormat,
+    AudioCodec, AudioEncode, CONTAINER_CODEC_RECOMMENDATIONS, ConversionMode, ConversionPreset,
+    CropRect, DeinterlaceMode, DispositionTarget, HardwareAccel, MetadataOptions, OutputSplit,
+    PresetManager, QualityTarget, TrimAccuracy, VideoCodec, VideoFormat, load_session,
+    save_session,
+};
+use crate::probe::{MediaInfo, detect_crop, detect_interlacing, is_faststart, probe_media};
+use crate::recommend::{Recommendation, recommend_settings, repair_settings};
+use crate::segmented::{can_encode_segmented, execute_segmented, segment_count};
+use crate::segments::{SegmentOutput, join_clips, parse_segment_list};
+use crate::sequence::{
+    DEFAULT_SEQUENCE_FRAMERATE, IMAGE_EXTENSIONS, InputSource, MAX_SEQUENCE_FRAMERATE,
+    scan_image_sequence, sequence_pattern_for, validate_framerate,
+};
+use crate::subtitles::{SubtitleFormat, extract_subtitles};
+use crate::updater::{UpdateError, UpdateInfo, UpdateStatus, Updater};
+use crate::utils::{file_in_use, open_in_default_app};
+use crate::validation::{
+    DEFAULT_TARGET_VMAF, MAX_AUDIO_OFFSET_MS, MAX_EXTRA_AUDIO_TRACKS, MAX_SPLIT_MB,
+    MAX_SPLIT_MINUTES, MAX_TARGET_VMAF, MIN_SPLIT_MB, MIN_TARGET_VMAF, SettingsIssue,
+    audio_codec_caveat, is_safe_metadata_value, is_valid_metadata_key, parse_extra_args,
+    parse_milestones, parse_thread_count, sanitize_filename, validate_conversion_settings,
+    validate_cover_art, validate_crop, validate_input_format, validate_stream_copy,
+    video_codec_caveat,
 };
-use crate::updater::{UpdateInfo, UpdateStatus, Updater};
 use egui::{
-    CentralPanel, Color32, Context, RichText, ScrollArea, SidePanel, Stroke, TopBottomPanel,
+    CentralPanel, Color32, Context, Key, KeyboardShortcut, Modifiers, RichText, ScrollArea,
+    SidePanel, Stroke, Theme, ThemePreference, TopBottomPanel,
 };
+use egui_plot::{Line, Plot, PlotPoints};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::sync::mpsc::{Receiver, Sender};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::runtime::Runtime;
 use tokio::sync::mpsc;
 
+const PROGRESS_BUFFER_SIZE: usize = 600;
+const TEST_CONVERSION_DURATION: Duration = Duration::from_secs(10);
+const FILMSTRIP_FRAMES: usize = 8;
+// Split output defaults, sized for common upload limits
+const DEFAULT_SPLIT_MINUTES: u32 = 20;
+const DEFAULT_SPLIT_MB: u64 = 2000;
+// Existing files named in the overwrite prompt before it sums up the rest
+const MAX_LISTED_OVERWRITES: usize = 3;
+// Starting point and cap for a fixed conversion timeout set in the settings
+const DEFAULT_TIMEOUT_MINUTES: u64 = 120;
+const MAX_TIMEOUT_MINUTES: u64 = 7 * 24 * 60;
+// How often the current settings and window size are saved, so a crash
+// loses at most this much
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
+const PALETTE_SHORTCUT: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::P);
+
+/// Colors of one theme, picked by `FFmpegApp::colors`
+struct ThemeColors {
+    bg_base: Color32,
+    bg_panel: Color32,
+    bg_card: Color32,
+    border_soft: Color32,
+    accent: Color32,
+    text_main: Color32,
+    success: Color32,
+    warning: Color32,
+    danger: Color32,
+    selection: Color32,
+    widget_inactive: Color32,
+    widget_hovered: Color32,
+    widget_active: Color32,
+}
+
+const DARK_COLORS: ThemeColors = ThemeColors {
+    bg_base: Color32::from_rgb(6, 6, 8),
+    bg_panel: Color32::from_rgb(10, 10, 13),
+    bg_card: Color32::from_rgb(14, 14, 18),
+    border_soft: Color32::from_rgb(45, 45, 54),
+    accent: Color32::from_rgb(162, 162, 176),
+    text_main: Color32::from_rgb(235, 235, 239),
+    success: Color32::from_rgb(104, 184, 149),
+    warning: Color32::from_rgb(214, 178, 96),
+    danger: Color32::from_rgb(225, 106, 106),
+    selection: Color32::from_rgb(60, 60, 72),
+    widget_inactive: Color32::from_rgb(20, 20, 25),
+    widget_hovered: Color32::from_rgb(30, 30, 38),
+    widget_active: Color32::from_rgb(36, 36, 46),
+};
+
+// The same grays mirrored, with the status colors darkened to stay
+// readable on white
+const LIGHT_COLORS: ThemeColors = ThemeColors {
+    bg_base: Color32::from_rgb(232, 232, 236),
+    bg_panel: Color32::from_rgb(244, 244, 247),
+    bg_card: Color32::from_rgb(252, 252, 253),
+    border_soft: Color32::from_rgb(206, 206, 214),
+    accent: Color32::from_rgb(86, 86, 102),
+    text_main: Color32::from_rgb(22, 22, 26),
+    success: Color32::from_rgb(34, 122, 86),
+    warning: Color32::from_rgb(160, 110, 16),
+    danger: Color32::from_rgb(186, 52, 52),
+    selection: Color32::from_rgb(208, 208, 222),
+    widget_inactive: Color32::from_rgb(238, 238, 242),
+    widget_hovered: Color32::from_rgb(226, 226, 234),
+    widget_active: Color32::from_rgb(216, 216, 226),
+};
+
+// Input the frames belong to, and the RGBA frames themselves
+type FilmstripResult = (PathBuf, Result<Vec<Vec<u8>>, String>);
+
+#[derive(Debug, Clone, Copy)]
+struct ProgressSample {
+    elapsed_secs: f64,
+    fps: Option<f32>,
+    bitrate_kbps: Option<f32>,
+    speed: Option<f32>,
+}
+
+/// Lifecycle of the conversion started from the main window. Pause state,
+/// progress and the test-run flag only exist while one is running, so they
+/// can't disagree with it.
+#[derive(Debug, Clone, Default)]
+enum ConversionState {
+    #[default]
+    Idle,
+    Converting {
+        test_run: bool,
+        paused: bool,
+        /// `None` until FFmpeg first reports progress
+        progress: Option<ConversionProgress>,
+    },
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl ConversionState {
+    fn is_converting(&self) -> bool {
+        matches!(self, ConversionState::Converting { .. })
+    }
+
+    fn is_paused(&self) -> bool {
+        matches!(self, ConversionState::Converting { paused: true, .. })
+    }
+
+    fn is_test_run(&self) -> bool {
+        matches!(self, ConversionState::Converting { test_run: true, .. })
+    }
+
+    fn progress(&self) -> Option<&ConversionProgress> {
+        match self {
+            ConversionState::Converting { progress, .. } => progress.as_ref(),
+            _ => None,
+        }
+    }
+
+    fn transition_to_converting(&mut self, test_run: bool) {
+        *self = ConversionState::Converting {
+            test_run,
+            paused: false,
+            progress: None,
+        };
+    }
+
+    fn transition_to_completed(&mut self) {
+        *self = ConversionState::Completed;
+    }
+
+    fn transition_to_failed(&mut self) {
+        *self = ConversionState::Failed;
+    }
+
+    fn transition_to_cancelled(&mut self) {
+        *self = ConversionState::Cancelled;
+    }
+
+    /// Ignored unless a conversion is running, so a late report can't
+    /// resurrect a finished one
+    fn set_progress(&mut self, update: ConversionProgress) {
+        if let ConversionState::Converting { progress, .. } = self {
+            *progress = Some(update);
+        }
+    }
+
+    fn set_paused(&mut self, update: bool) {
+        if let ConversionState::Converting { paused, .. } = self {
+            *paused = update;
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct FailureReport {
+    command: String,
+    stderr: Vec<String>,
+}
+
+/// What to do when the output file already exists
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ExistingOutput {
+    Overwrite,
+    Rename,
+    /// Leave the file alone and don't convert; batch items move on to the next
+    Skip,
+}
+
+/// How the frame comparison window shows the source and the test encode
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FrameView {
+    Source,
+    Encoded,
+    /// Source on the left of a draggable divider, encode on the right
+    Split,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum BatchMove {
+    Up,
+    Down,
+    Front,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum GraphMetric {
+    Speed,
+    Bitrate,
+    Fps,
+}
+
+impl GraphMetric {
+    fn label(&self) -> &'static str {
+        match self {
+            GraphMetric::Speed => "Speed (x)",
+            GraphMetric::Bitrate => "Bitrate (kbit/s)",
+            GraphMetric::Fps => "FPS",
+        }
+    }
+
+    fn value(&self, sample: &ProgressSample) -> Option<f32> {
+        match self {
+            GraphMetric::Speed => sample.speed,
+            GraphMetric::Bitrate => sample.bitrate_kbps,
+            GraphMetric::Fps => sample.fps,
+        }
+    }
+}
+
 pub struct FFmpegApp {
     config: Config,
     runtime: Arc<Runtime>,
@@ -26,82 +279,327 @@ pub struct FFmpegApp {
     output_folder: Option<PathBuf>,
     output_file_name: String,
 
+    media_info: Option<MediaInfo>,
+    /// Set when `input_file` is an image sequence pattern rather than a file
+    sequence_framerate: Option<u32>,
+    sequence_frames: usize,
+    probe_receiver: Option<Receiver<Result<MediaInfo, String>>>,
+    audio_track: Option<usize>,
+    selected_subtitles: Vec<usize>,
+    subtitle_format: SubtitleFormat,
+    subtitle_receiver: Option<Receiver<Vec<Result<PathBuf, String>>>>,
+    cover_art: Option<PathBuf>,
+    cover_receiver: Option<Receiver<Result<PathBuf, String>>>,
+    recommendation: Option<Recommendation>,
+    filmstrips: HashMap<PathBuf, Vec<egui::TextureHandle>>,
+    filmstrip_receiver: Option<Receiver<FilmstripResult>>,
+    segment_list: String,
+    segment_output: SegmentOutput,
+    /// The batch summary the join finishes after, and the join's result
+    join_receiver: Option<Receiver<(String, Result<PathBuf, String>)>>,
+
     mode: ConversionMode,
     video_format: VideoFormat,
     video_codec: VideoCodec,
     audio_codec: AudioCodec,
+    audio_codec_user_set: bool,
+    codec_note: Option<String>,
+    hardware_accel: HardwareAccel,
+    hw_device_index: Option<u32>,
+    hardware_devices: Vec<HardwareDevice>,
+    available_encoders: Option<HashSet<String>>,
+    encoder_receiver: Option<Receiver<Result<HashSet<String>, String>>>,
+    device_receiver: Option<Receiver<Vec<HardwareDevice>>>,
 
     video_bitrate: String,
     audio_bitrate: String,
     resolution: String,
     frame_rate: String,
+    threads: String,
+    codec_params: String,
+    extra_args: String,
+    fix_timestamps: bool,
+    web_optimized: bool,
+    disable_video: bool,
+    disable_audio: bool,
+    keep_all_streams: bool,
+    extra_audio: Vec<AudioEncode>,
+    audio_offset_ms: i32,
+    crop: Option<CropRect>,
+    crop_receiver: Option<Receiver<Result<CropRect, String>>>,
+    /// Detected crop waiting for the user to accept it
+    crop_suggestion: Option<CropRect>,
+    deinterlace: Option<DeinterlaceMode>,
+    interlace_receiver: Option<Receiver<Result<bool, String>>>,
+    /// What idet made of the input, overriding the container's field order
+    detected_interlacing: Option<bool>,
+    crf: Option<u32>,
+    quality_target: Option<QualityTarget>,
+    split_output: Option<OutputSplit>,
+    trim_accuracy: TrimAccuracy,
 
     metadata_options: MetadataOptions,
 
-    is_converting: bool,
-    progress: Option<ConversionProgress>,
+    conversion_state: ConversionState,
+    preview_file: Option<PathBuf>,
+    progress_history: VecDeque<ProgressSample>,
+    conversion_started_at: Option<Instant>,
+    /// Newest progress not shown yet, held back by the update throttle
+    pending_progress: Option<ConversionProgress>,
+    progress_shown_at: Option<Instant>,
+    graph_metric: GraphMetric,
     conversion_receiver: Option<Receiver<ConversionMessage>>,
+    conversion_control: Option<mpsc::UnboundedSender<ConversionControl>>,
+    conversion_log: Option<LogBuffer>,
+    /// Command and log tail of the last failed conversion, for bug reports
+    failure_report: Option<FailureReport>,
+    log_autoscroll: bool,
     status_message: String,
     error_message: Option<String>,
 
     preset_manager: PresetManager,
     selected_preset: Option<String>,
     new_preset_name: String,
+    /// Comma-separated tags for the preset being saved
+    new_preset_tags: String,
+    preset_tag_filter: BTreeSet<String>,
+    /// Require every selected tag instead of any of them
+    preset_tags_match_all: bool,
     show_save_preset: bool,
 
     show_help_dialog: bool,
     show_about_dialog: bool,
+    show_palette: bool,
+    palette_query: String,
+    /// Highlighted row of the palette list; Enter runs it
+    palette_selected: usize,
+    show_noop_dialog: bool,
+    /// Input and output of a conversion waiting for the user to confirm
+    /// replacing the existing output
+    pending_overwrite: Option<(PathBuf, PathBuf)>,
+    overwrite_dont_ask: bool,
+    /// Answer to the overwrite question for the rest of this session
+    existing_output_choice: Option<ExistingOutput>,
+    show_benchmark: bool,
+    benchmark_receiver: Option<Receiver<BenchmarkMessage>>,
+    benchmark_results: Vec<BenchmarkResult>,
+    benchmark_expected: usize,
+    benchmark_sort: BenchmarkSort,
+    benchmark_sort_ascending: bool,
+    show_compare: bool,
+    /// Files picked in the compare window; `last_output_file` and the input
+    /// fill them in when it opens
+    compare_files: (Option<PathBuf>, Option<PathBuf>),
+    compare_receiver: Option<Receiver<Result<MediaComparison, String>>>,
+    comparison: Option<MediaComparison>,
+    show_frame_compare: bool,
+    /// Seconds into the test encode, which starts at the start of the input
+    frame_position: f64,
+    frame_view: FrameView,
+    /// Where the divider sits in the split view, from 0 (left) to 1
+    frame_split: f32,
+    frame_receiver: Option<Receiver<Result<FramePair, String>>>,
+    /// Source and encoded frame, and the position they were taken at
+    frame_textures: Option<(f64, [egui::TextureHandle; 2])>,
+    frame_error: Option<String>,
+    last_output_file: Option<PathBuf>,
+    show_library: bool,
+    library_receiver: Option<Receiver<LibraryMessage>>,
+    library_entries: Vec<LibraryEntry>,
+    library_expected: usize,
+    library_truncated: bool,
+    library_recursive: bool,
+    library_filter: String,
+    library_sort: LibrarySort,
+    library_sort_ascending: bool,
+    library_selected: HashSet<PathBuf>,
+    show_reset_dialog: bool,
+    autosaved_at: Option<Instant>,
+    saved_window_size: (f32, f32),
+    /// Settings as last written to `session.json`, to skip unchanged saves
+    autosaved_settings: String,
+    show_trash_source_dialog: bool,
+    /// Session-only on purpose, so a restart never starts out deleting files
+    trash_source_on_success: bool,
+    /// Set when the running conversion's source went to the trash
+    trashed_source_note: Option<String>,
+    /// Parts a split output was written to, added to the completion status
+    output_parts_note: Option<String>,
+    /// The last progress milestone the running conversion passed
+    milestone_note: Option<String>,
+    /// Milestones as typed in the settings, saved once they parse
+    milestones_input: String,
+
+    batch: BatchQueue,
+    saved_batch: Option<SavedBatch>,
     ffmpeg_status: Option<Result<String, String>>,
+    diagnostics_receiver: Option<Receiver<Vec<DiagnosticCheck>>>,
+    diagnostics: Vec<DiagnosticCheck>,
 
     updater: Option<Updater>,
     update_status: Option<UpdateStatus>,
     update_status_receiver: Option<Receiver<UpdateStatus>>,
+    update_banner_dismissed: bool,
     download_progress_receiver: Option<tokio::sync::mpsc::UnboundedReceiver<f32>>,
+    download_cancel: Option<tokio::sync::watch::Sender<bool>>,
 
     style_initialized: bool,
 }
 
-impl Default for FFmpegApp {
-    fn default() -> Self {
+impl FFmpegApp {
+    /// Every field at its starting value; `new` then restores the session and
+    /// starts the background checks. The runtime and config are loaded by the
+    /// caller, so a failure to create the runtime can be reported instead of
+    /// panicking here.
+    fn initial_state(runtime: Arc<Runtime>, config: Config) -> Self {
         Self {
-            config: Config::load(),
-            runtime: Arc::new(tokio::runtime::Runtime::new().expect("runtime init failed")),
+            config,
+            runtime,
 
             input_file: None,
             output_folder: None,
             output_file_name: String::new(),
 
+            media_info: None,
+            sequence_framerate: None,
+            sequence_frames: 0,
+            probe_receiver: None,
+            audio_track: None,
+            selected_subtitles: Vec::new(),
+            subtitle_format: SubtitleFormat::Srt,
+            subtitle_receiver: None,
+            cover_art: None,
+            cover_receiver: None,
+            recommendation: None,
+            filmstrips: HashMap::new(),
+            filmstrip_receiver: None,
+            segment_list: String::new(),
+            segment_output: SegmentOutput::SeparateClips,
+            join_receiver: None,
+
             mode: ConversionMode::Convert,
             video_format: VideoFormat::Mp4,
             video_codec: VideoCodec::H264,
             audio_codec: AudioCodec::Aac,
+            audio_codec_user_set: false,
+            codec_note: None,
+            hardware_accel: HardwareAccel::None,
+            hw_device_index: None,
+            hardware_devices: Vec::new(),
+            available_encoders: None,
+            encoder_receiver: None,
+            device_receiver: None,
 
             video_bitrate: String::new(),
             audio_bitrate: String::new(),
             resolution: String::new(),
             frame_rate: String::new(),
+            threads: String::new(),
+            codec_params: String::new(),
+            extra_args: String::new(),
+            fix_timestamps: false,
+            web_optimized: false,
+            disable_video: false,
+            disable_audio: false,
+            keep_all_streams: false,
+            extra_audio: Vec::new(),
+            audio_offset_ms: 0,
+            crop: None,
+            crop_receiver: None,
+            crop_suggestion: None,
+            deinterlace: None,
+            interlace_receiver: None,
+            detected_interlacing: None,
+            crf: None,
+            quality_target: None,
+            split_output: None,
+            trim_accuracy: TrimAccuracy::KeyframeFast,
 
             metadata_options: MetadataOptions::default(),
 
-            is_converting: false,
-            progress: None,
+            conversion_state: ConversionState::Idle,
+            preview_file: None,
+            progress_history: VecDeque::with_capacity(PROGRESS_BUFFER_SIZE),
+            conversion_started_at: None,
+            pending_progress: None,
+            progress_shown_at: None,
+            graph_metric: GraphMetric::Speed,
             conversion_receiver: None,
+            conversion_control: None,
+            conversion_log: None,
+            failure_report: None,
+            log_autoscroll: true,
             status_message: "Ready".to_string(),
             error_message: None,
 
             preset_manager: PresetManager::new(),
             selected_preset: None,
             new_preset_name: String::new(),
+            new_preset_tags: String::new(),
+            preset_tag_filter: BTreeSet::new(),
+            preset_tags_match_all: false,
             show_save_preset: false,
 
             show_help_dialog: false,
             show_about_dialog: false,
+            show_palette: false,
+            palette_query: String::new(),
+            palette_selected: 0,
+            show_noop_dialog: false,
+            pending_overwrite: None,
+            overwrite_dont_ask: false,
+            existing_output_choice: None,
+            show_benchmark: false,
+            benchmark_receiver: None,
+            benchmark_results: Vec::new(),
+            benchmark_expected: 0,
+            benchmark_sort: BenchmarkSort::Time,
+            benchmark_sort_ascending: true,
+            show_compare: false,
+            compare_files: (None, None),
+            compare_receiver: None,
+            comparison: None,
+            show_frame_compare: false,
+            frame_position: 0.0,
+            frame_view: FrameView::Split,
+            frame_split: 0.5,
+            frame_receiver: None,
+            frame_textures: None,
+            frame_error: None,
+            last_output_file: None,
+            show_library: false,
+            library_receiver: None,
+            library_entries: Vec::new(),
+            library_expected: 0,
+            library_truncated: false,
+            library_recursive: true,
+            library_filter: String::new(),
+            library_sort: LibrarySort::Name,
+            library_sort_ascending: true,
+            library_selected: HashSet::new(),
+            show_reset_dialog: false,
+            autosaved_at: None,
+            saved_window_size: (0.0, 0.0),
+            autosaved_settings: String::new(),
+            show_trash_source_dialog: false,
+            trash_source_on_success: false,
+            trashed_source_note: None,
+            output_parts_note: None,
+            milestone_note: None,
+            milestones_input: String::new(),
+
+            batch: BatchQueue::default(),
+            saved_batch: None,
             ffmpeg_status: None,
+            diagnostics_receiver: None,
+            diagnostics: Vec::new(),
 
             updater: None,
             update_status: None,
             update_status_receiver: None,
+            update_banner_dismissed: false,
             download_progress_receiver: None,
+            download_cancel: None,
 
             style_initialized: false,
         }
@@ -109,47 +607,83 @@ impl Default for FFmpegApp {
 }
 
 impl FFmpegApp {
-    fn bg_base() -> Color32 {
-        Color32::from_rgb(6, 6, 8)
+    /// Palette of the theme `ui` is drawn with; with `UiTheme::System` the
+    /// OS can switch it at any time
+    fn colors(ui: &egui::Ui) -> &'static ThemeColors {
+        if ui.visuals().dark_mode {
+            &DARK_COLORS
+        } else {
+            &LIGHT_COLORS
+        }
     }
 
-    fn bg_panel() -> Color32 {
-        Color32::from_rgb(10, 10, 13)
+    fn bg_card(ui: &egui::Ui) -> Color32 {
+        Self::colors(ui).bg_card
     }
 
-    fn bg_card() -> Color32 {
-        Color32::from_rgb(14, 14, 18)
+    fn border_soft(ui: &egui::Ui) -> Color32 {
+        Self::colors(ui).border_soft
     }
 
-    fn border_soft() -> Color32 {
-        Color32::from_rgb(45, 45, 54)
+    fn accent(ui: &egui::Ui) -> Color32 {
+        Self::colors(ui).accent
     }
 
-    fn accent() -> Color32 {
-        Color32::from_rgb(162, 162, 176)
+    fn text_main(ui: &egui::Ui) -> Color32 {
+        Self::colors(ui).text_main
     }
 
-    fn text_main() -> Color32 {
-        Color32::from_rgb(235, 235, 239)
+    fn success(ui: &egui::Ui) -> Color32 {
+        Self::colors(ui).success
     }
 
-    fn success() -> Color32 {
-        Color32::from_rgb(104, 184, 149)
+    fn warning(ui: &egui::Ui) -> Color32 {
+        Self::colors(ui).warning
     }
 
-    fn danger() -> Color32 {
-        Color32::from_rgb(225, 106, 106)
+    fn danger(ui: &egui::Ui) -> Color32 {
+        Self::colors(ui).danger
     }
 
-    pub fn new(runtime: Arc<Runtime>) -> Self {
-        let mut app = Self {
-            runtime,
-            ..Default::default()
-        };
+    pub fn new(runtime: Arc<Runtime>, config: Config) -> Self {
+        let mut app = Self::initial_state(runtime, config);
 
         if let Ok(updater) = Updater::new("1.0.0", "pater/ffmpegrust") {
-            app.updater = Some(updater);
+            app.updater =
+                Some(updater.with_download_rate_limit(app.config.update_download_rate_limit));
+        }
+
+        if app.config.auto_check_updates {
+            app.check_for_updates();
+        }
+
+        if let Some(preset) = load_session() {
+            app.load_settings(&preset);
         }
+        app.error_message = app.preset_manager.take_load_warning();
+        app.milestones_input = app
+            .config
+            .progress_milestones
+            .iter()
+            .map(u32::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        app.saved_window_size = (app.config.window_width, app.config.window_height);
+        app.autosaved_at = Some(Instant::now());
+        app.saved_batch = BatchQueue::load_unfinished();
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        app.encoder_receiver = Some(receiver);
+        app.runtime.spawn(async move {
+            let result = match list_encoders().await {
+                Ok(mut encoders) => {
+                    drop_unusable_hardware_encoders(&mut encoders).await;
+                    Ok(encoders)
+                }
+                Err(err) => Err(err),
+            };
+            let _ = sender.send(result);
+        });
 
         app
     }
@@ -159,872 +693,5353 @@ impl FFmpegApp {
             return;
         }
 
-        let mut visuals = egui::Visuals::dark();
-        visuals.panel_fill = Self::bg_panel();
-        visuals.extreme_bg_color = Self::bg_base();
-        visuals.faint_bg_color = Self::bg_card();
-        visuals.window_fill = Self::bg_panel();
-        visuals.override_text_color = Some(Self::text_main());
-        visuals.selection.bg_fill = Color32::from_rgb(60, 60, 72);
-        visuals.selection.stroke = Stroke::new(1.0, Self::accent());
-
-        visuals.widgets.noninteractive.bg_fill = Self::bg_card();
-        visuals.widgets.noninteractive.bg_stroke = Stroke::new(1.0, Self::border_soft());
-        visuals.widgets.inactive.bg_fill = Color32::from_rgb(20, 20, 25);
-        visuals.widgets.inactive.bg_stroke = Stroke::new(1.0, Self::border_soft());
-        visuals.widgets.hovered.bg_fill = Color32::from_rgb(30, 30, 38);
-        visuals.widgets.hovered.bg_stroke = Stroke::new(1.0, Self::accent());
-        visuals.widgets.active.bg_fill = Color32::from_rgb(36, 36, 46);
-        visuals.widgets.active.bg_stroke = Stroke::new(1.0, Self::accent());
-        visuals.window_stroke = Stroke::new(1.0, Self::border_soft());
-        ctx.set_visuals(visuals);
-
-        let mut style = (*ctx.style()).clone();
-        style.spacing.item_spacing = egui::vec2(6.0, 6.0);
-        style.spacing.button_padding = egui::vec2(8.0, 4.0);
-        style.spacing.interact_size.y = 24.0;
-        style
-            .text_styles
-            .insert(egui::TextStyle::Heading, egui::FontId::proportional(15.0));
-        style
-            .text_styles
-            .insert(egui::TextStyle::Body, egui::FontId::proportional(13.0));
-        style
-            .text_styles
-            .insert(egui::TextStyle::Button, egui::FontId::proportional(12.5));
-        style
-            .text_styles
-            .insert(egui::TextStyle::Small, egui::FontId::proportional(11.0));
-        ctx.set_style(style);
+        ctx.set_visuals_of(
+            Theme::Dark,
+            Self::themed_visuals(egui::Visuals::dark(), &DARK_COLORS),
+        );
+        ctx.set_visuals_of(
+            Theme::Light,
+            Self::themed_visuals(egui::Visuals::light(), &LIGHT_COLORS),
+        );
+
+        ctx.all_styles_mut(|style| {
+            style.spacing.item_spacing = egui::vec2(6.0, 6.0);
+            style.spacing.button_padding = egui::vec2(8.0, 4.0);
+            style.spacing.interact_size.y = 24.0;
+            style
+                .text_styles
+                .insert(egui::TextStyle::Heading, egui::FontId::proportional(15.0));
+            style
+                .text_styles
+                .insert(egui::TextStyle::Body, egui::FontId::proportional(13.0));
+            style
+                .text_styles
+                .insert(egui::TextStyle::Button, egui::FontId::proportional(12.5));
+            style
+                .text_styles
+                .insert(egui::TextStyle::Small, egui::FontId::proportional(11.0));
+        });
+        ctx.set_theme(Self::theme_preference(self.config.theme));
 
         self.style_initialized = true;
     }
 
+    fn themed_visuals(mut visuals: egui::Visuals, colors: &ThemeColors) -> egui::Visuals {
+        visuals.panel_fill = colors.bg_panel;
+        visuals.extreme_bg_color = colors.bg_base;
+        visuals.faint_bg_color = colors.bg_card;
+        visuals.window_fill = colors.bg_panel;
+        visuals.override_text_color = Some(colors.text_main);
+        visuals.selection.bg_fill = colors.selection;
+        visuals.selection.stroke = Stroke::new(1.0, colors.accent);
+
+        visuals.widgets.noninteractive.bg_fill = colors.bg_card;
+        visuals.widgets.noninteractive.bg_stroke = Stroke::new(1.0, colors.border_soft);
+        visuals.widgets.inactive.bg_fill = colors.widget_inactive;
+        visuals.widgets.inactive.bg_stroke = Stroke::new(1.0, colors.border_soft);
+        visuals.widgets.hovered.bg_fill = colors.widget_hovered;
+        visuals.widgets.hovered.bg_stroke = Stroke::new(1.0, colors.accent);
+        visuals.widgets.active.bg_fill = colors.widget_active;
+        visuals.widgets.active.bg_stroke = Stroke::new(1.0, colors.accent);
+        visuals.window_stroke = Stroke::new(1.0, colors.border_soft);
+        visuals
+    }
+
+    fn theme_preference(theme: UiTheme) -> ThemePreference {
+        match theme {
+            UiTheme::Dark => ThemePreference::Dark,
+            UiTheme::Light => ThemePreference::Light,
+            UiTheme::System => ThemePreference::System,
+        }
+    }
+
+    fn set_theme(&mut self, ctx: &Context, theme: UiTheme) {
+        self.config.theme = theme;
+        self.config.save();
+        ctx.set_theme(Self::theme_preference(theme));
+    }
+
     fn select_input_file(&mut self) {
         let default_dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
         let start_dir = self.config.last_input_folder.clone().unwrap_or(default_dir);
 
         if let Some(file) = rfd::FileDialog::new()
             .set_title("Select Input Video File")
-            .add_filter(
-                "Video Files",
-                &[
-                    "mp4", "mkv", "mov", "avi", "webm", "flv", "wmv", "m4v", "3gp", "ts", "mts",
-                    "m2ts", "vob", "mpg", "mpeg", "ogv",
-                ],
-            )
+            .add_filter("Video Files", VIDEO_EXTENSIONS)
             .set_directory(start_dir)
             .pick_file()
         {
-            if let Some(parent) = file.parent() {
-                self.config.update_input_folder(Some(parent.to_path_buf()));
-                if self.output_folder.is_none() {
-                    let out = parent.to_path_buf();
-                    self.output_folder = Some(out.clone());
-                    self.config.update_output_folder(Some(out));
-                }
-            }
+            self.open_input_file(file);
+        }
+    }
 
-            let default_output = generate_output_filename(&file, &self.video_format);
-            self.output_file_name = default_output
-                .file_stem()
-                .map(|name| name.to_string_lossy().to_string())
-                .unwrap_or_default();
-            self.input_file = Some(file);
-            self.error_message = None;
-            self.status_message = "Input file selected".to_string();
+    fn open_input_file(&mut self, file: PathBuf) {
+        if let Some(parent) = file.parent() {
+            self.remember_input_folder(parent);
         }
+        self.config.add_recent_file(&file);
+
+        let default_output =
+            generate_output_filename(&file, &self.video_format, &self.output_suffix());
+        self.output_file_name = default_output
+            .file_stem()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        self.input_file = Some(file.clone());
+        self.sequence_framerate = None;
+        self.error_message = None;
+        self.status_message = "Input file selected".to_string();
+        self.start_probe(file);
     }
 
-    fn select_output_folder(&mut self) {
-        let default_dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
-        let start_dir = self
-            .config
-            .last_output_folder
-            .clone()
-            .unwrap_or(default_dir);
+    fn render_recent_files_menu(&mut self, ui: &mut egui::Ui) {
+        let recent = self.config.recent_files.clone();
+        ui.add_enabled_ui(!recent.is_empty(), |ui| {
+            ui.menu_button("Recent", |ui| {
+                for file in recent {
+                    let name = file
+                        .file_name()
+                        .map(|name| name.to_string_lossy().to_string())
+                        .unwrap_or_else(|| file.display().to_string());
+                    let folder = file.parent().map(Self::short_path).unwrap_or_default();
+                    if ui
+                        .button(Self::ellipsize(&name, 48))
+                        .on_hover_text(format!("{name}\nin {folder}"))
+                        .clicked()
+                    {
+                        ui.close();
+                        if file.is_file() {
+                            self.open_input_file(file);
+                        } else {
+                            self.config.prune_recent_files();
+                            self.config.save();
+                            self.error_message =
+                                Some(format!("{} no longer exists", file.display()));
+                        }
+                    }
+                }
+                ui.separator();
+                if ui.button("Clear Recent Files").clicked() {
+                    ui.close();
+                    self.config.recent_files.clear();
+                    self.config.save();
+                }
+            })
+            .response
+            .on_disabled_hover_text("Files you open are listed here");
+        });
+    }
 
-        if let Some(folder) = rfd::FileDialog::new()
-            .set_title("Select Output Folder")
-            .set_directory(start_dir)
-            .pick_folder()
-        {
-            self.config.update_output_folder(Some(folder.clone()));
-            self.output_folder = Some(folder);
-            self.status_message = "Output folder selected".to_string();
+    fn remember_input_folder(&mut self, folder: &Path) {
+        self.config.update_input_folder(Some(folder.to_path_buf()));
+        match self.config.output_mode {
+            OutputMode::SameAsInput => {
+                self.output_folder = Some(folder.to_path_buf());
+            }
+            OutputMode::FixedFolder if self.output_folder.is_none() => {
+                let out = folder.to_path_buf();
+                self.output_folder = Some(out.clone());
+                self.config.update_output_folder(Some(out));
+            }
+            _ => {}
         }
     }
 
-    fn select_output_file(&mut self) {
-        let default_dir = self
-            .output_folder
-            .clone()
-            .or_else(|| self.config.last_output_folder.clone())
-            .or_else(|| std::env::current_dir().ok())
-            .unwrap_or_else(|| PathBuf::from("."));
+    /// Picks one image and uses every image numbered like it (or, without a
+    /// number, every image of its type in the folder) as the input frames
+    fn select_image_sequence(&mut self) {
+        let default_dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        let start_dir = self.config.last_input_folder.clone().unwrap_or(default_dir);
 
-        let default_stem = if self.output_file_name.trim().is_empty() {
-            self.input_file
-                .as_ref()
-                .map(|f| generate_output_filename(f, &self.video_format))
-                .and_then(|p| p.file_stem().map(|n| n.to_string_lossy().to_string()))
-                .unwrap_or_else(|| "output".to_string())
-        } else {
-            Self::normalize_output_name(&self.output_file_name)
+        let Some(image) = rfd::FileDialog::new()
+            .set_title("Select Any Image of the Sequence")
+            .add_filter("Images", IMAGE_EXTENSIONS)
+            .set_directory(start_dir)
+            .pick_file()
+        else {
+            return;
         };
 
-        let default_name = format!("{default_stem}.{}", self.video_format.extension());
-
-        if let Some(path) = rfd::FileDialog::new()
-            .set_title("Select Output File")
-            .set_directory(default_dir)
-            .set_file_name(&default_name)
-            .save_file()
-        {
-            if let Some(parent) = path.parent() {
-                let parent_buf = parent.to_path_buf();
-                self.output_folder = Some(parent_buf.clone());
-                self.config.update_output_folder(Some(parent_buf));
-            }
-
-            if let Some(name) = path.file_stem() {
-                self.output_file_name = name.to_string_lossy().to_string();
+        let Some(pattern) = sequence_pattern_for(&image) else {
+            self.error_message =
+                Some("Images with % in their name can't be read as a sequence".to_string());
+            return;
+        };
+        let scan = match scan_image_sequence(&pattern) {
+            Ok(scan) => scan,
+            Err(error) => {
+                self.error_message = Some(error);
+                return;
             }
+        };
 
-            self.status_message = "Output file selected".to_string();
-        }
+        let folder = pattern.parent().unwrap_or(Path::new(".")).to_path_buf();
+        self.remember_input_folder(&folder);
+        let folder_name = folder
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| "slideshow".to_string());
+        let default_output = generate_output_filename(
+            &folder.join(folder_name),
+            &self.video_format,
+            &self.output_suffix(),
+        );
+        self.output_file_name = default_output
+            .file_stem()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        self.media_info = None;
+        self.probe_receiver = None;
+        self.audio_track = None;
+        self.selected_subtitles.clear();
+        self.cover_art = None;
+        self.recommendation = None;
+        self.crop_receiver = None;
+        self.crop_suggestion = None;
+        self.interlace_receiver = None;
+        self.detected_interlacing = None;
+
+        self.input_file = Some(pattern);
+        self.sequence_framerate = Some(
+            self.sequence_framerate
+                .unwrap_or(DEFAULT_SEQUENCE_FRAMERATE),
+        );
+        self.sequence_frames = scan.frames;
+        self.error_message = None;
+        self.status_message = format!("Image sequence selected: {} images", scan.frames);
     }
 
-    fn can_start_conversion(&self) -> bool {
-        !self.is_converting
-    }
+    fn start_probe(&mut self, file: PathBuf) {
+        self.media_info = None;
+        self.audio_track = None;
+        self.selected_subtitles.clear();
+        self.cover_art = None;
+        self.recommendation = None;
+        self.crop_receiver = None;
+        self.crop_suggestion = None;
+        self.interlace_receiver = None;
+        self.detected_interlacing = None;
 
-    fn build_current_preset(&self, name: String) -> ConversionPreset {
-        ConversionPreset {
-            name,
-            mode: self.mode.clone(),
-            video_format: self.video_format.clone(),
-            video_codec: self.video_codec.clone(),
-            audio_codec: self.audio_codec.clone(),
-            video_bitrate: (!self.video_bitrate.is_empty()).then(|| self.video_bitrate.clone()),
-            audio_bitrate: (!self.audio_bitrate.is_empty()).then(|| self.audio_bitrate.clone()),
-            resolution: (!self.resolution.is_empty()).then(|| self.resolution.clone()),
-            frame_rate: (!self.frame_rate.is_empty()).then(|| self.frame_rate.clone()),
-            metadata_options: self.metadata_options.clone(),
-        }
+        let (sender, receiver) = std::sync::mpsc::channel();
+        self.probe_receiver = Some(receiver);
+
+        self.runtime.spawn(async move {
+            let _ = sender.send(probe_media(&file).await);
+        });
     }
 
-    fn start_conversion(&mut self) {
-        let Some(input_file) = self.input_file.clone() else {
-            self.error_message = Some("Please select an input file".to_string());
-            self.status_message = "Input file required".to_string();
+    fn check_probe_results(&mut self) {
+        let Some(receiver) = &self.probe_receiver else {
             return;
         };
 
-        let output_folder = self
-            .output_folder
-            .clone()
-            .or_else(|| input_file.parent().map(|p| p.to_path_buf()))
-            .or_else(|| self.config.last_output_folder.clone())
-            .unwrap_or_else(|| std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")));
+        let Ok(result) = receiver.try_recv() else {
+            return;
+        };
 
-        if self.output_folder.is_none() {
-            self.output_folder = Some(output_folder.clone());
-            self.config
-                .update_output_folder(Some(output_folder.clone()));
-            self.status_message = "Using input folder as output folder".to_string();
+        self.probe_receiver = None;
+        match result {
+            Ok(info) => {
+                self.audio_track = if info.audio_streams().len() > 1 {
+                    info.default_audio_track()
+                } else {
+                    None
+                };
+                self.media_info = Some(info);
+            }
+            Err(err) => {
+                self.status_message = format!("Could not inspect input: {err}");
+            }
         }
+    }
 
-        if self.is_converting {
+    fn start_filmstrip_extraction(&mut self) {
+        let Some(info) = &self.media_info else {
+            return;
+        };
+        let (Some(input_file), Some(duration)) = (self.input_file.clone(), info.duration) else {
             return;
-        }
-
-        let normalized_name = Self::normalize_output_name(&self.output_file_name);
-
-        let output_file = if normalized_name.is_empty() {
-            let output_filename = generate_output_filename(&input_file, &self.video_format);
-            let Some(file_name) = output_filename.file_name() else {
-                self.error_message = Some("Failed to generate output filename".to_string());
-                return;
-            };
-            output_folder.join(file_name)
-        } else {
-            self.output_file_name = normalized_name.clone();
-            output_folder.join(format!(
-                "{normalized_name}.{}",
-                self.video_format.extension()
-            ))
         };
-        let preset = self.build_current_preset("Current".to_string());
+        if self.filmstrip_receiver.is_some()
+            || info.video_stream().is_none()
+            || self.filmstrips.contains_key(&input_file)
+        {
+            return;
+        }
 
         let (sender, receiver) = std::sync::mpsc::channel();
-        self.conversion_receiver = Some(receiver);
+        self.filmstrip_receiver = Some(receiver);
 
-        let task = ConversionTask::new(input_file, output_file, preset, sender);
         self.runtime.spawn(async move {
-            task.execute().await;
+            let result = extract_filmstrip(&input_file, duration, FILMSTRIP_FRAMES).await;
+            let _ = sender.send((input_file, result));
         });
-
-        self.is_converting = true;
-        self.progress = None;
-        self.error_message = None;
-        self.status_message = "Starting conversion...".to_string();
     }
 
-    fn stop_conversion(&mut self) {
-        self.is_converting = false;
-        self.progress = None;
-        self.conversion_receiver = None;
-        self.status_message = "Conversion stopped".to_string();
-    }
+    fn check_filmstrip_results(&mut self, ctx: &Context) {
+        let Some(receiver) = &self.filmstrip_receiver else {
+            return;
+        };
 
-    fn check_conversion_progress(&mut self) {
-        let mut messages = Vec::new();
+        let Ok((input_file, result)) = receiver.try_recv() else {
+            return;
+        };
 
-        if let Some(receiver) = &self.conversion_receiver {
-            while let Ok(message) = receiver.try_recv() {
-                messages.push(message);
-            }
+        self.filmstrip_receiver = None;
+        // A missing preview isn't worth interrupting the user over; keep the
+        // empty strip so it isn't retried every frame
+        let frames = result.unwrap_or_default();
+
+        let textures = frames
+            .iter()
+            .enumerate()
+            .map(|(i, rgba)| {
+                let image = egui::ColorImage::from_rgba_unmultiplied(FILMSTRIP_FRAME_SIZE, rgba);
+                ctx.load_texture(
+                    format!("filmstrip_{}_{i}", input_file.display()),
+                    image,
+                    egui::TextureOptions::LINEAR,
+                )
+            })
+            .collect();
+        self.filmstrips.insert(input_file, textures);
+    }
+
+    /// Frames are only extracted once the preview is opened, since each one
+    /// is a separate seek into the input
+    fn render_filmstrip(&mut self, ui: &mut egui::Ui) {
+        let Some(info) = &self.media_info else {
+            return;
+        };
+        if info.video_stream().is_none() {
+            return;
         }
 
-        for message in messages {
-            match message {
-                ConversionMessage::Progress(progress) => {
-                    let percentage = progress.percentage;
-                    self.progress = Some(progress);
-                    self.status_message = format!("Converting... {percentage:.1}%");
+        let mut extract = false;
+        ui.collapsing("Preview frames", |ui| {
+            let frames = self
+                .input_file
+                .as_ref()
+                .and_then(|input_file| self.filmstrips.get(input_file));
+            match frames {
+                Some(frames) if frames.is_empty() => {
+                    ui.label(RichText::new("No preview available").small());
                 }
-                ConversionMessage::Completed(output_path) => {
-                    self.is_converting = false;
-                    self.progress = None;
-                    self.conversion_receiver = None;
-                    self.status_message =
-                        format!("Conversion completed: {}", output_path.display());
+                Some(frames) => {
+                    ScrollArea::horizontal()
+                        .id_salt("filmstrip")
+                        .show(ui, |ui| {
+                            ui.horizontal(|ui| {
+                                ui.spacing_mut().item_spacing.x = 2.0;
+                                for texture in frames {
+                                    ui.add(
+                                        egui::Image::new(texture)
+                                            .fit_to_exact_size(egui::vec2(80.0, 45.0)),
+                                    );
+                                }
+                            });
+                        });
                 }
-                ConversionMessage::Error(error) => {
-                    self.is_converting = false;
-                    self.progress = None;
-                    self.conversion_receiver = None;
-                    self.error_message = Some(error);
-                    self.status_message = "Conversion failed".to_string();
+                None => {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label(RichText::new("Extracting frames...").small());
+                    });
+                    extract = true;
                 }
             }
+        });
+        if extract {
+            self.start_filmstrip_extraction();
         }
     }
 
-    fn apply_preset(&mut self, preset_name: &str) {
-        if let Some(preset) = self.preset_manager.get_preset(preset_name) {
-            self.mode = preset.mode.clone();
-            self.video_format = preset.video_format.clone();
-            self.video_codec = preset.video_codec.clone();
-            self.audio_codec = preset.audio_codec.clone();
-            self.video_bitrate = preset.video_bitrate.clone().unwrap_or_default();
-            self.audio_bitrate = preset.audio_bitrate.clone().unwrap_or_default();
-            self.resolution = preset.resolution.clone().unwrap_or_default();
-            self.frame_rate = preset.frame_rate.clone().unwrap_or_default();
-            self.metadata_options = preset.metadata_options.clone();
-            self.selected_preset = Some(preset_name.to_string());
-            self.status_message = format!("Applied preset: {preset_name}");
-        }
+    /// Where the main output would go, without asking the user anything
+    fn planned_output_file(&self, input_file: &Path) -> PathBuf {
+        let folder = self
+            .output_folder
+            .clone()
+            .or_else(|| input_file.parent().map(Path::to_path_buf))
+            .unwrap_or_else(|| PathBuf::from("."));
+        let name = Self::normalize_output_name(&self.output_file_name);
+
+        let output_file = if name.is_empty() {
+            let generated =
+                generate_output_filename(input_file, &self.video_format, &self.output_suffix());
+            folder.join(generated.file_name().unwrap_or_default())
+        } else {
+            folder.join(format!("{name}.{}", self.video_format.extension()))
+        };
+        avoid_input_collision(input_file, output_file)
     }
 
-    fn save_current_preset(&mut self) {
-        if self.new_preset_name.trim().is_empty() {
+    fn start_subtitle_extraction(&mut self) {
+        let (Some(input_file), Some(info)) = (self.input_file.clone(), self.media_info.clone())
+        else {
             return;
-        }
+        };
 
-        let preset_name = self.new_preset_name.trim().to_string();
-        let preset = self.build_current_preset(preset_name.clone());
-        self.preset_manager.add_preset(preset);
+        let output_base = self.planned_output_file(&input_file);
+        let mut selected = self.selected_subtitles.clone();
+        selected.sort_unstable();
+        let format = self.subtitle_format;
 
-        self.status_message = format!("Saved preset: {preset_name}");
-        self.selected_preset = Some(preset_name);
-        self.new_preset_name.clear();
-        self.show_save_preset = false;
-    }
+        let (sender, receiver) = std::sync::mpsc::channel();
+        self.subtitle_receiver = Some(receiver);
+        self.status_message = "Extracting subtitles...".to_string();
 
-    fn check_ffmpeg(&mut self) {
-        self.ffmpeg_status = Some(check_ffmpeg_installation());
+        self.runtime.spawn(async move {
+            let results =
+                extract_subtitles(&input_file, &info, &selected, format, &output_base).await;
+            let _ = sender.send(results);
+        });
     }
 
-    fn check_for_updates(&mut self) {
-        let Some(updater) = self.updater.clone() else {
-            self.update_status = Some(UpdateStatus::Error("Updater unavailable".to_string()));
+    fn start_crop_detection(&mut self) {
+        let Some(input_file) = self.input_file.clone() else {
             return;
         };
+        let duration = self.media_info.as_ref().and_then(|info| info.duration);
 
-        let (sender, receiver): (Sender<UpdateStatus>, Receiver<UpdateStatus>) =
-            std::sync::mpsc::channel();
-        self.update_status_receiver = Some(receiver);
-        self.update_status = Some(UpdateStatus::CheckingForUpdates);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        self.crop_receiver = Some(receiver);
+        self.crop_suggestion = None;
+        self.status_message = "Detecting black bars...".to_string();
 
         self.runtime.spawn(async move {
-            let status = updater.check_for_updates().await;
-            let _ = sender.send(status);
+            let _ = sender.send(detect_crop(&input_file, duration).await);
         });
     }
 
-    fn start_update_download(&mut self, update_info: UpdateInfo) {
-        let Some(updater) = self.updater.clone() else {
-            self.update_status = Some(UpdateStatus::Error("Updater unavailable".to_string()));
+    fn check_crop_results(&mut self) {
+        let Some(receiver) = &self.crop_receiver else {
             return;
         };
 
-        let (progress_tx, progress_rx) = mpsc::unbounded_channel();
-        self.download_progress_receiver = Some(progress_rx);
-
-        let (status_tx, status_rx): (Sender<UpdateStatus>, Receiver<UpdateStatus>) =
-            std::sync::mpsc::channel();
-        self.update_status_receiver = Some(status_rx);
-        self.update_status = Some(UpdateStatus::DownloadingUpdate(0.0));
+        let Ok(result) = receiver.try_recv() else {
+            return;
+        };
 
-        self.runtime.spawn(async move {
-            match updater
-                .download_update(&update_info, Some(progress_tx))
-                .await
-            {
-                Ok(file_path) => {
-                    let _ = status_tx.send(UpdateStatus::InstallingUpdate);
-                    match updater.apply_update(&file_path).await {
-                        Ok(()) => {
-                            let _ = updater.restart_application().await;
-                        }
-                        Err(err) => {
-                            let _ = status_tx.send(UpdateStatus::Error(format!(
-                                "Failed to apply update: {err}"
-                            )));
-                        }
-                    }
-                }
-                Err(err) => {
-                    let _ = status_tx.send(UpdateStatus::Error(format!(
-                        "Failed to download update: {err}"
-                    )));
+        self.crop_receiver = None;
+        match result {
+            Ok((width, height, x, y)) => {
+                let source = self
+                    .media_info
+                    .as_ref()
+                    .and_then(MediaInfo::video_stream)
+                    .and_then(|video| Some((video.width?, video.height?)));
+                if source == Some((width, height)) {
+                    self.status_message = "No black bars found".to_string();
+                } else {
+                    self.status_message = format!("Suggested crop: {width}x{height} at {x},{y}");
+                    self.crop_suggestion = Some((width, height, x, y));
                 }
             }
+            Err(error) => {
+                self.status_message = "Black bar detection failed".to_string();
+                self.error_message = Some(error);
+            }
+        }
+    }
+
+    fn start_interlace_detection(&mut self) {
+        let Some(input_file) = self.input_file.clone() else {
+            return;
+        };
+        let duration = self.media_info.as_ref().and_then(|info| info.duration);
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        self.interlace_receiver = Some(receiver);
+        self.detected_interlacing = None;
+        self.status_message = "Checking for interlacing...".to_string();
+
+        self.runtime.spawn(async move {
+            let _ = sender.send(detect_interlacing(&input_file, duration).await);
         });
     }
 
-    fn poll_async_updates(&mut self) {
-        if let Some(receiver) = &self.update_status_receiver {
-            while let Ok(status) = receiver.try_recv() {
-                self.update_status = Some(status);
-            }
-        }
+    fn check_interlace_results(&mut self) {
+        let Some(receiver) = &self.interlace_receiver else {
+            return;
+        };
 
-        if let Some(receiver) = &mut self.download_progress_receiver {
-            while let Ok(progress) = receiver.try_recv() {
-                self.update_status = Some(UpdateStatus::DownloadingUpdate(progress));
+        let Ok(result) = receiver.try_recv() else {
+            return;
+        };
+
+        self.interlace_receiver = None;
+        match result {
+            Ok(interlaced) => {
+                self.status_message = if interlaced {
+                    "The input is interlaced".to_string()
+                } else {
+                    "The input is progressive".to_string()
+                };
+                self.detected_interlacing = Some(interlaced);
+            }
+            Err(error) => {
+                self.status_message = "Interlacing detection failed".to_string();
+                self.error_message = Some(error);
             }
         }
     }
 
-    fn short_path(path: &Path) -> String {
-        path.file_name()
-            .map(|name| name.to_string_lossy().to_string())
-            .filter(|name| !name.is_empty())
-            .unwrap_or_else(|| path.display().to_string())
+    /// Whether the input looks interlaced: idet's verdict once it has run,
+    /// otherwise the field order the container declares
+    fn input_is_interlaced(&self) -> bool {
+        self.detected_interlacing.unwrap_or_else(|| {
+            self.media_info
+                .as_ref()
+                .and_then(MediaInfo::video_stream)
+                .is_some_and(|video| video.is_interlaced())
+        })
     }
 
-    fn ellipsize(text: &str, max_chars: usize) -> String {
-        let count = text.chars().count();
-        if count <= max_chars {
-            return text.to_string();
-        }
+    fn check_subtitle_results(&mut self) {
+        let Some(receiver) = &self.subtitle_receiver else {
+            return;
+        };
 
-        if max_chars <= 3 {
-            return "...".to_string();
-        }
+        let Ok(results) = receiver.try_recv() else {
+            return;
+        };
 
-        let take = max_chars - 3;
-        let mut out = String::new();
-        for ch in text.chars().take(take) {
-            out.push(ch);
+        self.subtitle_receiver = None;
+        let extracted = results.iter().filter(|result| result.is_ok()).count();
+        let problems: Vec<String> = results.into_iter().filter_map(Result::err).collect();
+
+        self.status_message = format!("Extracted {extracted} subtitle file(s)");
+        if !problems.is_empty() {
+            self.error_message = Some(problems.join("; "));
         }
-        out.push_str("...");
-        out
     }
 
-    fn normalize_output_name(name: &str) -> String {
-        let trimmed = name.trim();
-        if trimmed.is_empty() {
-            return String::new();
+    fn select_output_folder(&mut self) {
+        let default_dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        let start_dir = self
+            .config
+            .last_output_folder
+            .clone()
+            .unwrap_or(default_dir);
+
+        if let Some(folder) = rfd::FileDialog::new()
+            .set_title("Select Output Folder")
+            .set_directory(start_dir)
+            .pick_folder()
+        {
+            self.config.update_output_folder(Some(folder.clone()));
+            self.output_folder = Some(folder);
+            self.status_message = "Output folder selected".to_string();
         }
+    }
 
-        let file_name = Path::new(trimmed)
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or(trimmed);
+    fn select_output_file(&mut self) {
+        let default_dir = self
+            .output_folder
+            .clone()
+            .or_else(|| self.config.last_output_folder.clone())
+            .or_else(|| std::env::current_dir().ok())
+            .unwrap_or_else(|| PathBuf::from("."));
 
-        Path::new(file_name)
-            .file_stem()
-            .and_then(|s| s.to_str())
-            .unwrap_or(file_name)
-            .to_string()
+        let default_stem = if self.output_file_name.trim().is_empty() {
+            self.input_file
+                .as_ref()
+                .map(|f| generate_output_filename(f, &self.video_format, &self.output_suffix()))
+                .and_then(|p| p.file_stem().map(|n| n.to_string_lossy().to_string()))
+                .unwrap_or_else(|| "output".to_string())
+        } else {
+            Self::normalize_output_name(&self.output_file_name)
+        };
+
+        let default_name = format!("{default_stem}.{}", self.video_format.extension());
+
+        if let Some(path) = rfd::FileDialog::new()
+            .set_title("Select Output File")
+            .set_directory(default_dir)
+            .set_file_name(&default_name)
+            .save_file()
+        {
+            if let Some(parent) = path.parent() {
+                let parent_buf = parent.to_path_buf();
+                self.output_folder = Some(parent_buf.clone());
+                self.config.update_output_folder(Some(parent_buf));
+            }
+
+            if let Some(name) = path.file_name() {
+                self.output_file_name = Self::normalize_output_name(&name.to_string_lossy());
+            }
+
+            // Don't let the chosen extension and the container silently disagree
+            if let Some(format) = path
+                .extension()
+                .and_then(|extension| VideoFormat::from_extension(&extension.to_string_lossy()))
+                && format != self.video_format
+            {
+                self.video_format = format;
+                self.on_container_changed();
+                self.status_message = format!(
+                    "Container switched to {} to match the file name",
+                    self.video_format.display_name()
+                );
+                return;
+            }
+
+            self.status_message = "Output file selected".to_string();
+        }
     }
 
-    fn output_filename_for_container(&self) -> String {
-        let stem = Self::normalize_output_name(&self.output_file_name);
-        if stem.is_empty() {
-            format!("output.{}", self.video_format.extension())
+    /// The configured suffix when outputs go next to their inputs, made
+    /// safe to put in a file name
+    fn output_suffix(&self) -> String {
+        if self.config.output_mode == OutputMode::SameAsInput {
+            sanitize_filename(&self.config.output_suffix)
         } else {
-            format!("{stem}.{}", self.video_format.extension())
+            String::new()
         }
     }
 
-    fn section_card(ui: &mut egui::Ui, title: &str, add_contents: impl FnOnce(&mut egui::Ui)) {
-        egui::Frame::group(ui.style())
-            .fill(Self::bg_card())
-            .stroke(Stroke::new(1.0, Self::border_soft()))
-            .inner_margin(egui::Margin::symmetric(6, 6))
-            .show(ui, |ui| {
-                ui.set_width(ui.available_width());
-                ui.label(
-                    RichText::new(title)
-                        .strong()
-                        .size(13.0)
-                        .color(Self::text_main()),
-                );
-                ui.add_space(2.0);
-                add_contents(ui);
-            });
+    fn settings_issues(&self) -> Vec<SettingsIssue> {
+        let mut issues = validate_conversion_settings(
+            &self.build_current_preset("Current".to_string()),
+            &self.threads,
+            &self.extra_args,
+        );
+
+        // Only a probed input says whether the crop fits
+        let source_size = self
+            .media_info
+            .as_ref()
+            .and_then(MediaInfo::video_stream)
+            .and_then(|video| Some((video.width?, video.height?)));
+        if let Some(framerate) = self.sequence_framerate {
+            if let Err(error) = validate_framerate(framerate) {
+                issues.push(SettingsIssue::Error(error));
+            }
+            if self.mode == ConversionMode::Remux
+                || self.video_codec == VideoCodec::Copy
+                || self.disable_video
+            {
+                issues.push(SettingsIssue::Error(
+                    "An image sequence has to be encoded; choose a video codec".to_string(),
+                ));
+            }
+        }
+        if let Some(crop) = self.crop
+            && source_size.is_some()
+            && let Err(error) = validate_crop(crop, source_size)
+        {
+            issues.push(SettingsIssue::Error(error));
+        }
+        if let (Some(input_file), Some(info)) = (&self.input_file, &self.media_info) {
+            issues.extend(validate_input_format(input_file, info));
+            issues.extend(validate_stream_copy(
+                &self.build_current_preset("Current".to_string()),
+                info,
+                self.audio_track,
+                self.cover_art
+                    .as_deref()
+                    .filter(|_| self.video_format.supports_cover_art()),
+            ));
+        }
+        issues
     }
 
-    fn render_media_panel(&mut self, ui: &mut egui::Ui) {
-        Self::section_card(ui, "Media", |ui| {
-            egui::Grid::new("media_grid")
-                .num_columns(3)
-                .spacing(egui::vec2(6.0, 6.0))
-                .show(ui, |ui| {
-                    ui.label("Input");
-                    let input = self
-                        .input_file
-                        .as_deref()
-                        .map(Self::short_path)
-                        .unwrap_or_else(|| "none".to_string());
-                    ui.label(Self::ellipsize(&input, 42)).on_hover_text(&input);
-                    if ui.button("Browse").clicked() {
-                        self.select_input_file();
-                    }
-                    ui.end_row();
-
-                    ui.label("Output");
-                    let output = self
-                        .output_folder
-                        .as_deref()
-                        .map(Self::short_path)
-                        .unwrap_or_else(|| "none".to_string());
-                    ui.label(Self::ellipsize(&output, 42))
-                        .on_hover_text(&output);
-                    if ui.button("Browse").clicked() {
-                        self.select_output_folder();
-                    }
-                    ui.end_row();
+    fn can_start_conversion(&self) -> bool {
+        !self.conversion_state.is_converting()
+            && !self.settings_issues().iter().any(SettingsIssue::is_error)
+    }
 
-                    ui.label("Name");
-                    let response = ui.add(
-                        egui::TextEdit::singleline(&mut self.output_file_name).desired_width(220.0),
-                    );
-                    if response.changed() {
+    fn build_current_preset(&self, name: String) -> ConversionPreset {
+        ConversionPreset {
+            name,
+            tags: Vec::new(),
+            mode: self.mode.clone(),
+            video_format: self.video_format.clone(),
+            video_codec: self.video_codec.clone(),
+            audio_codec: self.audio_codec.clone(),
+            video_bitrate: (!self.video_bitrate.is_empty()).then(|| self.video_bitrate.clone()),
+            audio_bitrate: (!self.audio_bitrate.is_empty()).then(|| self.audio_bitrate.clone()),
+            resolution: (!self.resolution.is_empty()).then(|| self.resolution.clone()),
+            frame_rate: (!self.frame_rate.is_empty()).then(|| self.frame_rate.clone()),
+            threads: parse_thread_count(&self.threads).ok().flatten(),
+            fix_timestamps: self.fix_timestamps,
+            web_optimized: self.web_optimized,
+            disable_video: self.disable_video,
+            disable_audio: self.disable_audio,
+            keep_all_streams: self.keep_all_streams,
+            extra_audio: self.extra_audio.clone(),
+            audio_offset_ms: (self.audio_offset_ms != 0).then_some(self.audio_offset_ms),
+            deinterlace: self.deinterlace,
+            crop: self.crop,
+            crf: self.crf,
+            quality_target: self.quality_target,
+            trim_accuracy: self.trim_accuracy,
+            split_output: self.split_output,
+            codec_params: (!self.codec_params.trim().is_empty())
+                .then(|| self.codec_params.trim().to_string()),
+            extra_args: parse_extra_args(&self.extra_args).unwrap_or_default(),
+            hardware_accel: self.hardware_accel,
+            hw_device_index: self.hw_device_index,
+            metadata_options: self.metadata_options.clone(),
+        }
+    }
+
+    fn start_conversion(&mut self) {
+        if !self.conversion_state.is_converting()
+            && let (Some(input_file), Some(info)) = (&self.input_file, &self.media_info)
+            && is_conversion_noop(
+                input_file,
+                info,
+                &self.build_current_preset("Current".to_string()),
+            )
+        {
+            self.show_noop_dialog = true;
+            return;
+        }
+
+        self.start_conversion_confirmed();
+    }
+
+    fn start_conversion_confirmed(&mut self) {
+        let Some(input_file) = self.input_file.clone() else {
+            self.error_message = Some("Please select an input file".to_string());
+            self.status_message = "Input file required".to_string();
+            return;
+        };
+
+        // A batch item may be waiting for its existing output to be resolved
+        if self.conversion_state.is_converting() || self.batch.running_index().is_some() {
+            return;
+        }
+
+        let Some(output_file) = self.resolve_output_file(&input_file) else {
+            return;
+        };
+
+        if !self.existing_outputs(&output_file).is_empty() && self.config.confirm_overwrite {
+            match self.existing_output_choice {
+                Some(choice) => self.resolve_existing_output(input_file, output_file, choice),
+                None => {
+                    self.overwrite_dont_ask = false;
+                    self.pending_overwrite = Some((input_file, output_file));
+                }
+            }
+            return;
+        }
+
+        self.launch_conversion(input_file, output_file, None);
+    }
+
+    fn resolve_existing_output(
+        &mut self,
+        input_file: PathBuf,
+        output_file: PathBuf,
+        choice: ExistingOutput,
+    ) {
+        // A batch item waiting for the choice is already marked running
+        let batch_item = self.batch.running_index();
+        let output_file = match choice {
+            ExistingOutput::Overwrite => output_file,
+            ExistingOutput::Rename => {
+                let renamed = self.free_output_name(&output_file);
+                self.status_message = format!(
+                    "{} exists, saving as {}",
+                    Self::short_path(&output_file),
+                    Self::short_path(&renamed)
+                );
+                renamed
+            }
+            ExistingOutput::Skip => {
+                self.status_message = format!("Skipped: {} exists", Self::short_path(&output_file));
+                if batch_item.is_some() {
+                    self.advance_batch(BatchStatus::Cancelled);
+                }
+                return;
+            }
+        };
+        match batch_item {
+            Some(index) => {
+                self.batch.items[index].output_file = Some(output_file.clone());
+                self.persist_batch();
+                self.launch_batch_item(index, input_file, output_file);
+            }
+            None => {
+                self.launch_conversion(input_file, output_file, None);
+            }
+        }
+    }
+
+    /// Files a conversion to `output_file` would replace: the file itself,
+    /// and its numbered parts when the output is split
+    fn existing_outputs(&self, output_file: &Path) -> Vec<PathBuf> {
+        let mut existing: Vec<PathBuf> = Some(output_file.to_path_buf())
+            .filter(|file| file.exists())
+            .into_iter()
+            .collect();
+        if self.split_output.is_some() {
+            existing.extend(existing_split_parts(output_file));
+        }
+        existing
+    }
+
+    fn free_output_name(&self, output_file: &Path) -> PathBuf {
+        if self.split_output.is_some() {
+            next_free_split_name(output_file)
+        } else {
+            next_free_name(output_file)
+        }
+    }
+
+    fn start_test_conversion(&mut self) {
+        let Some(input_file) = self.input_file.clone() else {
+            self.error_message = Some("Please select an input file".to_string());
+            self.status_message = "Input file required".to_string();
+            return;
+        };
+
+        if self.conversion_state.is_converting() {
+            return;
+        }
+
+        self.clear_preview();
+        let preview_dir = std::env::temp_dir().join("ffmpegrust_preview");
+        if let Err(e) = std::fs::create_dir_all(&preview_dir) {
+            self.error_message = Some(format!("Failed to create preview folder: {e}"));
+            return;
+        }
+        let output_file = preview_dir.join(format!("preview.{}", self.video_format.extension()));
+
+        self.launch_conversion(input_file, output_file, Some(TEST_CONVERSION_DURATION));
+    }
+
+    fn start_benchmark(&mut self) {
+        let Some(input_file) = self.input_file.clone() else {
+            return;
+        };
+        let candidates = benchmark_candidates(&self.build_current_preset("Benchmark".to_string()));
+        if candidates.is_empty() {
+            self.error_message = Some("Choose a video codec to benchmark".to_string());
+            return;
+        }
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        self.benchmark_receiver = Some(receiver);
+        self.benchmark_results.clear();
+        self.benchmark_expected = candidates.len();
+        self.show_benchmark = true;
+        self.status_message = format!("Benchmarking {} settings...", candidates.len());
+
+        self.runtime.spawn(async move {
+            run_benchmark(input_file, candidates, sender).await;
+        });
+    }
+
+    fn check_benchmark_results(&mut self) {
+        let Some(receiver) = &self.benchmark_receiver else {
+            return;
+        };
+
+        let mut finished = false;
+        while let Ok(message) = receiver.try_recv() {
+            match message {
+                BenchmarkMessage::Result(result) => self.benchmark_results.push(result),
+                BenchmarkMessage::Finished => finished = true,
+            }
+        }
+        sort_results(
+            &mut self.benchmark_results,
+            self.benchmark_sort,
+            self.benchmark_sort_ascending,
+        );
+
+        if finished {
+            self.benchmark_receiver = None;
+            self.status_message = "Benchmark finished".to_string();
+        }
+    }
+
+    fn open_compare(&mut self) {
+        self.show_compare = true;
+        if self.compare_files.0.is_none() {
+            self.compare_files.0 = self.input_file.clone();
+        }
+        if self.compare_files.1.is_none() {
+            self.compare_files.1 = self.last_output_file.clone();
+        }
+    }
+
+    fn pick_compare_file(&self, current: Option<&Path>) -> Option<PathBuf> {
+        let default_dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        let start_dir = current
+            .and_then(Path::parent)
+            .map(Path::to_path_buf)
+            .or_else(|| self.config.last_input_folder.clone())
+            .unwrap_or(default_dir);
+        rfd::FileDialog::new()
+            .set_title("Choose a File to Compare")
+            .add_filter("Video Files", VIDEO_EXTENSIONS)
+            .set_directory(start_dir)
+            .pick_file()
+    }
+
+    fn start_compare(&mut self) {
+        let (Some(left), Some(right)) = self.compare_files.clone() else {
+            return;
+        };
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        self.compare_receiver = Some(receiver);
+        self.comparison = None;
+        self.runtime.spawn(async move {
+            let _ = sender.send(compare_media(&left, &right).await);
+        });
+    }
+
+    fn check_compare_results(&mut self) {
+        let Some(receiver) = &self.compare_receiver else {
+            return;
+        };
+
+        let Ok(result) = receiver.try_recv() else {
+            return;
+        };
+
+        self.compare_receiver = None;
+        match result {
+            Ok(comparison) => self.comparison = Some(comparison),
+            Err(error) => self.error_message = Some(error),
+        }
+    }
+
+    fn open_frame_compare(&mut self) {
+        self.show_frame_compare = true;
+        if self.frame_textures.is_none() {
+            self.start_frame_compare();
+        }
+    }
+
+    /// Length of the test encode, for the frame comparison slider
+    fn preview_duration(&self) -> f64 {
+        let sample = TEST_CONVERSION_DURATION.as_secs_f64();
+        self.media_info
+            .as_ref()
+            .and_then(|info| info.duration)
+            .map_or(sample, |duration| duration.min(sample))
+    }
+
+    fn start_frame_compare(&mut self) {
+        let (Some(source), Some(encoded)) = (self.input_file.clone(), self.preview_file.clone())
+        else {
+            return;
+        };
+        let size = comparison_frame_size(
+            self.media_info
+                .as_ref()
+                .and_then(MediaInfo::video_stream)
+                .and_then(|video| Some((video.width?, video.height?))),
+        );
+        // The last frames of the sample may be missing from the encode
+        let position = self
+            .frame_position
+            .clamp(0.0, (self.preview_duration() - 0.5).max(0.0));
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        self.frame_receiver = Some(receiver);
+        self.frame_error = None;
+        self.runtime.spawn(async move {
+            let _ = sender.send(extract_frame_pair(&source, &encoded, position, size).await);
+        });
+    }
+
+    fn check_frame_compare_results(&mut self, ctx: &Context) {
+        let Some(receiver) = &self.frame_receiver else {
+            return;
+        };
+
+        let Ok(result) = receiver.try_recv() else {
+            return;
+        };
+
+        self.frame_receiver = None;
+        match result {
+            Ok(pair) => {
+                let texture = |name: &str, rgba: &[u8]| {
+                    ctx.load_texture(
+                        format!("frame_compare_{name}"),
+                        egui::ColorImage::from_rgba_unmultiplied(pair.size, rgba),
+                        egui::TextureOptions::LINEAR,
+                    )
+                };
+                self.frame_textures = Some((
+                    pair.position,
+                    [
+                        texture("source", &pair.source),
+                        texture("encoded", &pair.encoded),
+                    ],
+                ));
+            }
+            Err(error) => self.frame_error = Some(error),
+        }
+    }
+
+    fn start_library_scan(&mut self) {
+        let default_dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        let start_dir = self.config.last_input_folder.clone().unwrap_or(default_dir);
+        let Some(folder) = rfd::FileDialog::new()
+            .set_title("Scan Media Folder")
+            .set_directory(start_dir)
+            .pick_folder()
+        else {
+            return;
+        };
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        self.library_receiver = Some(receiver);
+        self.library_entries.clear();
+        self.library_selected.clear();
+        self.library_expected = 0;
+        self.library_truncated = false;
+        self.status_message = format!("Scanning {}...", Self::short_path(&folder));
+        let recursive = self.library_recursive;
+        self.runtime.spawn(async move {
+            scan_library(folder, recursive, sender).await;
+        });
+    }
+
+    fn check_library_results(&mut self) {
+        let Some(receiver) = &self.library_receiver else {
+            return;
+        };
+
+        let mut finished = false;
+        while let Ok(message) = receiver.try_recv() {
+            match message {
+                LibraryMessage::Found { files, truncated } => {
+                    self.library_expected = files;
+                    self.library_truncated = truncated;
+                }
+                LibraryMessage::Entry(entry) => self.library_entries.push(entry),
+                LibraryMessage::Finished => finished = true,
+                LibraryMessage::Failed(error) => {
+                    self.error_message = Some(error);
+                    finished = true;
+                }
+            }
+        }
+        sort_entries(
+            &mut self.library_entries,
+            self.library_sort,
+            self.library_sort_ascending,
+        );
+
+        if finished {
+            self.library_receiver = None;
+            self.status_message = format!("Scanned {} file(s)", self.library_entries.len());
+        }
+    }
+
+    fn resolve_output_file(&mut self, input_file: &Path) -> Option<PathBuf> {
+        match self.config.output_mode {
+            OutputMode::SameAsInput => {
+                self.output_folder = input_file.parent().map(|p| p.to_path_buf());
+            }
+            OutputMode::Ask => {
+                self.output_folder = None;
+                self.select_output_file();
+                if self.output_folder.is_none() {
+                    self.status_message = "Output location required".to_string();
+                    return None;
+                }
+            }
+            OutputMode::FixedFolder => {}
+        }
+
+        let output_folder = self
+            .output_folder
+            .clone()
+            .or_else(|| input_file.parent().map(|p| p.to_path_buf()))
+            .or_else(|| self.config.last_output_folder.clone())
+            .unwrap_or_else(|| std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")));
+
+        if self.output_folder.is_none() {
+            self.output_folder = Some(output_folder.clone());
+            self.config
+                .update_output_folder(Some(output_folder.clone()));
+            self.status_message = "Using input folder as output folder".to_string();
+        }
+
+        let normalized_name = Self::normalize_output_name(&self.output_file_name);
+
+        let output_file = if normalized_name.is_empty() {
+            let output_filename =
+                generate_output_filename(input_file, &self.video_format, &self.output_suffix());
+            let Some(file_name) = output_filename.file_name() else {
+                self.error_message = Some("Failed to generate output filename".to_string());
+                return None;
+            };
+            output_folder.join(file_name)
+        } else {
+            self.output_file_name = normalized_name.clone();
+            output_folder.join(format!(
+                "{normalized_name}.{}",
+                self.video_format.extension()
+            ))
+        };
+
+        let safe_output = avoid_input_collision(input_file, output_file.clone());
+        if safe_output != output_file {
+            self.status_message = format!(
+                "Output would overwrite the input, saving as {}",
+                Self::short_path(&safe_output)
+            );
+        }
+        Some(safe_output)
+    }
+
+    fn launch_conversion(
+        &mut self,
+        input_file: PathBuf,
+        output_file: PathBuf,
+        test_duration: Option<Duration>,
+    ) -> bool {
+        if let Err(error) =
+            parse_thread_count(&self.threads).and_then(|_| parse_extra_args(&self.extra_args))
+        {
+            self.error_message = Some(error);
+            return false;
+        }
+
+        // The probe results and track choice belong to the selected file,
+        // not to whichever batch item is about to run
+        let running = self.batch.running_index();
+        let batch_item = running.is_some();
+        let trim = running.and_then(|index| self.batch.items[index].segment.clone());
+        let audio_track = if batch_item { None } else { self.audio_track };
+        let source_metadata = match &self.media_info {
+            Some(info) if !batch_item => info.tags.clone(),
+            _ => BTreeMap::new(),
+        };
+        let cover_art = self
+            .cover_art
+            .clone()
+            .filter(|_| !batch_item && self.video_format.supports_cover_art());
+
+        // FFmpeg would only report a bare "Permission denied" for these
+        if file_in_use(&output_file) {
+            self.error_message = Some(format!(
+                "{} is open in another program; close it before replacing it",
+                output_file.display()
+            ));
+            return false;
+        }
+        let input_in_use = file_in_use(&input_file).then(|| {
+            if self.trash_source_on_success {
+                format!(
+                    "{} is open in another program, so it can't go to the trash afterwards",
+                    Self::short_path(&input_file)
+                )
+            } else {
+                format!(
+                    "{} is open in another program; the conversion may fail if it changes \
+                     the file",
+                    Self::short_path(&input_file)
+                )
+            }
+        });
+
+        if let Some(cover) = &cover_art
+            && let Err(error) = validate_cover_art(cover, &self.video_format)
+        {
+            self.error_message = Some(error);
+            return false;
+        }
+
+        if let Some(track) = audio_track {
+            let audio_count = self
+                .media_info
+                .as_ref()
+                .map_or(0, |info| info.audio_streams().len());
+            if track >= audio_count {
+                self.error_message = Some(format!(
+                    "Audio track {} does not exist in the input",
+                    track + 1
+                ));
+                return false;
+            }
+        }
+
+        if let Some(index) = self.hw_device_index
+            && !self.hardware_devices.iter().any(|d| d.index == index)
+        {
+            self.error_message = Some(format!(
+                "Hardware device {index} is not available for {}",
+                self.hardware_accel.display_name()
+            ));
+            return false;
+        }
+
+        let mut preset = self.build_current_preset("Current".to_string());
+
+        // Presets can name a hardware encoder this FFmpeg build lacks (AV1 in
+        // particular), so fall back to the software encoder instead of failing
+        let mut fallback_note = None;
+        if preset.hardware_accel != HardwareAccel::None
+            && preset.mode == ConversionMode::Convert
+            && preset.video_codec != VideoCodec::Copy
+        {
+            let encoder = preset.video_codec.encoder_name(preset.hardware_accel);
+            if !self.encoder_available(encoder) {
+                fallback_note = Some(format!(
+                    "{encoder} is not available, using {}",
+                    preset.video_codec.ffmpeg_name()
+                ));
+                preset.hardware_accel = HardwareAccel::None;
+                preset.hw_device_index = None;
+            }
+        }
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        self.conversion_receiver = Some(receiver);
+
+        let (control_tx, control_rx) = mpsc::unbounded_channel();
+        self.conversion_control = Some(control_tx);
+
+        let log = LogBuffer::new();
+        self.conversion_log = Some(log.clone());
+        let log_file = if self.config.keep_ffmpeg_logs {
+            new_log_file(&self.config.logs_folder(), &output_file)
+        } else {
+            None
+        };
+
+        let input_source = match self.sequence_framerate.filter(|_| !batch_item) {
+            Some(framerate) => InputSource::ImageSequence {
+                pattern: input_file.clone(),
+                framerate,
+            },
+            None => InputSource::File(input_file.clone()),
+        };
+        // A pattern has no file times of its own and can't go to the trash
+        let single_file = !input_source.is_image_sequence();
+
+        let task = ConversionTask::new(input_file, output_file, preset, sender)
+            .with_input_source(input_source)
+            .with_audio_track(audio_track)
+            .with_test_duration(test_duration)
+            .with_control(control_rx)
+            .with_log(log)
+            .with_grace_period(self.config.shutdown_grace_period())
+            .with_timeout(self.config.conversion_timeout())
+            .with_source_metadata(source_metadata)
+            .with_cover_art(cover_art)
+            .with_trim(trim)
+            .with_log_file(log_file)
+            .with_preserved_file_times(
+                self.config.preserve_file_times && test_duration.is_none() && single_file,
+            )
+            .with_pause_on_low_disk(self.config.pause_on_low_disk)
+            .with_trash_source(self.trash_source_on_success && single_file)
+            .with_milestones(self.config.progress_milestones.clone());
+        let segment_count =
+            segment_count().filter(|_| self.config.segmented_encode && can_encode_segmented(&task));
+        self.runtime.spawn(async move {
+            match segment_count {
+                Some(parts) => execute_segmented(task, parts).await,
+                None => task.execute().await,
+            }
+        });
+
+        self.conversion_state
+            .transition_to_converting(test_duration.is_some());
+        self.progress_history.clear();
+        self.milestone_note = None;
+        self.conversion_started_at = Some(Instant::now());
+        self.error_message = input_in_use;
+        self.failure_report = None;
+        self.status_message = if test_duration.is_some() {
+            "Starting test conversion...".to_string()
+        } else {
+            "Starting conversion...".to_string()
+        };
+        if let Some(note) = fallback_note {
+            self.status_message = format!("{} ({note})", self.status_message);
+        }
+        true
+    }
+
+    fn clear_preview(&mut self) {
+        if let Some(preview) = self.preview_file.take() {
+            let _ = std::fs::remove_file(preview);
+        }
+        // Frames of the old encode would no longer match the settings
+        self.frame_textures = None;
+        self.frame_error = None;
+    }
+
+    fn stop_conversion(&mut self) {
+        if let Some(control) = &self.conversion_control
+            && control.send(ConversionControl::Cancel).is_ok()
+        {
+            self.status_message = "Stopping conversion...".to_string();
+            return;
+        }
+
+        self.finish_conversion();
+        self.status_message = "Conversion stopped".to_string();
+    }
+
+    /// Stops the batch and any running conversion, then clears the input,
+    /// settings and errors. The runtime, presets and encoder list are kept;
+    /// a cancelled conversion still reports back and cleans up its output.
+    fn reset_all(&mut self) {
+        self.batch.stop();
+        self.persist_batch();
+        let was_converting = self.conversion_state.is_converting();
+        if was_converting {
+            self.stop_conversion();
+        }
+
+        self.input_file = None;
+        self.sequence_framerate = None;
+        self.output_file_name.clear();
+        self.media_info = None;
+        self.probe_receiver = None;
+        self.audio_track = None;
+        self.selected_subtitles.clear();
+        self.cover_art = None;
+        self.recommendation = None;
+        self.crop_receiver = None;
+        self.crop_suggestion = None;
+        self.interlace_receiver = None;
+        self.detected_interlacing = None;
+        self.segment_list.clear();
+        self.clear_preview();
+
+        self.load_settings(&ConversionPreset::default());
+        self.selected_preset = None;
+        self.audio_codec_user_set = false;
+        self.codec_note = None;
+        self.trash_source_on_success = false;
+
+        self.error_message = None;
+        self.failure_report = None;
+        if !was_converting {
+            self.conversion_state = ConversionState::Idle;
+            self.conversion_log = None;
+            self.progress_history.clear();
+        }
+
+        self.status_message = if was_converting {
+            "Reset; stopping the running conversion...".to_string()
+        } else {
+            "Ready".to_string()
+        };
+    }
+
+    /// Cancel the running conversion and block until FFmpeg has exited or the
+    /// shutdown grace period runs out
+    fn cancel_for_shutdown(&mut self) {
+        let Some(control) = self.conversion_control.take() else {
+            return;
+        };
+        if control.send(ConversionControl::Cancel).is_err() {
+            return;
+        }
+
+        let Some(receiver) = self.conversion_receiver.take() else {
+            return;
+        };
+        let deadline = Instant::now() + self.config.shutdown_grace_period();
+
+        while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+            match receiver.recv_timeout(remaining) {
+                Ok(
+                    ConversionMessage::Completed(_)
+                    | ConversionMessage::Cancelled
+                    | ConversionMessage::Error(_),
+                ) => break,
+                Ok(_) => {}
+                Err(_) => break,
+            }
+        }
+    }
+
+    fn toggle_pause(&mut self) {
+        let command = if self.conversion_state.is_paused() {
+            ConversionControl::Resume
+        } else {
+            ConversionControl::Pause
+        };
+
+        if let Some(control) = &self.conversion_control {
+            let _ = control.send(command);
+        }
+    }
+
+    fn finish_conversion(&mut self) {
+        self.conversion_receiver = None;
+        self.conversion_control = None;
+        self.pending_progress = None;
+        self.progress_shown_at = None;
+    }
+
+    /// Shows the newest progress at most once per update interval, so fast
+    /// stream copies don't redraw the window dozens of times a second
+    fn show_pending_progress(&mut self) {
+        if !self.conversion_state.is_converting()
+            || self
+                .progress_shown_at
+                .is_some_and(|shown| shown.elapsed() < self.config.progress_update_interval())
+        {
+            return;
+        }
+        let Some(progress) = self.pending_progress.take() else {
+            return;
+        };
+
+        self.progress_shown_at = Some(Instant::now());
+        self.record_progress_sample(&progress);
+        self.status_message = format!("Converting... {}", progress.percentage_label());
+        self.conversion_state.set_progress(progress);
+    }
+
+    /// Faststart check for MP4/MOV outputs, appended to the completion status.
+    /// A web-optimized conversion that isn't streamable is reported as an error.
+    fn streamability_note(&mut self, output_path: &Path) -> &'static str {
+        let is_mp4_family = output_path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| {
+                ["mp4", "m4v", "mov"]
+                    .iter()
+                    .any(|known| ext.eq_ignore_ascii_case(known))
+            });
+        if !is_mp4_family {
+            return "";
+        }
+
+        if is_faststart(output_path) {
+            " (streamable)"
+        } else if self.web_optimized {
+            self.error_message = Some(
+                "Web optimization was requested, but the output's index is not at the start \
+                 of the file. It will only play once fully downloaded."
+                    .to_string(),
+            );
+            " (not streamable)"
+        } else {
+            " (not streamable; enable \"Optimize for web\" for faststart)"
+        }
+    }
+
+    fn check_conversion_progress(&mut self, ctx: &Context) {
+        let mut messages = Vec::new();
+
+        if let Some(receiver) = &self.conversion_receiver {
+            while let Ok(message) = receiver.try_recv() {
+                messages.push(message);
+            }
+        }
+
+        for message in messages {
+            match message {
+                // Only the newest one counts; it is shown below
+                ConversionMessage::Progress(progress) => self.pending_progress = Some(progress),
+                ConversionMessage::Milestone {
+                    percent,
+                    time_remaining,
+                } => {
+                    self.milestone_note = Some(milestone_text(percent, time_remaining));
+                    // Flashes the taskbar entry of a window in the background
+                    if !ctx.input(|input| input.focused) {
+                        ctx.send_viewport_cmd(egui::ViewportCommand::RequestUserAttention(
+                            egui::UserAttentionType::Informational,
+                        ));
+                    }
+                }
+                ConversionMessage::CrfSearch { crf, vmaf, chosen } => {
+                    let score =
+                        vmaf.map_or("no score".to_string(), |vmaf| format!("VMAF {vmaf:.1}"));
+                    self.status_message = if chosen {
+                        format!("Encoding at CRF {crf} ({score})")
+                    } else {
+                        format!("Finding the CRF... {crf} scored {score}")
+                    };
+                }
+                ConversionMessage::HardwareFallback { encoder, fallback } => {
+                    self.error_message = Some(format!(
+                        "{encoder} failed with a driver or device error, so the conversion \
+                         restarted with the software encoder {fallback}"
+                    ));
+                }
+                ConversionMessage::TrimAdjusted {
+                    requested,
+                    keyframe,
+                    reencoded,
+                } => {
+                    if reencoded {
+                        self.status_message = format!(
+                            "Re-encoding the video to cut exactly at {}",
+                            format_duration(requested)
+                        );
+                    } else {
+                        self.error_message = Some(format!(
+                            "Copied video can only start on a keyframe, so the clip starts at {} \
+                             instead of {}. Choose frame accurate trimming to cut exactly.",
+                            format_duration(keyframe),
+                            format_duration(requested)
+                        ));
+                    }
+                }
+                ConversionMessage::Paused(paused) => {
+                    self.conversion_state.set_paused(paused);
+                    self.status_message = if paused {
+                        "Conversion paused".to_string()
+                    } else {
+                        "Conversion resumed".to_string()
+                    };
+                }
+                ConversionMessage::LowDiskSpace { available, paused } => {
+                    let available_mb = available / (1024 * 1024);
+                    self.error_message = Some(if paused {
+                        format!(
+                            "Only {available_mb} MB left on the output drive. The conversion is \
+                             paused; free up space, then resume."
+                        )
+                    } else {
+                        format!(
+                            "Only {available_mb} MB left on the output drive; the conversion \
+                             will fail if it runs out"
+                        )
+                    });
+                }
+                ConversionMessage::OutputParts(parts) => {
+                    if let (Some(first), Some(last)) = (parts.first(), parts.last()) {
+                        self.output_parts_note = Some(format!(
+                            "split into {} parts, {} to {}",
+                            parts.len(),
+                            Self::short_path(first),
+                            Self::short_path(last)
+                        ));
+                    }
+                }
+                ConversionMessage::SourceTrashed(Ok(source)) => {
+                    self.trashed_source_note =
+                        Some(format!("{} moved to the trash", Self::short_path(&source)));
+                    if self.input_file.as_ref() == Some(&source) {
+                        self.input_file = None;
+                        self.media_info = None;
+                    }
+                }
+                ConversionMessage::SourceTrashed(Err(error)) => {
+                    self.error_message = Some(error);
+                }
+                ConversionMessage::Completed(output_path) => {
+                    let test_run = self.conversion_state.is_test_run();
+                    self.conversion_state.transition_to_completed();
+                    self.finish_conversion();
+                    if test_run {
+                        self.preview_file = Some(output_path);
+                        self.status_message = "Test conversion ready".to_string();
+                        if self.show_frame_compare {
+                            self.start_frame_compare();
+                        }
+                    } else {
+                        self.last_output_file = Some(output_path.clone());
+                        self.status_message = format!(
+                            "Conversion completed: {}{}",
+                            output_path.display(),
+                            self.streamability_note(&output_path)
+                        );
+                        if let Some(note) = self.output_parts_note.take() {
+                            self.status_message = format!("{}; {note}", self.status_message);
+                        }
+                        if let Some(note) = self.trashed_source_note.take() {
+                            self.status_message = format!("{}; {note}", self.status_message);
+                        }
+                    }
+                    self.advance_batch(BatchStatus::Done);
+                }
+                ConversionMessage::Cancelled => {
+                    self.conversion_state.transition_to_cancelled();
+                    self.finish_conversion();
+                    self.status_message = "Conversion stopped".to_string();
+                    self.batch.finish_running(BatchStatus::Cancelled);
+                    self.batch.stop();
+                    self.persist_batch();
+                }
+                ConversionMessage::Error(error) => {
+                    if let ConversionError::NonZeroExit {
+                        command, stderr, ..
+                    }
+                    | ConversionError::Killed {
+                        command, stderr, ..
+                    } = &error
+                    {
+                        self.failure_report = Some(FailureReport {
+                            command: command.clone(),
+                            stderr: stderr.clone(),
+                        });
+                    }
+                    let error = error.to_string();
+                    self.conversion_state.transition_to_failed();
+                    self.finish_conversion();
+                    self.error_message = Some(error.clone());
+                    self.status_message = "Conversion failed".to_string();
+                    self.advance_batch(BatchStatus::Failed(error));
+                }
+            }
+        }
+        self.show_pending_progress();
+    }
+
+    fn add_batch_files(&mut self) {
+        let default_dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        let start_dir = self.config.last_input_folder.clone().unwrap_or(default_dir);
+
+        let Some(files) = rfd::FileDialog::new()
+            .set_title("Add Files to Batch")
+            .add_filter("Video Files", VIDEO_EXTENSIONS)
+            .set_directory(start_dir)
+            .pick_files()
+        else {
+            return;
+        };
+
+        if let Some(parent) = files.first().and_then(|file| file.parent()) {
+            self.config.update_input_folder(Some(parent.to_path_buf()));
+        }
+        self.queue_batch_files(files);
+    }
+
+    /// Queues `files` at the current priority, skipping any that are already
+    /// in the batch or aren't video files, and reports what happened
+    fn queue_batch_files(&mut self, files: Vec<PathBuf>) {
+        let priority = self.queue_priority();
+        let (mut added, mut duplicates, mut skipped) = (0, 0, 0);
+        for file in files {
+            if !file.is_file() || !is_video_file(&file) {
+                skipped += 1;
+            } else if self.batch.add(file, priority) {
+                added += 1;
+            } else {
+                duplicates += 1;
+            }
+        }
+        if added > 0 {
+            self.persist_batch();
+        }
+
+        let mut message = format!("{added} file(s) queued");
+        if duplicates > 0 {
+            message.push_str(&format!(", {duplicates} already in the batch"));
+        }
+        if skipped > 0 {
+            message.push_str(&format!(", {skipped} skipped as not video files"));
+        }
+        self.status_message = message;
+    }
+
+    /// Priority for items queued now, from the current settings
+    fn queue_priority(&self) -> BatchPriority {
+        BatchPriority::for_preset(&self.build_current_preset("Current".to_string()))
+    }
+
+    /// Output path the settings give the batch item at `index`, before
+    /// `batch_outputs` keeps it from clashing with the other items
+    fn batch_output_file(&self, index: usize) -> PathBuf {
+        let item = &self.batch.items[index];
+        let input_file = item.input_file.as_path();
+        let fixed_folder = match self.config.output_mode {
+            OutputMode::FixedFolder => self.output_folder.clone(),
+            OutputMode::SameAsInput | OutputMode::Ask => None,
+        };
+        // The suffix only matters when outputs land next to their inputs
+        let suffix = if fixed_folder.is_some() {
+            ""
+        } else {
+            self.config.output_suffix.as_str()
+        };
+        let folder = fixed_folder
+            .or_else(|| input_file.parent().map(Path::to_path_buf))
+            .unwrap_or_else(|| PathBuf::from("."));
+
+        let mut stem = render_rename_template(
+            &self.config.batch_rename_template,
+            input_file,
+            suffix,
+            item.sequence,
+        );
+        if let Some(segment) = &item.segment {
+            let clip = self.batch.clip_number(index).unwrap_or(1);
+            stem.push_str(&format!("_clip{clip:02}"));
+            if let Some(label) = &segment.label {
+                let label = sanitize_filename(label);
+                if !label.is_empty() {
+                    stem.push_str(&format!("_{label}"));
+                }
+            }
+        }
+        let extension = self.video_format.extension();
+        avoid_input_collision(input_file, folder.join(format!("{stem}.{extension}")))
+    }
+
+    /// Output path of every batch item, in queue order, no two the same
+    fn batch_outputs(&self) -> Vec<PathBuf> {
+        self.batch
+            .plan_outputs(|index| self.batch_output_file(index))
+    }
+
+    /// Saves the settings when they changed since the last autosave, and
+    /// the window size when it did
+    fn autosave(&mut self) {
+        let window_changed = self.config.window_width != self.saved_window_size.0
+            || self.config.window_height != self.saved_window_size.1;
+        if window_changed {
+            self.config.save();
+            self.saved_window_size = (self.config.window_width, self.config.window_height);
+        }
+
+        let preset = self.build_current_preset("Session".to_string());
+        if let Ok(json) = serde_json::to_string_pretty(&preset)
+            && json != self.autosaved_settings
+        {
+            save_session(&json);
+            self.autosaved_settings = json;
+        }
+        self.autosaved_at = Some(Instant::now());
+    }
+
+    fn persist_batch(&self) {
+        self.batch
+            .save(&self.build_current_preset("Batch".to_string()));
+    }
+
+    fn resume_saved_batch(&mut self) {
+        let Some(saved) = self.saved_batch.take() else {
+            return;
+        };
+
+        self.load_settings(&saved.preset);
+        self.batch = BatchQueue::restore(saved);
+        // Carry on the way the batch was set to, not the way a new one would
+        self.config.batch_failure_policy = self.batch.failure_policy;
+        self.status_message = "Resuming unfinished batch".to_string();
+        self.start_batch();
+    }
+
+    fn render_resume_batch_dialog(&mut self, ctx: &Context) {
+        let Some(saved) = &self.saved_batch else {
+            return;
+        };
+        let pending = saved
+            .items
+            .iter()
+            .filter(|item| item.status == BatchStatus::Pending)
+            .count();
+
+        egui::Window::new("Unfinished batch")
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "The last session ended with {pending} file(s) still waiting to be converted."
+                ));
+                ui.add_space(8.0);
+
+                ui.horizontal(|ui| {
+                    if ui.button("Resume").clicked() {
+                        self.resume_saved_batch();
+                    }
+                    if ui.button("Discard").clicked() {
+                        self.saved_batch = None;
+                        BatchQueue::discard_saved();
+                    }
+                });
+            });
+    }
+
+    fn start_batch(&mut self) {
+        if self.conversion_state.is_converting() || self.batch.pending_count() == 0 {
+            return;
+        }
+
+        self.batch.failure_policy = self.config.batch_failure_policy;
+        self.batch.start();
+        self.start_next_batch_item();
+    }
+
+    fn start_next_batch_item(&mut self) {
+        if !self.batch.is_active() {
+            return;
+        }
+
+        // The settings may have changed since the items were queued
+        self.batch.refresh_priorities(self.queue_priority());
+        let Some(index) = self.batch.next_pending() else {
+            self.batch.stop();
+            self.status_message = format!("Batch finished: {}", self.batch.summary());
+            if let Some(join) = self.batch.take_join() {
+                self.start_join(join);
+            }
+            return;
+        };
+
+        let input_file = self.batch.items[index].input_file.clone();
+        let output_file = self.batch_outputs().swap_remove(index);
+        self.batch.items[index].status = BatchStatus::Running;
+        self.batch.items[index].output_file = Some(output_file.clone());
+        self.persist_batch();
+
+        if !self.existing_outputs(&output_file).is_empty() && self.config.confirm_overwrite {
+            match self.existing_output_choice {
+                Some(choice) => self.resolve_existing_output(input_file, output_file, choice),
+                None => {
+                    self.status_message = format!(
+                        "Batch: {} exists, waiting for a choice",
+                        Self::short_path(&output_file)
+                    );
+                    self.overwrite_dont_ask = false;
+                    self.pending_overwrite = Some((input_file, output_file));
+                }
+            }
+            return;
+        }
+        self.launch_batch_item(index, input_file, output_file);
+    }
+
+    fn launch_batch_item(&mut self, index: usize, input_file: PathBuf, output_file: PathBuf) {
+        if self.launch_conversion(input_file, output_file, None) {
+            self.status_message = format!(
+                "Batch: converting {} of {}",
+                index + 1,
+                self.batch.items.len()
+            );
+        } else {
+            let error = self
+                .error_message
+                .clone()
+                .unwrap_or_else(|| "Conversion could not start".to_string());
+            self.batch.finish_running(BatchStatus::Failed(error));
+            self.batch.stop();
+            self.persist_batch();
+        }
+    }
+
+    /// Record the finished batch item and move on to the next one
+    fn advance_batch(&mut self, status: BatchStatus) {
+        if self.batch.running_index().is_none() {
+            return;
+        }
+
+        self.batch.finish_running(status);
+        self.persist_batch();
+        self.start_next_batch_item();
+    }
+
+    fn record_progress_sample(&mut self, progress: &ConversionProgress) {
+        let elapsed_secs = self
+            .conversion_started_at
+            .map(|started| started.elapsed().as_secs_f64())
+            .unwrap_or_default();
+
+        if self.progress_history.len() >= PROGRESS_BUFFER_SIZE {
+            self.progress_history.pop_front();
+        }
+        self.progress_history.push_back(ProgressSample {
+            elapsed_secs,
+            fps: progress.fps,
+            bitrate_kbps: progress.bitrate_kbps,
+            speed: progress.speed,
+        });
+    }
+
+    fn apply_preset(&mut self, preset_name: &str) {
+        if let Some(preset) = self.preset_manager.get_preset(preset_name).cloned() {
+            self.load_settings(&preset);
+            self.selected_preset = Some(preset_name.to_string());
+            self.status_message = format!("Applied preset: {preset_name}");
+        }
+    }
+
+    fn load_settings(&mut self, preset: &ConversionPreset) {
+        self.mode = preset.mode.clone();
+        self.video_format = preset.video_format.clone();
+        self.video_codec = preset.video_codec.clone();
+        self.audio_codec = preset.audio_codec.clone();
+        self.audio_codec_user_set = true;
+        self.codec_note = None;
+        self.video_bitrate = preset.video_bitrate.clone().unwrap_or_default();
+        self.audio_bitrate = preset.audio_bitrate.clone().unwrap_or_default();
+        self.resolution = preset.resolution.clone().unwrap_or_default();
+        self.frame_rate = preset.frame_rate.clone().unwrap_or_default();
+        self.threads = preset
+            .threads
+            .map(|threads| threads.to_string())
+            .unwrap_or_default();
+        self.codec_params = preset.codec_params.clone().unwrap_or_default();
+        self.fix_timestamps = preset.fix_timestamps;
+        self.web_optimized = preset.web_optimized;
+        self.disable_video = preset.disable_video;
+        self.disable_audio = preset.disable_audio;
+        self.keep_all_streams = preset.keep_all_streams;
+        self.extra_audio = preset.extra_audio.clone();
+        self.audio_offset_ms = preset.audio_offset_ms.unwrap_or(0);
+        self.deinterlace = preset.deinterlace;
+        self.crop = preset.crop;
+        self.crf = preset.crf;
+        self.quality_target = preset.quality_target;
+        self.split_output = preset.split_output;
+        self.trim_accuracy = preset.trim_accuracy;
+        self.extra_args = preset.extra_args.join(" ");
+        self.metadata_options = preset.metadata_options.clone();
+        self.set_hardware_accel(preset.hardware_accel);
+        self.hw_device_index = preset.hw_device_index;
+    }
+
+    /// Keeps the audio codec usable after a container switch, leaving an
+    /// explicit choice alone as long as the new container can hold it
+    fn on_container_changed(&mut self) {
+        self.codec_note = None;
+
+        if self.audio_codec_user_set && self.video_format.supports_audio_codec(&self.audio_codec) {
+            return;
+        }
+
+        let recommended = self.video_format.recommended_audio_codec();
+        if recommended != self.audio_codec {
+            self.codec_note = Some(format!(
+                "Audio codec adjusted to {} for {}",
+                recommended.display_name(),
+                self.video_format.display_name()
+            ));
+            self.audio_codec = recommended;
+            self.audio_codec_user_set = false;
+        }
+    }
+
+    fn set_hardware_accel(&mut self, accel: HardwareAccel) {
+        self.hardware_accel = accel;
+        self.hw_device_index = None;
+        self.hardware_devices.clear();
+
+        if accel == HardwareAccel::None {
+            self.device_receiver = None;
+            return;
+        }
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        self.device_receiver = Some(receiver);
+        self.runtime.spawn(async move {
+            let _ = sender.send(list_hardware_devices(accel).await);
+        });
+    }
+
+    fn check_device_results(&mut self) {
+        if let Some(receiver) = &self.device_receiver
+            && let Ok(devices) = receiver.try_recv()
+        {
+            self.hardware_devices = devices;
+            self.device_receiver = None;
+
+            // A preset's index may be from another machine or a removed GPU;
+            // only now can it be checked against what is really there
+            if let Some(index) = self.hw_device_index
+                && !self.hardware_devices.iter().any(|d| d.index == index)
+            {
+                self.hw_device_index = None;
+                self.error_message = Some(format!(
+                    "Hardware device {index} is not available for {}; using the default device",
+                    self.hardware_accel.display_name()
+                ));
+            }
+        }
+    }
+
+    fn save_current_preset(&mut self) {
+        if self.new_preset_name.trim().is_empty() {
+            return;
+        }
+
+        let preset_name = self.new_preset_name.trim().to_string();
+        let mut preset = self.build_current_preset(preset_name.clone());
+        preset.tags = self
+            .new_preset_tags
+            .split(',')
+            .map(|tag| tag.trim().to_string())
+            .filter(|tag| !tag.is_empty())
+            .collect();
+        self.preset_manager.add_preset(preset);
+
+        self.status_message = format!("Saved preset: {preset_name}");
+        self.selected_preset = Some(preset_name);
+        self.new_preset_name.clear();
+        self.new_preset_tags.clear();
+        self.show_save_preset = false;
+    }
+
+    fn check_ffmpeg(&mut self) {
+        self.ffmpeg_status = Some(check_ffmpeg_installation());
+    }
+
+    fn start_diagnostics(&mut self) {
+        // Likely output drives; duplicates and missing folders are skipped
+        let folders: Vec<PathBuf> = [
+            self.config.last_output_folder.clone(),
+            self.input_file
+                .as_deref()
+                .and_then(Path::parent)
+                .map(Path::to_path_buf),
+            dirs::video_dir(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        self.diagnostics_receiver = Some(receiver);
+        self.diagnostics.clear();
+        self.runtime.spawn(async move {
+            let _ = sender.send(run_diagnostics(folders).await);
+        });
+    }
+
+    fn check_diagnostics_results(&mut self) {
+        let Some(receiver) = &self.diagnostics_receiver else {
+            return;
+        };
+
+        let Ok(checks) = receiver.try_recv() else {
+            return;
+        };
+
+        self.diagnostics_receiver = None;
+        self.diagnostics = checks;
+    }
+
+    fn check_for_updates(&mut self) {
+        let Some(updater) = self.updater.clone() else {
+            self.update_status = Some(UpdateStatus::Error("Updater unavailable".to_string()));
+            return;
+        };
+
+        let (sender, receiver): (Sender<UpdateStatus>, Receiver<UpdateStatus>) =
+            std::sync::mpsc::channel();
+        self.update_status_receiver = Some(receiver);
+        self.update_status = Some(UpdateStatus::CheckingForUpdates);
+
+        self.runtime.spawn(async move {
+            let status = updater.check_for_updates().await;
+            let _ = sender.send(status);
+        });
+    }
+
+    fn start_update_download(&mut self, update_info: UpdateInfo) {
+        let Some(updater) = self.updater.clone() else {
+            self.update_status = Some(UpdateStatus::Error("Updater unavailable".to_string()));
+            return;
+        };
+
+        let (progress_tx, progress_rx) = mpsc::unbounded_channel();
+        self.download_progress_receiver = Some(progress_rx);
+        let (cancel_tx, cancel_rx) = tokio::sync::watch::channel(false);
+        self.download_cancel = Some(cancel_tx);
+
+        let (status_tx, status_rx): (Sender<UpdateStatus>, Receiver<UpdateStatus>) =
+            std::sync::mpsc::channel();
+        self.update_status_receiver = Some(status_rx);
+        self.update_status = Some(UpdateStatus::DownloadingUpdate(0.0));
+
+        self.runtime.spawn(async move {
+            match updater
+                .download_update(&update_info, Some(progress_tx), Some(cancel_rx))
+                .await
+            {
+                Ok(file_path) => {
+                    let _ = status_tx.send(UpdateStatus::InstallingUpdate);
+                    match updater.apply_update(&file_path).await {
+                        Ok(()) => {
+                            let _ = updater.restart_application().await;
+                        }
+                        Err(err) => {
+                            let _ = status_tx.send(UpdateStatus::Error(format!(
+                                "Failed to apply update: {err}"
+                            )));
+                        }
+                    }
+                }
+                Err(UpdateError::Cancelled) => {
+                    let _ = status_tx.send(UpdateStatus::DownloadCancelled);
+                }
+                Err(err) => {
+                    let _ = status_tx.send(UpdateStatus::Error(format!(
+                        "Failed to download update: {err}"
+                    )));
+                }
+            }
+        });
+    }
+
+    fn cancel_update_download(&mut self) {
+        if let Some(cancel) = self.download_cancel.take() {
+            let _ = cancel.send(true);
+        }
+        // Progress still in flight would otherwise overwrite the cancelled status
+        self.download_progress_receiver = None;
+    }
+
+    fn poll_async_updates(&mut self) {
+        if let Some(receiver) = &self.update_status_receiver {
+            while let Ok(status) = receiver.try_recv() {
+                self.update_status = Some(status);
+            }
+        }
+
+        if let Some(receiver) = &mut self.download_progress_receiver {
+            while let Ok(progress) = receiver.try_recv() {
+                self.update_status = Some(UpdateStatus::DownloadingUpdate(progress));
+            }
+        }
+    }
+
+    fn short_path(path: &Path) -> String {
+        path.file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| path.display().to_string())
+    }
+
+    fn ellipsize(text: &str, max_chars: usize) -> String {
+        let count = text.chars().count();
+        if count <= max_chars {
+            return text.to_string();
+        }
+
+        if max_chars <= 3 {
+            return "...".to_string();
+        }
+
+        let take = max_chars - 3;
+        let mut out = String::new();
+        for ch in text.chars().take(take) {
+            out.push(ch);
+        }
+        out.push_str("...");
+        out
+    }
+
+    fn normalize_output_name(name: &str) -> String {
+        let trimmed = name.trim();
+        if trimmed.is_empty() {
+            return String::new();
+        }
+
+        let file_name = Path::new(trimmed)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or(trimmed);
+
+        strip_media_extensions(file_name).to_string()
+    }
+
+    fn output_filename_for_container(&self) -> String {
+        let stem = Self::normalize_output_name(&self.output_file_name);
+        if stem.is_empty() {
+            format!("output.{}", self.video_format.extension())
+        } else {
+            format!("{stem}.{}", self.video_format.extension())
+        }
+    }
+
+    fn section_card(ui: &mut egui::Ui, title: &str, add_contents: impl FnOnce(&mut egui::Ui)) {
+        egui::Frame::group(ui.style())
+            .fill(Self::bg_card(ui))
+            .stroke(Stroke::new(1.0, Self::border_soft(ui)))
+            .inner_margin(egui::Margin::symmetric(6, 6))
+            .show(ui, |ui| {
+                ui.set_width(ui.available_width());
+                ui.label(
+                    RichText::new(title)
+                        .strong()
+                        .size(13.0)
+                        .color(Self::text_main(ui)),
+                );
+                ui.add_space(2.0);
+                add_contents(ui);
+            });
+    }
+
+    fn render_media_panel(&mut self, ui: &mut egui::Ui) {
+        Self::section_card(ui, "Media", |ui| {
+            egui::Grid::new("media_grid")
+                .num_columns(3)
+                .spacing(egui::vec2(6.0, 6.0))
+                .show(ui, |ui| {
+                    ui.label("Input");
+                    let input = self
+                        .input_file
+                        .as_deref()
+                        .map(Self::short_path)
+                        .unwrap_or_else(|| "none".to_string());
+                    ui.label(Self::ellipsize(&input, 42)).on_hover_text(&input);
+                    ui.horizontal(|ui| {
+                        if ui.button("Browse").clicked() {
+                            self.select_input_file();
+                        }
+                        self.render_recent_files_menu(ui);
+                        if ui
+                            .button("Images")
+                            .on_hover_text("Use a folder of numbered images as the video frames")
+                            .clicked()
+                        {
+                            self.select_image_sequence();
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("Output");
+                    let output = self
+                        .output_folder
+                        .as_deref()
+                        .map(Self::short_path)
+                        .unwrap_or_else(|| "none".to_string());
+                    ui.label(Self::ellipsize(&output, 42))
+                        .on_hover_text(&output);
+                    if ui.button("Browse").clicked() {
+                        self.select_output_folder();
+                    }
+                    ui.end_row();
+
+                    ui.label("Name");
+                    let response = ui.add(
+                        egui::TextEdit::singleline(&mut self.output_file_name).desired_width(220.0),
+                    );
+                    if response.changed() {
                         self.output_file_name = Self::normalize_output_name(&self.output_file_name);
                     }
-                    if ui.button("Save As").clicked() {
-                        self.select_output_file();
+                    if ui.button("Save As").clicked() {
+                        self.select_output_file();
+                    }
+                    ui.end_row();
+                });
+
+            if let Some(framerate) = &mut self.sequence_framerate {
+                ui.horizontal(|ui| {
+                    ui.label(
+                        RichText::new(format!("{} images", self.sequence_frames))
+                            .small()
+                            .color(Self::accent(ui)),
+                    );
+                    ui.add(
+                        egui::DragValue::new(framerate)
+                            .range(1..=MAX_SEQUENCE_FRAMERATE)
+                            .suffix(" fps"),
+                    );
+                    let seconds = self.sequence_frames as f64 / f64::from((*framerate).max(1));
+                    ui.label(RichText::new(format_duration(seconds)).small());
+                });
+            } else if let Some(info) = &self.media_info {
+                ui.label(
+                    RichText::new(info.summary())
+                        .small()
+                        .color(Self::accent(ui)),
+                );
+                self.render_filmstrip(ui);
+            } else if self.probe_receiver.is_some() {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label(RichText::new("Inspecting input...").small());
+                });
+            }
+            self.render_recommendation(ui);
+            self.render_subtitle_extraction(ui);
+            self.render_segment_list(ui);
+
+            ui.horizontal(|ui| {
+                ui.label("Save to");
+                let previous_mode = self.config.output_mode;
+                egui::ComboBox::from_id_salt("output_mode")
+                    .selected_text(self.config.output_mode.display_name())
+                    .show_ui(ui, |ui| {
+                        for mode in [
+                            OutputMode::SameAsInput,
+                            OutputMode::FixedFolder,
+                            OutputMode::Ask,
+                        ] {
+                            ui.selectable_value(
+                                &mut self.config.output_mode,
+                                mode,
+                                mode.display_name(),
+                            );
+                        }
+                    });
+                if self.config.output_mode != previous_mode {
+                    let mode = self.config.output_mode;
+                    self.config.update_output_mode(mode);
+                }
+
+                if self.config.output_mode == OutputMode::SameAsInput {
+                    ui.label("Suffix");
+                    let response = ui.add(
+                        egui::TextEdit::singleline(&mut self.config.output_suffix)
+                            .desired_width(90.0),
+                    );
+                    if response.lost_focus() {
+                        self.config.save();
+                    }
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Mode");
+                ui.selectable_value(&mut self.mode, ConversionMode::Convert, "Convert");
+                ui.selectable_value(&mut self.mode, ConversionMode::Remux, "Remux");
+            });
+        });
+    }
+
+    fn render_presets_panel(&mut self, ui: &mut egui::Ui) {
+        Self::section_card(ui, "Presets", |ui| {
+            self.render_preset_tag_cloud(ui);
+
+            let mut preset_names: Vec<String> = self
+                .preset_manager
+                .filter_by_tags(&self.preset_tag_filter, self.preset_tags_match_all)
+                .iter()
+                .map(|preset| preset.name.clone())
+                .collect();
+            preset_names.sort_unstable();
+
+            egui::ComboBox::from_id_salt("preset_selector")
+                .selected_text(self.selected_preset.as_deref().unwrap_or("Choose preset"))
+                .show_ui(ui, |ui| {
+                    for preset_name in &preset_names {
+                        if ui.selectable_label(false, preset_name).clicked() {
+                            self.apply_preset(preset_name);
+                        }
+                    }
+                });
+
+            ui.horizontal_wrapped(|ui| {
+                if ui.button("Save Current").clicked() {
+                    self.show_save_preset = true;
+                    // Start from the loaded preset's tags when saving a variant of it
+                    self.new_preset_tags = self
+                        .selected_preset
+                        .as_deref()
+                        .and_then(|name| self.preset_manager.get_preset(name))
+                        .map(|preset| preset.tags.join(", "))
+                        .unwrap_or_default();
+                }
+
+                if let Some(preset_name) = self.selected_preset.clone()
+                    && ui.button("Duplicate").clicked()
+                    && let Some(copy) = self.preset_manager.duplicate_preset(&preset_name)
+                {
+                    self.apply_preset(&copy.name);
+                    self.status_message = format!("Duplicated preset as: {}", copy.name);
+                }
+
+                if let Some(preset_name) = self.selected_preset.clone()
+                    && ui.button("Delete").clicked()
+                {
+                    self.preset_manager.remove_preset(&preset_name);
+                    self.selected_preset = None;
+                    self.status_message = format!("Deleted preset: {preset_name}");
+                }
+            });
+
+            if self.show_save_preset {
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.new_preset_tags)
+                            .hint_text("Tags, comma separated")
+                            .desired_width(160.0),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut self.new_preset_name);
+                    if ui.button("Save").clicked() {
+                        self.save_current_preset();
+                    }
+                    if ui.button("Cancel").clicked() {
+                        self.show_save_preset = false;
+                        self.new_preset_name.clear();
+                    }
+                });
+            }
+        });
+    }
+
+    /// Clickable tags narrowing the preset list
+    fn render_preset_tag_cloud(&mut self, ui: &mut egui::Ui) {
+        let tags = self.preset_manager.get_all_tags();
+        if tags.is_empty() {
+            return;
+        }
+
+        // Tags of deleted presets can't match anything anymore
+        self.preset_tag_filter.retain(|tag| tags.contains(tag));
+
+        ui.horizontal_wrapped(|ui| {
+            for tag in tags {
+                let selected = self.preset_tag_filter.contains(&tag);
+                if ui
+                    .selectable_label(selected, RichText::new(&tag).small())
+                    .clicked()
+                {
+                    if selected {
+                        self.preset_tag_filter.remove(&tag);
+                    } else {
+                        self.preset_tag_filter.insert(tag);
+                    }
+                }
+            }
+        });
+
+        if self.preset_tag_filter.len() > 1 {
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut self.preset_tags_match_all, false, "Any tag");
+                ui.radio_value(&mut self.preset_tags_match_all, true, "All tags");
+            });
+        }
+    }
+
+    /// Until the encoder list arrives (or if it can't be read) everything is allowed
+    fn encoder_available(&self, encoder: &str) -> bool {
+        encoder == "copy"
+            || self
+                .available_encoders
+                .as_ref()
+                .is_none_or(|encoders| encoders.contains(encoder))
+    }
+
+    /// Combo box entry that is grayed out when its encoder is missing from
+    /// the installed FFmpeg build
+    fn encoder_option<T: PartialEq>(
+        ui: &mut egui::Ui,
+        current: &mut T,
+        value: T,
+        label: &str,
+        available: bool,
+    ) {
+        let response = ui
+            .add_enabled(
+                available,
+                egui::Button::selectable(*current == value, label),
+            )
+            .on_disabled_hover_text(
+                "Not available in your FFmpeg build, or a test encode on this machine failed",
+            );
+        if response.clicked() {
+            *current = value;
+        }
+    }
+
+    fn check_encoder_results(&mut self) {
+        let Some(receiver) = &self.encoder_receiver else {
+            return;
+        };
+
+        let Ok(result) = receiver.try_recv() else {
+            return;
+        };
+
+        self.encoder_receiver = None;
+        self.available_encoders = result.ok();
+    }
+
+    fn render_batch_panel(&mut self, ui: &mut egui::Ui) {
+        Self::section_card(ui, "Batch", |ui| {
+            ui.horizontal_wrapped(|ui| {
+                if ui.button("Add Files...").clicked() {
+                    self.add_batch_files();
+                }
+                if ui
+                    .button("Library...")
+                    .on_hover_text("Scan a media folder and queue the files worth converting")
+                    .clicked()
+                {
+                    self.show_library = true;
+                }
+                if ui
+                    .add_enabled(!self.batch.items.is_empty(), egui::Button::new("Clear"))
+                    .clicked()
+                {
+                    self.batch.clear();
+                    self.persist_batch();
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Rename");
+                let response = ui
+                    .add(
+                        egui::TextEdit::singleline(&mut self.config.batch_rename_template)
+                            .hint_text("{name}{suffix}")
+                            .desired_width(160.0),
+                    )
+                    .on_hover_text(
+                        "Tokens: {name} input name, {suffix} output suffix, {n} queued order",
+                    );
+                if response.lost_focus() {
+                    self.config.save();
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("On failure");
+                let mut policy = self.config.batch_failure_policy;
+                egui::ComboBox::from_id_salt("batch_failure_policy")
+                    .selected_text(policy.label())
+                    .show_ui(ui, |ui| {
+                        for option in BatchFailurePolicy::all() {
+                            ui.selectable_value(&mut policy, option, option.label());
+                        }
+                    });
+                if policy != self.config.batch_failure_policy {
+                    // Also applies to a batch that is already running
+                    self.config.batch_failure_policy = policy;
+                    self.batch.failure_policy = policy;
+                    self.config.save();
+                }
+            });
+
+            if self.batch.items.is_empty() {
+                ui.small("No files queued");
+                return;
+            }
+            ui.small(format!("{} file(s) queued", self.batch.items.len()));
+
+            let mut remove = None;
+            let mut reorder = None;
+            let mut reprioritize = None;
+            let pending_count = self.batch.pending_count();
+            let outputs = self.batch_outputs();
+            for ((index, item), output_file) in self.batch.items.iter().enumerate().zip(outputs) {
+                ui.horizontal(|ui| {
+                    let name = item
+                        .input_file
+                        .file_name()
+                        .map(|name| name.to_string_lossy().to_string())
+                        .unwrap_or_default();
+
+                    let status = match &item.status {
+                        BatchStatus::Done => {
+                            RichText::new(item.status.label()).color(Self::success(ui))
+                        }
+                        BatchStatus::Failed(_) => {
+                            RichText::new(item.status.label()).color(Self::danger(ui))
+                        }
+                        _ => RichText::new(item.status.label()).color(Self::accent(ui)),
+                    };
+                    let status_response = ui.label(status);
+                    if let BatchStatus::Failed(error) = &item.status {
+                        status_response.on_hover_text(error);
+                    }
+
+                    ui.label(name)
+                        .on_hover_text(format!("→ {}", output_file.display()));
+
+                    if item.status == BatchStatus::Pending {
+                        egui::ComboBox::from_id_salt(("batch_priority", index))
+                            .selected_text(item.priority.label())
+                            .width(70.0)
+                            .show_ui(ui, |ui| {
+                                for priority in BatchPriority::all() {
+                                    if ui
+                                        .selectable_label(
+                                            item.priority == priority,
+                                            priority.label(),
+                                        )
+                                        .clicked()
+                                    {
+                                        reprioritize = Some((index, priority));
+                                    }
+                                }
+                            })
+                            .response
+                            .on_hover_text(
+                                "Higher priorities run first; remuxes and copies start out High",
+                            );
+                    }
+                    if item.status == BatchStatus::Pending && pending_count > 1 {
+                        if ui.small_button("▲").on_hover_text("Move up").clicked() {
+                            reorder = Some((index, BatchMove::Up));
+                        }
+                        if ui.small_button("▼").on_hover_text("Move down").clicked() {
+                            reorder = Some((index, BatchMove::Down));
+                        }
+                        if ui.small_button("⏫").on_hover_text("Run next").clicked() {
+                            reorder = Some((index, BatchMove::Front));
+                        }
+                    }
+                    if item.status != BatchStatus::Running && ui.small_button("✕").clicked() {
+                        remove = Some(index);
+                    }
+                });
+            }
+            if let Some(index) = remove {
+                self.batch.remove(index);
+                self.persist_batch();
+            }
+            if let Some((index, priority)) = reprioritize {
+                self.batch.set_priority(index, priority);
+                self.persist_batch();
+            }
+            if let Some((index, direction)) = reorder {
+                match direction {
+                    BatchMove::Up => self.batch.move_up(index),
+                    BatchMove::Down => self.batch.move_down(index),
+                    BatchMove::Front => self.batch.move_to_front(index),
+                }
+                self.persist_batch();
+            }
+
+            let pending = self.batch.pending_count();
+            if ui
+                .add_enabled(
+                    self.can_start_conversion() && pending > 0,
+                    egui::Button::new(format!("Start Batch ({pending})")),
+                )
+                .clicked()
+            {
+                self.start_batch();
+            }
+        });
+    }
+
+    fn render_conversion_panel(&mut self, ui: &mut egui::Ui) {
+        Self::section_card(ui, "Conversion", |ui| {
+            egui::Grid::new("conversion_grid")
+                .num_columns(2)
+                .spacing(egui::vec2(8.0, 6.0))
+                .show(ui, |ui| {
+                    ui.label("Container");
+                    let previous_format = self.video_format.clone();
+                    egui::ComboBox::from_id_salt("video_format")
+                        .selected_text(self.video_format.display_name())
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut self.video_format, VideoFormat::Mp4, "MP4");
+                            ui.selectable_value(&mut self.video_format, VideoFormat::Mkv, "MKV");
+                            ui.selectable_value(&mut self.video_format, VideoFormat::Mov, "MOV");
+                            ui.selectable_value(&mut self.video_format, VideoFormat::Avi, "AVI");
+                            ui.selectable_value(&mut self.video_format, VideoFormat::Webm, "WebM");
+                        });
+                    if self.video_format != previous_format {
+                        self.on_container_changed();
+                    }
+                    ui.end_row();
+
+                    if self.mode == ConversionMode::Convert {
+                        ui.label("Video codec");
+                        let video_options: Vec<(VideoCodec, bool)> = [
+                            VideoCodec::H264,
+                            VideoCodec::H265,
+                            VideoCodec::VP9,
+                            VideoCodec::AV1,
+                            VideoCodec::Copy,
+                        ]
+                        .into_iter()
+                        .map(|codec| {
+                            let available =
+                                self.encoder_available(codec.encoder_name(self.hardware_accel));
+                            (codec, available)
+                        })
+                        .collect();
+                        egui::ComboBox::from_id_salt("video_codec")
+                            .selected_text(self.video_codec.display_name())
+                            .show_ui(ui, |ui| {
+                                for (codec, available) in video_options {
+                                    let label = codec.display_name();
+                                    Self::encoder_option(
+                                        ui,
+                                        &mut self.video_codec,
+                                        codec,
+                                        label,
+                                        available,
+                                    );
+                                }
+                            });
+                        ui.end_row();
+
+                        ui.label("Hardware");
+                        let mut accel = self.hardware_accel;
+                        let accel_options: Vec<(HardwareAccel, bool)> = HardwareAccel::all()
+                            .into_iter()
+                            .map(|option| {
+                                let encoder = self.video_codec.encoder_name(option);
+                                (option, self.encoder_available(encoder))
+                            })
+                            .collect();
+                        egui::ComboBox::from_id_salt("hardware_accel")
+                            .selected_text(accel.display_name())
+                            .show_ui(ui, |ui| {
+                                for (option, available) in accel_options {
+                                    let label = option.display_name();
+                                    Self::encoder_option(ui, &mut accel, option, label, available);
+                                }
+                            });
+                        if accel != self.hardware_accel {
+                            self.set_hardware_accel(accel);
+                        }
+                        ui.end_row();
+
+                        if self.hardware_accel != HardwareAccel::None {
+                            ui.label("Device");
+                            ui.horizontal(|ui| {
+                                let selected = self
+                                    .hw_device_index
+                                    .and_then(|index| {
+                                        self.hardware_devices.iter().find(|d| d.index == index)
+                                    })
+                                    .map_or("Auto".to_string(), |d| d.label.clone());
+                                egui::ComboBox::from_id_salt("hw_device")
+                                    .selected_text(selected)
+                                    .show_ui(ui, |ui| {
+                                        ui.selectable_value(
+                                            &mut self.hw_device_index,
+                                            None,
+                                            "Auto",
+                                        );
+                                        for device in &self.hardware_devices {
+                                            ui.selectable_value(
+                                                &mut self.hw_device_index,
+                                                Some(device.index),
+                                                &device.label,
+                                            );
+                                        }
+                                    });
+                                if self.device_receiver.is_some() {
+                                    ui.spinner();
+                                }
+                            });
+                            ui.end_row();
+                        }
+
+                        ui.label("Audio codec");
+                        let previous_codec = self.audio_codec.clone();
+                        let audio_options: Vec<(AudioCodec, bool)> = [
+                            AudioCodec::Aac,
+                            AudioCodec::Mp3,
+                            AudioCodec::Opus,
+                            AudioCodec::Vorbis,
+                            AudioCodec::Ac3,
+                            AudioCodec::Eac3,
+                            AudioCodec::Flac,
+                            AudioCodec::Pcm16,
+                            AudioCodec::Copy,
+                        ]
+                        .into_iter()
+                        .map(|codec| {
+                            let available = self.encoder_available(codec.ffmpeg_name());
+                            (codec, available)
+                        })
+                        .collect();
+                        egui::ComboBox::from_id_salt("audio_codec")
+                            .selected_text(self.audio_codec.display_name())
+                            .show_ui(ui, |ui| {
+                                for (codec, available) in audio_options {
+                                    let label = codec.display_name();
+                                    Self::encoder_option(
+                                        ui,
+                                        &mut self.audio_codec,
+                                        codec,
+                                        label,
+                                        available,
+                                    );
+                                }
+                            });
+                        if self.audio_codec != previous_codec {
+                            self.audio_codec_user_set = true;
+                            self.codec_note = None;
+                        }
+                        ui.end_row();
+
+                        if let Some(note) = &self.codec_note {
+                            ui.label("");
+                            ui.small(egui::RichText::new(note).weak());
+                            ui.end_row();
+                        }
+                    }
+                });
+
+            self.render_audio_track_selector(ui);
+            if self.mode == ConversionMode::Convert && !self.disable_audio {
+                self.render_extra_audio(ui);
+            }
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.disable_video, "No video")
+                    .on_hover_text("Leave every video stream out (-vn), e.g. to extract audio");
+                ui.checkbox(&mut self.disable_audio, "No audio")
+                    .on_hover_text("Leave every audio stream out (-an) for a silent video");
+            });
+            self.render_audio_offset(ui);
+            self.render_settings_issues(ui);
+            self.render_cover_art(ui);
+            self.render_timestamp_fix(ui);
+            if self.video_format.supports_faststart() {
+                ui.checkbox(&mut self.web_optimized, "Optimize for web")
+                    .on_hover_text(
+                        "Places the index at the start of the file (faststart) so browsers \
+                         can begin playback before the download finishes",
+                    );
+            }
+            self.render_output_split(ui);
+            self.render_file_tags(ui);
+
+            if self.mode == ConversionMode::Convert {
+                ui.collapsing("Advanced", |ui| {
+                    egui::Grid::new("advanced_grid")
+                        .num_columns(2)
+                        .spacing(egui::vec2(8.0, 6.0))
+                        .show(ui, |ui| {
+                            ui.label("Video bitrate");
+                            ui.text_edit_singleline(&mut self.video_bitrate);
+                            ui.end_row();
+
+                            ui.label("Audio bitrate");
+                            ui.text_edit_singleline(&mut self.audio_bitrate);
+                            ui.end_row();
+
+                            ui.label("Resolution");
+                            ui.text_edit_singleline(&mut self.resolution);
+                            ui.end_row();
+
+                            ui.label("Frame rate");
+                            ui.text_edit_singleline(&mut self.frame_rate);
+                            ui.end_row();
+
+                            if let Some(option) =
+                                self.video_codec.params_option(self.hardware_accel)
+                            {
+                                ui.label("Codec params")
+                                    .on_hover_text(format!("Passed to FFmpeg as {option}"));
+                                ui.add(
+                                    egui::TextEdit::singleline(&mut self.codec_params)
+                                        .hint_text("aq-mode=3:psy-rd=1.0,0.15"),
+                                );
+                                ui.end_row();
+                            }
+                        });
+                    self.render_crf(ui);
+                    self.render_deinterlace(ui);
+                    self.render_crop(ui);
+                    self.render_quality_target(ui);
+                });
+
+                ui.collapsing("Performance", |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Threads");
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.threads)
+                                .hint_text("default")
+                                .desired_width(60.0),
+                        );
+                    });
+                    ui.small(
+                        "0 lets FFmpeg decide. Some encoders, including hardware ones, ignore it.",
+                    );
+                });
+
+                ui.collapsing("Extra FFmpeg arguments", |ui| {
+                    ui.label(
+                        RichText::new("Advanced, at your own risk")
+                            .small()
+                            .color(Self::warning(ui)),
+                    );
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.extra_args)
+                            .hint_text("-tune film -x264-params keyint=240")
+                            .desired_width(f32::INFINITY),
+                    );
+                    ui.small(
+                        "Only known encoder options are accepted, e.g. -tune, -profile:v, -crf, -x264-params.",
+                    );
+                });
+            } else {
+                ui.checkbox(&mut self.keep_all_streams, "Keep all streams")
+                    .on_hover_text(
+                        "Copies every video, audio and subtitle track instead of FFmpeg's pick \
+                         of one of each. Data tracks are left out.",
+                    );
+                ui.label("Metadata");
+                ui.checkbox(
+                    &mut self.metadata_options.copy_file_metadata,
+                    "Copy file-level metadata",
+                );
+                self.render_metadata_key_list(ui);
+                ui.checkbox(&mut self.metadata_options.copy_chapters, "Copy chapters");
+                ui.checkbox(
+                    &mut self.metadata_options.copy_attachments,
+                    "Copy attachments (MKV)",
+                );
+
+                Self::render_language(
+                    ui,
+                    "video_language",
+                    "Video language",
+                    &mut self.metadata_options.video_language,
+                );
+                Self::render_language(
+                    ui,
+                    "audio_language",
+                    "Audio language",
+                    &mut self.metadata_options.audio_language,
+                );
+
+                ui.horizontal(|ui| {
+                    ui.label("Video title");
+                    ui.text_edit_singleline(&mut self.metadata_options.video_title);
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Audio title");
+                    ui.text_edit_singleline(&mut self.metadata_options.audio_title);
+                });
+            }
+        });
+    }
+
+    /// A list of common languages plus a field for any other ISO 639 code,
+    /// which is rewritten to the code FFmpeg gets once it is recognised
+    fn render_language(ui: &mut egui::Ui, id: &str, label: &str, language: &mut String) {
+        ui.horizontal(|ui| {
+            ui.label(label);
+            let name = MetadataOptions::language_name(language).unwrap_or("Other");
+            egui::ComboBox::from_id_salt(id)
+                .selected_text(name)
+                .show_ui(ui, |ui| {
+                    for (code, name) in MetadataOptions::get_common_languages() {
+                        ui.selectable_value(language, code.to_string(), name);
+                    }
+                });
+
+            let response = ui.add(
+                egui::TextEdit::singleline(language)
+                    .hint_text("code")
+                    .desired_width(40.0),
+            );
+            if response.lost_focus()
+                && let Some(code) = MetadataOptions::normalize_language(language)
+            {
+                *language = code.to_string();
+            }
+            if MetadataOptions::normalize_language(language).is_none() {
+                ui.colored_label(Self::danger(ui), "Unknown code")
+                    .on_hover_text("Use an ISO 639 code such as eng, en or fre");
+            }
+        });
+    }
+
+    fn render_file_tags(&mut self, ui: &mut egui::Ui) {
+        egui::Grid::new("file_tags_grid")
+            .num_columns(2)
+            .spacing(egui::vec2(8.0, 6.0))
+            .show(ui, |ui| {
+                ui.label("Title")
+                    .on_hover_text("The file's title tag, shown by most players");
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.metadata_options.file_title)
+                        .hint_text("Optional"),
+                );
+                ui.end_row();
+
+                ui.label("Comment");
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.metadata_options.file_comment)
+                        .hint_text("Optional"),
+                );
+                ui.end_row();
+            });
+    }
+
+    fn render_deinterlace(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Deinterlace");
+            egui::ComboBox::from_id_salt("deinterlace")
+                .selected_text(self.deinterlace.map_or("Off", |mode| mode.display_name()))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.deinterlace, None, "Off");
+                    for mode in DeinterlaceMode::all() {
+                        ui.selectable_value(
+                            &mut self.deinterlace,
+                            Some(*mode),
+                            mode.display_name(),
+                        );
+                    }
+                });
+
+            let busy = self.interlace_receiver.is_some();
+            if ui
+                .add_enabled(
+                    !busy && self.input_file.is_some(),
+                    egui::Button::new("Detect"),
+                )
+                .on_hover_text("Runs FFmpeg's idet over a short sample of the input")
+                .clicked()
+            {
+                self.start_interlace_detection();
+            }
+            if busy {
+                ui.spinner();
+            }
+        });
+
+        if self.deinterlace.is_none() && self.input_is_interlaced() {
+            ui.horizontal(|ui| {
+                ui.label(
+                    RichText::new("The input is interlaced and will look combed")
+                        .small()
+                        .color(Self::warning(ui)),
+                );
+                if ui.small_button("Deinterlace").clicked() {
+                    self.deinterlace = Some(DeinterlaceMode::Yadif);
+                }
+            });
+        }
+    }
+
+    fn render_crop(&mut self, ui: &mut egui::Ui) {
+        let mut enabled = self.crop.is_some();
+        ui.horizontal(|ui| {
+            let source = self
+                .media_info
+                .as_ref()
+                .and_then(MediaInfo::video_stream)
+                .and_then(|video| Some((video.width?, video.height?)));
+            if ui.checkbox(&mut enabled, "Crop").changed() {
+                self.crop = enabled.then(|| {
+                    let (width, height) = source.unwrap_or((1920, 1080));
+                    (width, height, 0, 0)
+                });
+            }
+
+            let busy = self.crop_receiver.is_some();
+            if ui
+                .add_enabled(
+                    !busy && self.input_file.is_some(),
+                    egui::Button::new("Detect black bars"),
+                )
+                .on_hover_text("Runs FFmpeg's cropdetect over a short sample of the input")
+                .clicked()
+            {
+                self.start_crop_detection();
+            }
+            if busy {
+                ui.spinner();
+            }
+        });
+
+        if let Some((width, height, x, y)) = self.crop_suggestion {
+            ui.horizontal(|ui| {
+                ui.label(
+                    RichText::new(format!("Detected {width}x{height} at {x},{y}"))
+                        .small()
+                        .color(Self::accent(ui)),
+                );
+                if ui.small_button("Accept").clicked() {
+                    self.crop = Some((width, height, x, y));
+                    self.crop_suggestion = None;
+                }
+                if ui.small_button("Dismiss").clicked() {
+                    self.crop_suggestion = None;
+                }
+            });
+        }
+
+        if let Some((width, height, x, y)) = &mut self.crop {
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(width).prefix("w "));
+                ui.add(egui::DragValue::new(height).prefix("h "));
+                ui.add(egui::DragValue::new(x).prefix("x "));
+                ui.add(egui::DragValue::new(y).prefix("y "));
+            });
+        }
+    }
+
+    /// CRF slider for software encoders. Until it is moved it follows the
+    /// codec's default, so switching codecs picks a suitable value.
+    fn render_crf(&mut self, ui: &mut egui::Ui) {
+        if self.hardware_accel != HardwareAccel::None {
+            return;
+        }
+        let (Some((min, max)), Some(default)) =
+            (self.video_codec.crf_range(), self.video_codec.default_crf())
+        else {
+            return;
+        };
+
+        let mut crf = self.crf.unwrap_or(default).clamp(min, max);
+        ui.horizontal(|ui| {
+            ui.label("Quality (CRF)");
+            let bitrate_set = !self.video_bitrate.trim().is_empty();
+            if ui
+                .add_enabled(
+                    !bitrate_set && self.quality_target.is_none(),
+                    egui::Slider::new(&mut crf, min..=max),
+                )
+                .on_hover_text("Lower is better quality and a bigger file")
+                .on_disabled_hover_text("Unused while a video bitrate or VMAF target is set")
+                .changed()
+            {
+                self.crf = Some(crf);
+            }
+            if let Some((low, high)) = self.video_codec.recommended_crf_range() {
+                ui.small(format!("recommended {low}–{high}"));
+            }
+            if self.crf.is_some_and(|crf| crf != default)
+                && ui
+                    .small_button("Default")
+                    .on_hover_text(format!("Back to {default}, the default for this codec"))
+                    .clicked()
+            {
+                self.crf = None;
+            }
+        });
+    }
+
+    fn render_quality_target(&mut self, ui: &mut egui::Ui) {
+        let mut enabled = self.quality_target.is_some();
+        ui.horizontal(|ui| {
+            if ui
+                .checkbox(&mut enabled, "Target VMAF")
+                .on_hover_text(format!(
+                    "Encodes up to {MAX_SEARCH_ITERATIONS} short samples first and picks the \
+                     highest CRF that still reaches this score. Needs FFmpeg with libvmaf \
+                     and a software encoder; the bitrate setting is ignored."
+                ))
+                .changed()
+            {
+                self.quality_target = enabled.then_some(QualityTarget {
+                    vmaf: DEFAULT_TARGET_VMAF,
+                });
+            }
+            if let Some(target) = &mut self.quality_target {
+                ui.add(
+                    egui::DragValue::new(&mut target.vmaf)
+                        .range(MIN_TARGET_VMAF..=MAX_TARGET_VMAF)
+                        .speed(0.1)
+                        .max_decimals(1),
+                );
+            }
+        });
+    }
+
+    fn render_output_split(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Split output").on_hover_text(
+                "Writes numbered parts (name_000, name_001, ...) that each start at a keyframe. \
+                 Trims and test conversions are never split.",
+            );
+            let selected = match self.split_output {
+                None => "Off",
+                Some(OutputSplit::Duration(_)) => "By duration",
+                Some(OutputSplit::Size(_)) => "By size",
+            };
+            egui::ComboBox::from_id_salt("split_output")
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    let by_duration = matches!(self.split_output, Some(OutputSplit::Duration(_)));
+                    let by_size = matches!(self.split_output, Some(OutputSplit::Size(_)));
+                    if ui
+                        .selectable_label(self.split_output.is_none(), "Off")
+                        .clicked()
+                    {
+                        self.split_output = None;
+                    }
+                    if ui.selectable_label(by_duration, "By duration").clicked() && !by_duration {
+                        self.split_output = Some(OutputSplit::Duration(DEFAULT_SPLIT_MINUTES));
+                    }
+                    if ui.selectable_label(by_size, "By size").clicked() && !by_size {
+                        self.split_output = Some(OutputSplit::Size(DEFAULT_SPLIT_MB));
+                    }
+                });
+            match &mut self.split_output {
+                Some(OutputSplit::Duration(minutes)) => {
+                    ui.add(
+                        egui::DragValue::new(minutes)
+                            .range(1..=MAX_SPLIT_MINUTES)
+                            .suffix(" min"),
+                    );
+                }
+                Some(OutputSplit::Size(megabytes)) => {
+                    ui.add(
+                        egui::DragValue::new(megabytes)
+                            .range(MIN_SPLIT_MB..=MAX_SPLIT_MB)
+                            .speed(10.0)
+                            .suffix(" MB"),
+                    )
+                    .on_hover_text("Part lengths are estimated from the bitrate");
+                }
+                None => {}
+            }
+        });
+    }
+
+    fn render_audio_offset(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Audio offset");
+            ui.add(
+                egui::Slider::new(
+                    &mut self.audio_offset_ms,
+                    -MAX_AUDIO_OFFSET_MS..=MAX_AUDIO_OFFSET_MS,
+                )
+                .logarithmic(true)
+                .suffix(" ms"),
+            )
+            .on_hover_text(
+                "Positive values delay the audio, negative values make it play earlier. \
+                 Large shifts may need the audio re-encoded, since some containers (MP4 in \
+                 particular) handle copied streams with big start gaps poorly.",
+            );
+            if self.audio_offset_ms != 0 && ui.small_button("Reset").clicked() {
+                self.audio_offset_ms = 0;
+            }
+        });
+    }
+
+    fn render_timestamp_fix(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.fix_timestamps, "Fix timestamps")
+            .on_hover_text(
+                "Regenerates missing timestamps (-fflags +genpts) to cure desync and seeking \
+                 problems in broken captures. Streams that are copied keep their own timing \
+                 quirks, so re-encode if the problem remains.",
+            );
+
+        if !self.fix_timestamps
+            && self
+                .media_info
+                .as_ref()
+                .is_some_and(MediaInfo::has_irregular_timestamps)
+        {
+            ui.label(
+                RichText::new("The input has irregular timestamps; enabling this may avoid desync")
+                    .small()
+                    .color(Self::warning(ui)),
+            );
+        }
+    }
+
+    fn render_cover_art(&mut self, ui: &mut egui::Ui) {
+        if !self.video_format.supports_cover_art() {
+            return;
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Cover art");
+            let name = self
+                .cover_art
+                .as_ref()
+                .and_then(|path| path.file_name())
+                .map_or("none".to_string(), |name| {
+                    name.to_string_lossy().to_string()
+                });
+            ui.label(Self::ellipsize(&name, 28));
+
+            if ui.button("Choose...").clicked()
+                && let Some(file) = rfd::FileDialog::new()
+                    .set_title("Select Cover Image")
+                    .add_filter("Images", &["png", "jpg", "jpeg"])
+                    .pick_file()
+            {
+                self.cover_art = Some(file);
+            }
+
+            let can_extract = self.input_file.is_some() && self.cover_receiver.is_none();
+            if ui
+                .add_enabled(can_extract, egui::Button::new("From Video"))
+                .on_hover_text("Use a frame from 10% into the input")
+                .clicked()
+            {
+                self.start_poster_extraction();
+            }
+            if self.cover_receiver.is_some() {
+                ui.spinner();
+            }
+
+            if self.cover_art.is_some() && ui.button("Clear").clicked() {
+                self.cover_art = None;
+            }
+        });
+    }
+
+    fn start_poster_extraction(&mut self) {
+        let Some(input_file) = self.input_file.clone() else {
+            return;
+        };
+
+        let position = self
+            .media_info
+            .as_ref()
+            .and_then(|info| info.duration)
+            .map_or(0.0, |duration| duration * 0.1);
+        let stem = input_file
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| "cover".to_string());
+        let output_file = std::env::temp_dir()
+            .join("ffmpegrust_cover")
+            .join(format!("{stem}_cover.jpg"));
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        self.cover_receiver = Some(receiver);
+
+        self.runtime.spawn(async move {
+            let result =
+                match tokio::fs::create_dir_all(output_file.parent().unwrap_or(&output_file)).await
+                {
+                    Ok(()) => extract_poster_frame(&input_file, position, &output_file)
+                        .await
+                        .map(|()| output_file),
+                    Err(e) => Err(format!("Failed to create temp directory: {}", e)),
+                };
+            let _ = sender.send(result);
+        });
+    }
+
+    /// Parses the segment list for the selected input and queues one batch
+    /// item per segment
+    fn queue_segments(&mut self) {
+        let Some(input_file) = self.input_file.clone() else {
+            return;
+        };
+        let duration = self.media_info.as_ref().and_then(|info| info.duration);
+
+        let segments = match parse_segment_list(&self.segment_list, duration) {
+            Ok(segments) => segments,
+            Err(error) => {
+                self.error_message = Some(error);
+                return;
+            }
+        };
+
+        let count = segments.len();
+        let priority = self.queue_priority();
+        let items = self.batch.add_segments(&input_file, segments, priority);
+        if self.segment_output == SegmentOutput::Joined {
+            let output_file = self.planned_output_file(&input_file);
+            self.batch
+                .set_join(Some(PendingJoin { items, output_file }));
+        }
+
+        self.error_message = None;
+        self.status_message = format!("Queued {count} segment(s)");
+        self.persist_batch();
+        self.start_batch();
+    }
+
+    fn start_join(&mut self, join: PendingJoin) {
+        let clips: Option<Vec<PathBuf>> = join
+            .items
+            .iter()
+            .map(|&index| {
+                self.batch
+                    .items
+                    .get(index)
+                    .filter(|item| item.status == BatchStatus::Done)
+                    .and_then(|item| item.output_file.clone())
+            })
+            .collect();
+        // Added to the batch summary rather than replacing it
+        let summary = self.status_message.clone();
+        let Some(clips) = clips else {
+            self.status_message =
+                format!("{summary}; not every segment converted, so the clips were kept separate");
+            return;
+        };
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        self.join_receiver = Some(receiver);
+        self.status_message = format!("{summary}; joining segments...");
+
+        let output_file = join.output_file;
+        self.runtime.spawn(async move {
+            let result = join_clips(&clips, &output_file).await;
+            let _ = sender.send((summary, result.map(|()| output_file)));
+        });
+    }
+
+    fn check_join_results(&mut self) {
+        let Some(receiver) = &self.join_receiver else {
+            return;
+        };
+
+        let Ok((summary, result)) = receiver.try_recv() else {
+            return;
+        };
+
+        self.join_receiver = None;
+        match result {
+            Ok(path) => {
+                self.status_message = format!("{summary}; segments joined into {}", path.display());
+            }
+            Err(error) => {
+                self.status_message = format!("{summary}; joining the segments failed");
+                self.error_message = Some(error);
+            }
+        }
+    }
+
+    fn render_segment_list(&mut self, ui: &mut egui::Ui) {
+        if self.input_file.is_none() {
+            return;
+        }
+
+        ui.collapsing("Cut segments", |ui| {
+            ui.add(
+                egui::TextEdit::multiline(&mut self.segment_list)
+                    .hint_text("00:01:10, 00:01:45, intro\n12:30, 13:05")
+                    .desired_rows(3)
+                    .desired_width(f32::INFINITY),
+            );
+            ui.small("One in, out[, label] per line; times as seconds, MM:SS or HH:MM:SS");
+
+            ui.horizontal(|ui| {
+                ui.selectable_value(
+                    &mut self.segment_output,
+                    SegmentOutput::SeparateClips,
+                    "Separate clips",
+                );
+                ui.selectable_value(
+                    &mut self.segment_output,
+                    SegmentOutput::Joined,
+                    "Join into one",
+                );
+
+                let can_queue = !self.segment_list.trim().is_empty()
+                    && !self.batch.is_active()
+                    && self.join_receiver.is_none();
+                if ui
+                    .add_enabled(can_queue, egui::Button::new("Queue"))
+                    .on_hover_text("Adds each segment to the batch and starts it")
+                    .clicked()
+                {
+                    self.queue_segments();
+                }
+                if self.join_receiver.is_some() {
+                    ui.spinner();
+                }
+            });
+
+            if self.mode == ConversionMode::Remux || self.video_codec == VideoCodec::Copy {
+                ui.horizontal(|ui| {
+                    ui.label("Cuts");
+                    for accuracy in [TrimAccuracy::KeyframeFast, TrimAccuracy::FrameAccurate] {
+                        ui.selectable_value(
+                            &mut self.trim_accuracy,
+                            accuracy,
+                            accuracy.display_name(),
+                        );
+                    }
+                })
+                .response
+                .on_hover_text(
+                    "Copied video can only start on a keyframe. Fast cuts start at the keyframe \
+                     before each in time; frame accurate cuts re-encode the video.",
+                );
+            }
+        });
+    }
+
+    fn check_cover_results(&mut self) {
+        let Some(receiver) = &self.cover_receiver else {
+            return;
+        };
+
+        let Ok(result) = receiver.try_recv() else {
+            return;
+        };
+
+        self.cover_receiver = None;
+        match result {
+            Ok(path) => {
+                self.cover_art = Some(path);
+                self.status_message = "Cover art taken from the video".to_string();
+            }
+            Err(error) => self.error_message = Some(error),
+        }
+    }
+
+    fn render_settings_issues(&self, ui: &mut egui::Ui) {
+        for issue in self.settings_issues() {
+            let (prefix, color) = if issue.is_error() {
+                ("Error", Self::danger(ui))
+            } else {
+                ("Warning", Self::warning(ui))
+            };
+            ui.label(
+                RichText::new(format!("{prefix}: {}", issue.message()))
+                    .small()
+                    .color(color),
+            );
+        }
+    }
+
+    fn render_audio_track_selector(&mut self, ui: &mut egui::Ui) {
+        let Some(info) = &self.media_info else {
+            return;
+        };
+
+        let tracks: Vec<String> = info
+            .audio_streams()
+            .iter()
+            .enumerate()
+            .map(|(i, stream)| format!("#{} {}", i + 1, stream.describe()))
+            .collect();
+        if tracks.len() < 2 {
+            return;
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Audio track");
+            let default_label = "All (FFmpeg default)";
+            let selected = self
+                .audio_track
+                .and_then(|i| tracks.get(i).cloned())
+                .unwrap_or_else(|| default_label.to_string());
+            egui::ComboBox::from_id_salt("audio_track")
+                .selected_text(Self::ellipsize(&selected, 48))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.audio_track, None, default_label);
+                    for (i, label) in tracks.iter().enumerate() {
+                        ui.selectable_value(&mut self.audio_track, Some(i), label);
+                    }
+                });
+        });
+    }
+
+    /// Further encodes of the main audio track, each its own output track
+    fn render_extra_audio(&mut self, ui: &mut egui::Ui) {
+        let codecs: Vec<AudioCodec> = AudioCodec::encoded()
+            .into_iter()
+            .filter(|codec| self.video_format.supports_audio_codec(codec))
+            .collect();
+        let mut remove = None;
+        for (index, encode) in self.extra_audio.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.label(format!("Audio track {}", index + 2));
+                egui::ComboBox::from_id_salt(("extra_audio_codec", index))
+                    .selected_text(encode.codec.display_name())
+                    .show_ui(ui, |ui| {
+                        for codec in &codecs {
+                            ui.selectable_value(
+                                &mut encode.codec,
+                                codec.clone(),
+                                codec.display_name(),
+                            );
+                        }
+                    });
+
+                let mut bitrate = encode.bitrate.clone().unwrap_or_default();
+                if ui
+                    .add(
+                        egui::TextEdit::singleline(&mut bitrate)
+                            .hint_text("bitrate")
+                            .desired_width(60.0),
+                    )
+                    .changed()
+                {
+                    encode.bitrate =
+                        (!bitrate.trim().is_empty()).then(|| bitrate.trim().to_string());
+                }
+
+                let channels_label = |channels: Option<u32>| match channels {
+                    None => "Source".to_string(),
+                    Some(1) => "Mono".to_string(),
+                    Some(2) => "Stereo".to_string(),
+                    Some(6) => "5.1".to_string(),
+                    Some(8) => "7.1".to_string(),
+                    Some(channels) => format!("{channels} ch"),
+                };
+                egui::ComboBox::from_id_salt(("extra_audio_channels", index))
+                    .selected_text(channels_label(encode.channels))
+                    .show_ui(ui, |ui| {
+                        for channels in [None, Some(1), Some(2), Some(6), Some(8)] {
+                            ui.selectable_value(
+                                &mut encode.channels,
+                                channels,
+                                channels_label(channels),
+                            );
+                        }
+                    });
+
+                if ui.small_button("✕").clicked() {
+                    remove = Some(index);
+                }
+            });
+        }
+        if let Some(index) = remove {
+            self.extra_audio.remove(index);
+        }
+
+        let can_add = self.video_format.supports_multiple_audio()
+            && self.extra_audio.len() < MAX_EXTRA_AUDIO_TRACKS;
+        if ui
+            .add_enabled(can_add, egui::Button::new("Add Audio Track").small())
+            .on_hover_text(
+                "Encode the audio again as another track, e.g. a 5.1 AC3 next to a stereo AAC",
+            )
+            .on_disabled_hover_text(format!(
+                "Up to {MAX_EXTRA_AUDIO_TRACKS} extra tracks, in MP4, MKV or MOV"
+            ))
+            .clicked()
+        {
+            self.extra_audio.push(AudioEncode {
+                codec: AudioCodec::Ac3,
+                bitrate: None,
+                channels: Some(6),
+            });
+        }
+    }
+
+    fn render_recommendation(&mut self, ui: &mut egui::Ui) {
+        let Some(info) = &self.media_info else {
+            return;
+        };
+
+        let Some(recommendation) = &self.recommendation else {
+            let mut recommendation = None;
+            ui.horizontal(|ui| {
+                if info.video_stream().is_some()
+                    && ui
+                        .small_button("Analyze")
+                        .on_hover_text(
+                            "Suggest settings based on the input's codec, resolution and bitrate",
+                        )
+                        .clicked()
+                {
+                    recommendation = recommend_settings(info);
+                }
+                if let Some(input_file) = &self.input_file
+                    && self.sequence_framerate.is_none()
+                    && ui
+                        .small_button("Repair")
+                        .on_hover_text(
+                            "Remux without re-encoding to fix broken seeking, timestamps or \
+                             indexes",
+                        )
+                        .clicked()
+                {
+                    recommendation = Some(repair_settings(info, input_file));
+                }
+            });
+            self.recommendation = recommendation;
+            return;
+        };
+
+        let mut apply = false;
+        let mut start = false;
+        let mut dismiss = false;
+        egui::Frame::group(ui.style())
+            .stroke(Stroke::new(1.0, Self::accent(ui)))
+            .show(ui, |ui| {
+                ui.label(
+                    RichText::new(&recommendation.title)
+                        .strong()
+                        .color(Self::accent(ui)),
+                );
+                for reason in &recommendation.reasons {
+                    ui.label(RichText::new(format!("• {reason}")).small());
+                }
+                ui.horizontal(|ui| {
+                    apply = ui.button("Apply").clicked();
+                    start = ui
+                        .add_enabled(
+                            !self.conversion_state.is_converting(),
+                            egui::Button::new("Apply and Start"),
+                        )
+                        .clicked();
+                    dismiss = ui.button("Dismiss").clicked();
+                });
+            });
+
+        if apply || start {
+            let preset = recommendation.preset.clone();
+            let title = recommendation.title.clone();
+            self.load_settings(&preset);
+            self.recommendation = None;
+            self.status_message = format!("Applied recommendation: {title}");
+            if start && self.can_start_conversion() {
+                self.start_conversion();
+            }
+        } else if dismiss {
+            self.recommendation = None;
+        }
+    }
+
+    fn render_subtitle_extraction(&mut self, ui: &mut egui::Ui) {
+        let Some(info) = &self.media_info else {
+            return;
+        };
+
+        let subtitles: Vec<String> = info
+            .streams_of_type("subtitle")
+            .iter()
+            .enumerate()
+            .map(|(i, stream)| format!("#{} {}", i + 1, stream.describe()))
+            .collect();
+        if subtitles.is_empty() {
+            return;
+        }
+
+        ui.collapsing("Extract subtitles", |ui| {
+            for (i, label) in subtitles.iter().enumerate() {
+                let mut selected = self.selected_subtitles.contains(&i);
+                if ui.checkbox(&mut selected, label).changed() {
+                    if selected {
+                        self.selected_subtitles.push(i);
+                    } else {
+                        self.selected_subtitles.retain(|&index| index != i);
+                    }
+                }
+            }
+
+            ui.horizontal(|ui| {
+                for format in [SubtitleFormat::Srt, SubtitleFormat::WebVtt] {
+                    ui.selectable_value(&mut self.subtitle_format, format, format.display_name());
+                }
+
+                let busy = self.subtitle_receiver.is_some();
+                if ui
+                    .add_enabled(
+                        !busy && !self.selected_subtitles.is_empty(),
+                        egui::Button::new("Extract"),
+                    )
+                    .on_hover_text("Saved next to the output file, named by language")
+                    .clicked()
+                {
+                    self.start_subtitle_extraction();
+                }
+                if busy {
+                    ui.spinner();
+                }
+            });
+        });
+    }
+
+    fn render_metadata_key_list(&mut self, ui: &mut egui::Ui) {
+        if !self.metadata_options.copy_file_metadata {
+            return;
+        }
+
+        let Some(info) = &self.media_info else {
+            return;
+        };
+        if info.tags.is_empty() {
+            return;
+        }
+
+        let metadata = &mut self.metadata_options;
+        ui.collapsing("File metadata keys", |ui| {
+            for (key, value) in &info.tags {
+                let mut keep = metadata.keeps_key(key);
+                let usable = is_valid_metadata_key(key) && is_safe_metadata_value(value);
+                let response = ui
+                    .add_enabled(usable, egui::Checkbox::new(&mut keep, key))
+                    .on_hover_text(Self::ellipsize(value, 120));
+                if response.changed() {
+                    metadata.set_key_kept(key, keep);
+                }
+            }
+        });
+    }
+
+    fn render_progress_panel(&mut self, ui: &mut egui::Ui) {
+        Self::section_card(ui, "Progress", |ui| {
+            if let Some(progress) = self.conversion_state.progress() {
+                ui.add(
+                    egui::ProgressBar::new(progress.percentage / 100.0)
+                        .desired_width(ui.available_width())
+                        .text(progress.percentage_label()),
+                );
+
+                let mut info = format!("{} / {}", progress.current_time, progress.total_time);
+                if let Some(remaining) = progress.time_remaining {
+                    let remaining_secs = remaining.as_secs();
+                    let minutes = remaining_secs / 60;
+                    let seconds = remaining_secs % 60;
+                    info.push_str(&format!(" | ETA {minutes:02}:{seconds:02}"));
+                }
+                ui.horizontal(|ui| {
+                    if let Some(speed) = progress.speed_label() {
+                        let color = if progress.is_realtime() == Some(true) {
+                            Self::success(ui)
+                        } else {
+                            Self::warning(ui)
+                        };
+                        ui.label(RichText::new(speed).strong().size(16.0).color(color))
+                            .on_hover_text(
+                                "How fast the encode runs compared to playing the video; \
+                                 below 1× it takes longer than the video lasts",
+                            );
+                    }
+                    ui.label(info);
+                });
+                if let Some(note) = &self.milestone_note {
+                    ui.label(RichText::new(note).small().color(Self::accent(ui)));
+                }
+
+                self.render_progress_graph(ui);
+            } else {
+                let output_file_preview = if self.output_file_name.trim().is_empty() {
+                    self.input_file
+                        .as_ref()
+                        .map(|f| {
+                            generate_output_filename(f, &self.video_format, &self.output_suffix())
+                        })
+                        .and_then(|p| p.file_name().map(|n| n.to_string_lossy().to_string()))
+                        .unwrap_or_else(|| format!("output.{}", self.video_format.extension()))
+                } else {
+                    self.output_filename_for_container()
+                };
+
+                let input = self
+                    .input_file
+                    .as_deref()
+                    .map(Self::short_path)
+                    .unwrap_or_else(|| "none".to_string());
+                let output = self
+                    .output_folder
+                    .as_deref()
+                    .map(Self::short_path)
+                    .unwrap_or_else(|| "none".to_string());
+                ui.label(format!("Input: {}", Self::ellipsize(&input, 80)));
+                ui.label(format!("Output: {}", Self::ellipsize(&output, 80)));
+                ui.label(format!(
+                    "File: {}",
+                    Self::ellipsize(&output_file_preview, 80)
+                ));
+
+                if let Some(info) = &self.media_info {
+                    let preset = self.build_current_preset("Current".to_string());
+                    let show_dispositions = preset.video_format.supports_dispositions();
+                    let mut kept_audio = 0;
+                    let mut kept_subtitles = 0;
+                    let cover_art = self
+                        .cover_art
+                        .as_deref()
+                        .filter(|_| self.video_format.supports_cover_art());
+                    for plan in plan_streams(&preset, info, self.audio_track, cover_art) {
+                        let color = match plan.action {
+                            StreamAction::Copy => Self::success(ui),
+                            StreamAction::Encode(_) => Self::accent(ui),
+                            StreamAction::Drop => Self::danger(ui),
+                        };
+                        // Output tracks are numbered per type, counting only kept ones
+                        let output_track =
+                            match DispositionTarget::from_codec_type(&plan.codec_type) {
+                                Some(target) if plan.action != StreamAction::Drop => {
+                                    let counter = match target {
+                                        DispositionTarget::Audio => &mut kept_audio,
+                                        DispositionTarget::Subtitle => &mut kept_subtitles,
+                                    };
+                                    *counter += 1;
+                                    Some((target, *counter - 1))
+                                }
+                                _ => None,
+                            };
+                        ui.horizontal(|ui| {
+                            ui.label(RichText::new(Self::ellipsize(&plan.description, 48)).small());
+                            ui.label(RichText::new(plan.action.describe()).small().color(color));
+
+                            let Some((target, index)) = output_track.filter(|_| show_dispositions)
+                            else {
+                                return;
+                            };
+                            let flags: &[&str] = match target {
+                                DispositionTarget::Audio => &["default"],
+                                DispositionTarget::Subtitle => &["default", "forced"],
+                            };
+                            for flag in flags {
+                                let metadata = &mut self.metadata_options;
+                                let mut enabled = metadata.has_disposition(target, index, flag);
+                                if ui
+                                    .checkbox(&mut enabled, RichText::new(*flag).small())
+                                    .on_hover_text(format!(
+                                        "Mark this track as {flag} (-disposition:{}:{index})",
+                                        target.specifier()
+                                    ))
+                                    .changed()
+                                {
+                                    metadata.set_disposition(target, index, flag, enabled);
+                                }
+                            }
+                        });
+                    }
+                }
+
+                ui.label("Ready");
+            }
+
+            self.render_failure_report(ui);
+            self.render_log_view(ui);
+
+            if self.conversion_state.is_converting() {
+                ui.horizontal(|ui| {
+                    let pause_label = if self.conversion_state.is_paused() {
+                        "Resume"
+                    } else {
+                        "Pause"
+                    };
+                    if ui.button(pause_label).clicked() {
+                        self.toggle_pause();
+                    }
+                    if ui.button("Stop Conversion").clicked() {
+                        self.stop_conversion();
+                    }
+                });
+            }
+        });
+    }
+
+    fn render_failure_report(&mut self, ui: &mut egui::Ui) {
+        let Some(report) = &self.failure_report else {
+            return;
+        };
+
+        let mut dismissed = false;
+        egui::Frame::group(ui.style()).show(ui, |ui| {
+            ui.colored_label(Self::danger(ui), "The last conversion failed");
+            ui.label(RichText::new("Command").small().strong());
+            ui.label(RichText::new(&report.command).small().monospace());
+            if !report.stderr.is_empty() {
+                ui.label(RichText::new("FFmpeg output").small().strong());
+                ScrollArea::vertical()
+                    .id_salt("failure_stderr")
+                    .max_height(120.0)
+                    .show(ui, |ui| {
+                        for line in &report.stderr {
+                            ui.label(RichText::new(line).small().monospace());
+                        }
+                    });
+            }
+
+            ui.horizontal(|ui| {
+                if ui.button("Copy Command").clicked() {
+                    ui.ctx().copy_text(report.command.clone());
+                }
+                if ui.button("Copy Report").clicked() {
+                    ui.ctx().copy_text(format!(
+                        "Command:\n{}\n\nFFmpeg output:\n{}",
+                        report.command,
+                        report.stderr.join("\n")
+                    ));
+                }
+                if ui.button("Dismiss").clicked() {
+                    dismissed = true;
+                }
+            });
+        });
+
+        if dismissed {
+            self.failure_report = None;
+        }
+    }
+
+    fn render_log_view(&mut self, ui: &mut egui::Ui) {
+        let Some(log) = &self.conversion_log else {
+            return;
+        };
+        let lines = log.lines();
+        let stick_to_bottom = self.conversion_state.is_converting() && self.log_autoscroll;
+
+        ui.collapsing("Show FFmpeg output", |ui| {
+            ui.checkbox(&mut self.log_autoscroll, "Follow newest output");
+
+            ScrollArea::vertical()
+                .id_salt("ffmpeg_log")
+                .max_height(180.0)
+                .stick_to_bottom(stick_to_bottom)
+                .show(ui, |ui| {
+                    if lines.is_empty() {
+                        ui.label(RichText::new("No output yet").small());
+                    }
+                    for line in &lines {
+                        let text = RichText::new(line).small().monospace();
+                        let text = match Self::log_line_color(ui, line) {
+                            Some(color) => text.color(color),
+                            None => text,
+                        };
+                        ui.label(text);
+                    }
+                });
+        });
+    }
+
+    /// FFmpeg doesn't tag its log lines by level, so go by the wording
+    fn log_line_color(ui: &egui::Ui, line: &str) -> Option<Color32> {
+        let lower = line.to_ascii_lowercase();
+        if lower.contains("error") || lower.contains("invalid") || lower.contains("failed") {
+            Some(Self::danger(ui))
+        } else if lower.contains("warning") || lower.contains("deprecated") {
+            Some(Self::warning(ui))
+        } else {
+            None
+        }
+    }
+
+    fn render_progress_graph(&mut self, ui: &mut egui::Ui) {
+        if self.progress_history.is_empty() {
+            return;
+        }
+
+        ui.horizontal(|ui| {
+            for metric in [GraphMetric::Speed, GraphMetric::Bitrate, GraphMetric::Fps] {
+                ui.selectable_value(&mut self.graph_metric, metric, metric.label());
+            }
+        });
+
+        let metric = self.graph_metric;
+        let points: PlotPoints = self
+            .progress_history
+            .iter()
+            .filter_map(|sample| {
+                metric
+                    .value(sample)
+                    .map(|value| [sample.elapsed_secs, value as f64])
+            })
+            .collect();
+        let color = Self::accent(ui);
+
+        Plot::new("progress_graph")
+            .height(120.0)
+            .include_y(0.0)
+            .allow_drag(false)
+            .allow_zoom(false)
+            .allow_scroll(false)
+            .x_axis_label("Elapsed (s)")
+            .show(ui, |plot_ui| {
+                plot_ui.line(Line::new(metric.label(), points).color(color));
+            });
+    }
+
+    fn render_actions_panel(&mut self, ui: &mut egui::Ui) {
+        Self::section_card(ui, "Actions", |ui| {
+            let start_label = if self.conversion_state.is_converting() {
+                "Converting..."
+            } else {
+                "Start Conversion"
+            };
+
+            let button = egui::Button::new(RichText::new(start_label).strong())
+                .min_size(egui::vec2(170.0, 26.0))
+                .fill(Self::colors(ui).widget_hovered)
+                .stroke(Stroke::new(1.0, Self::accent(ui)));
+
+            ui.horizontal_wrapped(|ui| {
+                if ui
+                    .add_enabled(self.can_start_conversion(), button)
+                    .clicked()
+                {
+                    self.start_conversion();
+                }
+
+                if ui
+                    .add_enabled(
+                        self.can_start_conversion(),
+                        egui::Button::new(format!(
+                            "Test ({}s)",
+                            TEST_CONVERSION_DURATION.as_secs()
+                        )),
+                    )
+                    .on_hover_text("Convert a short sample to a temporary preview file")
+                    .clicked()
+                {
+                    self.start_test_conversion();
+                }
+
+                if ui
+                    .add_enabled(
+                        self.can_start_conversion()
+                            && self.benchmark_receiver.is_none()
+                            && self.input_file.is_some()
+                            && self.sequence_framerate.is_none(),
+                        egui::Button::new("Benchmark"),
+                    )
+                    .on_hover_text(format!(
+                        "Time encoder speed presets on a {}s sample and compare their size \
+                         and quality",
+                        BENCHMARK_SAMPLE.as_secs()
+                    ))
+                    .clicked()
+                {
+                    self.start_benchmark();
+                }
+
+                if ui
+                    .button("Compare")
+                    .on_hover_text(
+                        "Compare the properties of two files, e.g. the input and its output",
+                    )
+                    .clicked()
+                {
+                    self.open_compare();
+                }
+
+                if let Some(preview) = self.preview_file.clone() {
+                    if ui.button("Open Preview").clicked()
+                        && let Err(err) = open_in_default_app(&preview)
+                    {
+                        self.error_message = Some(err);
+                    }
+                    if ui
+                        .add_enabled(
+                            self.sequence_framerate.is_none(),
+                            egui::Button::new("Compare Frames"),
+                        )
+                        .on_hover_text(
+                            "Flip between a frame of the input and the same frame of the \
+                             test encode",
+                        )
+                        .clicked()
+                    {
+                        self.open_frame_compare();
+                    }
+                }
+
+                let palette_hint = format!(
+                    "{} searches presets and actions",
+                    ui.ctx().format_shortcut(&PALETTE_SHORTCUT)
+                );
+                if ui.button("Help").on_hover_text(palette_hint).clicked() {
+                    self.show_help_dialog = true;
+                }
+                if ui.button("About").clicked() {
+                    self.show_about_dialog = true;
+                }
+                let mut theme = self.config.theme;
+                egui::ComboBox::from_id_salt("ui_theme")
+                    .width(80.0)
+                    .selected_text(theme.display_name())
+                    .show_ui(ui, |ui| {
+                        for option in UiTheme::all() {
+                            ui.selectable_value(&mut theme, option, option.display_name());
+                        }
+                    })
+                    .response
+                    .on_hover_text("Color theme; System follows the operating system");
+                if theme != self.config.theme {
+                    self.set_theme(ui.ctx(), theme);
+                }
+                if ui.button("Check Updates").clicked() {
+                    self.check_for_updates();
+                }
+                if self.conversion_state.is_converting() && ui.button("Stop").clicked() {
+                    self.stop_conversion();
+                }
+                if ui
+                    .button("Reset")
+                    .on_hover_text("Clear the input, settings and errors")
+                    .clicked()
+                {
+                    if self.conversion_state.is_converting() {
+                        self.show_reset_dialog = true;
+                    } else {
+                        self.reset_all();
+                    }
+                }
+            });
+
+            let mut trash_source = self.trash_source_on_success;
+            if ui
+                .checkbox(&mut trash_source, "Move source to trash after success")
+                .on_hover_text(
+                    "Once the output is checked to be complete, the input goes to the trash. \
+                     Failed, stopped, trimmed and test conversions never touch it.",
+                )
+                .changed()
+            {
+                if trash_source {
+                    self.show_trash_source_dialog = true;
+                } else {
+                    self.trash_source_on_success = false;
+                }
+            }
+        });
+    }
+
+    /// A dismissible strip offering an update found by a check, unless the
+    /// user skipped that version
+    fn render_update_banner(&mut self, ctx: &Context) {
+        let Some(UpdateStatus::UpdateAvailable(info)) = &self.update_status else {
+            return;
+        };
+        if self.update_banner_dismissed
+            || self.config.skipped_update_version.as_deref() == Some(info.version.as_str())
+        {
+            return;
+        }
+
+        let version = info.version.clone();
+        TopBottomPanel::top("update_banner").show(ctx, |ui| {
+            ui.horizontal_wrapped(|ui| {
+                ui.colored_label(
+                    Self::success(ui),
+                    format!("Version {version} is available."),
+                );
+                if ui.small_button("Details").clicked() {
+                    self.show_help_dialog = true;
+                }
+                if ui.small_button("Skip This Version").clicked() {
+                    self.config.update_skipped_version(Some(version.clone()));
+                }
+                if ui.small_button("Later").clicked() {
+                    self.update_banner_dismissed = true;
+                }
+            });
+        });
+    }
+
+    fn render_status_bar(&mut self, ctx: &Context) {
+        TopBottomPanel::bottom("status_panel").show(ctx, |ui| {
+            ui.horizontal_wrapped(|ui| {
+                ui.label(RichText::new("Status").strong());
+                ui.label(&self.status_message);
+                if let Some(error) = &self.error_message {
+                    ui.colored_label(Self::danger(ui), format!("Error: {error}"));
+                    if ui.button("Clear").clicked() {
+                        self.error_message = None;
+                    }
+                }
+            });
+        });
+    }
+
+    fn render_help_dialog(&mut self, ctx: &Context) {
+        if !self.show_help_dialog {
+            return;
+        }
+
+        egui::Window::new("Help and Diagnostics")
+            .resizable(false)
+            .collapsible(false)
+            .default_width(500.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if ui.button("Check FFmpeg").clicked() {
+                        self.check_ffmpeg();
+                    }
+                    if ui
+                        .add_enabled(
+                            self.diagnostics_receiver.is_none(),
+                            egui::Button::new("Run Diagnostics"),
+                        )
+                        .on_hover_text(
+                            "Checks FFmpeg, hardware encoders, folder permissions and free space",
+                        )
+                        .clicked()
+                    {
+                        self.start_diagnostics();
+                    }
+                    if self.diagnostics_receiver.is_some() {
+                        ui.spinner();
+                    } else if !self.diagnostics.is_empty() && ui.button("Copy Report").clicked() {
+                        ui.ctx().copy_text(diagnostics_report(&self.diagnostics));
+                    }
+                });
+
+                if let Some(status) = &self.ffmpeg_status {
+                    match status {
+                        Ok(version) => {
+                            ui.colored_label(Self::success(ui), version);
+                        }
+                        Err(err) => {
+                            ui.colored_label(Self::danger(ui), err);
+                        }
+                    }
+                }
+
+                if !self.diagnostics.is_empty() {
+                    egui::Grid::new("diagnostics")
+                        .num_columns(3)
+                        .striped(true)
+                        .show(ui, |ui| {
+                            for check in &self.diagnostics {
+                                let color = match check.status {
+                                    CheckStatus::Pass => Self::success(ui),
+                                    CheckStatus::Warn => Self::warning(ui),
+                                    CheckStatus::Fail => Self::danger(ui),
+                                };
+                                ui.colored_label(color, check.status.label());
+                                ui.label(&check.name);
+                                ui.label(RichText::new(&check.detail).small());
+                                ui.end_row();
+                            }
+                        });
+                }
+
+                ui.label(
+                    RichText::new(format!("Settings folder: {}", config_location().display()))
+                        .small(),
+                );
+
+                ui.horizontal(|ui| {
+                    ui.label("Progress milestones");
+                    let response = ui
+                        .add(
+                            egui::TextEdit::singleline(&mut self.milestones_input)
+                                .hint_text("25, 50, 75, 100")
+                                .desired_width(140.0),
+                        )
+                        .on_hover_text(
+                            "Percentages at which a conversion reports how far it got; \
+                             leave empty for none",
+                        );
+                    if response.lost_focus() {
+                        match parse_milestones(&self.milestones_input) {
+                            Ok(milestones) => {
+                                self.milestones_input = milestones
+                                    .iter()
+                                    .map(u32::to_string)
+                                    .collect::<Vec<_>>()
+                                    .join(", ");
+                                self.config.progress_milestones = milestones;
+                                self.config.save();
+                            }
+                            Err(error) => self.error_message = Some(error),
+                        }
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Conversion timeout");
+                    let mut automatic = self.config.conversion_timeout_secs.is_none();
+                    if ui
+                        .checkbox(&mut automatic, "Automatic")
+                        .on_hover_text("Scales the limit with the input's duration")
+                        .changed()
+                    {
+                        self.config.conversion_timeout_secs =
+                            (!automatic).then_some(DEFAULT_TIMEOUT_MINUTES * 60);
+                        self.config.save();
+                    }
+                    if let Some(secs) = self.config.conversion_timeout_secs {
+                        let mut minutes = secs / 60;
+                        let response = ui
+                            .add(
+                                egui::DragValue::new(&mut minutes)
+                                    .range(0..=MAX_TIMEOUT_MINUTES)
+                                    .suffix(" min"),
+                            )
+                            .on_hover_text("0 disables the limit");
+                        if response.changed() {
+                            self.config.conversion_timeout_secs = Some(minutes * 60);
+                        }
+                        // Saved once dragging ends rather than on every step
+                        if response.drag_stopped() || (response.changed() && !response.dragged()) {
+                            self.config.save();
+                        }
                     }
-                    ui.end_row();
                 });
 
-            ui.horizontal(|ui| {
-                ui.label("Mode");
-                ui.selectable_value(&mut self.mode, ConversionMode::Convert, "Convert");
-                ui.selectable_value(&mut self.mode, ConversionMode::Remux, "Remux");
-            });
-        });
-    }
+                ui.horizontal_wrapped(|ui| {
+                    let mut keep_logs = self.config.keep_ffmpeg_logs;
+                    if ui
+                        .checkbox(&mut keep_logs, "Keep FFmpeg logs")
+                        .on_hover_text("Save each conversion's full log; the newest 50 are kept")
+                        .changed()
+                    {
+                        self.config.keep_ffmpeg_logs = keep_logs;
+                        self.config.save();
+                    }
+                    let mut pause_on_low_disk = self.config.pause_on_low_disk;
+                    if ui
+                        .checkbox(&mut pause_on_low_disk, "Pause when disk is low")
+                        .on_hover_text(format!(
+                            "Pauses a conversion when less than {DISK_SPACE_THRESHOLD_MB} MB \
+                             is left on the output drive"
+                        ))
+                        .changed()
+                    {
+                        self.config.pause_on_low_disk = pause_on_low_disk;
+                        self.config.save();
+                    }
+                    let mut confirm_overwrite = self.config.confirm_overwrite;
+                    if ui
+                        .checkbox(&mut confirm_overwrite, "Ask before overwriting")
+                        .on_hover_text("Offers to rename the output when it already exists")
+                        .changed()
+                    {
+                        self.config.confirm_overwrite = confirm_overwrite;
+                        self.existing_output_choice = None;
+                        self.config.save();
+                    }
+                    let mut preserve_times = self.config.preserve_file_times;
+                    if ui
+                        .checkbox(&mut preserve_times, "Preserve file times")
+                        .on_hover_text("Outputs get the modification date of their input")
+                        .changed()
+                    {
+                        self.config.preserve_file_times = preserve_times;
+                        self.config.save();
+                    }
+                    let mut segmented = self.config.segmented_encode;
+                    if ui
+                        .checkbox(&mut segmented, "Parallel segments (experimental)")
+                        .on_hover_text(
+                            "Splits re-encoded single files at keyframes and encodes the parts \
+                             at the same time; needs at least 8 CPU cores",
+                        )
+                        .changed()
+                    {
+                        self.config.segmented_encode = segmented;
+                        self.config.save();
+                    }
+                    let logs_folder = self.config.logs_folder();
+                    if ui
+                        .add_enabled(logs_folder.is_dir(), egui::Button::new("Open Logs Folder"))
+                        .clicked()
+                        && let Err(err) = open_in_default_app(&logs_folder)
+                    {
+                        self.error_message = Some(err);
+                    }
+                });
 
-    fn render_presets_panel(&mut self, ui: &mut egui::Ui) {
-        Self::section_card(ui, "Presets", |ui| {
-            let mut preset_names: Vec<String> = self
-                .preset_manager
-                .list_presets()
-                .iter()
-                .map(|preset| preset.name.clone())
-                .collect();
-            preset_names.sort_unstable();
+                ui.collapsing("Container compatibility", |ui| {
+                    self.render_compatibility_matrix(ui);
+                });
 
-            egui::ComboBox::from_id_salt("preset_selector")
-                .selected_text(self.selected_preset.as_deref().unwrap_or("Choose preset"))
-                .show_ui(ui, |ui| {
-                    for preset_name in &preset_names {
-                        if ui.selectable_label(false, preset_name).clicked() {
-                            self.apply_preset(preset_name);
-                        }
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    if ui.button("Check for Updates").clicked() {
+                        self.check_for_updates();
+                    }
+                    let mut auto_check = self.config.auto_check_updates;
+                    if ui.checkbox(&mut auto_check, "Check on startup").changed() {
+                        self.config.update_auto_check_updates(auto_check);
                     }
                 });
 
-            ui.horizontal_wrapped(|ui| {
-                if ui.button("Save Current").clicked() {
-                    self.show_save_preset = true;
+                if let Some(status) = self.update_status.clone() {
+                    match status {
+                        UpdateStatus::CheckingForUpdates => {
+                            ui.label("Checking for updates...");
+                        }
+                        UpdateStatus::UpdateAvailable(info) => {
+                            ui.colored_label(
+                                Self::success(ui),
+                                format!("Update available: v{}", info.version),
+                            );
+                            if ui.button("Download and Install").clicked() {
+                                self.start_update_download(info.clone());
+                            }
+
+                            ui.add_space(6.0);
+                            ui.label(RichText::new("Release Notes").strong());
+                            ScrollArea::vertical().max_height(180.0).show(ui, |ui| {
+                                ui.label(info.release_notes.clone());
+                            });
+                        }
+                        UpdateStatus::NoUpdateAvailable => {
+                            ui.label("You already have the latest version.");
+                        }
+                        UpdateStatus::DownloadingUpdate(progress) => {
+                            ui.label(format!("Downloading update: {progress:.1}%"));
+                            ui.add(egui::ProgressBar::new(progress / 100.0));
+                            if ui.button("Cancel Download").clicked() {
+                                self.cancel_update_download();
+                            }
+                        }
+                        UpdateStatus::InstallingUpdate => {
+                            ui.label("Installing update...");
+                            ui.spinner();
+                        }
+                        UpdateStatus::DownloadCancelled => {
+                            ui.label("Update download cancelled.");
+                        }
+                        UpdateStatus::Error(error) => {
+                            ui.colored_label(Self::danger(ui), error);
+                        }
+                    }
                 }
 
-                if let Some(preset_name) = self.selected_preset.clone()
-                    && ui.button("Delete").clicked()
-                {
-                    self.preset_manager.remove_preset(&preset_name);
-                    self.selected_preset = None;
-                    self.status_message = format!("Deleted preset: {preset_name}");
+                ui.separator();
+                if ui.button("Close").clicked() {
+                    self.show_help_dialog = false;
                 }
             });
+    }
 
-            if self.show_save_preset {
-                ui.horizontal(|ui| {
-                    ui.text_edit_singleline(&mut self.new_preset_name);
-                    if ui.button("Save").clicked() {
-                        self.save_current_preset();
-                    }
-                    if ui.button("Cancel").clicked() {
-                        self.show_save_preset = false;
-                        self.new_preset_name.clear();
-                    }
-                });
+    /// Which codecs each container holds, from `CONTAINER_CODEC_RECOMMENDATIONS`,
+    /// with the current container and codecs highlighted
+    fn render_compatibility_matrix(&self, ui: &mut egui::Ui) {
+        let formats: Vec<&VideoFormat> = CONTAINER_CODEC_RECOMMENDATIONS
+            .iter()
+            .map(|entry| &entry.format)
+            .collect();
+        let accent = Self::accent(ui);
+        let highlight = |text: &str, selected: bool| {
+            let text = RichText::new(text).small();
+            if selected {
+                text.strong().color(accent)
+            } else {
+                text
             }
-        });
-    }
+        };
+        let mut caveats = Vec::new();
 
-    fn render_conversion_panel(&mut self, ui: &mut egui::Ui) {
-        Self::section_card(ui, "Conversion", |ui| {
-            egui::Grid::new("conversion_grid")
-                .num_columns(2)
-                .spacing(egui::vec2(8.0, 6.0))
-                .show(ui, |ui| {
-                    ui.label("Container");
-                    egui::ComboBox::from_id_salt("video_format")
-                        .selected_text(self.video_format.display_name())
-                        .show_ui(ui, |ui| {
-                            ui.selectable_value(&mut self.video_format, VideoFormat::Mp4, "MP4");
-                            ui.selectable_value(&mut self.video_format, VideoFormat::Mkv, "MKV");
-                            ui.selectable_value(&mut self.video_format, VideoFormat::Mov, "MOV");
-                            ui.selectable_value(&mut self.video_format, VideoFormat::Avi, "AVI");
-                            ui.selectable_value(&mut self.video_format, VideoFormat::Webm, "WebM");
-                        });
+        egui::Grid::new("compatibility_matrix")
+            .striped(true)
+            .spacing(egui::vec2(12.0, 4.0))
+            .show(ui, |ui| {
+                ui.label("");
+                for format in &formats {
+                    ui.label(highlight(
+                        format.display_name(),
+                        **format == self.video_format,
+                    ));
+                }
+                ui.end_row();
+
+                let video_rows = VideoCodec::encoded().map(|codec| {
+                    let selected = codec == self.video_codec;
+                    let cells: Vec<_> = formats
+                        .iter()
+                        .map(|format| {
+                            (
+                                format.supports_video_codec(&codec),
+                                video_codec_caveat(format, &codec),
+                            )
+                        })
+                        .collect();
+                    (codec.display_name(), selected, cells)
+                });
+                let audio_rows = AudioCodec::encoded().map(|codec| {
+                    let selected = codec == self.audio_codec;
+                    let cells: Vec<_> = formats
+                        .iter()
+                        .map(|format| {
+                            (
+                                format.supports_audio_codec(&codec),
+                                audio_codec_caveat(format, &codec),
+                            )
+                        })
+                        .collect();
+                    (codec.display_name(), selected, cells)
+                });
+
+                for (name, selected, cells) in video_rows.into_iter().chain(audio_rows) {
+                    ui.label(highlight(name, selected));
+                    for (format, (supported, caveat)) in formats.iter().zip(cells) {
+                        let in_selection = selected && **format == self.video_format;
+                        match (supported, caveat) {
+                            (true, Some(caveat)) => {
+                                ui.label(highlight("✔*", in_selection))
+                                    .on_hover_text(&caveat);
+                                caveats.push(caveat);
+                            }
+                            (true, None) => {
+                                ui.label(highlight("✔", in_selection));
+                            }
+                            (false, _) => {
+                                ui.label(RichText::new("–").small().weak());
+                            }
+                        }
+                    }
                     ui.end_row();
+                }
+            });
 
-                    if self.mode == ConversionMode::Convert {
-                        ui.label("Video codec");
-                        egui::ComboBox::from_id_salt("video_codec")
-                            .selected_text(self.video_codec.display_name())
-                            .show_ui(ui, |ui| {
-                                ui.selectable_value(
-                                    &mut self.video_codec,
-                                    VideoCodec::H264,
-                                    "H.264",
-                                );
-                                ui.selectable_value(
-                                    &mut self.video_codec,
-                                    VideoCodec::H265,
-                                    "H.265",
-                                );
-                                ui.selectable_value(&mut self.video_codec, VideoCodec::VP9, "VP9");
-                                ui.selectable_value(
-                                    &mut self.video_codec,
-                                    VideoCodec::Copy,
-                                    "Copy",
-                                );
-                            });
-                        ui.end_row();
+        for caveat in caveats {
+            ui.label(RichText::new(format!("* {caveat}")).small().weak());
+        }
+        ui.label(
+            RichText::new(
+                "Copy keeps the source codec and works with any container that can hold it",
+            )
+            .small()
+            .weak(),
+        );
+    }
 
-                        ui.label("Audio codec");
-                        egui::ComboBox::from_id_salt("audio_codec")
-                            .selected_text(self.audio_codec.display_name())
-                            .show_ui(ui, |ui| {
-                                ui.selectable_value(&mut self.audio_codec, AudioCodec::Aac, "AAC");
-                                ui.selectable_value(&mut self.audio_codec, AudioCodec::Mp3, "MP3");
-                                ui.selectable_value(
-                                    &mut self.audio_codec,
-                                    AudioCodec::Flac,
-                                    "FLAC",
-                                );
-                                ui.selectable_value(
-                                    &mut self.audio_codec,
-                                    AudioCodec::Pcm16,
-                                    "PCM (16-bit)",
-                                );
-                                ui.selectable_value(
-                                    &mut self.audio_codec,
-                                    AudioCodec::Copy,
-                                    "Copy",
+    fn render_benchmark_window(&mut self, ctx: &Context) {
+        if !self.show_benchmark {
+            return;
+        }
+
+        let mut open = true;
+        egui::Window::new("Benchmark")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                if self.benchmark_receiver.is_some() {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label(format!(
+                            "{} of {} done",
+                            self.benchmark_results.len(),
+                            self.benchmark_expected
+                        ));
+                    });
+                }
+
+                let metric = self
+                    .benchmark_results
+                    .first()
+                    .map_or("Quality", |result| result.metric.display_name());
+                egui::Grid::new("benchmark_grid")
+                    .num_columns(4)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for (sort, label) in [
+                            (BenchmarkSort::Label, "Settings"),
+                            (BenchmarkSort::Time, "Time"),
+                            (BenchmarkSort::Size, "Size"),
+                            (BenchmarkSort::Quality, metric),
+                        ] {
+                            let selected = self.benchmark_sort == sort;
+                            let arrow = match (selected, self.benchmark_sort_ascending) {
+                                (true, true) => " ⏶",
+                                (true, false) => " ⏷",
+                                (false, _) => "",
+                            };
+                            if ui
+                                .selectable_label(selected, format!("{label}{arrow}"))
+                                .clicked()
+                            {
+                                if selected {
+                                    self.benchmark_sort_ascending = !self.benchmark_sort_ascending;
+                                } else {
+                                    self.benchmark_sort = sort;
+                                    self.benchmark_sort_ascending = sort != BenchmarkSort::Quality;
+                                }
+                                sort_results(
+                                    &mut self.benchmark_results,
+                                    self.benchmark_sort,
+                                    self.benchmark_sort_ascending,
                                 );
-                            });
+                            }
+                        }
                         ui.end_row();
-                    }
-                });
 
-            if self.mode == ConversionMode::Convert {
-                ui.collapsing("Advanced", |ui| {
-                    egui::Grid::new("advanced_grid")
-                        .num_columns(2)
-                        .spacing(egui::vec2(8.0, 6.0))
-                        .show(ui, |ui| {
-                            ui.label("Video bitrate");
-                            ui.text_edit_singleline(&mut self.video_bitrate);
+                        for result in &self.benchmark_results {
+                            ui.label(&result.label);
+                            if let Some(error) = &result.error {
+                                ui.colored_label(Self::danger(ui), "failed")
+                                    .on_hover_text(error);
+                                ui.label("");
+                                ui.label("");
+                            } else {
+                                ui.label(format!("{:.1}s", result.elapsed.as_secs_f64()));
+                                ui.label(format!("{:.2} MB", result.size as f64 / 1_048_576.0));
+                                ui.label(result.quality.map_or("n/a".to_string(), |quality| {
+                                    match result.metric {
+                                        QualityMetric::Vmaf => format!("{quality:.1}"),
+                                        QualityMetric::Ssim => format!("{quality:.4}"),
+                                    }
+                                }));
+                            }
                             ui.end_row();
+                        }
+                    });
 
-                            ui.label("Audio bitrate");
-                            ui.text_edit_singleline(&mut self.audio_bitrate);
-                            ui.end_row();
+                ui.small(
+                    "Candidates run side by side when there are cores to spare, so times are \
+                     best compared with each other rather than with a normal conversion.",
+                );
+            });
+        self.show_benchmark = open;
+    }
 
-                            ui.label("Resolution");
-                            ui.text_edit_singleline(&mut self.resolution);
+    fn render_compare_window(&mut self, ctx: &Context) {
+        if !self.show_compare {
+            return;
+        }
+
+        let mut open = true;
+        egui::Window::new("Compare Files")
+            .open(&mut open)
+            .default_width(560.0)
+            .show(ctx, |ui| {
+                egui::Grid::new("compare_files_grid")
+                    .num_columns(3)
+                    .show(ui, |ui| {
+                        for (label, left_side) in [("A", true), ("B", false)] {
+                            let current = if left_side {
+                                self.compare_files.0.clone()
+                            } else {
+                                self.compare_files.1.clone()
+                            };
+                            ui.label(label);
+                            ui.label(
+                                current
+                                    .as_deref()
+                                    .map_or("No file chosen".to_string(), |path| {
+                                        Self::short_path(path)
+                                    }),
+                            )
+                            .on_hover_text(
+                                current
+                                    .as_deref()
+                                    .map(|path| path.display().to_string())
+                                    .unwrap_or_default(),
+                            );
+                            if ui.small_button("Choose...").clicked()
+                                && let Some(path) = self.pick_compare_file(current.as_deref())
+                            {
+                                if left_side {
+                                    self.compare_files.0 = Some(path);
+                                } else {
+                                    self.compare_files.1 = Some(path);
+                                }
+                                self.comparison = None;
+                            }
                             ui.end_row();
+                        }
+                    });
 
-                            ui.label("Frame rate");
-                            ui.text_edit_singleline(&mut self.frame_rate);
+                ui.horizontal(|ui| {
+                    let ready = self.compare_files.0.is_some()
+                        && self.compare_files.1.is_some()
+                        && self.compare_receiver.is_none();
+                    if ui
+                        .add_enabled(ready, egui::Button::new("Compare"))
+                        .clicked()
+                    {
+                        self.start_compare();
+                    }
+                    if ui.small_button("Swap").clicked() {
+                        let (left, right) = self.compare_files.clone();
+                        self.compare_files = (right, left);
+                        self.comparison = None;
+                    }
+                    if self.compare_receiver.is_some() {
+                        ui.spinner();
+                    }
+                });
+
+                let Some(comparison) = &self.comparison else {
+                    return;
+                };
+                ui.separator();
+                egui::Grid::new("compare_grid")
+                    .num_columns(4)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.label("");
+                        ui.label(RichText::new("A").strong());
+                        ui.label(RichText::new("B").strong());
+                        ui.label(RichText::new("Change").strong());
+                        ui.end_row();
+
+                        for row in &comparison.rows {
+                            ui.label(row.label);
+                            ui.label(&row.left);
+                            match &row.change {
+                                Some(change) => {
+                                    ui.label(RichText::new(&row.right).color(Self::accent(ui)));
+                                    ui.label(RichText::new(change).color(Self::accent(ui)));
+                                }
+                                None => {
+                                    ui.label(&row.right);
+                                    ui.label(RichText::new("unchanged").weak());
+                                }
+                            }
                             ui.end_row();
-                        });
+                        }
+                    });
+                ui.small(format!(
+                    "A: {}\nB: {}",
+                    comparison.left_file.display(),
+                    comparison.right_file.display()
+                ));
+            });
+        self.show_compare = open;
+    }
+
+    fn render_frame_compare_window(&mut self, ctx: &Context) {
+        // Hidden while a new test encode runs, then refreshed from it
+        if !self.show_frame_compare || self.preview_file.is_none() {
+            return;
+        }
+
+        let duration = self.preview_duration();
+        let mut open = true;
+        egui::Window::new("Compare Frames")
+            .open(&mut open)
+            .default_width(720.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Position");
+                    let response = ui.add(
+                        egui::Slider::new(&mut self.frame_position, 0.0..=duration)
+                            .suffix(" s")
+                            .fixed_decimals(1),
+                    );
+                    if response.drag_stopped() || (response.changed() && !response.dragged()) {
+                        self.start_frame_compare();
+                    }
+                    if self.frame_receiver.is_some() {
+                        ui.spinner();
+                    }
                 });
-            } else {
-                ui.label("Metadata");
-                ui.checkbox(
-                    &mut self.metadata_options.copy_file_metadata,
-                    "Copy file-level metadata",
-                );
-                ui.checkbox(&mut self.metadata_options.copy_chapters, "Copy chapters");
-                ui.checkbox(
-                    &mut self.metadata_options.copy_attachments,
-                    "Copy attachments (MKV)",
-                );
+                ui.horizontal(|ui| {
+                    ui.selectable_value(&mut self.frame_view, FrameView::Source, "Source");
+                    ui.selectable_value(&mut self.frame_view, FrameView::Encoded, "Encoded");
+                    ui.selectable_value(&mut self.frame_view, FrameView::Split, "Split");
+                });
+
+                if let Some(error) = &self.frame_error {
+                    ui.colored_label(Self::danger(ui), error);
+                }
+                let Some((position, [source, encoded])) = &self.frame_textures else {
+                    return;
+                };
+
+                let size = source.size_vec2();
+                let scale = (ui.available_width() / size.x).min(1.0);
+                let (rect, response) =
+                    ui.allocate_exact_size(size * scale, egui::Sense::click_and_drag());
+                let full = egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+                let painter = ui.painter_at(rect);
+                match self.frame_view {
+                    FrameView::Source => {
+                        painter.image(source.id(), rect, full, Color32::WHITE);
+                    }
+                    FrameView::Encoded => {
+                        painter.image(encoded.id(), rect, full, Color32::WHITE);
+                    }
+                    FrameView::Split => {
+                        if let Some(pointer) = response.interact_pointer_pos() {
+                            self.frame_split =
+                                ((pointer.x - rect.left()) / rect.width()).clamp(0.0, 1.0);
+                        }
+                        let divider = rect.left() + rect.width() * self.frame_split;
+                        painter.image(encoded.id(), rect, full, Color32::WHITE);
+                        painter.image(
+                            source.id(),
+                            egui::Rect::from_min_max(rect.min, egui::pos2(divider, rect.bottom())),
+                            egui::Rect::from_min_max(
+                                egui::pos2(0.0, 0.0),
+                                egui::pos2(self.frame_split, 1.0),
+                            ),
+                            Color32::WHITE,
+                        );
+                        painter.vline(divider, rect.y_range(), Stroke::new(2.0, Self::accent(ui)));
+                    }
+                }
+                // Clicking flips between the two, which shows small
+                // differences better than looking from one to the other
+                if response.clicked() {
+                    self.frame_view = match self.frame_view {
+                        FrameView::Source => FrameView::Encoded,
+                        FrameView::Encoded => FrameView::Source,
+                        FrameView::Split => FrameView::Split,
+                    };
+                }
+
+                ui.small(match self.frame_view {
+                    FrameView::Source => format!("Source at {}", format_duration(*position)),
+                    FrameView::Encoded => {
+                        format!("Test encode at {}", format_duration(*position))
+                    }
+                    FrameView::Split => format!(
+                        "Source left, test encode right, at {}; drag to move the divider",
+                        format_duration(*position)
+                    ),
+                });
+            });
+        self.show_frame_compare = open;
+    }
+
+    fn render_library_window(&mut self, ctx: &Context) {
+        if !self.show_library {
+            return;
+        }
 
+        let current = self.build_current_preset("Current".to_string());
+        let filter = self.library_filter.to_lowercase();
+        let mut open = true;
+        egui::Window::new("Library")
+            .open(&mut open)
+            .default_width(760.0)
+            .show(ctx, |ui| {
                 ui.horizontal(|ui| {
-                    ui.label("Video language");
-                    egui::ComboBox::from_id_salt("video_language")
-                        .selected_text(
-                            MetadataOptions::get_common_languages()
-                                .iter()
-                                .find(|(code, _)| *code == self.metadata_options.video_language)
-                                .map_or("Undetermined", |(_, name)| *name),
+                    if ui
+                        .add_enabled(
+                            self.library_receiver.is_none(),
+                            egui::Button::new("Scan Folder..."),
                         )
-                        .show_ui(ui, |ui| {
-                            for (code, name) in MetadataOptions::get_common_languages() {
-                                ui.selectable_value(
-                                    &mut self.metadata_options.video_language,
-                                    code.to_string(),
-                                    name,
-                                );
-                            }
-                        });
+                        .clicked()
+                    {
+                        self.start_library_scan();
+                    }
+                    ui.checkbox(&mut self.library_recursive, "Include subfolders");
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.library_filter)
+                            .hint_text("Filter by name, codec or verdict")
+                            .desired_width(200.0),
+                    );
+                    if self.library_receiver.is_some() {
+                        ui.spinner();
+                        ui.label(format!(
+                            "{} of {} probed",
+                            self.library_entries.len(),
+                            self.library_expected
+                        ));
+                    }
                 });
+                if self.library_truncated {
+                    ui.colored_label(
+                        Self::warning(ui),
+                        format!(
+                            "Only the first {} files were scanned",
+                            self.library_expected
+                        ),
+                    );
+                }
 
-                ui.horizontal(|ui| {
-                    ui.label("Audio language");
-                    egui::ComboBox::from_id_salt("audio_language")
-                        .selected_text(
-                            MetadataOptions::get_common_languages()
-                                .iter()
-                                .find(|(code, _)| *code == self.metadata_options.audio_language)
-                                .map_or("Undetermined", |(_, name)| *name),
-                        )
-                        .show_ui(ui, |ui| {
-                            for (code, name) in MetadataOptions::get_common_languages() {
-                                ui.selectable_value(
-                                    &mut self.metadata_options.audio_language,
-                                    code.to_string(),
-                                    name,
+                // Verdicts compare against the settings currently chosen
+                let verdict = |entry: &LibraryEntry| match &entry.info {
+                    Err(_) => (false, "unreadable".to_string()),
+                    Ok(info) if is_conversion_noop(&entry.path, info, &current) => {
+                        (false, "already matches".to_string())
+                    }
+                    Ok(_) => (true, entry.suggestion.clone().unwrap_or_default()),
+                };
+                let shown: Vec<usize> = (0..self.library_entries.len())
+                    .filter(|&index| {
+                        let entry = &self.library_entries[index];
+                        filter.is_empty()
+                            || [
+                                entry
+                                    .path
+                                    .file_name()
+                                    .map(|name| name.to_string_lossy().to_string())
+                                    .unwrap_or_default(),
+                                entry.container().to_string(),
+                                entry.video_codec().to_string(),
+                                entry.audio_codec().to_string(),
+                                verdict(entry).1,
+                            ]
+                            .iter()
+                            .any(|field| field.to_lowercase().contains(&filter))
+                    })
+                    .collect();
+
+                ScrollArea::vertical().max_height(420.0).show(ui, |ui| {
+                    egui::Grid::new("library_grid")
+                        .num_columns(8)
+                        .striped(true)
+                        .show(ui, |ui| {
+                            ui.label("");
+                            for (sort, label) in [
+                                (LibrarySort::Name, "File"),
+                                (LibrarySort::Container, "Container"),
+                                (LibrarySort::Video, "Codecs"),
+                                (LibrarySort::Resolution, "Resolution"),
+                                (LibrarySort::Bitrate, "Bitrate"),
+                                (LibrarySort::Size, "Size"),
+                            ] {
+                                let selected = self.library_sort == sort;
+                                let arrow = match (selected, self.library_sort_ascending) {
+                                    (true, true) => " ⏶",
+                                    (true, false) => " ⏷",
+                                    (false, _) => "",
+                                };
+                                if ui
+                                    .selectable_label(selected, format!("{label}{arrow}"))
+                                    .clicked()
+                                {
+                                    if selected {
+                                        self.library_sort_ascending = !self.library_sort_ascending;
+                                    } else {
+                                        self.library_sort = sort;
+                                        self.library_sort_ascending = true;
+                                    }
+                                    sort_entries(
+                                        &mut self.library_entries,
+                                        self.library_sort,
+                                        self.library_sort_ascending,
+                                    );
+                                }
+                            }
+                            ui.label("Verdict");
+                            ui.end_row();
+
+                            for &index in &shown {
+                                let entry = &self.library_entries[index];
+                                let mut selected = self.library_selected.contains(&entry.path);
+                                if ui.checkbox(&mut selected, "").changed() {
+                                    if selected {
+                                        self.library_selected.insert(entry.path.clone());
+                                    } else {
+                                        self.library_selected.remove(&entry.path);
+                                    }
+                                }
+                                let name = entry
+                                    .path
+                                    .file_name()
+                                    .map(|name| name.to_string_lossy().to_string())
+                                    .unwrap_or_default();
+                                ui.label(Self::ellipsize(&name, 40))
+                                    .on_hover_text(entry.path.display().to_string());
+                                ui.label(entry.container());
+                                ui.label(format!(
+                                    "{} / {}",
+                                    entry.video_codec(),
+                                    entry.audio_codec()
+                                ));
+                                ui.label(
+                                    entry.resolution().map_or(String::new(), |(width, height)| {
+                                        format!("{width}x{height}")
+                                    }),
+                                );
+                                ui.label(
+                                    entry.bit_rate().map_or(String::new(), |rate| {
+                                        format!("{} kb/s", rate / 1000)
+                                    }),
                                 );
+                                ui.label(format!("{:.1} MB", entry.size as f64 / 1_048_576.0));
+                                let (worth_converting, text) = verdict(entry);
+                                match &entry.info {
+                                    Err(error) => {
+                                        ui.colored_label(Self::danger(ui), text)
+                                            .on_hover_text(error);
+                                    }
+                                    Ok(_) if worth_converting => {
+                                        ui.colored_label(Self::accent(ui), text);
+                                    }
+                                    Ok(_) => {
+                                        ui.colored_label(Self::success(ui), text);
+                                    }
+                                }
+                                ui.end_row();
                             }
                         });
                 });
 
                 ui.horizontal(|ui| {
-                    ui.label("Video title");
-                    ui.text_edit_singleline(&mut self.metadata_options.video_title);
-                });
-
-                ui.horizontal(|ui| {
-                    ui.label("Audio title");
-                    ui.text_edit_singleline(&mut self.metadata_options.audio_title);
+                    if ui.button("Select Shown").clicked() {
+                        for &index in &shown {
+                            self.library_selected
+                                .insert(self.library_entries[index].path.clone());
+                        }
+                    }
+                    if ui
+                        .button("Select Worth Converting")
+                        .on_hover_text("Files that don't already match the current settings")
+                        .clicked()
+                    {
+                        for &index in &shown {
+                            let entry = &self.library_entries[index];
+                            if verdict(entry).0 {
+                                self.library_selected.insert(entry.path.clone());
+                            }
+                        }
+                    }
+                    if ui.button("Select None").clicked() {
+                        self.library_selected.clear();
+                    }
+                    if ui
+                        .add_enabled(
+                            !self.library_selected.is_empty(),
+                            egui::Button::new(format!(
+                                "Add {} to Batch",
+                                self.library_selected.len()
+                            )),
+                        )
+                        .clicked()
+                    {
+                        let mut selected: Vec<PathBuf> = self.library_selected.drain().collect();
+                        selected.sort();
+                        self.queue_batch_files(selected);
+                    }
                 });
-            }
-        });
+            });
+        self.show_library = open;
     }
 
-    fn render_progress_panel(&mut self, ui: &mut egui::Ui) {
-        Self::section_card(ui, "Progress", |ui| {
-            if let Some(progress) = &self.progress {
-                ui.add(
-                    egui::ProgressBar::new(progress.percentage / 100.0)
-                        .desired_width(ui.available_width())
-                        .text(format!("{:.1}%", progress.percentage)),
-                );
-
-                let mut info = format!("{} / {}", progress.current_time, progress.total_time);
-                if let Some(remaining) = progress.time_remaining {
-                    let remaining_secs = remaining.as_secs();
-                    let minutes = remaining_secs / 60;
-                    let seconds = remaining_secs % 60;
-                    info.push_str(&format!(" | ETA {minutes:02}:{seconds:02}"));
-                }
-                ui.label(info);
-            } else {
-                let output_file_preview = if self.output_file_name.trim().is_empty() {
-                    self.input_file
-                        .as_ref()
-                        .map(|f| generate_output_filename(f, &self.video_format))
-                        .and_then(|p| p.file_name().map(|n| n.to_string_lossy().to_string()))
-                        .unwrap_or_else(|| format!("output.{}", self.video_format.extension()))
-                } else {
-                    self.output_filename_for_container()
-                };
+    fn render_noop_dialog(&mut self, ctx: &Context) {
+        if !self.show_noop_dialog {
+            return;
+        }
 
-                let input = self
-                    .input_file
-                    .as_deref()
-                    .map(Self::short_path)
-                    .unwrap_or_else(|| "none".to_string());
-                let output = self
-                    .output_folder
-                    .as_deref()
-                    .map(Self::short_path)
-                    .unwrap_or_else(|| "none".to_string());
-                ui.label(format!("Input: {}", Self::ellipsize(&input, 80)));
-                ui.label(format!("Output: {}", Self::ellipsize(&output, 80)));
-                ui.label(format!(
-                    "File: {}",
-                    Self::ellipsize(&output_file_preview, 80)
-                ));
-                ui.label("Ready");
-            }
+        egui::Window::new("Already in the target format")
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.label(
+                    "The input already uses this container and these codecs. Re-encoding it \
+                     would take time and lose quality without changing the format.",
+                );
+                ui.add_space(8.0);
 
-            if self.is_converting && ui.button("Stop Conversion").clicked() {
-                self.stop_conversion();
-            }
-        });
+                ui.horizontal(|ui| {
+                    if ui.button("Remux instead").clicked() {
+                        self.show_noop_dialog = false;
+                        self.mode = ConversionMode::Remux;
+                        self.start_conversion_confirmed();
+                    }
+                    if ui.button("Convert anyway").clicked() {
+                        self.show_noop_dialog = false;
+                        self.start_conversion_confirmed();
+                    }
+                    if ui.button("Skip").clicked() {
+                        self.show_noop_dialog = false;
+                        self.status_message =
+                            "Skipped: input already matches the output settings".to_string();
+                    }
+                });
+            });
     }
 
-    fn render_actions_panel(&mut self, ui: &mut egui::Ui) {
-        Self::section_card(ui, "Actions", |ui| {
-            let start_label = if self.is_converting {
-                "Converting..."
-            } else {
-                "Start Conversion"
-            };
-
-            let button = egui::Button::new(RichText::new(start_label).strong())
-                .min_size(egui::vec2(170.0, 26.0))
-                .fill(Color32::from_rgb(30, 30, 38))
-                .stroke(Stroke::new(1.0, Self::accent()));
-
-            ui.horizontal_wrapped(|ui| {
-                if ui
-                    .add_enabled(self.can_start_conversion(), button)
-                    .clicked()
-                {
-                    self.start_conversion();
-                }
+    fn render_overwrite_dialog(&mut self, ctx: &Context) {
+        let Some((input_file, output_file)) = self.pending_overwrite.clone() else {
+            return;
+        };
 
-                if ui.button("Help").clicked() {
-                    self.show_help_dialog = true;
+        let batch_item = self.batch.running_index().is_some();
+        egui::Window::new("Replace existing file?")
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                let existing = self.existing_outputs(&output_file);
+                if existing.len() > 1 {
+                    ui.label(format!(
+                        "{} files of the output already exist and will be replaced:",
+                        existing.len()
+                    ));
+                } else {
+                    ui.label("The output already exists and will be replaced:");
                 }
-                if ui.button("About").clicked() {
-                    self.show_about_dialog = true;
+                for file in existing.iter().take(MAX_LISTED_OVERWRITES) {
+                    ui.label(RichText::new(file.display().to_string()).monospace());
                 }
-                if ui.button("Check Updates").clicked() {
-                    self.check_for_updates();
+                if existing.len() > MAX_LISTED_OVERWRITES {
+                    ui.label(format!(
+                        "and {} more",
+                        existing.len() - MAX_LISTED_OVERWRITES
+                    ));
                 }
-                if self.is_converting && ui.button("Stop").clicked() {
-                    self.stop_conversion();
+                ui.add_space(8.0);
+                ui.checkbox(&mut self.overwrite_dont_ask, "Don't ask again this session");
+
+                let mut choice = None;
+                ui.horizontal(|ui| {
+                    if ui.button("Overwrite").clicked() {
+                        choice = Some(ExistingOutput::Overwrite);
+                    }
+                    if ui
+                        .button("Rename")
+                        .on_hover_text(format!(
+                            "Saves as {}",
+                            Self::short_path(&self.free_output_name(&output_file))
+                        ))
+                        .clicked()
+                    {
+                        choice = Some(ExistingOutput::Rename);
+                    }
+                    if batch_item {
+                        if ui
+                            .button("Skip")
+                            .on_hover_text("Go on with the next file")
+                            .clicked()
+                        {
+                            choice = Some(ExistingOutput::Skip);
+                        }
+                        if ui.button("Stop batch").clicked() {
+                            self.pending_overwrite = None;
+                            self.batch.requeue_running();
+                            self.batch.stop();
+                            self.persist_batch();
+                            self.status_message =
+                                "Batch stopped: output already exists".to_string();
+                        }
+                    } else if ui.button("Cancel").clicked() {
+                        self.pending_overwrite = None;
+                        self.status_message = "Cancelled: output already exists".to_string();
+                    }
+                });
+
+                if let Some(choice) = choice {
+                    self.pending_overwrite = None;
+                    if self.overwrite_dont_ask {
+                        self.existing_output_choice = Some(choice);
+                    }
+                    self.resolve_existing_output(input_file.clone(), output_file.clone(), choice);
                 }
             });
-        });
     }
 
-    fn render_status_bar(&mut self, ctx: &Context) {
-        TopBottomPanel::bottom("status_panel").show(ctx, |ui| {
-            ui.horizontal_wrapped(|ui| {
-                ui.label(RichText::new("Status").strong());
-                ui.label(&self.status_message);
-                if let Some(error) = &self.error_message {
-                    ui.colored_label(Self::danger(), format!("Error: {error}"));
-                    if ui.button("Clear").clicked() {
-                        self.error_message = None;
+    fn render_trash_source_dialog(&mut self, ctx: &Context) {
+        if !self.show_trash_source_dialog {
+            return;
+        }
+
+        egui::Window::new("Move sources to the trash?")
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.label(
+                    "After each successful conversion the input file is moved to the trash, \
+                     once its output has been checked to be readable and complete. This \
+                     lasts until the app is closed.",
+                );
+                ui.add_space(8.0);
+
+                ui.horizontal(|ui| {
+                    if ui.button("Enable").clicked() {
+                        self.show_trash_source_dialog = false;
+                        self.trash_source_on_success = true;
                     }
-                }
+                    if ui.button("Cancel").clicked() {
+                        self.show_trash_source_dialog = false;
+                    }
+                });
             });
-        });
     }
 
-    fn render_help_dialog(&mut self, ctx: &Context) {
-        if !self.show_help_dialog {
+    fn render_reset_dialog(&mut self, ctx: &Context) {
+        if !self.show_reset_dialog {
             return;
         }
 
-        egui::Window::new("Help and Diagnostics")
+        egui::Window::new("Reset everything?")
             .resizable(false)
             .collapsible(false)
-            .default_width(500.0)
             .show(ctx, |ui| {
-                if ui.button("Check FFmpeg").clicked() {
-                    self.check_ffmpeg();
-                }
+                ui.label(
+                    "A conversion is running. Resetting stops it and the batch, and deletes \
+                     the unfinished output.",
+                );
+                ui.add_space(8.0);
 
-                if let Some(status) = &self.ffmpeg_status {
-                    match status {
-                        Ok(version) => {
-                            ui.colored_label(Self::success(), version);
-                        }
-                        Err(err) => {
-                            ui.colored_label(Self::danger(), err);
-                        }
+                ui.horizontal(|ui| {
+                    if ui.button("Stop and Reset").clicked() {
+                        self.show_reset_dialog = false;
+                        self.reset_all();
                     }
-                }
+                    if ui.button("Cancel").clicked() {
+                        self.show_reset_dialog = false;
+                    }
+                });
+            });
+    }
 
-                ui.separator();
+    fn open_palette(&mut self) {
+        self.show_palette = true;
+        self.palette_query.clear();
+        self.palette_selected = 0;
+    }
 
-                if ui.button("Check for Updates").clicked() {
-                    self.check_for_updates();
+    fn render_palette(&mut self, ctx: &Context) {
+        if !self.show_palette {
+            return;
+        }
+
+        // Taken before the text field sees them, so they steer the list
+        let (up, down, enter, escape) = ctx.input_mut(|input| {
+            (
+                input.consume_key(Modifiers::NONE, Key::ArrowUp),
+                input.consume_key(Modifiers::NONE, Key::ArrowDown),
+                input.consume_key(Modifiers::NONE, Key::Enter),
+                input.consume_key(Modifiers::NONE, Key::Escape),
+            )
+        });
+        if escape {
+            self.show_palette = false;
+            return;
+        }
+
+        let entries = palette_entries(&self.preset_manager, &self.palette_query);
+        if down {
+            self.palette_selected += 1;
+        }
+        if up {
+            self.palette_selected = self.palette_selected.saturating_sub(1);
+        }
+        self.palette_selected = self.palette_selected.min(entries.len().saturating_sub(1));
+
+        let mut chosen = enter
+            .then(|| entries.get(self.palette_selected))
+            .flatten()
+            .map(|entry| entry.action.clone());
+        egui::Window::new("Command palette")
+            .title_bar(false)
+            .resizable(false)
+            .collapsible(false)
+            .anchor(egui::Align2::CENTER_TOP, [0.0, 60.0])
+            .fixed_size([420.0, 0.0])
+            .show(ctx, |ui| {
+                let response = ui.add(
+                    egui::TextEdit::singleline(&mut self.palette_query)
+                        .hint_text("Search presets and actions")
+                        .desired_width(f32::INFINITY),
+                );
+                response.request_focus();
+                if response.changed() {
+                    self.palette_selected = 0;
                 }
+                ui.separator();
 
-                if let Some(status) = self.update_status.clone() {
-                    match status {
-                        UpdateStatus::CheckingForUpdates => {
-                            ui.label("Checking for updates...");
+                if entries.is_empty() {
+                    ui.label(RichText::new("No matches").small());
+                }
+                for (index, entry) in entries.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        if ui
+                            .selectable_label(index == self.palette_selected, &entry.label)
+                            .clicked()
+                        {
+                            chosen = Some(entry.action.clone());
                         }
-                        UpdateStatus::UpdateAvailable(info) => {
-                            ui.colored_label(
-                                Self::success(),
-                                format!("Update available: v{}", info.version),
-                            );
-                            if ui.button("Download and Install").clicked() {
-                                self.start_update_download(info.clone());
-                            }
+                        ui.label(RichText::new(entry.kind).small().weak());
+                    });
+                }
+            });
 
-                            ui.add_space(6.0);
-                            ui.label(RichText::new("Release Notes").strong());
-                            ScrollArea::vertical().max_height(180.0).show(ui, |ui| {
-                                ui.label(info.release_notes.clone());
-                            });
-                        }
-                        UpdateStatus::NoUpdateAvailable => {
-                            ui.label("You already have the latest version.");
-                        }
-                        UpdateStatus::DownloadingUpdate(progress) => {
-                            ui.label(format!("Downloading update: {progress:.1}%"));
-                            ui.add(egui::ProgressBar::new(progress / 100.0));
-                        }
-                        UpdateStatus::InstallingUpdate => {
-                            ui.label("Installing update...");
-                            ui.spinner();
-                        }
-                        UpdateStatus::Error(error) => {
-                            ui.colored_label(Self::danger(), error);
-                        }
+        if let Some(action) = chosen {
+            self.show_palette = false;
+            self.run_palette_action(action);
+        }
+    }
+
+    fn run_palette_action(&mut self, action: PaletteAction) {
+        match action {
+            PaletteAction::ApplyPreset(name) => self.apply_preset(&name),
+            PaletteAction::SetMode(mode) => {
+                self.status_message = format!(
+                    "Mode: {}",
+                    match mode {
+                        ConversionMode::Convert => "Convert",
+                        ConversionMode::Remux => "Remux",
                     }
+                );
+                self.mode = mode;
+            }
+            PaletteAction::SelectInput => self.select_input_file(),
+            PaletteAction::SelectOutput => self.select_output_folder(),
+            PaletteAction::StartConversion | PaletteAction::TestConversion
+                if !self.can_start_conversion() =>
+            {
+                self.status_message =
+                    "Can't start: finish the settings or wait for the running conversion"
+                        .to_string();
+            }
+            PaletteAction::StartConversion => self.start_conversion(),
+            PaletteAction::TestConversion => self.start_test_conversion(),
+            PaletteAction::StopConversion => {
+                if self.conversion_state.is_converting() {
+                    self.stop_conversion();
                 }
-
-                ui.separator();
-                if ui.button("Close").clicked() {
-                    self.show_help_dialog = false;
+            }
+            PaletteAction::Benchmark => {
+                if self.can_start_conversion()
+                    && self.benchmark_receiver.is_none()
+                    && self.sequence_framerate.is_none()
+                {
+                    self.start_benchmark();
                 }
-            });
+            }
+            PaletteAction::AddBatchFiles => self.add_batch_files(),
+            PaletteAction::Compare => self.open_compare(),
+            PaletteAction::ShowHelp => self.show_help_dialog = true,
+            PaletteAction::CheckUpdates => self.check_for_updates(),
+        }
     }
 
     fn render_about_dialog(&mut self, ctx: &Context) {
@@ -1061,9 +6076,43 @@ impl FFmpegApp {
 impl eframe::App for FFmpegApp {
     fn update(&mut self, ctx: &Context, _frame: &mut eframe::Frame) {
         self.initialize_style(ctx);
-        self.check_conversion_progress();
+        self.check_conversion_progress(ctx);
+        self.check_probe_results();
+        self.check_filmstrip_results(ctx);
+        self.check_subtitle_results();
+        self.check_crop_results();
+        self.check_interlace_results();
+        self.check_benchmark_results();
+        self.check_library_results();
+        self.check_compare_results();
+        self.check_frame_compare_results(ctx);
+        self.check_diagnostics_results();
+        self.check_cover_results();
+        self.check_join_results();
+        self.check_device_results();
+        self.check_encoder_results();
         self.poll_async_updates();
 
+        // Kept current every frame; only saved by the autosave and on exit
+        if let Some(rect) = ctx.input(|input| input.viewport().inner_rect) {
+            self.config.window_width = rect.width();
+            self.config.window_height = rect.height();
+        }
+        if self
+            .autosaved_at
+            .is_none_or(|at| at.elapsed() >= AUTOSAVE_INTERVAL)
+        {
+            self.autosave();
+        }
+
+        if ctx.input_mut(|input| input.consume_shortcut(&PALETTE_SHORTCUT)) {
+            if self.show_palette {
+                self.show_palette = false;
+            } else {
+                self.open_palette();
+            }
+        }
+
         TopBottomPanel::top("top_header").show(ctx, |ui| {
             ui.horizontal_wrapped(|ui| {
                 ui.label(RichText::new("FFmpeg Rust").strong());
@@ -1071,16 +6120,17 @@ impl eframe::App for FFmpegApp {
                     ConversionMode::Convert => "Mode: Convert",
                     ConversionMode::Remux => "Mode: Remux",
                 };
-                let state_label = if self.is_converting {
+                let state_label = if self.conversion_state.is_converting() {
                     "State: Running"
                 } else {
                     "State: Idle"
                 };
 
-                ui.label(RichText::new(mode_label).color(Self::accent()).strong());
-                ui.label(RichText::new(state_label).color(Self::accent()).italics());
+                ui.label(RichText::new(mode_label).color(Self::accent(ui)).strong());
+                ui.label(RichText::new(state_label).color(Self::accent(ui)).italics());
             });
         });
+        self.render_update_banner(ctx);
 
         self.render_status_bar(ctx);
 
@@ -1093,6 +6143,8 @@ impl eframe::App for FFmpegApp {
                     self.render_media_panel(ui);
                     ui.add_space(6.0);
                     self.render_presets_panel(ui);
+                    ui.add_space(6.0);
+                    self.render_batch_panel(ui);
                 });
             });
 
@@ -1110,15 +6162,46 @@ impl eframe::App for FFmpegApp {
 
         self.render_help_dialog(ctx);
         self.render_about_dialog(ctx);
-
-        if self.is_converting
-            || matches!(self.update_status, Some(UpdateStatus::DownloadingUpdate(_)))
+        self.render_noop_dialog(ctx);
+        self.render_overwrite_dialog(ctx);
+        self.render_benchmark_window(ctx);
+        self.render_library_window(ctx);
+        self.render_compare_window(ctx);
+        self.render_frame_compare_window(ctx);
+        self.render_reset_dialog(ctx);
+        self.render_trash_source_dialog(ctx);
+        self.render_resume_batch_dialog(ctx);
+        self.render_palette(ctx);
+
+        if self.probe_receiver.is_some()
+            || self.filmstrip_receiver.is_some()
+            || self.subtitle_receiver.is_some()
+            || self.crop_receiver.is_some()
+            || self.interlace_receiver.is_some()
+            || self.benchmark_receiver.is_some()
+            || self.library_receiver.is_some()
+            || self.compare_receiver.is_some()
+            || self.frame_receiver.is_some()
+            || self.diagnostics_receiver.is_some()
+            || self.cover_receiver.is_some()
+            || self.join_receiver.is_some()
+            || self.device_receiver.is_some()
+            || self.encoder_receiver.is_some()
+            || matches!(
+                self.update_status,
+                Some(UpdateStatus::CheckingForUpdates | UpdateStatus::DownloadingUpdate(_))
+            )
         {
             ctx.request_repaint_after(Duration::from_millis(100));
+        } else if self.conversion_state.is_converting() {
+            ctx.request_repaint_after(self.config.progress_update_interval());
         }
     }
 
     fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        self.cancel_for_shutdown();
+        self.clear_preview();
+        self.autosave();
         self.config.save();
     }
 }
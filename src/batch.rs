@@ -0,0 +1,686 @@
+// Queue of files converted one after another with the current settings
+
+use crate::config::config_location;
+use crate::conversion::unplanned_name;
+use crate::presets::{ConversionMode, ConversionPreset, VideoCodec};
+use crate::segments::Segment;
+use crate::validation::sanitize_filename;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::path::{Path, PathBuf};
+
+// Failed files named in the summary; the rest are only counted
+const MAX_SUMMARY_FAILURES: usize = 3;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum BatchStatus {
+    Pending,
+    Running,
+    Done,
+    Failed(String),
+    Cancelled,
+}
+
+impl BatchStatus {
+    pub fn label(&self) -> &str {
+        match self {
+            BatchStatus::Pending => "Pending",
+            BatchStatus::Running => "Running",
+            BatchStatus::Done => "Done",
+            BatchStatus::Failed(_) => "Failed",
+            BatchStatus::Cancelled => "Cancelled",
+        }
+    }
+}
+
+/// What a batch does when one of its items fails
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+pub enum BatchFailurePolicy {
+    /// Carry on with the rest and list the failures at the end
+    #[default]
+    ContinueOnError,
+    /// Cancel everything still pending, e.g. to catch a bad setting early
+    StopOnFirstFailure,
+}
+
+impl BatchFailurePolicy {
+    pub fn label(&self) -> &'static str {
+        match self {
+            BatchFailurePolicy::ContinueOnError => "Continue on error",
+            BatchFailurePolicy::StopOnFirstFailure => "Stop on first failure",
+        }
+    }
+
+    pub fn all() -> [BatchFailurePolicy; 2] {
+        [
+            BatchFailurePolicy::ContinueOnError,
+            BatchFailurePolicy::StopOnFirstFailure,
+        ]
+    }
+}
+
+/// Pending items run highest priority first, and in queue order within a
+/// priority
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Default)]
+pub enum BatchPriority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl BatchPriority {
+    pub fn label(&self) -> &'static str {
+        match self {
+            BatchPriority::Low => "Low",
+            BatchPriority::Normal => "Normal",
+            BatchPriority::High => "High",
+        }
+    }
+
+    pub fn all() -> [BatchPriority; 3] {
+        [
+            BatchPriority::High,
+            BatchPriority::Normal,
+            BatchPriority::Low,
+        ]
+    }
+
+    /// Priority for items queued with `preset`: copies finish in a fraction
+    /// of an encode's time, so they go first
+    pub fn for_preset(preset: &ConversionPreset) -> Self {
+        if preset.mode == ConversionMode::Remux
+            || preset.video_codec == VideoCodec::Copy
+            || preset.disable_video
+        {
+            BatchPriority::High
+        } else {
+            BatchPriority::Normal
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchItem {
+    pub input_file: PathBuf,
+    pub output_file: Option<PathBuf>,
+    pub status: BatchStatus,
+    /// Only this part of the input is converted
+    #[serde(default)]
+    pub segment: Option<Segment>,
+    #[serde(default)]
+    pub priority: BatchPriority,
+    /// Set by hand, so the settings no longer decide it
+    #[serde(default)]
+    pub manual_priority: bool,
+    /// Order the item was queued in, from 1; unlike its position it doesn't
+    /// change when items are reordered, so output names don't either
+    #[serde(default)]
+    pub sequence: usize,
+}
+
+/// Segment clips to be joined into one file once they have all converted
+#[derive(Debug, Clone)]
+pub struct PendingJoin {
+    pub items: Vec<usize>,
+    pub output_file: PathBuf,
+}
+
+#[derive(Debug, Default)]
+pub struct BatchQueue {
+    pub items: Vec<BatchItem>,
+    pub failure_policy: BatchFailurePolicy,
+    active: bool,
+    /// Not saved with the queue; a resumed batch keeps the separate clips
+    join: Option<PendingJoin>,
+}
+
+impl BatchQueue {
+    /// Adds a file unless it is already queued, behind the pending items of
+    /// the same or a higher priority
+    pub fn add(&mut self, input_file: PathBuf, priority: BatchPriority) -> bool {
+        if self.items.iter().any(|item| item.input_file == input_file) {
+            return false;
+        }
+
+        let sequence = self.next_sequence();
+        self.items.push(BatchItem {
+            input_file,
+            output_file: None,
+            status: BatchStatus::Pending,
+            segment: None,
+            priority,
+            manual_priority: false,
+            sequence,
+        });
+        self.place_by_priority(self.items.len() - 1);
+        true
+    }
+
+    /// Queues one item per segment of `input_file`, returning their indices
+    pub fn add_segments(
+        &mut self,
+        input_file: &Path,
+        segments: Vec<Segment>,
+        priority: BatchPriority,
+    ) -> Vec<usize> {
+        // Each clip lands right behind the previous one, so placing a clip
+        // never moves those already placed
+        segments
+            .into_iter()
+            .map(|segment| {
+                let sequence = self.next_sequence();
+                self.items.push(BatchItem {
+                    input_file: input_file.to_path_buf(),
+                    output_file: None,
+                    status: BatchStatus::Pending,
+                    segment: Some(segment),
+                    priority,
+                    manual_priority: false,
+                    sequence,
+                });
+                self.place_by_priority(self.items.len() - 1)
+            })
+            .collect()
+    }
+
+    fn next_sequence(&self) -> usize {
+        self.items
+            .iter()
+            .map(|item| item.sequence)
+            .max()
+            .unwrap_or(0)
+            + 1
+    }
+
+    /// Which clip of its input a segment item is, from 1 in the order the
+    /// clips were queued; `None` for whole files
+    pub fn clip_number(&self, index: usize) -> Option<usize> {
+        let item = self.items.get(index)?;
+        item.segment.as_ref()?;
+        let earlier = self
+            .items
+            .iter()
+            .filter(|other| {
+                other.input_file == item.input_file
+                    && other.segment.is_some()
+                    && other.sequence < item.sequence
+            })
+            .count();
+        Some(earlier + 1)
+    }
+
+    /// Output file of every item, in queue order. Started items keep the one
+    /// they were given; the rest get `plan(index)`, numbered like
+    /// `movie_2.mp4` when an item queued earlier already writes that file.
+    /// Two inputs with the same name can't overwrite each other this way, and
+    /// reordering the queue doesn't rename anything.
+    pub fn plan_outputs(&self, plan: impl Fn(usize) -> PathBuf) -> Vec<PathBuf> {
+        let mut outputs: Vec<Option<PathBuf>> = self
+            .items
+            .iter()
+            .map(|item| item.output_file.clone())
+            .collect();
+        let mut taken: Vec<PathBuf> = outputs.iter().flatten().cloned().collect();
+
+        let mut unplanned: Vec<usize> = (0..self.items.len())
+            .filter(|&index| outputs[index].is_none())
+            .collect();
+        unplanned.sort_by_key(|&index| self.items[index].sequence);
+        for index in unplanned {
+            let mut output = plan(index);
+            if taken.contains(&output) {
+                output = unplanned_name(&output, &taken);
+            }
+            taken.push(output.clone());
+            outputs[index] = Some(output);
+        }
+        outputs.into_iter().flatten().collect()
+    }
+
+    /// Changes a pending item's priority and moves it to where it now runs
+    pub fn set_priority(&mut self, index: usize, priority: BatchPriority) {
+        if self.is_pending(index) {
+            self.items[index].priority = priority;
+            self.items[index].manual_priority = true;
+            self.place_by_priority(index);
+        }
+    }
+
+    /// Gives every pending item whose priority wasn't set by hand the one
+    /// the settings call for now, since they can change after queueing
+    pub fn refresh_priorities(&mut self, priority: BatchPriority) {
+        loop {
+            let stale = |i: usize| {
+                self.is_pending(i)
+                    && !self.items[i].manual_priority
+                    && self.items[i].priority != priority
+            };
+            // Rising items are placed front to back and falling ones back to
+            // front, so those that end up sharing a priority keep their order
+            let Some(index) = (0..self.items.len())
+                .find(|&i| stale(i) && self.items[i].priority < priority)
+                .or_else(|| (0..self.items.len()).rev().find(|&i| stale(i)))
+            else {
+                break;
+            };
+            self.items[index].priority = priority;
+            self.place_by_priority(index);
+        }
+    }
+
+    // Keeps the pending items listed in the order they will run; returns
+    // where the item ended up
+    fn place_by_priority(&mut self, mut index: usize) -> usize {
+        let priority = self.items[index].priority;
+        while let Some(&target) = self.pending_before(index).last()
+            && self.items[target].priority < priority
+        {
+            self.move_item(index, target);
+            index = target;
+        }
+        while let Some(target) = (index + 1..self.items.len()).find(|&i| self.is_pending(i))
+            && self.items[target].priority > priority
+        {
+            self.move_item(index, target);
+            index = target;
+        }
+        index
+    }
+
+    pub fn set_join(&mut self, join: Option<PendingJoin>) {
+        self.join = join;
+    }
+
+    /// The join to run once the queue is done, if one was requested
+    pub fn take_join(&mut self) -> Option<PendingJoin> {
+        self.join.take()
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if self
+            .items
+            .get(index)
+            .is_some_and(|item| item.status != BatchStatus::Running)
+        {
+            self.items.remove(index);
+            // Indices shift, so a pending join no longer lines up
+            self.join = None;
+        }
+    }
+
+    /// Moves a pending item ahead of the pending item before it, raising its
+    /// priority to that item's if needed
+    pub fn move_up(&mut self, index: usize) {
+        if let Some(&target) = self.pending_before(index).last() {
+            let priority = self.items[index].priority.max(self.items[target].priority);
+            self.items[index].priority = priority;
+            self.items[index].manual_priority = true;
+            self.move_item(index, target);
+        }
+    }
+
+    /// Moves a pending item behind the pending item after it, lowering its
+    /// priority to that item's if needed
+    pub fn move_down(&mut self, index: usize) {
+        if !self.is_pending(index) {
+            return;
+        }
+        if let Some(target) = (index + 1..self.items.len()).find(|&i| self.is_pending(i)) {
+            let priority = self.items[index].priority.min(self.items[target].priority);
+            self.items[index].priority = priority;
+            self.items[index].manual_priority = true;
+            self.move_item(index, target);
+        }
+    }
+
+    /// Makes a pending item the next one to run
+    pub fn move_to_front(&mut self, index: usize) {
+        if let Some(&target) = self.pending_before(index).first() {
+            let priority = self.items[index].priority.max(self.items[target].priority);
+            self.items[index].priority = priority;
+            self.items[index].manual_priority = true;
+            self.move_item(index, target);
+        }
+    }
+
+    fn is_pending(&self, index: usize) -> bool {
+        self.items
+            .get(index)
+            .is_some_and(|item| item.status == BatchStatus::Pending)
+    }
+
+    // Pending items ahead of `index`, or nothing if it isn't pending itself.
+    // Started and finished items never move, so the running one is unaffected.
+    fn pending_before(&self, index: usize) -> Vec<usize> {
+        if !self.is_pending(index) {
+            return Vec::new();
+        }
+        (0..index).filter(|&i| self.is_pending(i)).collect()
+    }
+
+    fn move_item(&mut self, from: usize, to: usize) {
+        let item = self.items.remove(from);
+        self.items.insert(to, item);
+
+        if let Some(join) = &mut self.join {
+            for index in &mut join.items {
+                *index = if *index == from {
+                    to
+                } else if from < to && (from + 1..=to).contains(index) {
+                    *index - 1
+                } else if to < from && (to..from).contains(index) {
+                    *index + 1
+                } else {
+                    *index
+                };
+            }
+        }
+    }
+
+    /// Drops everything except the item currently converting
+    pub fn clear(&mut self) {
+        self.items
+            .retain(|item| item.status == BatchStatus::Running);
+        self.join = None;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub fn start(&mut self) {
+        self.active = true;
+    }
+
+    pub fn stop(&mut self) {
+        self.active = false;
+    }
+
+    pub fn pending_count(&self) -> usize {
+        self.items
+            .iter()
+            .filter(|item| item.status == BatchStatus::Pending)
+            .count()
+    }
+
+    /// The highest priority pending item, the earliest queued among equals
+    pub fn next_pending(&self) -> Option<usize> {
+        self.items
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| item.status == BatchStatus::Pending)
+            .max_by_key(|(index, item)| (item.priority, Reverse(*index)))
+            .map(|(index, _)| index)
+    }
+
+    pub fn running_index(&self) -> Option<usize> {
+        self.items
+            .iter()
+            .position(|item| item.status == BatchStatus::Running)
+    }
+
+    /// Puts the running item back in line, e.g. when it never got to start
+    pub fn requeue_running(&mut self) {
+        if let Some(index) = self.running_index() {
+            self.items[index].status = BatchStatus::Pending;
+            self.items[index].output_file = None;
+        }
+    }
+
+    /// Records the outcome of the running item, if any. Under
+    /// `StopOnFirstFailure` a failure cancels every pending item.
+    pub fn finish_running(&mut self, status: BatchStatus) {
+        let Some(index) = self.running_index() else {
+            return;
+        };
+
+        let failed = matches!(status, BatchStatus::Failed(_));
+        self.items[index].status = status;
+        if failed && self.failure_policy == BatchFailurePolicy::StopOnFirstFailure {
+            for item in &mut self.items {
+                if item.status == BatchStatus::Pending {
+                    item.status = BatchStatus::Cancelled;
+                }
+            }
+        }
+    }
+
+    /// How the batch went, e.g. "3 succeeded, 1 failed (clip.mov)"
+    pub fn summary(&self) -> String {
+        let count = |status: fn(&BatchStatus) -> bool| {
+            self.items
+                .iter()
+                .filter(|item| status(&item.status))
+                .count()
+        };
+        let failed: Vec<String> = self
+            .items
+            .iter()
+            .filter(|item| matches!(item.status, BatchStatus::Failed(_)))
+            .map(|item| {
+                item.input_file
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default()
+            })
+            .collect();
+
+        let mut summary = format!(
+            "{} succeeded, {} failed",
+            count(|status| *status == BatchStatus::Done),
+            failed.len()
+        );
+        if !failed.is_empty() {
+            let shown = failed.len().min(MAX_SUMMARY_FAILURES);
+            summary.push_str(&format!(" ({}", failed[..shown].join(", ")));
+            if failed.len() > shown {
+                summary.push_str(&format!(" and {} more", failed.len() - shown));
+            }
+            summary.push(')');
+        }
+        let cancelled = count(|status| *status == BatchStatus::Cancelled);
+        if cancelled > 0 {
+            summary.push_str(&format!(", {cancelled} cancelled"));
+        }
+        summary
+    }
+
+    pub fn has_unfinished(&self) -> bool {
+        self.items
+            .iter()
+            .any(|item| matches!(item.status, BatchStatus::Pending | BatchStatus::Running))
+    }
+
+    /// Writes the queue and its settings to `queue.json`, or removes the file
+    /// once nothing is left to resume
+    pub fn save(&self, preset: &ConversionPreset) {
+        let queue_path = config_location().join("queue.json");
+
+        if !self.has_unfinished() {
+            let _ = std::fs::remove_file(&queue_path);
+            return;
+        }
+
+        if std::fs::create_dir_all(config_location()).is_ok()
+            && let Ok(content) = serde_json::to_string_pretty(&self.saved(preset))
+        {
+            let _ = std::fs::write(&queue_path, content);
+        }
+    }
+
+    fn saved(&self, preset: &ConversionPreset) -> SavedBatch {
+        SavedBatch {
+            items: self.items.clone(),
+            preset: preset.clone(),
+            failure_policy: self.failure_policy,
+        }
+    }
+
+    /// A queue left unfinished by a previous session. Items that were running
+    /// when it ended are pending again, since their output is incomplete.
+    pub fn load_unfinished() -> Option<SavedBatch> {
+        let content = std::fs::read_to_string(config_location().join("queue.json")).ok()?;
+        let mut saved: SavedBatch = serde_json::from_str(&content).ok()?;
+
+        for item in &mut saved.items {
+            if item.status == BatchStatus::Running {
+                item.status = BatchStatus::Pending;
+            }
+        }
+
+        saved
+            .items
+            .iter()
+            .any(|item| item.status == BatchStatus::Pending)
+            .then_some(saved)
+    }
+
+    pub fn discard_saved() {
+        let _ = std::fs::remove_file(config_location().join("queue.json"));
+    }
+
+    pub fn restore(saved: SavedBatch) -> Self {
+        let mut items = saved.items;
+        // Queues saved before items were numbered
+        if items.iter().any(|item| item.sequence == 0) {
+            for (index, item) in items.iter_mut().enumerate() {
+                item.sequence = index + 1;
+            }
+        }
+        Self {
+            items,
+            failure_policy: saved.failure_policy,
+            active: false,
+            join: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedBatch {
+    pub items: Vec<BatchItem>,
+    /// Settings the batch was queued with
+    pub preset: ConversionPreset,
+    #[serde(default)]
+    pub failure_policy: BatchFailurePolicy,
+}
+
+/// Expands a rename template for one batch item.
+///
+/// Supported tokens are `{name}` (input file stem), `{suffix}` (the configured
+/// output suffix) and `{n}` (the item's `sequence`). The result is
+/// sanitized so it is always a single, valid file name.
+pub fn render_rename_template(template: &str, input_file: &Path, suffix: &str, n: usize) -> String {
+    let name = input_file
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| "output".to_string());
+
+    let rendered = template
+        .replace("{name}", &name)
+        .replace("{suffix}", suffix)
+        .replace("{n}", &n.to_string());
+
+    let sanitized = sanitize_filename(&rendered);
+    if sanitized.is_empty() {
+        sanitize_filename(&name)
+    } else {
+        sanitized
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(queue: &BatchQueue) -> Vec<String> {
+        queue
+            .items
+            .iter()
+            .map(|item| item.input_file.display().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn priorities_follow_the_settings_until_set_by_hand() {
+        let mut queue = BatchQueue::default();
+        queue.add(PathBuf::from("a.mkv"), BatchPriority::High);
+        queue.add(PathBuf::from("b.mkv"), BatchPriority::High);
+        queue.add(PathBuf::from("c.mkv"), BatchPriority::High);
+        queue.set_priority(2, BatchPriority::Normal);
+        assert_eq!(names(&queue), ["a.mkv", "b.mkv", "c.mkv"]);
+
+        // Switching from remux to an encode after queueing
+        queue.refresh_priorities(BatchPriority::Low);
+        assert_eq!(names(&queue), ["c.mkv", "a.mkv", "b.mkv"]);
+        assert_eq!(queue.items[0].priority, BatchPriority::Normal);
+        assert_eq!(queue.items[1].priority, BatchPriority::Low);
+        assert_eq!(queue.next_pending(), Some(0));
+
+        queue.refresh_priorities(BatchPriority::High);
+        assert_eq!(names(&queue), ["a.mkv", "b.mkv", "c.mkv"]);
+
+        // Priorities and the failure policy come back with a resumed queue
+        queue.failure_policy = BatchFailurePolicy::StopOnFirstFailure;
+        let content = serde_json::to_string(&queue.saved(&ConversionPreset::default())).unwrap();
+        let restored = BatchQueue::restore(serde_json::from_str(&content).unwrap());
+        assert_eq!(names(&restored), ["a.mkv", "b.mkv", "c.mkv"]);
+        assert_eq!(restored.items[2].priority, BatchPriority::Normal);
+        assert!(restored.items[2].manual_priority);
+        assert_eq!(
+            restored.failure_policy,
+            BatchFailurePolicy::StopOnFirstFailure
+        );
+    }
+
+    #[test]
+    fn sequence_numbers_survive_reordering() {
+        let mut queue = BatchQueue::default();
+        queue.add(PathBuf::from("a.mkv"), BatchPriority::Normal);
+        let segment = |start: f64| Segment {
+            start,
+            end: start + 1.0,
+            label: None,
+        };
+        queue.add_segments(
+            Path::new("b.mkv"),
+            vec![segment(0.0), segment(10.0)],
+            BatchPriority::Normal,
+        );
+        queue.move_to_front(2);
+
+        assert_eq!(names(&queue), ["b.mkv", "a.mkv", "b.mkv"]);
+        let sequences: Vec<usize> = queue.items.iter().map(|item| item.sequence).collect();
+        assert_eq!(sequences, [3, 1, 2]);
+        assert_eq!(queue.clip_number(0), Some(2));
+        assert_eq!(queue.clip_number(1), None);
+        assert_eq!(queue.clip_number(2), Some(1));
+    }
+
+    #[test]
+    fn planned_outputs_never_share_a_file() {
+        let mut queue = BatchQueue::default();
+        queue.add(PathBuf::from("one/movie.mkv"), BatchPriority::Normal);
+        queue.add(PathBuf::from("two/movie.mkv"), BatchPriority::Normal);
+        queue.add(PathBuf::from("three/movie.mkv"), BatchPriority::Normal);
+        queue.move_to_front(2);
+
+        let outputs = queue.plan_outputs(|_| PathBuf::from("out/movie.mp4"));
+        assert_eq!(
+            outputs,
+            [
+                PathBuf::from("out/movie_3.mp4"),
+                PathBuf::from("out/movie.mp4"),
+                PathBuf::from("out/movie_2.mp4"),
+            ]
+        );
+
+        // A started item keeps its file, and later plans steer around it
+        queue.items[0].output_file = Some(PathBuf::from("out/movie.mp4"));
+        let outputs = queue.plan_outputs(|_| PathBuf::from("out/movie.mp4"));
+        assert_eq!(outputs[0], PathBuf::from("out/movie.mp4"));
+        assert_eq!(outputs[1], PathBuf::from("out/movie_2.mp4"));
+        assert_eq!(outputs[2], PathBuf::from("out/movie_3.mp4"));
+    }
+}
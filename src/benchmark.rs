@@ -0,0 +1,290 @@
+// Timing encoder settings against each other on a short sample
+
+use crate::capabilities::has_filter;
+use crate::config::ffmpeg_binary;
+use crate::conversion::{ConversionMessage, ConversionTask};
+use crate::presets::{ConversionPreset, HardwareAccel, VideoCodec};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+use tokio::process::Command as AsyncCommand;
+use tokio::sync::Semaphore;
+
+/// Length of the sample every candidate encodes, like a test conversion
+pub const BENCHMARK_SAMPLE: Duration = Duration::from_secs(10);
+const MAX_CONCURRENT_ENCODES: usize = 4;
+// Options that select an encoder's speed; replaced for every candidate
+const SPEED_OPTIONS: &[&str] = &["-preset", "-cpu-used", "-deadline"];
+
+/// One set of settings to time
+#[derive(Debug, Clone)]
+pub struct BenchmarkCandidate {
+    pub label: String,
+    pub preset: ConversionPreset,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QualityMetric {
+    /// 0–100, from the `libvmaf` filter when FFmpeg has it
+    Vmaf,
+    /// 0–1, from the always available `ssim` filter
+    Ssim,
+}
+
+impl QualityMetric {
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            QualityMetric::Vmaf => "VMAF",
+            QualityMetric::Ssim => "SSIM",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BenchmarkResult {
+    pub label: String,
+    pub elapsed: Duration,
+    pub size: u64,
+    pub quality: Option<f64>,
+    pub metric: QualityMetric,
+    pub error: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum BenchmarkMessage {
+    Result(BenchmarkResult),
+    Finished,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BenchmarkSort {
+    Label,
+    Time,
+    Size,
+    Quality,
+}
+
+/// Speed presets of the selected encoder, or for hardware encoders the
+/// current settings next to their software counterpart
+pub fn benchmark_candidates(base: &ConversionPreset) -> Vec<BenchmarkCandidate> {
+    let with_speed = |label: &str, args: &[&str]| {
+        let mut preset = base.clone();
+        // Every candidate is timed at the same settings, not at a searched CRF
+        preset.quality_target = None;
+        preset.extra_args = strip_speed_options(&base.extra_args);
+        preset
+            .extra_args
+            .extend(args.iter().map(|arg| arg.to_string()));
+        BenchmarkCandidate {
+            label: label.to_string(),
+            preset,
+        }
+    };
+
+    if base.video_codec == VideoCodec::Copy {
+        return Vec::new();
+    }
+    if base.hardware_accel != HardwareAccel::None {
+        let mut software = with_speed(
+            &format!("{} (software)", base.video_codec.ffmpeg_name()),
+            &[],
+        );
+        software.preset.hardware_accel = HardwareAccel::None;
+        software.preset.hw_device_index = None;
+        return vec![
+            BenchmarkCandidate {
+                label: base
+                    .video_codec
+                    .encoder_name(base.hardware_accel)
+                    .to_string(),
+                preset: ConversionPreset {
+                    quality_target: None,
+                    ..base.clone()
+                },
+            },
+            software,
+        ];
+    }
+
+    match base.video_codec {
+        VideoCodec::H264 | VideoCodec::H265 => ["veryfast", "fast", "medium", "slow"]
+            .iter()
+            .map(|speed| with_speed(speed, &["-preset", speed]))
+            .collect(),
+        VideoCodec::VP9 | VideoCodec::AV1 => ["8", "6", "4"]
+            .iter()
+            .map(|level| with_speed(&format!("cpu-used {level}"), &["-cpu-used", level]))
+            .collect(),
+        VideoCodec::Copy => Vec::new(),
+    }
+}
+
+fn strip_speed_options(args: &[String]) -> Vec<String> {
+    let mut kept = Vec::new();
+    let mut skip_value = false;
+    for arg in args {
+        if skip_value {
+            skip_value = false;
+        } else if SPEED_OPTIONS.contains(&arg.as_str()) {
+            skip_value = true;
+        } else {
+            kept.push(arg.clone());
+        }
+    }
+    kept
+}
+
+/// Encodes that run at once; each encoder already uses several threads, so
+/// more would only make the timings less meaningful
+pub fn benchmark_concurrency() -> usize {
+    let cores = std::thread::available_parallelism().map_or(1, |cores| cores.get());
+    (cores / 8).clamp(1, MAX_CONCURRENT_ENCODES)
+}
+
+/// Encodes the sample with every candidate and reports each result as soon
+/// as it is scored, then `Finished`
+pub async fn run_benchmark(
+    input_file: PathBuf,
+    candidates: Vec<BenchmarkCandidate>,
+    sender: Sender<BenchmarkMessage>,
+) {
+    let metric = if has_filter("libvmaf").await {
+        QualityMetric::Vmaf
+    } else {
+        QualityMetric::Ssim
+    };
+    let sample_dir = std::env::temp_dir().join("ffmpegrust_benchmark");
+    let _ = tokio::fs::create_dir_all(&sample_dir).await;
+
+    let limit = Arc::new(Semaphore::new(benchmark_concurrency()));
+    let mut jobs = Vec::new();
+    for (index, candidate) in candidates.into_iter().enumerate() {
+        let limit = limit.clone();
+        let input_file = input_file.clone();
+        let output_file = sample_dir.join(format!(
+            "sample{index}.{}",
+            candidate.preset.video_format.extension()
+        ));
+        let sender = sender.clone();
+        jobs.push(tokio::spawn(async move {
+            let Ok(_permit) = limit.acquire().await else {
+                return;
+            };
+            let result = benchmark_candidate(&input_file, &output_file, candidate, metric).await;
+            let _ = tokio::fs::remove_file(&output_file).await;
+            let _ = sender.send(BenchmarkMessage::Result(result));
+        }));
+    }
+
+    for job in jobs {
+        let _ = job.await;
+    }
+    let _ = sender.send(BenchmarkMessage::Finished);
+}
+
+async fn benchmark_candidate(
+    input_file: &Path,
+    output_file: &Path,
+    candidate: BenchmarkCandidate,
+    metric: QualityMetric,
+) -> BenchmarkResult {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let task = ConversionTask::new(
+        input_file.to_path_buf(),
+        output_file.to_path_buf(),
+        candidate.preset,
+        sender,
+    )
+    .with_test_duration(Some(BENCHMARK_SAMPLE));
+
+    let started = Instant::now();
+    task.execute().await;
+    let elapsed = started.elapsed();
+
+    let error = receiver.try_iter().find_map(|message| match message {
+        ConversionMessage::Error(error) => Some(error.to_string()),
+        ConversionMessage::Cancelled => Some("Cancelled".to_string()),
+        _ => None,
+    });
+    let (size, quality) = if error.is_none() {
+        (
+            std::fs::metadata(output_file).map_or(0, |metadata| metadata.len()),
+            measure_quality(input_file, 0.0, output_file, metric).await,
+        )
+    } else {
+        (0, None)
+    };
+
+    BenchmarkResult {
+        label: candidate.label,
+        elapsed,
+        size,
+        quality,
+        metric,
+        error,
+    }
+}
+
+/// Scores `distorted` against the sample of `reference` starting at
+/// `reference_start` seconds, scaling it to the reference size first in case
+/// the settings change the resolution
+pub async fn measure_quality(
+    reference: &Path,
+    reference_start: f64,
+    distorted: &Path,
+    metric: QualityMetric,
+) -> Option<f64> {
+    let filter = match metric {
+        QualityMetric::Vmaf => "libvmaf",
+        QualityMetric::Ssim => "ssim",
+    };
+    let output = AsyncCommand::new(ffmpeg_binary())
+        .arg("-hide_banner")
+        .arg("-nostats")
+        .arg("-i")
+        .arg(distorted)
+        .arg("-ss")
+        .arg(format!("{reference_start:.3}"))
+        .arg("-t")
+        .arg(BENCHMARK_SAMPLE.as_secs().to_string())
+        .arg("-i")
+        .arg(reference)
+        .arg("-lavfi")
+        .arg(format!(
+            "[0:v][1:v]scale2ref=flags=bicubic[dist][ref];[dist][ref]{filter}"
+        ))
+        .arg("-f")
+        .arg("null")
+        .arg("-")
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await
+        .ok()?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let line = stderr.lines().rev().find_map(|line| match metric {
+        QualityMetric::Vmaf => line.split_once("VMAF score:").map(|(_, score)| score),
+        QualityMetric::Ssim => line.split_once(" All:").map(|(_, score)| score),
+    })?;
+    line.split_whitespace().next()?.parse::<f64>().ok()
+}
+
+/// Orders results by one column; failed runs always go last
+pub fn sort_results(results: &mut [BenchmarkResult], sort: BenchmarkSort, ascending: bool) {
+    results.sort_by(|a, b| {
+        let order = match sort {
+            BenchmarkSort::Label => a.label.cmp(&b.label),
+            BenchmarkSort::Time => a.elapsed.cmp(&b.elapsed),
+            BenchmarkSort::Size => a.size.cmp(&b.size),
+            BenchmarkSort::Quality => a
+                .quality
+                .unwrap_or(f64::NEG_INFINITY)
+                .total_cmp(&b.quality.unwrap_or(f64::NEG_INFINITY)),
+        };
+        let order = if ascending { order } else { order.reverse() };
+        a.error.is_some().cmp(&b.error.is_some()).then(order)
+    });
+}
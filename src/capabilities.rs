@@ -0,0 +1,264 @@
+// What the installed FFmpeg build can actually do
+
+use crate::config::{config_location, ffmpeg_binary};
+use crate::hardware::{list_render_nodes, test_hardware_encode};
+use crate::presets::HardwareAccel;
+use futures_util::future::join_all;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use tokio::process::Command as AsyncCommand;
+
+const HARDWARE_CACHE_FILE: &str = "hardware_encoders.json";
+
+/// Test encode results saved between runs. They only hold for the FFmpeg
+/// build and the render nodes they were made with.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+struct HardwareProbeCache {
+    setup: String,
+    tested: Vec<String>,
+    unusable: Vec<String>,
+}
+
+impl HardwareProbeCache {
+    fn load() -> Option<Self> {
+        let content = std::fs::read_to_string(config_location().join(HARDWARE_CACHE_FILE)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    fn save(&self) {
+        if std::fs::create_dir_all(config_location()).is_ok()
+            && let Ok(content) = serde_json::to_string_pretty(self)
+        {
+            let _ = std::fs::write(config_location().join(HARDWARE_CACHE_FILE), content);
+        }
+    }
+
+    /// The unusable encoders among `candidates`, if they were all tested
+    /// with this `setup`
+    fn unusable_among(
+        &self,
+        setup: &str,
+        candidates: &[(String, HardwareAccel)],
+    ) -> Option<Vec<String>> {
+        let covered = self.setup == setup
+            && candidates
+                .iter()
+                .all(|(encoder, _)| self.tested.contains(encoder));
+        covered.then(|| {
+            candidates
+                .iter()
+                .map(|(encoder, _)| encoder)
+                .filter(|encoder| self.unusable.contains(encoder))
+                .cloned()
+                .collect()
+        })
+    }
+}
+
+/// Names of every encoder compiled into the FFmpeg on PATH
+pub async fn list_encoders() -> Result<HashSet<String>, String> {
+    let output = AsyncCommand::new(ffmpeg_binary())
+        .arg("-hide_banner")
+        .arg("-encoders")
+        .output()
+        .await
+        .map_err(|e| format!("Failed to run ffmpeg: {}", e))?;
+
+    if !output.status.success() {
+        return Err("ffmpeg could not list its encoders".to_string());
+    }
+
+    Ok(parse_encoders(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Whether the FFmpeg on PATH has the named filter, e.g. `libvmaf`
+pub async fn has_filter(name: &str) -> bool {
+    let Ok(output) = AsyncCommand::new(ffmpeg_binary())
+        .arg("-hide_banner")
+        .arg("-filters")
+        .output()
+        .await
+    else {
+        return false;
+    };
+
+    // Each entry is a flags column, the name, then the pad types
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .any(|line| line.split_whitespace().nth(1) == Some(name))
+}
+
+/// VAAPI and QSV encoders are listed whenever FFmpeg was built with them,
+/// even without a usable `/dev/dri` device or permission to open it. On
+/// Linux each one is tried with a test encode and dropped if that fails.
+/// The results are reused until FFmpeg or the render nodes change.
+pub async fn drop_unusable_hardware_encoders(encoders: &mut HashSet<String>) {
+    drop_hardware_encoders(encoders, true).await;
+}
+
+/// Like `drop_unusable_hardware_encoders`, but always runs the test encodes
+/// and refreshes the saved results
+pub async fn retest_hardware_encoders(encoders: &mut HashSet<String>) {
+    drop_hardware_encoders(encoders, false).await;
+}
+
+async fn drop_hardware_encoders(encoders: &mut HashSet<String>, use_cache: bool) {
+    if !cfg!(target_os = "linux") {
+        return;
+    }
+
+    let candidates = hardware_candidates(encoders);
+    if candidates.is_empty() {
+        return;
+    }
+
+    let setup = hardware_setup().await;
+    let cached = use_cache
+        .then(HardwareProbeCache::load)
+        .flatten()
+        .and_then(|cache| cache.unusable_among(&setup, &candidates));
+    let unusable = match cached {
+        Some(unusable) => unusable,
+        None => {
+            // Each probe may wait out its timeout on a hanging driver, so
+            // run them side by side
+            let results = join_all(
+                candidates
+                    .iter()
+                    .map(|(encoder, accel)| test_hardware_encode(encoder, *accel)),
+            )
+            .await;
+            let unusable: Vec<String> = candidates
+                .iter()
+                .zip(results)
+                .filter(|(_, usable)| !usable)
+                .map(|((encoder, _), _)| encoder.clone())
+                .collect();
+
+            HardwareProbeCache {
+                setup,
+                tested: candidates.into_iter().map(|(encoder, _)| encoder).collect(),
+                unusable: unusable.clone(),
+            }
+            .save();
+            unusable
+        }
+    };
+
+    for encoder in unusable {
+        encoders.remove(&encoder);
+    }
+}
+
+fn hardware_candidates(encoders: &HashSet<String>) -> Vec<(String, HardwareAccel)> {
+    let mut candidates: Vec<(String, HardwareAccel)> = encoders
+        .iter()
+        .filter_map(|encoder| {
+            let accel = if encoder.ends_with("_vaapi") {
+                HardwareAccel::Vaapi
+            } else if encoder.ends_with("_qsv") {
+                HardwareAccel::Qsv
+            } else {
+                return None;
+            };
+            Some((encoder.clone(), accel))
+        })
+        .collect();
+    candidates.sort_by(|a, b| a.0.cmp(&b.0));
+    candidates
+}
+
+/// The FFmpeg path and version plus the render nodes, which together decide
+/// whether a saved test encode result still holds
+async fn hardware_setup() -> String {
+    let version = AsyncCommand::new(ffmpeg_binary())
+        .arg("-version")
+        .output()
+        .await
+        .ok()
+        .and_then(|output| {
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .next()
+                .map(str::to_string)
+        })
+        .unwrap_or_default();
+    let nodes: Vec<String> = list_render_nodes()
+        .iter()
+        .map(|node| node.display().to_string())
+        .collect();
+
+    format!(
+        "{}\n{version}\n{}",
+        ffmpeg_binary().display(),
+        nodes.join(",")
+    )
+}
+
+// The list follows a legend terminated by a " ------" line; each entry is
+// a flags column (e.g. "V....D") followed by the encoder name
+fn parse_encoders(output: &str) -> HashSet<String> {
+    output
+        .lines()
+        .skip_while(|line| !line.trim_start().starts_with("---"))
+        .skip(1)
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENCODERS: &str = "Encoders:
+ V..... = Video
+ ------
+ V....D libx264              libx264 H.264 / AVC / MPEG-4 AVC
+ V....D h264_vaapi           H.264/AVC (VAAPI) (codec h264)
+ V..... hevc_qsv             HEVC (Intel Quick Sync Video acceleration)
+ A....D aac                  AAC (Advanced Audio Coding)
+";
+
+    #[test]
+    fn encoders_follow_the_legend() {
+        let encoders = parse_encoders(ENCODERS);
+        let expected: HashSet<String> = ["libx264", "h264_vaapi", "hevc_qsv", "aac"]
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        assert_eq!(encoders, expected);
+    }
+
+    #[test]
+    fn only_vaapi_and_qsv_encoders_are_tested() {
+        let candidates = hardware_candidates(&parse_encoders(ENCODERS));
+        assert_eq!(
+            candidates,
+            vec![
+                ("h264_vaapi".to_string(), HardwareAccel::Vaapi),
+                ("hevc_qsv".to_string(), HardwareAccel::Qsv),
+            ]
+        );
+    }
+
+    #[test]
+    fn cached_results_need_the_same_setup_and_encoders() {
+        let cache = HardwareProbeCache {
+            setup: "ffmpeg 7.1".to_string(),
+            tested: vec!["h264_vaapi".to_string(), "hevc_qsv".to_string()],
+            unusable: vec!["hevc_qsv".to_string()],
+        };
+        let candidates = hardware_candidates(&parse_encoders(ENCODERS));
+
+        assert_eq!(
+            cache.unusable_among("ffmpeg 7.1", &candidates),
+            Some(vec!["hevc_qsv".to_string()])
+        );
+        assert_eq!(cache.unusable_among("ffmpeg 7.2", &candidates), None);
+
+        let mut more = candidates.clone();
+        more.push(("av1_vaapi".to_string(), HardwareAccel::Vaapi));
+        assert_eq!(cache.unusable_among("ffmpeg 7.1", &more), None);
+    }
+}
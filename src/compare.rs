@@ -0,0 +1,221 @@
+// Comparing the media properties of two files, e.g. a source and its output
+
+use crate::conversion::{extract_frame, format_duration};
+use crate::probe::{MediaInfo, StreamInfo, parse_frame_rate, probe_media};
+use std::path::{Path, PathBuf};
+
+// Relative changes smaller than this are shown as unchanged; durations and
+// bitrates always shift a little in a remux
+const UNCHANGED_PERCENT: f64 = 0.5;
+// Frames are compared at the source size, shrunk to fit inside this
+const MAX_FRAME_SIZE: [u32; 2] = [960, 540];
+
+#[derive(Debug, Clone)]
+pub struct ComparisonRow {
+    pub label: &'static str,
+    pub left: String,
+    pub right: String,
+    /// `None` when both sides match; otherwise "changed", or for numbers the
+    /// relative change from left to right, e.g. "−42%"
+    pub change: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MediaComparison {
+    pub left_file: PathBuf,
+    pub right_file: PathBuf,
+    pub rows: Vec<ComparisonRow>,
+}
+
+/// The same moment of a source and its encode, as RGBA pixels of `size`
+#[derive(Debug, Clone)]
+pub struct FramePair {
+    pub position: f64,
+    pub size: [usize; 2],
+    pub source: Vec<u8>,
+    pub encoded: Vec<u8>,
+}
+
+/// Size both frames are shown at: the source's aspect ratio within
+/// `MAX_FRAME_SIZE`, 16:9 when the source size is unknown
+pub fn comparison_frame_size(source: Option<(u32, u32)>) -> [usize; 2] {
+    let [max_width, max_height] = MAX_FRAME_SIZE;
+    let Some((width, height)) = source.filter(|(width, height)| *width > 0 && *height > 0) else {
+        return [max_width as usize, max_height as usize];
+    };
+
+    let scale = (f64::from(max_width) / f64::from(width))
+        .min(f64::from(max_height) / f64::from(height))
+        .min(1.0);
+    // Even sizes keep FFmpeg's scaler from rounding them differently
+    let even = |value: u32| ((f64::from(value) * scale) as usize / 2 * 2).max(2);
+    [even(width), even(height)]
+}
+
+/// Extracts the frame at `position` seconds from both files at once. The
+/// encode is scaled to `size` like the source, so a change of resolution
+/// doesn't shift the picture.
+pub async fn extract_frame_pair(
+    source: &Path,
+    encoded: &Path,
+    position: f64,
+    size: [usize; 2],
+) -> Result<FramePair, String> {
+    let (source_frame, encoded_frame) = tokio::join!(
+        extract_frame(source, position, size),
+        extract_frame(encoded, position, size)
+    );
+    Ok(FramePair {
+        position,
+        size,
+        source: source_frame?,
+        encoded: encoded_frame?,
+    })
+}
+
+/// Probes both files at once and lines their properties up row by row
+pub async fn compare_media(left: &Path, right: &Path) -> Result<MediaComparison, String> {
+    let (left_info, right_info) = tokio::join!(probe_media(left), probe_media(right));
+    let left_info = left_info.map_err(|e| format!("{}: {e}", left.display()))?;
+    let right_info = right_info.map_err(|e| format!("{}: {e}", right.display()))?;
+    let size = |path: &Path| std::fs::metadata(path).map_or(0, |metadata| metadata.len());
+
+    Ok(MediaComparison {
+        left_file: left.to_path_buf(),
+        right_file: right.to_path_buf(),
+        rows: comparison_rows((&left_info, size(left)), (&right_info, size(right))),
+    })
+}
+
+/// One row per property, from container and size down to the audio and
+/// subtitle tracks. Each side is the probe and the file size in bytes.
+pub fn comparison_rows(left: (&MediaInfo, u64), right: (&MediaInfo, u64)) -> Vec<ComparisonRow> {
+    let (left, left_size) = left;
+    let (right, right_size) = right;
+    let container = |info: &MediaInfo| {
+        info.container_label()
+            .map_or(info.format_name.clone(), str::to_string)
+    };
+    let video = |info: &MediaInfo, field: fn(&StreamInfo) -> Option<String>| {
+        info.video_stream().and_then(field).unwrap_or_default()
+    };
+    let audio = |info: &MediaInfo, field: fn(&StreamInfo) -> Option<String>| {
+        info.audio_streams()
+            .first()
+            .and_then(|stream| field(stream))
+            .unwrap_or_default()
+    };
+    let count =
+        |info: &MediaInfo, codec_type: &str| info.streams_of_type(codec_type).len().to_string();
+
+    vec![
+        text_row("Container", container(left), container(right)),
+        number_row("Duration", left.duration, right.duration, format_duration),
+        number_row(
+            "Size",
+            Some(left_size as f64),
+            Some(right_size as f64),
+            |bytes| format!("{:.1} MB", bytes / 1_048_576.0),
+        ),
+        number_row(
+            "Bitrate",
+            left.bit_rate.map(|rate| rate as f64),
+            right.bit_rate.map(|rate| rate as f64),
+            |rate| format!("{} kb/s", (rate / 1000.0).round()),
+        ),
+        text_row(
+            "Video codec",
+            video(left, |stream| Some(stream.codec_name.clone())),
+            video(right, |stream| Some(stream.codec_name.clone())),
+        ),
+        text_row(
+            "Resolution",
+            video(left, resolution),
+            video(right, resolution),
+        ),
+        text_row(
+            "Frame rate",
+            video(left, frame_rate),
+            video(right, frame_rate),
+        ),
+        text_row(
+            "Pixel format",
+            video(left, |stream| stream.pix_fmt.clone()),
+            video(right, |stream| stream.pix_fmt.clone()),
+        ),
+        text_row(
+            "Color space",
+            video(left, |stream| stream.color_space.clone()),
+            video(right, |stream| stream.color_space.clone()),
+        ),
+        text_row(
+            "Transfer",
+            video(left, |stream| stream.color_transfer.clone()),
+            video(right, |stream| stream.color_transfer.clone()),
+        ),
+        text_row(
+            "Audio codec",
+            audio(left, |stream| Some(stream.codec_name.clone())),
+            audio(right, |stream| Some(stream.codec_name.clone())),
+        ),
+        text_row(
+            "Audio channels",
+            audio(left, |stream| {
+                stream.channels.map(|channels| channels.to_string())
+            }),
+            audio(right, |stream| {
+                stream.channels.map(|channels| channels.to_string())
+            }),
+        ),
+        text_row("Audio tracks", count(left, "audio"), count(right, "audio")),
+        text_row(
+            "Subtitle tracks",
+            count(left, "subtitle"),
+            count(right, "subtitle"),
+        ),
+    ]
+}
+
+fn resolution(stream: &StreamInfo) -> Option<String> {
+    Some(format!("{}x{}", stream.width?, stream.height?))
+}
+
+fn frame_rate(stream: &StreamInfo) -> Option<String> {
+    let rate = parse_frame_rate(stream.avg_frame_rate.as_deref()?)?;
+    Some(format!("{rate:.3} fps"))
+}
+
+fn text_row(label: &'static str, left: String, right: String) -> ComparisonRow {
+    let change = (left != right).then(|| "changed".to_string());
+    ComparisonRow {
+        label,
+        left,
+        right,
+        change,
+    }
+}
+
+fn number_row(
+    label: &'static str,
+    left: Option<f64>,
+    right: Option<f64>,
+    format: fn(f64) -> String,
+) -> ComparisonRow {
+    let change = match (left, right) {
+        (Some(left), Some(right)) if left > 0.0 => {
+            let percent = (right - left) / left * 100.0;
+            (percent.abs() >= UNCHANGED_PERCENT).then(|| {
+                let sign = if percent > 0.0 { "+" } else { "−" };
+                format!("{sign}{:.0}%", percent.abs())
+            })
+        }
+        (None, None) => None,
+        _ => (left != right).then(|| "changed".to_string()),
+    };
+    ComparisonRow {
+        label,
+        left: left.map(format).unwrap_or_default(),
+        right: right.map(format).unwrap_or_default(),
+        change,
+    }
+}
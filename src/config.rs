@@ -1,13 +1,200 @@
+use crate::batch::BatchFailurePolicy;
+use crate::conversion::{ConversionTimeout, DEFAULT_PROGRESS_MILESTONES};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// Directory holding `config.json` and `presets.json`.
+///
+/// Prefers the platform config dir, then `$XDG_CONFIG_HOME`, then the
+/// executable's directory and finally the temp dir, so settings still persist
+/// on minimal systems where `dirs::config_dir()` is unavailable.
+pub fn config_location() -> &'static PathBuf {
+    static LOCATION: OnceLock<PathBuf> = OnceLock::new();
+
+    LOCATION.get_or_init(|| {
+        if let Some(config_dir) = dirs::config_dir() {
+            return config_dir.join("ffmpegrust");
+        }
+
+        let fallback = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(|dir| PathBuf::from(dir).join("ffmpegrust"))
+            .or_else(|| {
+                std::env::current_exe()
+                    .ok()
+                    .and_then(|exe| exe.parent().map(|dir| dir.join("ffmpegrust-config")))
+                    .filter(|dir| std::fs::create_dir_all(dir).is_ok())
+            })
+            .unwrap_or_else(|| std::env::temp_dir().join("ffmpegrust"));
+
+        eprintln!(
+            "No platform config directory available, storing settings in {}",
+            fallback.display()
+        );
+        fallback
+    })
+}
+
+/// FFmpeg executable every command runs: `ffmpeg_path` when configured,
+/// otherwise whatever `ffmpeg` is on the PATH
+pub fn ffmpeg_binary() -> &'static Path {
+    &tool_paths().0
+}
+
+/// ffprobe from the same folder as `ffmpeg_binary`
+pub fn ffprobe_binary() -> &'static Path {
+    &tool_paths().1
+}
+
+static TOOL_PATHS: OnceLock<(PathBuf, PathBuf)> = OnceLock::new();
+
+/// Fixes the tools `ffmpeg_binary` and `ffprobe_binary` return to those of
+/// `config`, so they don't load (and report overrides in) the config again.
+/// Only the first call, or first use of the tools, counts.
+pub fn use_tool_paths(config: &Config) {
+    let _ = TOOL_PATHS.set(tool_paths_for(config));
+}
+
+fn tool_paths() -> &'static (PathBuf, PathBuf) {
+    TOOL_PATHS.get_or_init(|| tool_paths_for(&Config::load()))
+}
+
+fn tool_paths_for(config: &Config) -> (PathBuf, PathBuf) {
+    match config.ffmpeg_path.clone() {
+        Some(ffmpeg) => {
+            let ffprobe = ffmpeg.with_file_name(format!("ffprobe{}", std::env::consts::EXE_SUFFIX));
+            (ffmpeg, ffprobe)
+        }
+        None => (PathBuf::from("ffmpeg"), PathBuf::from("ffprobe")),
+    }
+}
+
+// Every config key can be overridden by `FFMPEGRUST_` and its name in upper
+// case, e.g. `FFMPEGRUST_KEEP_FFMPEG_LOGS=true`
+const ENV_PREFIX: &str = "FFMPEGRUST_";
+// Shorter names for the overrides containers commonly need
+const ENV_ALIASES: &[(&str, &str)] = &[("FFMPEGRUST_OUTPUT_DIR", "last_output_folder")];
+
+/// Default for how often a running conversion's progress reaches the UI
+pub const PROGRESS_UPDATE_INTERVAL_MS: u64 = 250;
+// Bounds for a hand-edited interval: faster buys nothing a person can see,
+// slower makes the bar look stuck
+const PROGRESS_INTERVAL_RANGE_MS: std::ops::RangeInclusive<u64> = 50..=2000;
+// Bounds for a hand-edited shutdown grace period: no time at all cuts the
+// output off mid-write, and a long one leaves the app hanging on exit
+const SHUTDOWN_GRACE_RANGE_MS: std::ops::RangeInclusive<u64> = 500..=30_000;
+// Input files remembered for quick re-selection
+const MAX_RECENT_FILES: usize = 10;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum OutputMode {
+    SameAsInput,
+    FixedFolder,
+    Ask,
+}
+
+impl OutputMode {
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            OutputMode::SameAsInput => "Same as input",
+            OutputMode::FixedFolder => "Fixed folder",
+            OutputMode::Ask => "Ask every time",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+pub enum UiTheme {
+    #[default]
+    Dark,
+    Light,
+    /// Follow the operating system's light or dark setting
+    System,
+}
+
+impl UiTheme {
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            UiTheme::Dark => "Dark",
+            UiTheme::Light => "Light",
+            UiTheme::System => "System",
+        }
+    }
+
+    pub fn all() -> [UiTheme; 3] {
+        [UiTheme::Dark, UiTheme::Light, UiTheme::System]
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
     pub last_input_folder: Option<PathBuf>,
     pub last_output_folder: Option<PathBuf>,
+    /// Input files picked recently, newest first; missing ones are dropped
+    /// on load
+    pub recent_files: Vec<PathBuf>,
+    pub output_mode: OutputMode,
+    pub theme: UiTheme,
+    pub output_suffix: String,
+    /// Output file name template for batch conversions, see `render_rename_template`
+    pub batch_rename_template: String,
+    pub batch_failure_policy: BatchFailurePolicy,
     pub auto_check_updates: bool,
+    /// Release the user chose to skip; the startup check won't offer it again
+    pub skipped_update_version: Option<String>,
     pub window_width: f32,
     pub window_height: f32,
+    /// How long a running conversion gets to finish writing when the app closes
+    pub shutdown_grace_ms: u64,
+    /// Limit for a single conversion; unset scales it from the input
+    /// duration and `0` disables it
+    pub conversion_timeout_secs: Option<u64>,
+    /// Save each conversion's complete FFmpeg log under `logs/` in the config folder
+    pub keep_ffmpeg_logs: bool,
+    /// Give each output the modification (and, where possible, creation) time
+    /// of its input
+    pub preserve_file_times: bool,
+    /// Pause a running conversion when the output drive runs low on space
+    pub pause_on_low_disk: bool,
+    /// Ask before a conversion replaces a file that already exists
+    pub confirm_overwrite: bool,
+    /// Experimental: encode long single files as several segments at once
+    /// on machines with enough cores
+    pub segmented_encode: bool,
+    /// Bytes per second for update downloads; unset means no limit
+    pub update_download_rate_limit: Option<u64>,
+    /// Least time between progress updates shown while converting; FFmpeg
+    /// reports far more often than that when copying streams
+    pub progress_update_interval_ms: u64,
+    /// Percentages at which a running conversion reports how far it got;
+    /// empty turns the reports off
+    pub progress_milestones: Vec<u32>,
+    /// FFmpeg executable to run instead of the one on the PATH; ffprobe is
+    /// expected next to it
+    pub ffmpeg_path: Option<PathBuf>,
+    /// Conversions `serve` runs at once, the rest waiting their turn; unset
+    /// runs every submission right away
+    pub max_concurrent: Option<NonZeroUsize>,
+    /// Keys an environment variable overrides, so saving never writes the
+    /// override into `config.json`
+    #[serde(skip)]
+    env_overrides: BTreeMap<String, EnvOverride>,
+}
+
+/// A setting taken from the environment instead of the file
+#[derive(Debug, Clone)]
+struct EnvOverride {
+    /// File (or default) value, saved while the setting keeps the override
+    original: Value,
+    /// Value from the environment; once the setting differs, it was changed
+    /// in the app and is saved as it is
+    value: Value,
 }
 
 impl Default for Config {
@@ -15,42 +202,158 @@ impl Default for Config {
         Self {
             last_input_folder: None,
             last_output_folder: None,
+            recent_files: Vec::new(),
+            output_mode: OutputMode::FixedFolder,
+            theme: UiTheme::Dark,
+            output_suffix: "_converted".to_string(),
+            batch_rename_template: "{name}{suffix}".to_string(),
+            batch_failure_policy: BatchFailurePolicy::ContinueOnError,
             auto_check_updates: true,
+            skipped_update_version: None,
             window_width: 1000.0,
             window_height: 600.0,
+            shutdown_grace_ms: 3000,
+            conversion_timeout_secs: None,
+            keep_ffmpeg_logs: false,
+            preserve_file_times: false,
+            pause_on_low_disk: true,
+            confirm_overwrite: true,
+            segmented_encode: false,
+            update_download_rate_limit: None,
+            progress_update_interval_ms: PROGRESS_UPDATE_INTERVAL_MS,
+            progress_milestones: DEFAULT_PROGRESS_MILESTONES.to_vec(),
+            ffmpeg_path: None,
+            max_concurrent: None,
+            env_overrides: BTreeMap::new(),
         }
     }
 }
 
 impl Config {
+    /// Settings from `config.json`, with environment variables taking
+    /// precedence over the file and the file over the defaults
     pub fn load() -> Self {
-        if let Some(config_dir) = dirs::config_dir() {
-            let config_path = config_dir.join("ffmpegrust").join("config.json");
+        let config_path = config_location().join("config.json");
+
+        if config_path.exists()
+            && let Ok(content) = std::fs::read_to_string(&config_path)
+            && let Ok(mut config) = serde_json::from_str::<Config>(&content)
+        {
+            config.prune_recent_files();
+            return config.with_env_overrides();
+        }
+
+        Self::default().with_env_overrides()
+    }
+
+    /// Applies every `FFMPEGRUST_<KEY>` variable that holds a valid value
+    /// for its key. Values are read as JSON, or else as a plain string, and
+    /// checked by the same deserialization as the file; invalid ones are
+    /// reported and ignored.
+    fn with_env_overrides(self) -> Self {
+        self.with_overrides(|name| std::env::var(name).ok())
+    }
 
-            if config_path.exists() {
-                if let Ok(content) = std::fs::read_to_string(&config_path) {
-                    if let Ok(config) = serde_json::from_str::<Config>(&content) {
-                        return config;
+    /// `with_env_overrides` with variables read through `var`
+    fn with_overrides(self, var: impl Fn(&str) -> Option<String>) -> Self {
+        let Ok(Value::Object(mut merged)) = serde_json::to_value(&self) else {
+            return self;
+        };
+
+        let mut originals = BTreeMap::new();
+        let keys: Vec<String> = merged.keys().cloned().collect();
+        for key in keys {
+            let alias = ENV_ALIASES
+                .iter()
+                .find(|(_, aliased)| *aliased == key)
+                .map(|(name, _)| name.to_string());
+            let Some((name, raw)) = std::iter::once(format!("{ENV_PREFIX}{}", key.to_uppercase()))
+                .chain(alias)
+                .find_map(|name| var(&name).map(|raw| (name, raw)))
+            else {
+                continue;
+            };
+
+            let candidates = serde_json::from_str::<Value>(&raw)
+                .ok()
+                .into_iter()
+                .chain(std::iter::once(Value::String(raw.clone())));
+            let mut accepted = None;
+            for value in candidates {
+                let mut candidate = merged.clone();
+                candidate.insert(key.clone(), value.clone());
+                if serde_json::from_value::<Config>(Value::Object(candidate)).is_ok() {
+                    accepted = Some(value);
+                    break;
+                }
+            }
+            match accepted {
+                Some(value) => {
+                    if let Some(original) = merged.insert(key.clone(), value.clone()) {
+                        originals.insert(key, EnvOverride { original, value });
                     }
                 }
+                None => eprintln!("Ignoring {name}: \"{raw}\" is not a valid {key}"),
             }
         }
 
-        Self::default()
+        match serde_json::from_value::<Config>(Value::Object(merged)) {
+            Ok(mut config) => {
+                config.env_overrides = originals;
+                config
+            }
+            Err(_) => self,
+        }
     }
 
     pub fn save(&self) {
-        if let Some(config_dir) = dirs::config_dir() {
-            let app_config_dir = config_dir.join("ffmpegrust");
+        let app_config_dir = config_location();
 
-            if let Ok(()) = std::fs::create_dir_all(&app_config_dir) {
-                let config_path = app_config_dir.join("config.json");
+        if let Ok(()) = std::fs::create_dir_all(app_config_dir) {
+            let config_path = app_config_dir.join("config.json");
 
-                if let Ok(content) = serde_json::to_string_pretty(self) {
-                    let _ = std::fs::write(&config_path, content);
-                }
+            if let Some(value) = self.saved_value()
+                && let Ok(content) = serde_json::to_string_pretty(&value)
+            {
+                let _ = std::fs::write(&config_path, content);
+            }
+        }
+    }
+
+    /// What `save` writes: settings still overridden by the environment
+    /// keep their file values, ones changed since are saved as changed
+    fn saved_value(&self) -> Option<Value> {
+        let Ok(Value::Object(mut map)) = serde_json::to_value(self) else {
+            return None;
+        };
+        for (key, env) in &self.env_overrides {
+            if map.get(key) == Some(&env.value) {
+                map.insert(key.clone(), env.original.clone());
             }
         }
+        Some(Value::Object(map))
+    }
+
+    pub fn shutdown_grace_period(&self) -> Duration {
+        Duration::from_millis(self.shutdown_grace_ms.clamp(
+            *SHUTDOWN_GRACE_RANGE_MS.start(),
+            *SHUTDOWN_GRACE_RANGE_MS.end(),
+        ))
+    }
+
+    pub fn progress_update_interval(&self) -> Duration {
+        Duration::from_millis(self.progress_update_interval_ms.clamp(
+            *PROGRESS_INTERVAL_RANGE_MS.start(),
+            *PROGRESS_INTERVAL_RANGE_MS.end(),
+        ))
+    }
+
+    pub fn conversion_timeout(&self) -> ConversionTimeout {
+        match self.conversion_timeout_secs {
+            None => ConversionTimeout::Auto,
+            Some(0) => ConversionTimeout::Disabled,
+            Some(secs) => ConversionTimeout::Fixed(Duration::from_secs(secs)),
+        }
     }
 
     pub fn update_input_folder(&mut self, path: Option<PathBuf>) {
@@ -62,4 +365,83 @@ impl Config {
         self.last_output_folder = path;
         self.save();
     }
+
+    /// Puts `file` at the top of the recent files
+    pub fn add_recent_file(&mut self, file: &Path) {
+        self.recent_files.retain(|recent| recent != file);
+        self.recent_files.insert(0, file.to_path_buf());
+        self.recent_files.truncate(MAX_RECENT_FILES);
+        self.save();
+    }
+
+    /// Drops recent files that were moved or deleted, or were never files
+    pub fn prune_recent_files(&mut self) {
+        self.recent_files
+            .retain(|recent| recent.is_absolute() && recent.is_file());
+        self.recent_files.truncate(MAX_RECENT_FILES);
+    }
+
+    pub fn update_output_mode(&mut self, mode: OutputMode) {
+        self.output_mode = mode;
+        self.save();
+    }
+
+    pub fn logs_folder(&self) -> PathBuf {
+        config_location().join("logs")
+    }
+
+    pub fn update_auto_check_updates(&mut self, enabled: bool) {
+        self.auto_check_updates = enabled;
+        self.save();
+    }
+
+    pub fn update_skipped_version(&mut self, version: Option<String>) {
+        self.skipped_update_version = version;
+        self.save();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shutdown_grace_period_is_clamped() {
+        let grace = |shutdown_grace_ms| {
+            Config {
+                shutdown_grace_ms,
+                ..Config::default()
+            }
+            .shutdown_grace_period()
+        };
+        assert_eq!(grace(0), Duration::from_millis(500));
+        assert_eq!(grace(3000), Duration::from_secs(3));
+        assert_eq!(grace(u64::MAX), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn env_overrides_apply_but_are_not_saved() {
+        let vars = BTreeMap::from([
+            ("FFMPEGRUST_SHUTDOWN_GRACE_MS", "5000"),
+            ("FFMPEGRUST_KEEP_FFMPEG_LOGS", "maybe"),
+            ("FFMPEGRUST_OUTPUT_DIR", "/srv/out"),
+            ("FFMPEGRUST_OUTPUT_SUFFIX", "_env"),
+        ]);
+        let mut config =
+            Config::default().with_overrides(|name| vars.get(name).map(|value| value.to_string()));
+
+        assert_eq!(config.shutdown_grace_ms, 5000);
+        // Not a bool, so the file value stays
+        assert!(!config.keep_ffmpeg_logs);
+        assert_eq!(config.last_output_folder, Some(PathBuf::from("/srv/out")));
+        // Read as a plain string, since it isn't JSON
+        assert_eq!(config.output_suffix, "_env");
+
+        config.output_suffix = "_edited".to_string();
+        let saved = config.saved_value().expect("config should serialize");
+        assert_eq!(saved["shutdown_grace_ms"], 3000);
+        assert_eq!(saved["last_output_folder"], Value::Null);
+        // Changed in the app after the override, so the edit is kept
+        assert_eq!(saved["output_suffix"], "_edited");
+    }
 }
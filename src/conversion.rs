@@ -1,10 +1,191 @@
-use crate::presets::{AudioCodec, ConversionMode, ConversionPreset, VideoCodec, VideoFormat};
-use std::path::PathBuf;
+use crate::config::{ffmpeg_binary, ffprobe_binary};
+use crate::crf_search::{apply_crf, search_crf};
+use crate::hardware::{encoder_device_args, input_device_args, is_hardware_failure};
+use crate::presets::{
+    AudioCodec, ConversionMode, ConversionPreset, DispositionTarget, HardwareAccel,
+    MetadataOptions, OutputSplit, TrimAccuracy, VideoCodec, VideoFormat,
+};
+use crate::probe::{
+    MediaInfo, cached_frame_count, keyframe_before, parse_frame_rate, probe_media,
+    remember_frame_count,
+};
+use crate::process_control::{
+    describe_termination, is_out_of_memory, resume_process, suspend_process, termination_signal,
+};
+use crate::segments::{Segment, parse_timestamp};
+use crate::sequence::{InputSource, scan_image_sequence};
+use crate::subtitles::IMAGE_SUBTITLE_CODECS;
+use crate::utils::{available_disk_space, copy_file_times, file_in_use, is_same_file};
+use crate::validation::{
+    audio_offset_seconds, is_safe_metadata_value, is_valid_disposition, is_valid_metadata_key,
+    parse_bitrate, parse_codec_params, sanitize_filename, validate_output_split, video_filter,
+};
+use futures_util::future::join_all;
+use std::collections::{BTreeMap, VecDeque};
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::sync::mpsc::Sender;
-use std::time::{Duration, Instant};
-use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::process::Command as AsyncCommand;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStderr, ChildStdin, ChildStdout, Command as AsyncCommand};
+use tokio::sync::mpsc::UnboundedReceiver;
+
+// How long FFmpeg gets to finalize the output after being asked to quit,
+// unless the task is given its own grace period
+const CANCEL_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+// Automatic timeouts allow this many times the input duration, but never
+// less than the floor so short clips on slow encoders still finish
+const AUTO_TIMEOUT_MULTIPLIER: f64 = 10.0;
+const AUTO_TIMEOUT_FLOOR: Duration = Duration::from_secs(30 * 60);
+
+// Exact frame counting decodes the whole file, so it's only worth it for
+// inputs below both limits; everything else gets an estimate
+const EXACT_FRAME_COUNT_MAX_BYTES: u64 = 500 * 1024 * 1024;
+const EXACT_FRAME_COUNT_MAX_DURATION: f64 = 15.0 * 60.0;
+// The count is abandoned after roughly this many seconds per 1080p-minute
+// of input, clamped between the bounds below
+const FRAME_COUNT_SECS_PER_1080P_MINUTE: f64 = 2.0;
+const FRAME_COUNT_MIN_TIMEOUT: Duration = Duration::from_secs(5);
+const FRAME_COUNT_MAX_TIMEOUT: Duration = Duration::from_secs(60);
+
+// Free space on the output volume below which a running conversion warns
+pub const DISK_SPACE_THRESHOLD_MB: u64 = 500;
+// Progress updates between free space checks, about every five seconds
+const DISK_CHECK_INTERVAL: u32 = 10;
+/// Percentages at which a conversion reports a milestone unless configured
+pub const DEFAULT_PROGRESS_MILESTONES: [u32; 4] = [25, 50, 75, 100];
+
+// Parts split by size are cut at this share of the limit, since the
+// bitrate they are estimated from varies over a file
+const SPLIT_SIZE_HEADROOM: f64 = 0.9;
+// Assumed for the audio when only the video bitrate is set
+const SPLIT_AUDIO_BITRATE: u64 = 192_000;
+
+// Number of FFmpeg stderr lines kept for diagnostics
+pub const MAX_LOG_LINES: usize = 500;
+
+// Saved conversion logs kept in the logs folder; older ones are deleted
+const MAX_SAVED_LOGS: usize = 50;
+
+/// A fresh file in `logs_dir` for one conversion's FFmpeg log, named after
+/// the start time and output. Prunes the oldest logs beyond the cap first.
+pub fn new_log_file(logs_dir: &Path, output_file: &Path) -> Option<PathBuf> {
+    std::fs::create_dir_all(logs_dir).ok()?;
+    prune_logs(logs_dir, MAX_SAVED_LOGS.saturating_sub(1));
+
+    let started = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or_default();
+    let stem = output_file
+        .file_stem()
+        .map(|stem| sanitize_filename(&stem.to_string_lossy()))
+        .filter(|stem| !stem.is_empty())
+        .unwrap_or_else(|| "conversion".to_string());
+
+    Some(logs_dir.join(format!("{started}_{stem}.log")))
+}
+
+fn prune_logs(logs_dir: &Path, keep: usize) {
+    let Ok(entries) = std::fs::read_dir(logs_dir) else {
+        return;
+    };
+
+    let mut logs: Vec<(std::time::SystemTime, PathBuf)> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "log"))
+        .filter_map(|path| {
+            let modified = path
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .ok()?;
+            Some((modified, path))
+        })
+        .collect();
+    if logs.len() <= keep {
+        return;
+    }
+
+    logs.sort();
+    for (_, path) in &logs[..logs.len() - keep] {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+/// Checks that `output_file` is readable media covering as much time as
+/// `input_file`, within a second or 1% (whichever is larger)
+pub async fn verify_output(input_file: &Path, output_file: &Path) -> Result<(), String> {
+    let output = probe_media(output_file)
+        .await
+        .map_err(|err| format!("the output can't be read ({err})"))?;
+    if output.streams.is_empty() {
+        return Err("the output has no streams".to_string());
+    }
+
+    let input = probe_media(input_file).await?;
+    if let (Some(expected), Some(actual)) = (input.duration, output.duration) {
+        let tolerance = (expected * 0.01).max(1.0);
+        if (expected - actual).abs() > tolerance {
+            return Err(format!(
+                "the output is {} long but the input is {}",
+                format_duration(actual),
+                format_duration(expected)
+            ));
+        }
+    } else if input.duration.is_some() {
+        return Err("the output duration is unknown".to_string());
+    }
+
+    Ok(())
+}
+
+/// Renders a command the way it would be typed in a shell, quoting any
+/// argument that contains spaces or quotes
+fn format_command_line(cmd: &std::process::Command) -> String {
+    std::iter::once(cmd.get_program())
+        .chain(cmd.get_args())
+        .map(|arg| {
+            let arg = arg.to_string_lossy();
+            if !arg.is_empty() && !arg.contains(|c: char| c.is_whitespace() || "'\"".contains(c)) {
+                arg.to_string()
+            } else {
+                format!("\"{}\"", arg.replace('"', "\\\""))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Bounded, shareable buffer of the most recent FFmpeg stderr lines
+#[derive(Debug, Clone, Default)]
+pub struct LogBuffer {
+    lines: Arc<Mutex<VecDeque<String>>>,
+}
+
+impl LogBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&self, line: String) {
+        if let Ok(mut lines) = self.lines.lock() {
+            if lines.len() >= MAX_LOG_LINES {
+                lines.pop_front();
+            }
+            lines.push_back(line);
+        }
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        self.lines
+            .lock()
+            .map(|lines| lines.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct ConversionProgress {
@@ -12,248 +193,2268 @@ pub struct ConversionProgress {
     pub time_remaining: Option<Duration>,
     pub current_time: String,
     pub total_time: String,
+    pub fps: Option<f32>,
+    pub bitrate_kbps: Option<f32>,
+    pub speed: Option<f32>,
+    /// Whether the percentage comes from an estimated frame count
+    pub estimated: bool,
 }
 
-#[derive(Debug, Clone)]
+impl ConversionProgress {
+    /// The percentage for display, e.g. "45.0%" or "~45.0%" when estimated
+    pub fn percentage_label(&self) -> String {
+        let prefix = if self.estimated { "~" } else { "" };
+        format!("{prefix}{:.1}%", self.percentage)
+    }
+
+    /// Encoding speed against playback, e.g. "3.2× realtime", or "0.4× slow"
+    /// when the encode takes longer than the video lasts
+    pub fn speed_label(&self) -> Option<String> {
+        let speed = self.speed?;
+        Some(if speed >= 1.0 {
+            format!("{speed:.1}× realtime")
+        } else {
+            format!("{speed:.1}× slow")
+        })
+    }
+
+    pub fn is_realtime(&self) -> Option<bool> {
+        self.speed.map(|speed| speed >= 1.0)
+    }
+}
+
+// Progress below this doesn't produce a time remaining estimate
+const MIN_ETA_PERCENTAGE: f32 = 0.5;
+
+fn parse_positive(value: &str) -> Option<f32> {
+    value
+        .trim()
+        .parse::<f32>()
+        .ok()
+        .filter(|value| value.is_finite() && *value > 0.0)
+}
+
+/// Total number of video frames in the input, and how it was obtained
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameCount {
+    /// Counted by decoding the stream with `-count_frames`
+    Exact(u64),
+    /// Derived from the duration and average frame rate
+    Estimated(u64),
+}
+
+impl FrameCount {
+    fn frames(&self) -> u64 {
+        match self {
+            FrameCount::Exact(frames) | FrameCount::Estimated(frames) => *frames,
+        }
+    }
+}
+
+/// Accumulates the `key=value` lines of FFmpeg's `-progress` output and
+/// produces a `ConversionProgress` at the end of each block.
+pub struct ProgressParser {
+    total_duration: f64,
+    total_frames: Option<FrameCount>,
+    current_time_seconds: f64,
+    current_frame: u64,
+    fps: Option<f32>,
+    bitrate_kbps: Option<f32>,
+    speed: Option<f32>,
+}
+
+impl ProgressParser {
+    pub fn new(total_duration: f64) -> Self {
+        Self {
+            total_duration,
+            total_frames: None,
+            current_time_seconds: 0.0,
+            current_frame: 0,
+            fps: None,
+            bitrate_kbps: None,
+            speed: None,
+        }
+    }
+
+    /// Measures progress by frames instead of time when the total is known
+    pub fn with_total_frames(mut self, total_frames: Option<FrameCount>) -> Self {
+        self.total_frames = total_frames.filter(|count| count.frames() > 0);
+        self
+    }
+
+    pub fn parse_line(&mut self, line: &str, elapsed: Duration) -> Option<ConversionProgress> {
+        let line = line.trim();
+        if line.starts_with("frame=") && line.contains("time=") {
+            return Some(self.parse_stats_line(line, elapsed));
+        }
+
+        let (key, value) = line.split_once('=')?;
+        let value = value.trim();
+
+        match key {
+            // Despite the name, out_time_ms is in microseconds like out_time_us.
+            // Before the first frame both are "N/A" or a huge negative number.
+            "out_time_us" | "out_time_ms" => {
+                if let Ok(time_microseconds) = value.parse::<i64>()
+                    && time_microseconds >= 0
+                {
+                    self.current_time_seconds = time_microseconds as f64 / 1_000_000.0;
+                }
+            }
+            "frame" => {
+                if let Ok(frame) = value.parse::<u64>() {
+                    self.current_frame = frame;
+                }
+            }
+            // 0 fps and 0x speed are what FFmpeg reports before it has a
+            // measurement, so treat them like N/A
+            "fps" => self.fps = parse_positive(value),
+            "bitrate" => {
+                self.bitrate_kbps = value.strip_suffix("kbits/s").and_then(parse_positive);
+            }
+            "speed" => self.speed = value.strip_suffix('x').and_then(parse_positive),
+            "progress" => {
+                let mut progress = self.snapshot(elapsed);
+                if value == "end" {
+                    progress.percentage = 100.0;
+                    progress.time_remaining = None;
+                }
+                return Some(progress);
+            }
+            _ => {}
+        }
+
+        None
+    }
+
+    /// The status line FFmpeg prints to stderr without `-nostats`, like
+    /// `frame=  240 fps= 60 q=28.0 size=    1024kB time=00:00:08.00
+    /// bitrate=1048.6kbits/s speed=2.0x`. Values may be padded after the `=`.
+    fn parse_stats_line(&mut self, line: &str, elapsed: Duration) -> ConversionProgress {
+        let mut tokens = line.split_whitespace();
+        while let Some(token) = tokens.next() {
+            let Some((key, value)) = token.split_once('=') else {
+                continue;
+            };
+            let value = if value.is_empty() {
+                tokens.next().unwrap_or_default()
+            } else {
+                value
+            };
+
+            match key {
+                "frame" => {
+                    if let Ok(frame) = value.parse::<u64>() {
+                        self.current_frame = frame;
+                    }
+                }
+                // Negative before the first frame, which parse_timestamp rejects
+                "time" => {
+                    if let Some(seconds) = parse_timestamp(value) {
+                        self.current_time_seconds = seconds;
+                    }
+                }
+                "fps" => self.fps = parse_positive(value),
+                "bitrate" => {
+                    self.bitrate_kbps = value.strip_suffix("kbits/s").and_then(parse_positive);
+                }
+                "speed" => self.speed = value.strip_suffix('x').and_then(parse_positive),
+                _ => {}
+            }
+        }
+
+        self.snapshot(elapsed)
+    }
+
+    fn snapshot(&self, elapsed: Duration) -> ConversionProgress {
+        let percentage = if let Some(total_frames) = self.total_frames {
+            (self.current_frame as f64 / total_frames.frames() as f64 * 100.0) as f32
+        } else if self.total_duration > 0.0 {
+            (self.current_time_seconds / self.total_duration * 100.0) as f32
+        } else {
+            0.0
+        };
+
+        // Extrapolating from a sliver of progress gives absurd (or infinite)
+        // estimates, so wait until there is something to go on
+        let time_remaining = if (MIN_ETA_PERCENTAGE..100.0).contains(&percentage) {
+            let estimated_total = elapsed.as_secs_f64() * 100.0 / percentage as f64;
+            Duration::try_from_secs_f64(estimated_total - elapsed.as_secs_f64())
+                .ok()
+                .filter(|remaining| !remaining.is_zero())
+        } else {
+            None
+        };
+
+        ConversionProgress {
+            percentage: percentage.min(100.0),
+            time_remaining,
+            current_time: format_duration(self.current_time_seconds),
+            total_time: format_duration(self.total_duration),
+            fps: self.fps,
+            bitrate_kbps: self.bitrate_kbps,
+            speed: self.speed,
+            estimated: matches!(self.total_frames, Some(FrameCount::Estimated(_))),
+        }
+    }
+}
+
+#[derive(Debug)]
 pub enum ConversionMessage {
     Progress(ConversionProgress),
+    /// Progress passed `percent`, one of the task's milestones
+    Milestone {
+        percent: u32,
+        time_remaining: Option<Duration>,
+    },
+    Paused(bool),
+    /// Free bytes left on the output volume dropped below the threshold;
+    /// `paused` says whether the conversion was paused because of it
+    LowDiskSpace {
+        available: u64,
+        paused: bool,
+    },
+    /// One sample of the CRF search for a quality target; `chosen` marks the
+    /// CRF the conversion goes on with. `vmaf` is `None` if scoring failed.
+    CrfSearch {
+        crf: u32,
+        vmaf: Option<f64>,
+        chosen: bool,
+    },
+    /// The hardware encoder `encoder` failed with a driver or device error,
+    /// so the conversion starts over with the software `fallback`
+    HardwareFallback {
+        encoder: String,
+        fallback: String,
+    },
+    /// The trim of copied video starts between keyframes at `requested`:
+    /// either it now starts at `keyframe`, or with `reencoded` the video is
+    /// re-encoded to cut exactly
+    TrimAdjusted {
+        requested: f64,
+        keyframe: f64,
+        reencoded: bool,
+    },
+    /// Every part a split output was written to, in order, sent before
+    /// `Completed` with the first of them
+    OutputParts(Vec<PathBuf>),
+    /// Outcome of moving the input to the trash, sent just before `Completed`
+    SourceTrashed(Result<PathBuf, String>),
     Completed(PathBuf),
-    Error(String),
+    Cancelled,
+    Error(ConversionError),
+}
+
+// Number of trailing stderr lines attached to a failed conversion
+const ERROR_LOG_TAIL: usize = 20;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConversionError {
+    #[error("{0}")]
+    ProbeFailed(String),
+    #[error("Failed to parse video duration")]
+    DurationUnavailable,
+    #[error("Failed to start FFmpeg: {0}")]
+    SpawnFailed(#[source] std::io::Error),
+    #[error("Failed to capture FFmpeg output")]
+    OutputCaptureFailed,
+    #[error(
+        "Conversion timed out after {}; raise the conversion timeout under Help (0 disables the limit)",
+        format_duration(.0.as_secs_f64())
+    )]
+    TimedOut(Duration),
+    #[error("Failed to wait for FFmpeg process: {0}")]
+    WaitFailed(#[source] std::io::Error),
+    #[error("The output file is the input file; choose a different name or folder")]
+    OutputIsInput,
+    /// `command` is the full command line that was run, for reproducing the
+    /// failure. Ends by a signal are reported as `Killed` instead.
+    #[error("FFmpeg conversion failed")]
+    NonZeroExit {
+        code: Option<i32>,
+        command: String,
+        stderr: Vec<String>,
+    },
+    /// FFmpeg was ended by the OS; `signal` comes from `termination_signal`
+    #[error("{}", killed_message(*.signal))]
+    Killed {
+        signal: i32,
+        command: String,
+        stderr: Vec<String>,
+    },
+    #[error("Couldn't reach the quality target: {0}")]
+    QualitySearch(String),
+    #[error("Invalid image sequence: {0}")]
+    ImageSequence(String),
+    #[error("Parallel segment encoding failed: {0}")]
+    SegmentedEncode(String),
+    #[error("Can't split the output: {0}")]
+    SplitOutput(String),
+}
+
+/// Upper bound on how long FFmpeg may run before it is stopped
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ConversionTimeout {
+    /// Scaled from the input duration
+    #[default]
+    Auto,
+    Disabled,
+    Fixed(Duration),
+}
+
+impl ConversionTimeout {
+    pub fn limit(&self, input_duration: f64) -> Option<Duration> {
+        match self {
+            ConversionTimeout::Auto => Some(
+                Duration::from_secs_f64(input_duration.max(0.0) * AUTO_TIMEOUT_MULTIPLIER)
+                    .max(AUTO_TIMEOUT_FLOOR),
+            ),
+            ConversionTimeout::Disabled => None,
+            ConversionTimeout::Fixed(limit) => Some(*limit),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConversionControl {
+    Pause,
+    Resume,
+    Cancel,
+}
+
+pub struct ConversionTask {
+    pub input_file: PathBuf,
+    /// How `input_file` is opened; an image sequence's pattern is also its
+    /// `input_file`
+    pub input_source: InputSource,
+    pub output_file: PathBuf,
+    pub preset: ConversionPreset,
+    pub audio_track: Option<usize>,
+    pub test_duration: Option<Duration>,
+    pub control: Option<UnboundedReceiver<ConversionControl>>,
+    pub source_metadata: BTreeMap<String, String>,
+    pub log: LogBuffer,
+    pub grace_period: Duration,
+    pub timeout: ConversionTimeout,
+    pub cover_art: Option<PathBuf>,
+    pub trim: Option<Segment>,
+    /// Encoder a remux re-encodes its video with, set when a frame accurate
+    /// trim can't start on a keyframe
+    pub trim_encoder: Option<VideoCodec>,
+    pub log_file: Option<PathBuf>,
+    pub preserve_file_times: bool,
+    pub pause_on_low_disk: bool,
+    pub trash_source: bool,
+    /// Ascending percentages to report a `Milestone` at
+    pub milestones: Vec<u32>,
+    milestones_reached: usize,
+    pub sender: Sender<ConversionMessage>,
+}
+
+impl ConversionTask {
+    pub fn new(
+        input_file: PathBuf,
+        output_file: PathBuf,
+        preset: ConversionPreset,
+        sender: Sender<ConversionMessage>,
+    ) -> Self {
+        Self {
+            input_source: InputSource::File(input_file.clone()),
+            input_file,
+            output_file,
+            preset,
+            audio_track: None,
+            test_duration: None,
+            control: None,
+            source_metadata: BTreeMap::new(),
+            log: LogBuffer::new(),
+            grace_period: CANCEL_GRACE_PERIOD,
+            timeout: ConversionTimeout::Auto,
+            cover_art: None,
+            trim: None,
+            trim_encoder: None,
+            log_file: None,
+            preserve_file_times: false,
+            pause_on_low_disk: false,
+            trash_source: false,
+            milestones: Vec::new(),
+            milestones_reached: 0,
+            sender,
+        }
+    }
+
+    /// Read the input as `input_source`, e.g. a numbered image sequence
+    pub fn with_input_source(mut self, input_source: InputSource) -> Self {
+        self.input_file = input_source.path().to_path_buf();
+        self.input_source = input_source;
+        self
+    }
+
+    /// Keep only the given audio track (relative to the input's audio streams)
+    pub fn with_audio_track(mut self, audio_track: Option<usize>) -> Self {
+        self.audio_track = audio_track;
+        self
+    }
+
+    /// Percentages from 1 to 100 to report a `Milestone` at
+    pub fn with_milestones(mut self, mut milestones: Vec<u32>) -> Self {
+        milestones.retain(|percent| (1..=100).contains(percent));
+        milestones.sort_unstable();
+        milestones.dedup();
+        self.milestones = milestones;
+        self.milestones_reached = 0;
+        self
+    }
+
+    /// Sends `progress`, and a `Milestone` when it passes the next one; if
+    /// it jumps past several, only the highest is reported. Milestones stay
+    /// reached when the conversion starts over, e.g. after a fallback.
+    pub fn send_progress(&mut self, progress: ConversionProgress) {
+        let passed = self.milestones[self.milestones_reached..]
+            .iter()
+            .take_while(|percent| progress.percentage >= **percent as f32)
+            .count();
+        let time_remaining = progress.time_remaining;
+        let _ = self.sender.send(ConversionMessage::Progress(progress));
+
+        if passed > 0 {
+            self.milestones_reached += passed;
+            let _ = self.sender.send(ConversionMessage::Milestone {
+                percent: self.milestones[self.milestones_reached - 1],
+                time_remaining,
+            });
+        }
+    }
+
+    /// Only encode the first `test_duration` of the input
+    pub fn with_test_duration(mut self, test_duration: Option<Duration>) -> Self {
+        self.test_duration = test_duration;
+        self
+    }
+
+    /// Listen for pause/resume/cancel requests while FFmpeg runs
+    pub fn with_control(mut self, control: UnboundedReceiver<ConversionControl>) -> Self {
+        self.control = Some(control);
+        self
+    }
+
+    /// File-level tags of the input, used to re-add the kept keys when
+    /// metadata is filtered
+    pub fn with_source_metadata(mut self, source_metadata: BTreeMap<String, String>) -> Self {
+        self.source_metadata = source_metadata;
+        self
+    }
+
+    /// Collect FFmpeg's stderr log into a buffer the caller can read
+    pub fn with_log(mut self, log: LogBuffer) -> Self {
+        self.log = log;
+        self
+    }
+
+    /// How long FFmpeg may take to exit after a cancel before it is killed
+    pub fn with_grace_period(mut self, grace_period: Duration) -> Self {
+        self.grace_period = grace_period;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: ConversionTimeout) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Embed a PNG/JPEG image as cover art, in containers that support it
+    pub fn with_cover_art(mut self, cover_art: Option<PathBuf>) -> Self {
+        self.cover_art = cover_art;
+        self
+    }
+
+    /// Also write FFmpeg's stderr to this file
+    pub fn with_log_file(mut self, log_file: Option<PathBuf>) -> Self {
+        self.log_file = log_file;
+        self
+    }
+
+    /// Copy the input's filesystem timestamps onto the finished output
+    pub fn with_preserved_file_times(mut self, preserve_file_times: bool) -> Self {
+        self.preserve_file_times = preserve_file_times;
+        self
+    }
+
+    /// Pause FFmpeg when the output volume runs low on space, instead of
+    /// letting it fail once the disk is full
+    pub fn with_pause_on_low_disk(mut self, pause_on_low_disk: bool) -> Self {
+        self.pause_on_low_disk = pause_on_low_disk;
+        self
+    }
+
+    /// Move the input to the trash once the output is verified. Never done
+    /// for trimmed or test conversions, which only cover part of the input.
+    pub fn with_trash_source(mut self, trash_source: bool) -> Self {
+        self.trash_source = trash_source;
+        self
+    }
+
+    /// Convert only this part of the input
+    pub fn with_trim(mut self, trim: Option<Segment>) -> Self {
+        self.trim = trim;
+        self
+    }
+
+    /// How much of the input ends up in the output, capped by the trim and
+    /// the test duration
+    fn output_duration_limit(&self) -> Option<f64> {
+        let trim = self.trim.as_ref().map(Segment::duration);
+        let test = self.test_duration.map(|test| test.as_secs_f64());
+        match (trim, test) {
+            (Some(trim), Some(test)) => Some(trim.min(test)),
+            (limit, None) | (None, limit) => limit,
+        }
+    }
+
+    pub async fn execute(mut self) {
+        if !self.resolve_quality_target().await {
+            return;
+        }
+        self.resolve_trim().await;
+        let mut result = self.run_conversion().await;
+
+        // Retried once; a software failure is reported as it is
+        if let Err(ConversionError::NonZeroExit { stderr, .. }) = &result
+            && self.uses_hardware()
+            && is_hardware_failure(stderr)
+        {
+            let encoder = self
+                .preset
+                .video_codec
+                .encoder_name(self.preset.hardware_accel);
+            let fallback = self.preset.video_codec.ffmpeg_name();
+            self.log
+                .push(format!("{encoder} failed, retrying with {fallback}"));
+            let _ = self.sender.send(ConversionMessage::HardwareFallback {
+                encoder: encoder.to_string(),
+                fallback: fallback.to_string(),
+            });
+            self.preset.hardware_accel = HardwareAccel::None;
+            self.preset.hw_device_index = None;
+            result = self.run_conversion().await;
+        }
+
+        match result {
+            Ok(Some(output_path)) => self.report_success(output_path).await,
+            Ok(None) => {
+                let _ = self.sender.send(ConversionMessage::Cancelled);
+            }
+            Err(error) => {
+                let _ = self.sender.send(ConversionMessage::Error(error));
+            }
+        }
+    }
+
+    /// Replaces a quality target with the CRF found for it. Returns `false`
+    /// after reporting a cancelled or failed search.
+    pub async fn resolve_quality_target(&mut self) -> bool {
+        let Some(target) = self.preset.quality_target.take() else {
+            return true;
+        };
+        // Copied video has no CRF to search; validation already warned
+        if self.preset.mode == ConversionMode::Remux
+            || self.preset.video_codec == VideoCodec::Copy
+            || self.preset.disable_video
+        {
+            return true;
+        }
+
+        match search_crf(self, target).await {
+            Ok(Some(crf)) => {
+                apply_crf(&mut self.preset, crf);
+                true
+            }
+            Ok(None) => {
+                let _ = self.sender.send(ConversionMessage::Cancelled);
+                false
+            }
+            Err(error) => {
+                let _ = self.sender.send(ConversionMessage::Error(error));
+                false
+            }
+        }
+    }
+
+    /// Whether the video goes through a hardware encoder
+    pub fn uses_hardware(&self) -> bool {
+        self.preset.mode == ConversionMode::Convert
+            && self.preset.video_codec != VideoCodec::Copy
+            && self.preset.hardware_accel != HardwareAccel::None
+    }
+
+    /// Lines a trim of copied video up with the keyframes, which are the only
+    /// places a copy can start: the start moves back to the keyframe before
+    /// it, or for `TrimAccuracy::FrameAccurate` the video gets re-encoded
+    pub async fn resolve_trim(&mut self) {
+        let copies_video = self.preset.mode == ConversionMode::Remux
+            || self.preset.video_codec == VideoCodec::Copy;
+        if !copies_video || self.preset.disable_video || self.input_source.is_image_sequence() {
+            return;
+        }
+        let Some(requested) = self.trim.as_ref().map(|trim| trim.start) else {
+            return;
+        };
+
+        let keyframe = match keyframe_before(&self.input_file, requested).await {
+            Ok(Some(keyframe)) => keyframe,
+            Ok(None) => return,
+            Err(error) => {
+                self.log.push(error);
+                return;
+            }
+        };
+        // Within a frame or so of the keyframe the cut is as good as exact
+        if requested - keyframe < 0.01 {
+            return;
+        }
+
+        let reencoded = self.preset.trim_accuracy == TrimAccuracy::FrameAccurate;
+        if reencoded {
+            // Keep the source codec where the container allows it
+            let format = &self.preset.video_format;
+            let codec = probe_media(&self.input_file)
+                .await
+                .ok()
+                .and_then(|info| VideoCodec::from_codec_name(&info.video_stream()?.codec_name))
+                .filter(|codec| format.supports_video_codec(codec))
+                .unwrap_or_else(|| format.recommended_video_codec());
+            match self.preset.mode {
+                ConversionMode::Remux => self.trim_encoder = Some(codec),
+                ConversionMode::Convert => {
+                    self.preset.video_codec = codec;
+                    self.preset.hardware_accel = HardwareAccel::None;
+                    self.preset.hw_device_index = None;
+                }
+            }
+        } else if let Some(trim) = &mut self.trim {
+            trim.start = keyframe;
+        }
+        let _ = self.sender.send(ConversionMessage::TrimAdjusted {
+            requested,
+            keyframe,
+            reencoded,
+        });
+    }
+
+    /// Finishes a conversion whose output was written to `output_path`
+    pub async fn report_success(&self, output_path: PathBuf) {
+        // The output is fine either way, so a failure only gets logged
+        if self.preserve_file_times
+            && let Err(err) = copy_file_times(&self.input_file, &output_path)
+        {
+            self.log.push(err);
+        }
+        if self.trash_source
+            && self.trim.is_none()
+            && self.test_duration.is_none()
+            && self.output_split().is_none()
+        {
+            let result = self.trash_input(&output_path).await;
+            let _ = self.sender.send(ConversionMessage::SourceTrashed(result));
+        }
+        // FFmpeg's last progress line often stops just short of 100%
+        if self.milestones[self.milestones_reached..].contains(&100) {
+            let _ = self.sender.send(ConversionMessage::Milestone {
+                percent: 100,
+                time_remaining: None,
+            });
+        }
+        let _ = self.sender.send(ConversionMessage::Completed(output_path));
+    }
+
+    async fn trash_input(&self, output_file: &Path) -> Result<PathBuf, String> {
+        verify_output(&self.input_file, output_file)
+            .await
+            .map_err(|err| format!("Kept the source: {err}"))?;
+        if file_in_use(&self.input_file) {
+            return Err(format!(
+                "Kept the source: {} is open in another program",
+                self.input_file.display()
+            ));
+        }
+
+        let input_file = self.input_file.clone();
+        tokio::task::spawn_blocking(move || {
+            trash::delete(&input_file)
+                .map(|()| input_file)
+                .map_err(|e| format!("Failed to move the source to the trash: {}", e))
+        })
+        .await
+        .map_err(|e| format!("Failed to move the source to the trash: {}", e))?
+    }
+
+    /// How the output is split; trims and test conversions are always
+    /// written as one file
+    pub fn output_split(&self) -> Option<OutputSplit> {
+        self.preset
+            .split_output
+            .filter(|_| self.trim.is_none() && self.test_duration.is_none())
+    }
+
+    /// Seconds per part. A size limit becomes a length at the bitrate set
+    /// for re-encoding, or else at the input's bitrate: `-fs` caps the whole
+    /// output rather than each segment, so the segment muxer can't cut by
+    /// size itself.
+    async fn split_seconds(&self, split: OutputSplit) -> Result<f64, ConversionError> {
+        validate_output_split(split).map_err(ConversionError::SplitOutput)?;
+        let max_bytes = match split {
+            OutputSplit::Duration(minutes) => return Ok(f64::from(minutes) * 60.0),
+            OutputSplit::Size(megabytes) => megabytes * 1024 * 1024,
+        };
+
+        let encoded_video = self.preset.mode == ConversionMode::Convert
+            && self.preset.video_codec != VideoCodec::Copy
+            && !self.preset.disable_video;
+        let set_bitrate = encoded_video
+            .then(|| self.preset.video_bitrate.as_deref().and_then(parse_bitrate))
+            .flatten()
+            .map(|video| {
+                let audio = self
+                    .preset
+                    .audio_bitrate
+                    .as_deref()
+                    .and_then(parse_bitrate)
+                    .unwrap_or(SPLIT_AUDIO_BITRATE);
+                video + if self.preset.disable_audio { 0 } else { audio }
+            });
+        let bitrate = match set_bitrate {
+            Some(bitrate) => bitrate,
+            None => probe_media(&self.input_file)
+                .await
+                .ok()
+                .and_then(|info| info.bit_rate)
+                .filter(|bitrate| *bitrate > 0)
+                .ok_or_else(|| {
+                    ConversionError::SplitOutput(
+                        "the input's bitrate is unknown; split by duration instead".to_string(),
+                    )
+                })?,
+        };
+        Ok((max_bytes as f64 * 8.0 * SPLIT_SIZE_HEADROOM / bitrate as f64).max(1.0))
+    }
+
+    /// Numbered name of one part of a split output, counting from 0 like
+    /// the segment muxer
+    fn split_part(&self, index: usize) -> PathBuf {
+        let (stem, extension) = name_parts(&self.output_file);
+        self.output_file
+            .with_file_name(format!("{stem}_{index:03}{extension}"))
+    }
+
+    /// Output pattern for the segment muxer; a `%` in the name is escaped so
+    /// it isn't read as a number placeholder
+    fn split_pattern(&self) -> PathBuf {
+        let (stem, extension) = name_parts(&self.output_file);
+        self.output_file
+            .with_file_name(format!("{}_%03d{extension}", stem.replace('%', "%%")))
+    }
+
+    /// Parts of a split output written since `since`, in order. Older files
+    /// under the same names are left from an earlier run, not this one.
+    fn split_parts(&self, since: SystemTime) -> Vec<PathBuf> {
+        // Some filesystems store modification times in whole seconds or two
+        let since = since - Duration::from_secs(2);
+        (0..)
+            .map(|index| self.split_part(index))
+            .take_while(|part| {
+                std::fs::metadata(part)
+                    .and_then(|metadata| metadata.modified())
+                    .is_ok_and(|modified| modified >= since)
+            })
+            .collect()
+    }
+
+    /// Removes an incomplete output, or every part of a split one written
+    /// since `since`
+    async fn remove_output(&self, since: SystemTime) {
+        if self.output_split().is_none() {
+            let _ = tokio::fs::remove_file(&self.output_file).await;
+            return;
+        }
+        for part in self.split_parts(since) {
+            let _ = tokio::fs::remove_file(part).await;
+        }
+    }
+
+    /// Returns `Ok(None)` when the conversion was cancelled
+    async fn run_conversion(&mut self) -> Result<Option<PathBuf>, ConversionError> {
+        // FFmpeg would truncate the input while still reading it
+        let overwrites_input = if self.output_split().is_some() {
+            existing_split_parts(&self.output_file)
+                .iter()
+                .any(|part| is_same_file(&self.input_file, part))
+        } else {
+            is_same_file(&self.input_file, &self.output_file)
+        };
+        if overwrites_input {
+            return Err(ConversionError::OutputIsInput);
+        }
+
+        let split_seconds = match self.output_split() {
+            Some(split) => Some(self.split_seconds(split).await?),
+            None => None,
+        };
+        let started_at = SystemTime::now();
+
+        // Frames from still images can only be encoded, never copied
+        let sequence = match &self.input_source {
+            InputSource::ImageSequence { pattern, framerate } => {
+                if self.preset.mode == ConversionMode::Remux
+                    || self.preset.video_codec == VideoCodec::Copy
+                    || self.preset.disable_video
+                {
+                    return Err(ConversionError::ImageSequence(
+                        "choose a video codec to encode the images with".to_string(),
+                    ));
+                }
+                let scan = scan_image_sequence(pattern).map_err(ConversionError::ImageSequence)?;
+                Some((scan.frames, *framerate))
+            }
+            InputSource::File(_) => None,
+        };
+
+        // Batch items aren't probed up front, but filtering needs the source tags
+        if sequence.is_none()
+            && self.source_metadata.is_empty()
+            && self.preset.metadata_options.filters_file_metadata()
+            && let Ok(info) = probe_media(&self.input_file).await
+        {
+            self.source_metadata = info.tags;
+        }
+
+        // Build FFmpeg command
+        let mut cmd = AsyncCommand::new(ffmpeg_binary());
+        cmd.arg("-y") // Overwrite output file
+            .arg("-nostats") // The -progress output replaces the status line
+            .arg("-progress")
+            .arg("pipe:1") // Send progress to stdout, keeping stderr for the log
+            .stdin(Stdio::piped()) // FFmpeg quits cleanly on "q"
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true); // Don't leave FFmpeg running if the app exits first
+
+        let uses_hardware = self.uses_hardware();
+
+        if uses_hardware {
+            cmd.args(input_device_args(
+                self.preset.hardware_accel,
+                self.preset.hw_device_index,
+            ));
+        }
+
+        let mut input_options = Vec::new();
+        // Rebuild presentation timestamps the source lacks
+        if self.preset.fix_timestamps {
+            input_options.extend(["-fflags".to_string(), "+genpts".to_string()]);
+        }
+        // Seeking before -i is fast and, when re-encoding, frame accurate
+        if let Some(trim) = &self.trim {
+            input_options.extend(["-ss".to_string(), format!("{:.3}", trim.start)]);
+        }
+
+        cmd.args(&input_options).args(
+            self.input_source
+                .input_args()
+                .map_err(ConversionError::ImageSequence)?,
+        );
+
+        // -itsoffset shifts every stream of an input, so the audio is read
+        // from a second, shifted copy of the input
+        let audio_offset = shifted_audio_offset(&self.preset).filter(|_| sequence.is_none());
+        if let Some(offset) = audio_offset {
+            cmd.arg("-itsoffset")
+                .arg(format!("{offset:.3}"))
+                .args(&input_options)
+                .arg("-i")
+                .arg(&self.input_file);
+        }
+
+        // Subtitles next to cover art or shifted audio are mapped one by
+        // one, so only those the container can take
+        let info = if sequence.is_none() && (self.cover_art.is_some() || audio_offset.is_some()) {
+            probe_media(&self.input_file).await.ok()
+        } else {
+            None
+        };
+        let selection = StreamSelection::new(
+            &self.preset,
+            info.as_ref(),
+            self.audio_track,
+            audio_offset.is_some(),
+            self.cover_art.is_some(),
+        );
+        let attached_cover = selection.cover_input().is_some();
+        if let Some(cover) = self.cover_art.as_ref().filter(|_| attached_cover) {
+            cmd.arg("-i").arg(cover);
+        }
+        cmd.args(selection.map_args());
+        if audio_offset.is_some() {
+            // Tags and chapters come from the original input, unless a
+            // remux's metadata options clear them anyway
+            let metadata = &self.preset.metadata_options;
+            let remux = self.preset.mode == ConversionMode::Remux;
+            if !remux || (metadata.copy_file_metadata && !metadata.filters_file_metadata()) {
+                cmd.arg("-map_metadata").arg("0");
+            }
+            if !remux || metadata.copy_chapters {
+                cmd.arg("-map_chapters").arg("0");
+            }
+        }
+
+        self.apply_codec_options(&mut cmd, uses_hardware, sequence.is_some());
+
+        if self.preset.disable_video {
+            cmd.arg("-vn");
+        }
+        if self.preset.disable_audio {
+            cmd.arg("-an");
+        }
+
+        if let Some(cover) = &self.cover_art {
+            let is_png = cover_encoder(cover) == "png";
+
+            if attached_cover {
+                cmd.arg("-c:v:1")
+                    .arg(cover_encoder(cover))
+                    .arg("-disposition:v:1")
+                    .arg("attached_pic");
+            } else if self.preset.video_format == VideoFormat::Mkv {
+                let (mimetype, filename) = if is_png {
+                    ("image/png", "cover.png")
+                } else {
+                    ("image/jpeg", "cover.jpg")
+                };
+                cmd.arg("-attach")
+                    .arg(cover)
+                    .arg("-metadata:s:t")
+                    .arg(format!("mimetype={mimetype}"))
+                    .arg("-metadata:s:t")
+                    .arg(format!("filename={filename}"));
+            }
+        }
+
+        // MP4/MOV edit lists handle negative start times poorly in many players
+        if self.preset.fix_timestamps
+            && matches!(
+                self.preset.video_format,
+                VideoFormat::Mp4 | VideoFormat::Mov
+            )
+        {
+            cmd.arg("-avoid_negative_ts").arg("make_zero");
+        }
+
+        if self.preset.web_optimized && self.preset.video_format.supports_faststart() {
+            if split_seconds.is_some() {
+                cmd.arg("-segment_format_options")
+                    .arg("movflags=+faststart");
+            } else {
+                cmd.arg("-movflags").arg("+faststart");
+            }
+        }
+
+        if let Some(threads) = self.preset.threads {
+            cmd.arg("-threads").arg(threads.to_string());
+        }
+
+        cmd.args(&self.preset.extra_args);
+
+        let duration_limit = self.output_duration_limit();
+        if let Some(limit) = duration_limit {
+            cmd.arg("-t").arg(format!("{limit:.3}"));
+        }
+
+        // Each part starts at a keyframe, with timestamps from zero so it
+        // plays on its own
+        if let Some(seconds) = split_seconds {
+            cmd.arg("-f")
+                .arg("segment")
+                .arg("-segment_time")
+                .arg(format!("{seconds:.3}"))
+                .arg("-reset_timestamps")
+                .arg("1")
+                .arg(self.split_pattern());
+        } else {
+            cmd.arg(&self.output_file);
+        }
+
+        let command_line = format_command_line(cmd.as_std());
+
+        // Get total duration first
+        let mut total_duration = match sequence {
+            Some((frames, framerate)) => frames as f64 / f64::from(framerate),
+            None => self.get_video_duration().await?,
+        };
+        if let Some(trim) = &self.trim {
+            total_duration = (total_duration - trim.start).max(0.0);
+        }
+        if let Some(limit) = duration_limit {
+            total_duration = total_duration.min(limit);
+        }
+
+        // Start the conversion process
+        let mut child = cmd.spawn().map_err(ConversionError::SpawnFailed)?;
+
+        let (stdout, stderr) = take_output_pipes(&mut child).await?;
+
+        // Drain stderr concurrently: if its pipe fills up while we're only
+        // reading progress from stdout, FFmpeg blocks and both sides hang
+        let log = self.log.clone();
+        let log_file = self.log_file.clone();
+        // FFmpeg's stderr status lines stand in for -progress if it never
+        // reports anything on stdout
+        let (stats_sender, mut stats_lines) = tokio::sync::mpsc::unbounded_channel();
+        let stderr_drain = tokio::spawn(async move {
+            // The on-disk copy is best effort; losing it never fails the conversion
+            let mut file = match log_file {
+                Some(path) => tokio::fs::File::create(path).await.ok(),
+                None => None,
+            };
+            let mut lines = LossyLines::new(stderr);
+            while let Ok(Some(line)) = lines.next_line().await {
+                // Status lines overwrite each other with '\r'
+                if let Some(stats) = line.rsplit('\r').find(|stats| stats.starts_with("frame=")) {
+                    let _ = stats_sender.send(stats.to_string());
+                }
+                if let Some(writer) = &mut file
+                    && writer
+                        .write_all(format!("{line}\n").as_bytes())
+                        .await
+                        .is_err()
+                {
+                    file = None;
+                }
+                log.push(line);
+            }
+        });
+
+        let mut stdin = child.stdin.take();
+        let mut reader = LossyLines::new(stdout);
+        let start_time = Instant::now();
+
+        // Counting can take up to a minute, so it runs alongside the
+        // conversion and progress goes by time until it's done. Without video
+        // there are no frames to count progress by.
+        let disable_video = self.preset.disable_video;
+        let output_rate = self.output_frame_rate();
+        let partial = self.output_duration_limit().is_some();
+        let count_input = self.input_file.clone();
+        let mut frame_count = std::pin::pin!(async move {
+            if disable_video {
+                None
+            } else if let Some((frames, framerate)) = sequence {
+                let frames = output_frames(frames as u64, Some(f64::from(framerate)), output_rate);
+                Some(FrameCount::Exact(frames))
+            } else {
+                count_frames(&count_input, total_duration, partial, output_rate).await
+            }
+        });
+        let mut counting = true;
+        let mut parser = ProgressParser::new(total_duration);
+        let mut paused = false;
+        let mut paused_at = None;
+        let mut progress_updates: u32 = 0;
+        let mut progress_reported = false;
+        let mut low_disk_reported = false;
+        let output_dir = self
+            .output_file
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."))
+            .to_path_buf();
+
+        // Time spent paused doesn't count towards the timeout
+        let timeout = self.timeout.limit(total_duration);
+        let mut deadline = timeout.map(|limit| tokio::time::Instant::now() + limit);
+
+        // Parse progress output while listening for control requests
+        loop {
+            tokio::select! {
+                total_frames = &mut frame_count, if counting => {
+                    counting = false;
+                    parser = parser.with_total_frames(total_frames);
+                }
+                _ = sleep_until_deadline(deadline), if !paused => {
+                    tokio::spawn(async move {
+                        while let Ok(Some(_)) = reader.next_line().await {}
+                    });
+                    stop_gracefully(&mut child, stdin.take(), self.grace_period).await;
+                    let _ = stderr_drain.await;
+                    self.remove_output(started_at).await;
+                    return Err(ConversionError::TimedOut(timeout.unwrap_or_default()));
+                }
+                Some(stats) = stats_lines.recv(), if !progress_reported => {
+                    if let Some(progress) = parser.parse_line(&stats, start_time.elapsed()) {
+                        self.send_progress(progress);
+                    }
+                }
+                line = reader.next_line() => {
+                    let Ok(Some(line)) = line else {
+                        break;
+                    };
+                    if let Some(progress) = parser.parse_line(&line, start_time.elapsed()) {
+                        progress_reported = true;
+                        self.send_progress(progress);
+
+                        progress_updates += 1;
+                        if progress_updates.is_multiple_of(DISK_CHECK_INTERVAL)
+                            && let Some(available) = available_disk_space(&output_dir)
+                        {
+                            let low = available < DISK_SPACE_THRESHOLD_MB * 1024 * 1024;
+                            if low && !low_disk_reported {
+                                let pause = self.pause_on_low_disk
+                                    && !paused
+                                    && child.id().is_some_and(|pid| suspend_process(pid).is_ok());
+                                if pause {
+                                    paused = true;
+                                    paused_at = Some(Instant::now());
+                                    let _ = self.sender.send(ConversionMessage::Paused(true));
+                                }
+                                let _ = self.sender.send(ConversionMessage::LowDiskSpace {
+                                    available,
+                                    paused: pause,
+                                });
+                            }
+                            // Warn again if space runs low a second time
+                            low_disk_reported = low;
+                        }
+                    }
+                }
+                command = next_control(&mut self.control) => {
+                    let Some(command) = command else {
+                        self.control = None;
+                        continue;
+                    };
+                    let Some(pid) = child.id() else {
+                        continue;
+                    };
+
+                    match command {
+                        ConversionControl::Pause if !paused && suspend_process(pid).is_ok() => {
+                            paused = true;
+                            paused_at = Some(Instant::now());
+                            let _ = self.sender.send(ConversionMessage::Paused(true));
+                        }
+                        ConversionControl::Resume if paused && resume_process(pid).is_ok() => {
+                            paused = false;
+                            if let (Some(deadline), Some(paused_at)) =
+                                (deadline.as_mut(), paused_at.take())
+                            {
+                                *deadline += paused_at.elapsed();
+                            }
+                            let _ = self.sender.send(ConversionMessage::Paused(false));
+                        }
+                        ConversionControl::Cancel => {
+                            if paused {
+                                let _ = resume_process(pid);
+                            }
+                            // Keep stdout drained while FFmpeg finalizes the output
+                            tokio::spawn(async move {
+                                while let Ok(Some(_)) = reader.next_line().await {}
+                            });
+                            stop_gracefully(&mut child, stdin.take(), self.grace_period).await;
+                            let _ = stderr_drain.await;
+                            // A cancelled output is incomplete, so don't leave it behind
+                            self.remove_output(started_at).await;
+                            return Ok(None);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        // Wait for the process to complete
+        let status = child.wait().await.map_err(ConversionError::WaitFailed)?;
+        let _ = stderr_drain.await;
+
+        if status.success() && split_seconds.is_some() {
+            let parts = self.split_parts(started_at);
+            let Some(first) = parts.first().cloned() else {
+                return Err(ConversionError::SplitOutput(
+                    "FFmpeg didn't write any parts".to_string(),
+                ));
+            };
+            let _ = self.sender.send(ConversionMessage::OutputParts(parts));
+            Ok(Some(first))
+        } else if status.success() {
+            Ok(Some(self.output_file.clone()))
+        } else {
+            let lines = self.log.lines();
+            let tail_start = lines.len().saturating_sub(ERROR_LOG_TAIL);
+            let stderr = lines[tail_start..].to_vec();
+            Err(match termination_signal(&status) {
+                Some(signal) => ConversionError::Killed {
+                    signal,
+                    command: command_line,
+                    stderr,
+                },
+                None => ConversionError::NonZeroExit {
+                    code: status.code(),
+                    command: command_line,
+                    stderr,
+                },
+            })
+        }
+    }
+
+    async fn get_video_duration(&self) -> Result<f64, ConversionError> {
+        probe_media(&self.input_file)
+            .await
+            .map_err(ConversionError::ProbeFailed)?
+            .duration
+            .ok_or(ConversionError::DurationUnavailable)
+    }
+
+    /// The `-r` rate the video is encoded at, if one is set
+    fn output_frame_rate(&self) -> Option<f64> {
+        if self.preset.mode != ConversionMode::Convert
+            || self.preset.video_codec == VideoCodec::Copy
+        {
+            return None;
+        }
+        let rate = self.preset.frame_rate.as_deref()?.trim();
+        rate.parse::<f64>()
+            .ok()
+            .filter(|rate| rate.is_finite() && *rate > 0.0)
+            .or_else(|| parse_frame_rate(rate))
+    }
+}
+
+/// Source frames converted to the number FFmpeg reports once `-r` has
+/// dropped or duplicated frames to reach `output_rate`
+fn output_frames(frames: u64, source_rate: Option<f64>, output_rate: Option<f64>) -> u64 {
+    match (source_rate, output_rate) {
+        (Some(source), Some(output)) => (frames as f64 * output / source).round() as u64,
+        _ => frames,
+    }
+}
+
+/// Frame total for progress reporting. Small inputs are counted exactly
+/// under a timeout scaled by resolution and duration; larger ones, partial
+/// outputs (test encodes, trims), or counts that take too long fall back to
+/// `duration × frame rate`. `None` when there is no video stream to count.
+async fn count_frames(
+    input_file: &Path,
+    duration: f64,
+    partial: bool,
+    output_rate: Option<f64>,
+) -> Option<FrameCount> {
+    let info = probe_media(input_file).await.ok()?;
+    let stream = info.video_stream()?;
+    let source_rate = stream.avg_frame_rate.as_deref().and_then(parse_frame_rate);
+    let estimate = output_rate
+        .or(source_rate)
+        .map(|rate| FrameCount::Estimated((duration * rate).round() as u64));
+
+    let size = std::fs::metadata(input_file)
+        .map(|metadata| metadata.len())
+        .unwrap_or(u64::MAX);
+    // Test encodes and trims cover only part of the file, so an exact count
+    // would overshoot; the estimate already uses the shorter duration
+    if partial || size > EXACT_FRAME_COUNT_MAX_BYTES || duration > EXACT_FRAME_COUNT_MAX_DURATION {
+        return estimate;
+    }
+    if let Some(frames) = cached_frame_count(input_file) {
+        return Some(FrameCount::Exact(output_frames(
+            frames,
+            source_rate,
+            output_rate,
+        )));
+    }
+
+    let pixels = f64::from(stream.width.unwrap_or(1920)) * f64::from(stream.height.unwrap_or(1080));
+    let budget = FRAME_COUNT_SECS_PER_1080P_MINUTE * (duration / 60.0) * pixels / (1920.0 * 1080.0);
+    let timeout =
+        Duration::from_secs_f64(budget).clamp(FRAME_COUNT_MIN_TIMEOUT, FRAME_COUNT_MAX_TIMEOUT);
+
+    let count = AsyncCommand::new(ffprobe_binary())
+        .arg("-v")
+        .arg("error")
+        .arg("-select_streams")
+        .arg("v:0")
+        .arg("-count_frames")
+        .arg("-show_entries")
+        .arg("stream=nb_read_frames")
+        .arg("-of")
+        .arg("csv=p=0")
+        .arg(input_file)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+
+    let counted = match tokio::time::timeout(timeout, count).await {
+        Ok(Ok(output)) if output.status.success() => String::from_utf8_lossy(&output.stdout)
+            .trim()
+            .parse::<u64>()
+            .ok(),
+        _ => None,
+    };
+    match counted {
+        Some(frames) => {
+            remember_frame_count(input_file, frames);
+            Some(FrameCount::Exact(output_frames(
+                frames,
+                source_rate,
+                output_rate,
+            )))
+        }
+        None => estimate,
+    }
+}
+
+/// FFmpeg's progress and log pipes. Without both we can't report anything,
+/// so don't leave an orphaned FFmpeg process writing the output behind our
+/// back.
+async fn take_output_pipes(
+    child: &mut Child,
+) -> Result<(ChildStdout, ChildStderr), ConversionError> {
+    match (child.stdout.take(), child.stderr.take()) {
+        (Some(stdout), Some(stderr)) => Ok((stdout, stderr)),
+        _ => {
+            let _ = child.kill().await;
+            Err(ConversionError::OutputCaptureFailed)
+        }
+    }
+}
+
+pub async fn next_control(
+    control: &mut Option<UnboundedReceiver<ConversionControl>>,
+) -> Option<ConversionControl> {
+    match control {
+        Some(receiver) => receiver.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Line reader that replaces invalid UTF-8 instead of failing. FFmpeg echoes
+/// file names in whatever encoding they have and may print garbage when it
+/// crashes; `Lines` would stop at the first bad byte, after which an
+/// undrained pipe fills up and FFmpeg blocks.
+struct LossyLines<R> {
+    reader: BufReader<R>,
+    buffer: Vec<u8>,
+}
+
+impl<R: AsyncRead + Unpin> LossyLines<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            reader: BufReader::new(reader),
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Cancel safe like `Lines::next_line`: a partly read line stays in the
+    /// buffer for the next call
+    async fn next_line(&mut self) -> std::io::Result<Option<String>> {
+        let read = self.reader.read_until(b'\n', &mut self.buffer).await?;
+        if read == 0 && self.buffer.is_empty() {
+            return Ok(None);
+        }
+
+        let mut line = std::mem::take(&mut self.buffer);
+        if line.last() == Some(&b'\n') {
+            line.pop();
+        }
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        Ok(Some(String::from_utf8_lossy(&line).into_owned()))
+    }
+}
+
+pub async fn sleep_until_deadline(deadline: Option<tokio::time::Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Ask FFmpeg to quit so it can finalize the output, killing it if it doesn't
+/// exit within the grace period
+pub async fn stop_gracefully(child: &mut Child, stdin: Option<ChildStdin>, grace_period: Duration) {
+    if let Some(mut stdin) = stdin {
+        let _ = stdin.write_all(b"q").await;
+        let _ = stdin.flush().await;
+    }
+
+    if tokio::time::timeout(grace_period, child.wait())
+        .await
+        .is_err()
+    {
+        let _ = child.kill().await;
+    }
+}
+
+/// Subtitle streams (relative to the input's subtitles) the output container
+/// can take: those it holds as they are when remuxing, or any text format
+/// when converting, since only text turns into the container's own format
+fn fitting_subtitle_streams(info: &MediaInfo, preset: &ConversionPreset) -> Vec<usize> {
+    let remux = preset.mode == ConversionMode::Remux;
+    info.streams_of_type("subtitle")
+        .iter()
+        .enumerate()
+        .filter(|(_, stream)| {
+            let codec = stream.codec_name.as_str();
+            match (&preset.video_format, remux) {
+                (VideoFormat::Avi, _) => false,
+                (VideoFormat::Mkv, true) => true,
+                (VideoFormat::Mp4 | VideoFormat::Mov, true) => codec == "mov_text",
+                (VideoFormat::Webm, true) => codec == "webvtt",
+                (_, false) => !IMAGE_SUBTITLE_CODECS.contains(&codec),
+            }
+        })
+        .map(|(index, _)| index)
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamAction {
+    Copy,
+    Encode(&'static str),
+    Drop,
+}
+
+#[derive(Debug, Clone)]
+pub struct StreamPlan {
+    pub codec_type: String,
+    pub description: String,
+    pub action: StreamAction,
+}
+
+impl StreamAction {
+    pub fn describe(&self) -> String {
+        match self {
+            StreamAction::Copy => "copy".to_string(),
+            StreamAction::Encode(encoder) => format!("encode ({encoder})"),
+            StreamAction::Drop => "dropped".to_string(),
+        }
+    }
+}
+
+/// Audio the `-map` arguments pick
+#[derive(Debug, Clone, PartialEq)]
+pub enum MappedAudio {
+    /// Every audio track
+    All,
+    /// One track, `copies` times when extra audio tracks are encoded from
+    /// it; unset is the first track, if there is one
+    Track { track: Option<usize>, copies: usize },
+}
+
+/// Input streams that go into the output. The `-map` arguments and the
+/// stream preview are both built from this, so the preview shows what is
+/// actually written.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamSelection {
+    /// No `-map`: FFmpeg keeps the first video stream, the audio stream with
+    /// the most channels and the first subtitle stream
+    Default,
+    /// Everything but data streams (timecodes, GPS tracks), which make MKV
+    /// and WebM refuse the output and rarely matter
+    AllButData,
+    /// Streams mapped one by one
+    Mapped {
+        /// Every video stream rather than only the first
+        all_video: bool,
+        audio: MappedAudio,
+        /// Input the audio is read from; 1 is the copy shifted by an audio offset
+        audio_input: usize,
+        /// Subtitle streams, relative to the input's subtitles
+        subtitles: Vec<usize>,
+        /// Input holding cover art added as an attached picture
+        cover_input: Option<usize>,
+    },
+}
+
+impl StreamSelection {
+    /// Streams the output gets for `preset`. `info` decides which subtitles
+    /// are kept next to cover art or shifted audio, and `cover_art` is
+    /// whether a cover image is added.
+    pub fn new(
+        preset: &ConversionPreset,
+        info: Option<&MediaInfo>,
+        audio_track: Option<usize>,
+        audio_offset: bool,
+        cover_art: bool,
+    ) -> Self {
+        let audio_input = usize::from(audio_offset);
+        let extra_audio = preset.extra_audio_encodes().len();
+        let track = MappedAudio::Track {
+            track: audio_track,
+            copies: extra_audio + 1,
+        };
+        let fitting_subtitles = || {
+            info.map(|info| fitting_subtitle_streams(info, preset))
+                .unwrap_or_default()
+        };
+        // MP4/MOV take the cover as a second video stream, MKV as an attachment
+        let attached_cover = cover_art
+            && !preset.disable_video
+            && matches!(preset.video_format, VideoFormat::Mp4 | VideoFormat::Mov);
+
+        if attached_cover {
+            // Mapped explicitly so FFmpeg's default selection can't pick the
+            // cover as the main video stream, keeping every audio track and
+            // the subtitles the container can hold
+            StreamSelection::Mapped {
+                all_video: false,
+                audio: if audio_track.is_none() && extra_audio == 0 {
+                    MappedAudio::All
+                } else {
+                    track
+                },
+                audio_input,
+                subtitles: fitting_subtitles(),
+                cover_input: Some(audio_input + 1),
+            }
+        } else if audio_track.is_some() || extra_audio > 0 {
+            // A selected audio track, or the copies extra tracks encode,
+            // next to the main video and the subtitles the container can hold
+            StreamSelection::Mapped {
+                all_video: false,
+                audio: track,
+                audio_input,
+                subtitles: fitting_subtitles(),
+                cover_input: None,
+            }
+        } else if audio_offset {
+            // Video and subtitles from the original input, every audio
+            // track from the shifted copy
+            StreamSelection::Mapped {
+                all_video: true,
+                audio: MappedAudio::All,
+                audio_input,
+                subtitles: fitting_subtitles(),
+                cover_input: None,
+            }
+        } else if preset.keep_all_streams && preset.mode == ConversionMode::Remux {
+            StreamSelection::AllButData
+        } else {
+            StreamSelection::Default
+        }
+    }
+
+    pub fn cover_input(&self) -> Option<usize> {
+        match self {
+            StreamSelection::Mapped { cover_input, .. } => *cover_input,
+            _ => None,
+        }
+    }
+
+    pub fn map_args(&self) -> Vec<String> {
+        let mut maps = Vec::new();
+        match self {
+            StreamSelection::Default => {}
+            StreamSelection::AllButData => maps.extend(["0".to_string(), "-0:d?".to_string()]),
+            StreamSelection::Mapped {
+                all_video,
+                audio,
+                audio_input,
+                subtitles,
+                cover_input,
+            } => {
+                maps.push(if *all_video { "0:v?" } else { "0:v:0?" }.to_string());
+                match audio {
+                    MappedAudio::All => maps.push(format!("{audio_input}:a?")),
+                    MappedAudio::Track { track, copies } => {
+                        let audio = match track {
+                            Some(track) => format!("{audio_input}:a:{track}"),
+                            None => format!("{audio_input}:a:0?"),
+                        };
+                        maps.extend(std::iter::repeat_n(audio, *copies));
+                    }
+                }
+                maps.extend(subtitles.iter().map(|index| format!("0:s:{index}")));
+                maps.extend(cover_input.map(|input| format!("{input}:v:0")));
+            }
+        }
+        maps.into_iter()
+            .flat_map(|map| ["-map".to_string(), map])
+            .collect()
+    }
+}
+
+/// Seconds the audio is shifted by, when the settings shift it at all
+pub fn shifted_audio_offset(preset: &ConversionPreset) -> Option<f64> {
+    preset
+        .audio_offset_ms
+        .filter(|offset| *offset != 0 && !preset.disable_audio)
+        .and_then(|offset| audio_offset_seconds(offset).ok())
+}
+
+/// Encoder for a cover image: PNG stays PNG, anything else is JPEG
+fn cover_encoder(cover: &Path) -> &'static str {
+    if cover
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("png"))
+    {
+        "png"
+    } else {
+        "mjpeg"
+    }
+}
+
+/// Predict what FFmpeg will do with each input stream for the given settings,
+/// from the same `StreamSelection` and codec arguments `ConversionTask` uses.
+/// There is one plan per input stream, in order, followed by the extra
+/// audio tracks and the cover art.
+pub fn plan_streams(
+    preset: &ConversionPreset,
+    info: &MediaInfo,
+    audio_track: Option<usize>,
+    cover_art: Option<&Path>,
+) -> Vec<StreamPlan> {
+    let remux = preset.mode == ConversionMode::Remux;
+
+    let video_action = if preset.disable_video {
+        StreamAction::Drop
+    } else if remux || preset.video_codec == VideoCodec::Copy {
+        StreamAction::Copy
+    } else {
+        StreamAction::Encode(preset.video_codec.encoder_name(preset.hardware_accel))
+    };
+    let audio_action = if preset.disable_audio {
+        StreamAction::Drop
+    } else if remux || preset.audio_codec == AudioCodec::Copy {
+        StreamAction::Copy
+    } else {
+        StreamAction::Encode(preset.audio_codec.ffmpeg_name())
+    };
+    let subtitle_action = match (&preset.video_format, remux) {
+        (VideoFormat::Avi, _) => StreamAction::Drop,
+        (_, true) => StreamAction::Copy,
+        (VideoFormat::Mkv, false) => StreamAction::Encode("ass"),
+        (VideoFormat::Webm, false) => StreamAction::Encode("webvtt"),
+        (VideoFormat::Mp4 | VideoFormat::Mov, false) => StreamAction::Encode("mov_text"),
+    };
+
+    let selection = StreamSelection::new(
+        preset,
+        Some(info),
+        audio_track,
+        shifted_audio_offset(preset).is_some(),
+        cover_art.is_some(),
+    );
+    // Audio stream kept (and encoded again for extra tracks); `None` with
+    // every track kept
+    let kept_audio = match &selection {
+        StreamSelection::Default => info
+            .audio_streams()
+            .iter()
+            .enumerate()
+            .rev()
+            .max_by_key(|(_, stream)| stream.channels.unwrap_or(0))
+            .map(|(i, _)| i),
+        StreamSelection::Mapped {
+            audio: MappedAudio::Track { track, .. },
+            ..
+        } => Some(track.unwrap_or(0)),
+        _ => None,
+    };
+    let source_audio = kept_audio
+        .and_then(|index| info.audio_streams().get(index).copied())
+        .map(|stream| stream.describe());
+
+    let mut video_seen = 0;
+    let mut audio_seen = 0;
+    let mut subtitle_seen = 0;
+
+    let mut plans: Vec<StreamPlan> = info
+        .streams
+        .iter()
+        .map(|stream| {
+            let action = match stream.codec_type.as_str() {
+                "video" => {
+                    video_seen += 1;
+                    let kept = match &selection {
+                        StreamSelection::AllButData => true,
+                        StreamSelection::Mapped { all_video, .. } => *all_video || video_seen == 1,
+                        StreamSelection::Default => video_seen == 1,
+                    };
+                    if kept {
+                        video_action.clone()
+                    } else {
+                        StreamAction::Drop
+                    }
+                }
+                "audio" => {
+                    let index = audio_seen;
+                    audio_seen += 1;
+                    if kept_audio.is_none_or(|kept| kept == index) {
+                        audio_action.clone()
+                    } else {
+                        StreamAction::Drop
+                    }
+                }
+                "subtitle" => {
+                    let index = subtitle_seen;
+                    subtitle_seen += 1;
+                    let kept = match &selection {
+                        StreamSelection::AllButData => true,
+                        StreamSelection::Mapped { subtitles, .. } => subtitles.contains(&index),
+                        StreamSelection::Default => index == 0,
+                    };
+                    if kept {
+                        subtitle_action.clone()
+                    } else {
+                        StreamAction::Drop
+                    }
+                }
+                "data" => StreamAction::Drop,
+                _ if selection == StreamSelection::AllButData => StreamAction::Copy,
+                _ => StreamAction::Drop,
+            };
+
+            StreamPlan {
+                codec_type: stream.codec_type.clone(),
+                description: format!("{} {}", stream.codec_type, stream.describe()),
+                action,
+            }
+        })
+        .collect();
+
+    if let Some(source_audio) = &source_audio {
+        plans.extend(
+            preset
+                .extra_audio_encodes()
+                .iter()
+                .map(|encode| StreamPlan {
+                    codec_type: "audio".to_string(),
+                    description: format!("extra audio from {source_audio}"),
+                    action: StreamAction::Encode(encode.codec.ffmpeg_name()),
+                }),
+        );
+    }
+    match cover_art {
+        Some(cover) if selection.cover_input().is_some() => plans.push(StreamPlan {
+            codec_type: "video".to_string(),
+            description: "cover art (attached picture)".to_string(),
+            action: StreamAction::Encode(cover_encoder(cover)),
+        }),
+        Some(_) if preset.video_format == VideoFormat::Mkv => plans.push(StreamPlan {
+            codec_type: "attachment".to_string(),
+            description: "cover art".to_string(),
+            action: StreamAction::Copy,
+        }),
+        _ => {}
+    }
+    plans
+}
+
+/// True when converting would re-encode the input into the codecs and
+/// container it already uses, with no scaling, rate, bitrate or quality
+/// changes
+pub fn is_conversion_noop(input_file: &Path, info: &MediaInfo, preset: &ConversionPreset) -> bool {
+    if preset.mode != ConversionMode::Convert
+        || preset.disable_video
+        || preset.disable_audio
+        || preset.audio_offset_ms.is_some_and(|offset| offset != 0)
+        || preset.deinterlace.is_some()
+        || preset.crop.is_some()
+        || !preset.extra_audio.is_empty()
+        || preset.video_bitrate.is_some()
+        || preset.audio_bitrate.is_some()
+        || preset.split_output.is_some()
+        || preset.resolution.is_some()
+        || preset.frame_rate.is_some()
+        || preset
+            .crf
+            .is_some_and(|crf| Some(crf) != preset.video_codec.default_crf())
+        || preset.quality_target.is_some()
+        || preset
+            .codec_params
+            .as_deref()
+            .is_some_and(|params| !params.trim().is_empty())
+        || !preset.extra_args.is_empty()
+        || preset.fix_timestamps
+        || preset.web_optimized
+    {
+        return false;
+    }
+
+    // ffprobe reports e.g. "mov,mp4,m4a,3gp,3g2,mj2" or "matroska,webm"
+    let container = match preset.video_format {
+        VideoFormat::Mp4 => "mp4",
+        VideoFormat::Mkv => "matroska",
+        VideoFormat::Mov => "mov",
+        VideoFormat::Avi => "avi",
+        VideoFormat::Webm => "webm",
+    };
+    if !info.format_name.split(',').any(|name| name == container) {
+        return false;
+    }
+    // MP4 and MOV share a demuxer and so a format name; the extension says
+    // which of the two the input is, so MOV to MP4 is still a real remux
+    let input_format = input_file
+        .extension()
+        .and_then(|extension| VideoFormat::from_extension(&extension.to_string_lossy()));
+    if input_format.as_ref() != Some(&preset.video_format) {
+        return false;
+    }
+
+    let video_codec = match preset.video_codec {
+        VideoCodec::H264 => Some("h264"),
+        VideoCodec::H265 => Some("hevc"),
+        VideoCodec::VP9 => Some("vp9"),
+        VideoCodec::AV1 => Some("av1"),
+        VideoCodec::Copy => None,
+    };
+    let audio_codec = match preset.audio_codec {
+        AudioCodec::Aac => Some("aac"),
+        AudioCodec::Mp3 => Some("mp3"),
+        AudioCodec::Flac => Some("flac"),
+        AudioCodec::Pcm16 => Some("pcm_s16le"),
+        AudioCodec::Opus => Some("opus"),
+        AudioCodec::Vorbis => Some("vorbis"),
+        AudioCodec::Ac3 => Some("ac3"),
+        AudioCodec::Eac3 => Some("eac3"),
+        AudioCodec::Copy => None,
+    };
+
+    let matches = |codec_type: &str, expected: Option<&str>| {
+        info.streams_of_type(codec_type)
+            .iter()
+            .all(|stream| expected.is_none_or(|codec| stream.codec_name == codec))
+    };
+
+    !info.streams.is_empty() && matches("video", video_codec) && matches("audio", audio_codec)
+}
+
+/// Grab a single frame at `position` seconds, e.g. for use as cover art
+pub async fn extract_poster_frame(
+    input_file: &Path,
+    position: f64,
+    output_file: &Path,
+) -> Result<(), String> {
+    let output = AsyncCommand::new(ffmpeg_binary())
+        .arg("-y")
+        .arg("-v")
+        .arg("error")
+        .arg("-ss")
+        .arg(format!("{position:.3}"))
+        .arg("-i")
+        .arg(input_file)
+        .arg("-frames:v")
+        .arg("1")
+        .arg(output_file)
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .map_err(|e| format!("Failed to start FFmpeg: {}", e))?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err("FFmpeg could not extract a frame".to_string())
+    }
+}
+
+/// Size of each filmstrip frame; inputs are letterboxed to fit
+pub const FILMSTRIP_FRAME_SIZE: [usize; 2] = [160, 90];
+
+/// Extracts up to `count` evenly spaced frames as RGBA pixels of
+/// `FILMSTRIP_FRAME_SIZE`, seeking to each one instead of decoding the whole
+/// input. Short inputs yield fewer frames, as do positions FFmpeg can't
+/// decode a frame at.
+pub async fn extract_filmstrip(
+    input_file: &Path,
+    duration: f64,
+    count: usize,
+) -> Result<Vec<Vec<u8>>, String> {
+    if duration <= 0.0 || count == 0 {
+        return Ok(Vec::new());
+    }
+
+    // Without this a clip shorter than `count` half-seconds repeats frames
+    let count = count.min((duration * 2.0).ceil() as usize).max(1);
+    let step = duration / count as f64;
+    let frames = join_all(
+        (0..count)
+            .map(|i| extract_frame(input_file, (i as f64 + 0.5) * step, FILMSTRIP_FRAME_SIZE)),
+    )
+    .await;
+
+    let frames: Vec<Vec<u8>> = frames.into_iter().filter_map(Result::ok).collect();
+    if frames.is_empty() {
+        return Err("FFmpeg could not extract preview frames".to_string());
+    }
+    Ok(frames)
+}
+
+/// One frame at `position` seconds as RGBA pixels of `size`, letterboxed to
+/// fit, so frames of different resolutions line up with each other
+pub async fn extract_frame(
+    input_file: &Path,
+    position: f64,
+    size: [usize; 2],
+) -> Result<Vec<u8>, String> {
+    let [width, height] = size;
+    let filter = format!(
+        "scale={width}:{height}:force_original_aspect_ratio=decrease,\
+         pad={width}:{height}:(ow-iw)/2:(oh-ih)/2"
+    );
+
+    let output = AsyncCommand::new(ffmpeg_binary())
+        .arg("-v")
+        .arg("error")
+        .arg("-ss")
+        .arg(format!("{position:.3}"))
+        .arg("-i")
+        .arg(input_file)
+        .arg("-an")
+        .arg("-sn")
+        .arg("-vf")
+        .arg(filter)
+        .arg("-frames:v")
+        .arg("1")
+        .arg("-f")
+        .arg("rawvideo")
+        .arg("-pix_fmt")
+        .arg("rgba")
+        .arg("pipe:1")
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| format!("Failed to start FFmpeg: {}", e))?;
+
+    let expected = width * height * 4;
+    if !output.status.success() || output.stdout.len() < expected {
+        return Err(format!(
+            "FFmpeg could not extract a frame at {} from {}",
+            format_duration(position),
+            input_file.display()
+        ));
+    }
+    Ok(output.stdout[..expected].to_vec())
+}
+
+pub fn check_ffmpeg_installation() -> Result<String, String> {
+    let output = Command::new(ffmpeg_binary())
+        .arg("-version")
+        .output()
+        .map_err(|_| "FFmpeg not found in PATH".to_string())?;
+
+    if output.status.success() {
+        let version_info = String::from_utf8_lossy(&output.stdout);
+        if let Some(first_line) = version_info.lines().next() {
+            Ok(first_line.to_string())
+        } else {
+            Err("Unable to parse FFmpeg version".to_string())
+        }
+    } else {
+        Err("FFmpeg command failed".to_string())
+    }
+}
+
+// Extensions replaced when the container changes; anything else after a dot
+// is treated as part of the name (e.g. "holiday.2024")
+const MEDIA_EXTENSIONS: &[&str] = &[
+    "mp4", "m4v", "mkv", "mov", "avi", "webm", "wav", "m4a", "ogg", "mp3", "flac", "opus",
+];
+
+/// Strips every trailing media extension, so `movie.mp4` and `movie.mp4.mkv`
+/// both become `movie` while `movie.tar.gz` is left alone
+pub fn strip_media_extensions(name: &str) -> &str {
+    let mut stem = name;
+    while let Some((rest, extension)) = stem.rsplit_once('.') {
+        let known = MEDIA_EXTENSIONS
+            .iter()
+            .any(|known| known.eq_ignore_ascii_case(extension));
+        if rest.is_empty() || !known {
+            break;
+        }
+        stem = rest;
+    }
+    stem
+}
+
+pub fn generate_output_filename(input_file: &Path, format: &VideoFormat, suffix: &str) -> PathBuf {
+    let mut output = input_file.to_path_buf();
+    let stem = input_file.file_stem().unwrap_or_default().to_string_lossy();
+
+    if !suffix.is_empty() {
+        output.set_file_name(format!("{stem}{suffix}.{}", format.extension()));
+    } else {
+        output.set_extension(format.extension());
+    }
+
+    avoid_input_collision(input_file, output)
+}
+
+/// Returns `output` unchanged unless it would overwrite `input`, in which case
+/// `_converted` (and then a counter) is appended to the file stem
+pub fn avoid_input_collision(input: &Path, output: PathBuf) -> PathBuf {
+    if !is_same_file(input, &output) {
+        return output;
+    }
+
+    let stem = output
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let extension = output
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+
+    let mut candidate = output.with_file_name(format!("{stem}_converted{extension}"));
+    let mut n = 2;
+    while is_same_file(input, &candidate) {
+        candidate = output.with_file_name(format!("{stem}_converted_{n}{extension}"));
+        n += 1;
+    }
+    candidate
+}
+
+/// First of `movie_2.mp4`, `movie_3.mp4`, ... that doesn't exist yet
+pub fn next_free_name(output: &Path) -> PathBuf {
+    free_name(output, |candidate| candidate.exists())
 }
 
-pub struct ConversionTask {
-    pub input_file: PathBuf,
-    pub output_file: PathBuf,
-    pub preset: ConversionPreset,
-    pub sender: Sender<ConversionMessage>,
+/// Like `next_free_name`, for a split output: the name is free only when
+/// none of its numbered parts exist either
+pub fn next_free_split_name(output: &Path) -> PathBuf {
+    free_name(output, |candidate| {
+        candidate.exists() || !existing_split_parts(candidate).is_empty()
+    })
 }
 
-impl ConversionTask {
-    pub fn new(
-        input_file: PathBuf,
-        output_file: PathBuf,
-        preset: ConversionPreset,
-        sender: Sender<ConversionMessage>,
-    ) -> Self {
-        Self {
-            input_file,
-            output_file,
-            preset,
-            sender,
+/// First of `movie_2.mp4`, `movie_3.mp4`, ... that isn't in `planned`,
+/// the outputs other conversions are going to write
+pub fn unplanned_name(output: &Path, planned: &[PathBuf]) -> PathBuf {
+    free_name(output, |candidate| {
+        planned.iter().any(|file| file == candidate)
+    })
+}
+
+fn free_name(output: &Path, taken: impl Fn(&Path) -> bool) -> PathBuf {
+    let (stem, extension) = name_parts(output);
+    let mut n = 2;
+    loop {
+        let candidate = output.with_file_name(format!("{stem}_{n}{extension}"));
+        if !taken(&candidate) {
+            return candidate;
         }
+        n += 1;
     }
+}
 
-    pub async fn execute(self) {
-        let result = self.run_conversion().await;
+/// The file stem, and the extension with its dot or empty
+fn name_parts(path: &Path) -> (String, String) {
+    let stem = path
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    (stem, extension)
+}
 
-        match result {
-            Ok(output_path) => {
-                let _ = self.sender.send(ConversionMessage::Completed(output_path));
-            }
-            Err(error) => {
-                let _ = self.sender.send(ConversionMessage::Error(error));
-            }
-        }
-    }
+/// Files already named like the parts of `output` split by the segment
+/// muxer, `<stem>_000<ext>` onwards, in order. A new split run writes over
+/// them.
+pub fn existing_split_parts(output: &Path) -> Vec<PathBuf> {
+    let (stem, extension) = name_parts(output);
+    let prefix = format!("{stem}_");
+    let folder = match output.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let Ok(entries) = std::fs::read_dir(folder) else {
+        return Vec::new();
+    };
 
-    async fn run_conversion(&self) -> Result<PathBuf, String> {
-        // Build FFmpeg command
-        let mut cmd = AsyncCommand::new("ffmpeg");
-        cmd.arg("-i")
-            .arg(&self.input_file)
-            .arg("-y") // Overwrite output file
-            .arg("-progress")
-            .arg("pipe:2") // Send progress to stderr
-            .stdout(Stdio::null())
-            .stderr(Stdio::piped());
+    let mut parts: Vec<(u64, PathBuf)> = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let name = entry.file_name().into_string().ok()?;
+            let number = name.strip_prefix(&prefix)?.strip_suffix(&extension)?;
+            if number.len() < 3 || !number.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            Some((number.parse().ok()?, output.with_file_name(&name)))
+        })
+        .collect();
+    parts.sort();
+    parts.into_iter().map(|(_, part)| part).collect()
+}
 
-        // Add codec arguments based on preset
+impl ConversionTask {
+    /// Codecs and per-stream options for the output, in either mode; images
+    /// from a sequence are encoded to a pixel format players can decode
+    fn apply_codec_options(
+        &self,
+        cmd: &mut AsyncCommand,
+        uses_hardware: bool,
+        image_sequence: bool,
+    ) {
+        let extra_audio = self.preset.extra_audio_encodes();
         match self.preset.mode {
             ConversionMode::Convert => {
                 // Video codec
                 if self.preset.video_codec != VideoCodec::Copy {
-                    cmd.arg("-c:v").arg(self.preset.video_codec.ffmpeg_name());
+                    cmd.arg("-c:v").arg(
+                        self.preset
+                            .video_codec
+                            .encoder_name(self.preset.hardware_accel),
+                    );
+
+                    if uses_hardware {
+                        cmd.args(encoder_device_args(
+                            self.preset.hardware_accel,
+                            self.preset.hw_device_index,
+                        ));
+                    }
+
+                    if let Some(option) = self
+                        .preset
+                        .video_codec
+                        .params_option(self.preset.hardware_accel)
+                        && let Some(params) = self
+                            .preset
+                            .codec_params
+                            .as_deref()
+                            .and_then(|params| parse_codec_params(params).ok())
+                        && !params.is_empty()
+                    {
+                        cmd.arg(option).arg(params);
+                    }
 
-                    // Video bitrate
-                    if let Some(ref bitrate) = self.preset.video_bitrate {
-                        if !bitrate.is_empty() {
-                            cmd.arg("-b:v").arg(bitrate);
+                    // Video bitrate, otherwise constant quality; hardware
+                    // encoders have quality options of their own
+                    if let Some(ref bitrate) = self.preset.video_bitrate
+                        && !bitrate.is_empty()
+                    {
+                        cmd.arg("-b:v").arg(bitrate);
+                    } else if !uses_hardware
+                        && let Some(crf) = self.preset.crf.or(self.preset.video_codec.default_crf())
+                    {
+                        cmd.arg("-crf").arg(crf.to_string());
+                        // libvpx and libaom otherwise treat the CRF as a cap
+                        // under a bitrate
+                        if matches!(self.preset.video_codec, VideoCodec::VP9 | VideoCodec::AV1) {
+                            cmd.arg("-b:v").arg("0");
                         }
                     }
 
+                    // Deinterlace and crop first, so a resolution scales the
+                    // cropped picture. Only the main video is filtered, never
+                    // an attached cover.
+                    let accel = if uses_hardware {
+                        self.preset.hardware_accel
+                    } else {
+                        HardwareAccel::None
+                    };
+                    if let Some(filter) =
+                        video_filter(self.preset.deinterlace, self.preset.crop, accel)
+                    {
+                        cmd.arg("-filter:v:0").arg(filter);
+                    }
+
                     // Resolution
-                    if let Some(ref resolution) = self.preset.resolution {
-                        if !resolution.is_empty() {
-                            cmd.arg("-s").arg(resolution);
-                        }
+                    if let Some(ref resolution) = self.preset.resolution
+                        && !resolution.is_empty()
+                    {
+                        cmd.arg("-s").arg(resolution);
                     }
 
                     // Frame rate
-                    if let Some(ref frame_rate) = self.preset.frame_rate {
-                        if !frame_rate.is_empty() {
-                            cmd.arg("-r").arg(frame_rate);
-                        }
+                    if let Some(ref frame_rate) = self.preset.frame_rate
+                        && !frame_rate.is_empty()
+                    {
+                        cmd.arg("-r").arg(frame_rate);
+                    }
+
+                    // Images are often full-chroma RGB or 4:4:4, which many
+                    // players can't decode; extra arguments may still override
+                    if image_sequence && !uses_hardware {
+                        cmd.arg("-pix_fmt").arg("yuv420p");
                     }
                 } else {
                     cmd.arg("-c:v").arg("copy");
                 }
 
-                // Audio codec
+                // Audio codec, for only the first track when there are more
+                let main_audio = if extra_audio.is_empty() { "" } else { ":0" };
                 if self.preset.audio_codec != AudioCodec::Copy {
-                    cmd.arg("-c:a").arg(self.preset.audio_codec.ffmpeg_name());
+                    cmd.arg(format!("-c:a{main_audio}"))
+                        .arg(self.preset.audio_codec.ffmpeg_name());
 
                     // Audio bitrate
-                    if let Some(ref bitrate) = self.preset.audio_bitrate {
-                        if !bitrate.is_empty() {
-                            cmd.arg("-b:a").arg(bitrate);
-                        }
+                    if let Some(ref bitrate) = self.preset.audio_bitrate
+                        && !bitrate.is_empty()
+                    {
+                        cmd.arg(format!("-b:a{main_audio}")).arg(bitrate);
                     }
                 } else {
-                    cmd.arg("-c:a").arg("copy");
+                    cmd.arg(format!("-c:a{main_audio}")).arg("copy");
+                }
+
+                for (index, encode) in (1..).zip(extra_audio) {
+                    cmd.arg(format!("-c:a:{index}"))
+                        .arg(encode.codec.ffmpeg_name());
+                    if let Some(bitrate) = encode.bitrate.as_deref().filter(|b| !b.is_empty()) {
+                        cmd.arg(format!("-b:a:{index}")).arg(bitrate);
+                    }
+                    if let Some(channels) = encode.channels {
+                        cmd.arg(format!("-ac:a:{index}")).arg(channels.to_string());
+                    }
                 }
             }
             ConversionMode::Remux => {
                 // Just copy streams for remuxing
                 cmd.arg("-c").arg("copy");
+                if let Some(codec) = &self.trim_encoder {
+                    cmd.arg("-c:v:0").arg(codec.ffmpeg_name());
+                }
 
                 // Handle metadata options
-                self.apply_metadata_options(&mut cmd);
-            }
-        }
-
-        cmd.arg(&self.output_file);
-
-        // Get total duration first
-        let total_duration = self.get_video_duration().await?;
-
-        // Start the conversion process
-        let mut child = cmd
-            .spawn()
-            .map_err(|e| format!("Failed to start FFmpeg: {}", e))?;
-
-        let stderr = child
-            .stderr
-            .take()
-            .ok_or("Failed to capture FFmpeg stderr")?;
-
-        let mut reader = BufReader::new(stderr).lines();
-        let start_time = Instant::now();
-
-        // Parse progress output
-        while let Ok(Some(line)) = reader.next_line().await {
-            if line.starts_with("out_time_ms=") {
-                if let Some(time_str) = line.strip_prefix("out_time_ms=") {
-                    if let Ok(time_microseconds) = time_str.parse::<u64>() {
-                        let current_time_seconds = time_microseconds as f64 / 1_000_000.0;
-                        let percentage = if total_duration > 0.0 {
-                            (current_time_seconds / total_duration * 100.0) as f32
-                        } else {
-                            0.0
-                        };
-
-                        let elapsed = start_time.elapsed();
-                        let time_remaining = if percentage > 0.0 {
-                            let estimated_total = elapsed.as_secs_f64() * 100.0 / percentage as f64;
-                            let remaining = estimated_total - elapsed.as_secs_f64();
-                            if remaining > 0.0 {
-                                Some(Duration::from_secs_f64(remaining))
-                            } else {
-                                None
-                            }
-                        } else {
-                            None
-                        };
-
-                        let progress = ConversionProgress {
-                            percentage: percentage.min(100.0),
-                            time_remaining,
-                            current_time: format_duration(current_time_seconds),
-                            total_time: format_duration(total_duration),
-                        };
-
-                        let _ = self.sender.send(ConversionMessage::Progress(progress));
-                    }
-                }
+                self.apply_metadata_options(cmd);
             }
         }
-
-        // Wait for the process to complete
-        let status = child
-            .wait()
-            .await
-            .map_err(|e| format!("Failed to wait for FFmpeg process: {}", e))?;
-
-        if status.success() {
-            Ok(self.output_file.clone())
-        } else {
-            Err("FFmpeg conversion failed".to_string())
-        }
-    }
-
-    async fn get_video_duration(&self) -> Result<f64, String> {
-        let output = AsyncCommand::new("ffprobe")
-            .arg("-v")
-            .arg("quiet")
-            .arg("-show_entries")
-            .arg("format=duration")
-            .arg("-of")
-            .arg("csv=p=0")
-            .arg(&self.input_file)
-            .output()
-            .await
-            .map_err(|e| format!("Failed to run ffprobe: {}", e))?;
-
-        let duration_str = String::from_utf8_lossy(&output.stdout);
-        duration_str
-            .trim()
-            .parse::<f64>()
-            .map_err(|_| "Failed to parse video duration".to_string())
+        self.apply_file_tags(cmd);
+        self.apply_dispositions(cmd);
     }
-}
-
-pub fn check_ffmpeg_installation() -> Result<String, String> {
-    let output = Command::new("ffmpeg")
-        .arg("-version")
-        .output()
-        .map_err(|_| "FFmpeg not found in PATH".to_string())?;
 
-    if output.status.success() {
-        let version_info = String::from_utf8_lossy(&output.stdout);
-        if let Some(first_line) = version_info.lines().next() {
-            Ok(first_line.to_string())
-        } else {
-            Err("Unable to parse FFmpeg version".to_string())
+    /// Sets the file title and comment; given after `-map_metadata`, they
+    /// replace copied values of the same keys
+    fn apply_file_tags(&self, cmd: &mut AsyncCommand) {
+        for (key, value) in self.preset.metadata_options.file_tags() {
+            if is_safe_metadata_value(value) {
+                cmd.arg("-metadata").arg(format!("{key}={value}"));
+            }
         }
-    } else {
-        Err("FFmpeg command failed".to_string())
-    }
-}
-
-pub fn generate_output_filename(input_file: &PathBuf, format: &VideoFormat) -> PathBuf {
-    let mut output = input_file.clone();
-    output.set_extension(format.extension());
-
-    // If the extension is the same, add "_converted" to avoid overwriting
-    if output == *input_file {
-        let stem = input_file.file_stem().unwrap_or_default();
-        let new_name = format!(
-            "{}_converted.{}",
-            stem.to_string_lossy(),
-            format.extension()
-        );
-        output.set_file_name(new_name);
     }
 
-    output
-}
-
-impl ConversionTask {
     fn apply_metadata_options(&self, cmd: &mut AsyncCommand) {
         let metadata = &self.preset.metadata_options;
 
         if !metadata.copy_file_metadata {
             // Clear file-level metadata
             cmd.arg("-map_metadata").arg("-1");
+        } else if metadata.filters_file_metadata() {
+            // Clear everything, then put back only the keys the user kept
+            cmd.arg("-map_metadata").arg("-1");
+            let tags = metadata.file_tags();
+            for (key, value) in &self.source_metadata {
+                if metadata.keeps_key(key)
+                    && !tags.iter().any(|(tag, _)| tag.eq_ignore_ascii_case(key))
+                    && is_valid_metadata_key(key)
+                    && is_safe_metadata_value(value)
+                {
+                    cmd.arg("-metadata").arg(format!("{key}={value}"));
+                }
+            }
         }
 
         if !metadata.copy_chapters {
@@ -266,20 +2467,18 @@ impl ConversionTask {
             cmd.arg("-map").arg("-0:t");
         }
 
-        // Set stream languages if specified
-        if !metadata.video_language.is_empty() && metadata.video_language != "und" {
-            cmd.arg("-metadata:s:v:0")
-                .arg(format!("language={}", metadata.video_language));
-        }
-
-        if !metadata.audio_language.is_empty() && metadata.audio_language != "und" {
-            cmd.arg("-metadata:s:a:0")
-                .arg(format!("language={}", metadata.audio_language));
-        }
-
-        if !metadata.subtitle_language.is_empty() && metadata.subtitle_language != "und" {
-            cmd.arg("-metadata:s:s:0")
-                .arg(format!("language={}", metadata.subtitle_language));
+        // Set stream languages if specified; only known codes are written,
+        // so a hand-edited preset can't smuggle text into the argument
+        for (specifier, language) in [
+            ("-metadata:s:v:0", &metadata.video_language),
+            ("-metadata:s:a:0", &metadata.audio_language),
+            ("-metadata:s:s:0", &metadata.subtitle_language),
+        ] {
+            if let Some(code) = MetadataOptions::normalize_language(language)
+                && code != "und"
+            {
+                cmd.arg(specifier).arg(format!("language={code}"));
+            }
         }
 
         // Set stream titles if specified
@@ -298,9 +2497,73 @@ impl ConversionTask {
                 .arg(format!("title={}", metadata.subtitle_title));
         }
     }
+
+    /// Default and forced flags for output tracks, set in either mode
+    fn apply_dispositions(&self, cmd: &mut AsyncCommand) {
+        let metadata = &self.preset.metadata_options;
+        if self.preset.video_format.supports_dispositions() {
+            // The last matching specifier wins, so a new default track first
+            // clears the flags copied from the source on the others
+            for target in [DispositionTarget::Audio, DispositionTarget::Subtitle] {
+                if metadata.dispositions.iter().any(|disposition| {
+                    disposition.target == target && disposition.flags.iter().any(|f| f == "default")
+                }) {
+                    cmd.arg(format!("-disposition:{}", target.specifier()))
+                        .arg("0");
+                }
+            }
+            for disposition in &metadata.dispositions {
+                let flags: Vec<&str> = disposition
+                    .flags
+                    .iter()
+                    .map(String::as_str)
+                    .filter(|flag| is_valid_disposition(flag))
+                    .collect();
+                if !flags.is_empty() {
+                    cmd.arg(format!(
+                        "-disposition:{}:{}",
+                        disposition.target.specifier(),
+                        disposition.index
+                    ))
+                    .arg(flags.join("+"));
+                }
+            }
+        }
+    }
+}
+
+/// Message for `ConversionError::Killed`, with advice when memory ran out
+fn killed_message(signal: i32) -> String {
+    let termination = describe_termination(signal);
+    if is_out_of_memory(signal) {
+        format!(
+            "FFmpeg was killed ({termination}), most likely because the system ran out of \
+             memory; try fewer threads, a lower resolution or closing other programs"
+        )
+    } else {
+        format!("FFmpeg was terminated ({termination})")
+    }
+}
+
+/// A milestone as people read it, e.g. "50% complete, ETA 22 min"
+pub fn milestone_text(percent: u32, time_remaining: Option<Duration>) -> String {
+    let Some(remaining) = time_remaining.filter(|_| percent < 100) else {
+        return format!("{percent}% complete");
+    };
+
+    let minutes = remaining.as_secs().div_ceil(60).max(1);
+    if minutes < 60 {
+        format!("{percent}% complete, ETA {minutes} min")
+    } else {
+        format!(
+            "{percent}% complete, ETA {} h {} min",
+            minutes / 60,
+            minutes % 60
+        )
+    }
 }
 
-fn format_duration(seconds: f64) -> String {
+pub fn format_duration(seconds: f64) -> String {
     let total_seconds = seconds as u64;
     let hours = total_seconds / 3600;
     let minutes = (total_seconds % 3600) / 60;
@@ -312,3 +2575,562 @@ fn format_duration(seconds: f64) -> String {
         format!("{:02}:{:02}", minutes, secs)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::presets::{AudioEncode, QualityTarget, StreamDisposition};
+    use crate::probe::StreamInfo;
+
+    #[cfg(unix)]
+    fn spawn_sleep(stdout: Stdio, stderr: Stdio) -> Child {
+        AsyncCommand::new("sh")
+            .arg("-c")
+            .arg("sleep 30")
+            .stdout(stdout)
+            .stderr(stderr)
+            .kill_on_drop(true)
+            .spawn()
+            .expect("sh should start")
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn missing_pipe_kills_the_process_and_fails() {
+        let mut child = spawn_sleep(Stdio::piped(), Stdio::null());
+
+        let result = take_output_pipes(&mut child).await;
+
+        assert!(matches!(result, Err(ConversionError::OutputCaptureFailed)));
+        let status = child.wait().await.expect("child should be reaped");
+        assert!(!status.success());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn both_pipes_are_handed_over() {
+        let mut child = spawn_sleep(Stdio::piped(), Stdio::piped());
+
+        assert!(take_output_pipes(&mut child).await.is_ok());
+        let _ = child.kill().await;
+    }
+
+    fn task() -> ConversionTask {
+        let (sender, _receiver) = std::sync::mpsc::channel();
+        ConversionTask::new(
+            PathBuf::from("input.mkv"),
+            PathBuf::from("output.mp4"),
+            ConversionPreset::default(),
+            sender,
+        )
+    }
+
+    #[test]
+    fn track_flags_are_set_when_converting() {
+        let mut task = task();
+        task.preset.metadata_options.dispositions = vec![StreamDisposition {
+            target: DispositionTarget::Audio,
+            index: 1,
+            flags: vec!["default".to_string()],
+        }];
+        let mut cmd = AsyncCommand::new("ffmpeg");
+        task.apply_codec_options(&mut cmd, false, false);
+
+        let args: Vec<String> = cmd
+            .as_std()
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect();
+        assert!(args.windows(2).any(|pair| pair == ["-c:v", "libx264"]));
+        assert!(args.windows(2).any(|pair| pair == ["-disposition:a", "0"]));
+        assert!(
+            args.windows(2)
+                .any(|pair| pair == ["-disposition:a:1", "default"])
+        );
+    }
+
+    #[test]
+    fn test_conversion_limits_the_output() {
+        assert_eq!(task().output_duration_limit(), None);
+
+        let test = task().with_test_duration(Some(Duration::from_secs(10)));
+        assert_eq!(test.output_duration_limit(), Some(10.0));
+    }
+
+    #[test]
+    fn shorter_trim_wins_over_the_test_duration() {
+        let trim = Segment {
+            start: 5.0,
+            end: 8.0,
+            label: None,
+        };
+        let task = task()
+            .with_test_duration(Some(Duration::from_secs(10)))
+            .with_trim(Some(trim));
+        assert_eq!(task.output_duration_limit(), Some(3.0));
+    }
+
+    fn media(format_name: &str, codecs: &[(&str, &str)]) -> MediaInfo {
+        MediaInfo {
+            format_name: format_name.to_string(),
+            streams: codecs
+                .iter()
+                .map(|(codec_type, codec_name)| StreamInfo {
+                    codec_type: codec_type.to_string(),
+                    codec_name: codec_name.to_string(),
+                    ..StreamInfo::default()
+                })
+                .collect(),
+            ..MediaInfo::default()
+        }
+    }
+
+    fn h264_aac(format: VideoFormat) -> ConversionPreset {
+        ConversionPreset {
+            video_format: format,
+            video_codec: VideoCodec::H264,
+            audio_codec: AudioCodec::Aac,
+            ..ConversionPreset::default()
+        }
+    }
+
+    #[test]
+    fn same_container_and_codecs_is_a_noop() {
+        let info = media(
+            "mov,mp4,m4a,3gp,3g2,mj2",
+            &[("video", "h264"), ("audio", "aac")],
+        );
+        assert!(is_conversion_noop(
+            Path::new("clip.mp4"),
+            &info,
+            &h264_aac(VideoFormat::Mp4)
+        ));
+    }
+
+    #[test]
+    fn mov_to_mp4_is_not_a_noop() {
+        let info = media(
+            "mov,mp4,m4a,3gp,3g2,mj2",
+            &[("video", "h264"), ("audio", "aac")],
+        );
+        assert!(!is_conversion_noop(
+            Path::new("clip.mov"),
+            &info,
+            &h264_aac(VideoFormat::Mp4)
+        ));
+    }
+
+    #[test]
+    fn quality_and_muxing_changes_are_not_a_noop() {
+        let info = media(
+            "mov,mp4,m4a,3gp,3g2,mj2",
+            &[("video", "h264"), ("audio", "aac")],
+        );
+        let noop =
+            |preset: ConversionPreset| is_conversion_noop(Path::new("clip.mp4"), &info, &preset);
+        let preset = h264_aac(VideoFormat::Mp4);
+
+        assert!(noop(ConversionPreset {
+            crf: VideoCodec::H264.default_crf(),
+            ..preset.clone()
+        }));
+        assert!(!noop(ConversionPreset {
+            crf: Some(18),
+            ..preset.clone()
+        }));
+        assert!(!noop(ConversionPreset {
+            quality_target: Some(QualityTarget { vmaf: 95.0 }),
+            ..preset.clone()
+        }));
+        assert!(!noop(ConversionPreset {
+            codec_params: Some("aq-mode=3".to_string()),
+            ..preset.clone()
+        }));
+        assert!(noop(ConversionPreset {
+            codec_params: Some(" ".to_string()),
+            ..preset.clone()
+        }));
+        assert!(!noop(ConversionPreset {
+            extra_args: vec!["-tune".to_string(), "film".to_string()],
+            ..preset.clone()
+        }));
+        assert!(!noop(ConversionPreset {
+            fix_timestamps: true,
+            ..preset.clone()
+        }));
+        assert!(!noop(ConversionPreset {
+            web_optimized: true,
+            ..preset
+        }));
+    }
+
+    #[test]
+    fn output_extension_follows_the_container() {
+        assert_eq!(
+            generate_output_filename(Path::new("movie.mp4"), &VideoFormat::Mkv, ""),
+            Path::new("movie.mkv")
+        );
+        assert_eq!(
+            generate_output_filename(Path::new("movie"), &VideoFormat::Mp4, ""),
+            Path::new("movie.mp4")
+        );
+    }
+
+    #[test]
+    fn only_media_extensions_are_stripped() {
+        assert_eq!(strip_media_extensions("movie.mp4"), "movie");
+        assert_eq!(strip_media_extensions("movie.MP4.mkv"), "movie");
+        assert_eq!(strip_media_extensions("movie"), "movie");
+        assert_eq!(strip_media_extensions("movie.tar.gz"), "movie.tar.gz");
+        assert_eq!(strip_media_extensions("holiday.2024.mov"), "holiday.2024");
+        assert_eq!(strip_media_extensions(".mp4"), ".mp4");
+    }
+
+    #[test]
+    fn only_subtitles_the_container_can_hold_are_mapped() {
+        let info = media(
+            "matroska,webm",
+            &[
+                ("video", "h264"),
+                ("subtitle", "subrip"),
+                ("subtitle", "hdmv_pgs_subtitle"),
+                ("subtitle", "mov_text"),
+            ],
+        );
+        let mut preset = h264_aac(VideoFormat::Mp4);
+        assert_eq!(fitting_subtitle_streams(&info, &preset), vec![0, 2]);
+        preset.video_format = VideoFormat::Avi;
+        assert!(fitting_subtitle_streams(&info, &preset).is_empty());
+
+        preset.mode = ConversionMode::Remux;
+        preset.video_format = VideoFormat::Mov;
+        assert_eq!(fitting_subtitle_streams(&info, &preset), vec![2]);
+        preset.video_format = VideoFormat::Mkv;
+        assert_eq!(fitting_subtitle_streams(&info, &preset), vec![0, 1, 2]);
+    }
+
+    fn mixed_streams() -> MediaInfo {
+        media(
+            "matroska,webm",
+            &[
+                ("video", "h264"),
+                ("audio", "aac"),
+                ("audio", "ac3"),
+                ("subtitle", "subrip"),
+                ("subtitle", "hdmv_pgs_subtitle"),
+                ("data", "bin_data"),
+            ],
+        )
+    }
+
+    fn kept(plans: &[StreamPlan]) -> Vec<bool> {
+        plans
+            .iter()
+            .map(|plan| plan.action != StreamAction::Drop)
+            .collect()
+    }
+
+    fn maps(preset: &ConversionPreset, audio_track: Option<usize>, cover_art: bool) -> Vec<String> {
+        StreamSelection::new(
+            preset,
+            Some(&mixed_streams()),
+            audio_track,
+            shifted_audio_offset(preset).is_some(),
+            cover_art,
+        )
+        .map_args()
+    }
+
+    #[test]
+    fn default_selection_keeps_one_stream_per_type() {
+        let preset = h264_aac(VideoFormat::Mkv);
+        assert!(maps(&preset, None, false).is_empty());
+        let plans = plan_streams(&preset, &mixed_streams(), None, None);
+        assert_eq!(kept(&plans), [true, true, false, true, false, false]);
+    }
+
+    #[test]
+    fn keeping_all_streams_maps_everything_but_data() {
+        let preset = ConversionPreset {
+            mode: ConversionMode::Remux,
+            keep_all_streams: true,
+            ..h264_aac(VideoFormat::Mkv)
+        };
+        assert_eq!(maps(&preset, None, false), ["-map", "0", "-map", "-0:d?"]);
+        let plans = plan_streams(&preset, &mixed_streams(), None, None);
+        assert_eq!(kept(&plans), [true, true, true, true, true, false]);
+        assert!(
+            plans[..5]
+                .iter()
+                .all(|plan| plan.action == StreamAction::Copy)
+        );
+    }
+
+    #[test]
+    fn shifted_audio_keeps_every_track_and_fitting_subtitles() {
+        let preset = ConversionPreset {
+            audio_offset_ms: Some(200),
+            ..h264_aac(VideoFormat::Mp4)
+        };
+        assert_eq!(
+            maps(&preset, None, false),
+            ["-map", "0:v?", "-map", "1:a?", "-map", "0:s:0"]
+        );
+        let plans = plan_streams(&preset, &mixed_streams(), None, None);
+        assert_eq!(kept(&plans), [true, true, true, true, false, false]);
+    }
+
+    #[test]
+    fn a_selected_audio_track_is_the_only_audio() {
+        let preset = h264_aac(VideoFormat::Mkv);
+        assert_eq!(
+            maps(&preset, Some(1), false),
+            ["-map", "0:v:0?", "-map", "0:a:1", "-map", "0:s:0"]
+        );
+        let plans = plan_streams(&preset, &mixed_streams(), Some(1), None);
+        assert_eq!(kept(&plans), [true, false, true, true, false, false]);
+    }
+
+    #[test]
+    fn extra_audio_tracks_keep_the_subtitles() {
+        let preset = ConversionPreset {
+            extra_audio: vec![AudioEncode {
+                codec: AudioCodec::Ac3,
+                bitrate: Some("448k".to_string()),
+                channels: Some(6),
+            }],
+            ..h264_aac(VideoFormat::Mkv)
+        };
+        assert_eq!(
+            maps(&preset, None, false),
+            [
+                "-map", "0:v:0?", "-map", "0:a:0?", "-map", "0:a:0?", "-map", "0:s:0"
+            ]
+        );
+        let plans = plan_streams(&preset, &mixed_streams(), None, None);
+        assert_eq!(kept(&plans), [true, true, false, true, false, false, true]);
+        assert_eq!(plans[6].action, StreamAction::Encode("ac3"));
+    }
+
+    #[test]
+    fn cover_art_is_an_extra_stream() {
+        let preset = h264_aac(VideoFormat::Mp4);
+        assert_eq!(
+            maps(&preset, None, true),
+            [
+                "-map", "0:v:0?", "-map", "0:a?", "-map", "0:s:0", "-map", "1:v:0"
+            ]
+        );
+        let plans = plan_streams(
+            &preset,
+            &mixed_streams(),
+            None,
+            Some(Path::new("cover.png")),
+        );
+        assert_eq!(kept(&plans), [true, true, true, true, false, false, true]);
+        assert_eq!(plans[6].action, StreamAction::Encode("png"));
+
+        // MKV attaches the cover and leaves the selection to FFmpeg
+        let preset = h264_aac(VideoFormat::Mkv);
+        assert!(maps(&preset, None, true).is_empty());
+        let plans = plan_streams(
+            &preset,
+            &mixed_streams(),
+            None,
+            Some(Path::new("cover.jpg")),
+        );
+        assert_eq!(kept(&plans), [true, true, false, true, false, false, true]);
+        assert_eq!(plans[6].codec_type, "attachment");
+    }
+
+    #[test]
+    fn frame_count_follows_the_output_rate() {
+        assert_eq!(output_frames(600, Some(60.0), Some(30.0)), 300);
+        assert_eq!(output_frames(250, Some(25.0), Some(30000.0 / 1001.0)), 300);
+        assert_eq!(output_frames(600, Some(60.0), None), 600);
+        assert_eq!(output_frames(600, None, Some(30.0)), 600);
+    }
+
+    #[test]
+    fn output_rate_only_applies_when_encoding_video() {
+        let mut task = task();
+        task.preset.frame_rate = Some("30000/1001".to_string());
+        task.preset.video_codec = VideoCodec::H264;
+        let rate = task.output_frame_rate().expect("rate should parse");
+        assert!((rate - 29.97).abs() < 0.01);
+
+        task.preset.frame_rate = Some("24".to_string());
+        assert_eq!(task.output_frame_rate(), Some(24.0));
+
+        task.preset.video_codec = VideoCodec::Copy;
+        assert_eq!(task.output_frame_rate(), None);
+    }
+
+    #[test]
+    fn output_matching_the_input_gets_a_suffix() {
+        assert_eq!(
+            generate_output_filename(Path::new("videos/clip.mp4"), &VideoFormat::Mp4, ""),
+            Path::new("videos/clip_converted.mp4")
+        );
+        assert_eq!(
+            avoid_input_collision(Path::new("clip.mkv"), PathBuf::from("./clip.mkv")),
+            Path::new("./clip_converted.mkv")
+        );
+        assert_eq!(
+            avoid_input_collision(Path::new("clip.mkv"), PathBuf::from("clip.mp4")),
+            Path::new("clip.mp4")
+        );
+    }
+
+    fn feed(parser: &mut ProgressParser, block: &str) -> Vec<ConversionProgress> {
+        block
+            .lines()
+            .filter_map(|line| parser.parse_line(line, Duration::from_secs(10)))
+            .collect()
+    }
+
+    #[test]
+    fn progress_block_reports_once_per_block() {
+        let mut parser = ProgressParser::new(100.0);
+        let reports = feed(
+            &mut parser,
+            "frame=250\nfps=25.00\nbitrate=1024.5kbits/s\nout_time_us=25000000\n\
+             speed=2.5x\nprogress=continue\n",
+        );
+
+        assert_eq!(reports.len(), 1);
+        let progress = &reports[0];
+        assert!((progress.percentage - 25.0).abs() < 0.01);
+        assert_eq!(progress.fps, Some(25.0));
+        assert_eq!(progress.bitrate_kbps, Some(1024.5));
+        assert_eq!(progress.speed, Some(2.5));
+        assert_eq!(progress.time_remaining, Some(Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn unmeasured_values_are_left_empty() {
+        let mut parser = ProgressParser::new(100.0);
+        let reports = feed(
+            &mut parser,
+            "frame=0\nfps=0.00\nbitrate=N/A\nout_time_us=N/A\n\
+             out_time_ms=-9223372036854775807\nspeed=0x\nprogress=continue\n",
+        );
+
+        let progress = &reports[0];
+        assert_eq!(progress.percentage, 0.0);
+        assert_eq!(progress.fps, None);
+        assert_eq!(progress.bitrate_kbps, None);
+        assert_eq!(progress.speed, None);
+        assert_eq!(progress.time_remaining, None);
+    }
+
+    #[test]
+    fn out_time_ms_is_in_microseconds() {
+        let mut parser = ProgressParser::new(60.0);
+        let reports = feed(&mut parser, "out_time_ms=30000000\nprogress=continue\n");
+        assert!((reports[0].percentage - 50.0).abs() < 0.01);
+        assert_eq!(reports[0].current_time, format_duration(30.0));
+    }
+
+    #[test]
+    fn end_block_completes_the_progress() {
+        let mut parser = ProgressParser::new(60.0);
+        let reports = feed(&mut parser, "out_time_us=59000000\nprogress=end\n");
+        assert_eq!(reports[0].percentage, 100.0);
+        assert_eq!(reports[0].time_remaining, None);
+    }
+
+    #[test]
+    fn frames_take_over_from_time_when_counted() {
+        let mut parser =
+            ProgressParser::new(100.0).with_total_frames(Some(FrameCount::Estimated(1000)));
+        let reports = feed(
+            &mut parser,
+            "frame=100\nout_time_us=50000000\nprogress=continue\n",
+        );
+        assert!((reports[0].percentage - 10.0).abs() < 0.01);
+        assert!(reports[0].estimated);
+    }
+
+    #[test]
+    fn legacy_status_line_is_parsed() {
+        let mut parser = ProgressParser::new(40.0);
+        let progress = parser
+            .parse_line(
+                "frame=  240 fps= 60 q=28.0 size=    1024kB time=00:00:10.00 \
+                 bitrate= 838.9kbits/s speed=2.00x",
+                Duration::from_secs(5),
+            )
+            .expect("status line should report progress");
+
+        assert!((progress.percentage - 25.0).abs() < 0.01);
+        assert_eq!(progress.fps, Some(60.0));
+        assert_eq!(progress.bitrate_kbps, Some(838.9));
+        assert_eq!(progress.speed, Some(2.0));
+        assert_eq!(parser.current_frame, 240);
+    }
+
+    #[test]
+    fn legacy_status_line_before_the_first_frame() {
+        let mut parser = ProgressParser::new(0.0);
+        let progress = parser
+            .parse_line(
+                "frame=    0 fps=0.0 q=0.0 size=       0kB time=-577014:32:22.77 \
+                 bitrate=N/A speed=N/A",
+                Duration::from_secs(1),
+            )
+            .expect("status line should report progress");
+
+        assert_eq!(progress.percentage, 0.0);
+        assert_eq!(progress.fps, None);
+        assert_eq!(progress.bitrate_kbps, None);
+        assert_eq!(progress.speed, None);
+        assert_eq!(progress.time_remaining, None);
+    }
+
+    #[tokio::test]
+    async fn invalid_utf8_does_not_stop_the_lines() {
+        let bytes: &[u8] = b"frame=1\ninput: caf\xe9.mkv\r\n\xff\xfe\nprogress=end";
+        let mut lines = LossyLines::new(bytes);
+
+        let mut read = Vec::new();
+        while let Some(line) = lines.next_line().await.expect("reading bytes can't fail") {
+            read.push(line);
+        }
+        assert_eq!(
+            read,
+            vec![
+                "frame=1",
+                "input: caf\u{fffd}.mkv",
+                "\u{fffd}\u{fffd}",
+                "progress=end"
+            ]
+        );
+    }
+
+    #[test]
+    fn existing_split_parts_are_found_in_order() {
+        let dir = std::env::temp_dir().join(format!("ffmpegrust_split_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in [
+            "clip_001.mp4",
+            "clip_000.mp4",
+            "clip_01.mp4",
+            "clip_abc.mp4",
+            "clip.mp4",
+        ] {
+            std::fs::write(dir.join(name), b"").unwrap();
+        }
+
+        let output = dir.join("clip.mp4");
+        assert_eq!(
+            existing_split_parts(&output),
+            [dir.join("clip_000.mp4"), dir.join("clip_001.mp4")]
+        );
+        assert_eq!(next_free_split_name(&output), dir.join("clip_2.mp4"));
+        std::fs::write(dir.join("clip_2_000.mp4"), b"").unwrap();
+        assert_eq!(next_free_split_name(&output), dir.join("clip_3.mp4"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,222 @@
+// Finding the CRF that just reaches a target VMAF score
+
+use crate::benchmark::{BENCHMARK_SAMPLE, QualityMetric, measure_quality};
+use crate::capabilities::has_filter;
+use crate::conversion::{
+    ConversionControl, ConversionError, ConversionMessage, ConversionTask, ConversionTimeout,
+};
+use crate::presets::{ConversionPreset, HardwareAccel, QualityTarget};
+use crate::probe::probe_media;
+use crate::segments::Segment;
+use std::time::Duration;
+use tokio::sync::mpsc::error::TryRecvError;
+use tokio::sync::mpsc::{UnboundedReceiver, unbounded_channel};
+
+/// Sample encodes per search; enough to narrow a 0–63 range to one step
+pub const MAX_SEARCH_ITERATIONS: usize = 6;
+// Below this CRF every encoder is close to lossless, so searching lower
+// only wastes samples
+const MIN_SEARCH_CRF: u32 = 10;
+// A sample is a few seconds of video, so one running this long is stuck
+const SAMPLE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// Binary searches the highest CRF whose sample still scores at least
+/// `target`, reporting each step. Returns `Ok(None)` when cancelled. If no
+/// sample reaches the target, the best-scoring CRF tried is used.
+pub async fn search_crf(
+    task: &mut ConversionTask,
+    target: QualityTarget,
+) -> Result<Option<u32>, ConversionError> {
+    let Some((_, max_crf)) = task.preset.video_codec.crf_range() else {
+        return Err(ConversionError::QualitySearch(
+            "quality targets need the video re-encoded".to_string(),
+        ));
+    };
+    if task.preset.hardware_accel != HardwareAccel::None {
+        return Err(ConversionError::QualitySearch(
+            "quality targets only work with software encoders".to_string(),
+        ));
+    }
+    if task.input_source.is_image_sequence() {
+        return Err(ConversionError::QualitySearch(
+            "image sequences can't be scored against a source video".to_string(),
+        ));
+    }
+    if !has_filter("libvmaf").await {
+        return Err(ConversionError::QualitySearch(
+            "this FFmpeg build has no libvmaf filter".to_string(),
+        ));
+    }
+
+    // Sample from a third of the way in, where openings and black intros
+    // are usually over
+    let sample = BENCHMARK_SAMPLE.as_secs_f64();
+    let start = match &task.trim {
+        Some(trim) => trim.start,
+        None => probe_media(&task.input_file)
+            .await
+            .ok()
+            .and_then(|info| info.duration)
+            .map_or(0.0, |duration| {
+                (duration / 3.0).min(duration - sample).max(0.0)
+            }),
+    };
+    let sample_file = std::env::temp_dir()
+        .join("ffmpegrust_benchmark")
+        .join(format!(
+            "crf_sample_{}.{}",
+            std::process::id(),
+            task.preset.video_format.extension()
+        ));
+    let _ = tokio::fs::create_dir_all(sample_file.parent().unwrap_or(&sample_file)).await;
+
+    let (mut low, mut high) = (MIN_SEARCH_CRF, max_crf);
+    let mut passing = None;
+    let mut best_tried: Option<(u32, f64)> = None;
+    for _ in 0..MAX_SEARCH_ITERATIONS {
+        if low > high {
+            break;
+        }
+        if search_cancelled(task) {
+            let _ = tokio::fs::remove_file(&sample_file).await;
+            return Ok(None);
+        }
+
+        let crf = (low + high) / 2;
+        let mut preset = task.preset.clone();
+        preset.quality_target = None;
+        preset.disable_audio = true;
+        preset.web_optimized = false;
+        apply_crf(&mut preset, crf);
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let sample_task =
+            ConversionTask::new(task.input_file.clone(), sample_file.clone(), preset, sender)
+                .with_trim(Some(Segment {
+                    start,
+                    end: start + sample,
+                    label: None,
+                }))
+                .with_grace_period(task.grace_period)
+                .with_timeout(match task.timeout {
+                    ConversionTimeout::Disabled => ConversionTimeout::Disabled,
+                    _ => ConversionTimeout::Fixed(SAMPLE_TIMEOUT),
+                });
+        if run_sample(task, sample_task).await {
+            let _ = tokio::fs::remove_file(&sample_file).await;
+            return Ok(None);
+        }
+        let error = receiver.try_iter().find_map(|message| match message {
+            ConversionMessage::Error(error) => Some(error),
+            _ => None,
+        });
+        if let Some(error) = error {
+            let _ = tokio::fs::remove_file(&sample_file).await;
+            return Err(ConversionError::QualitySearch(format!(
+                "sample encode at CRF {crf} failed: {error}"
+            )));
+        }
+
+        let vmaf =
+            measure_quality(&task.input_file, start, &sample_file, QualityMetric::Vmaf).await;
+        let _ = task.sender.send(ConversionMessage::CrfSearch {
+            crf,
+            vmaf,
+            chosen: false,
+        });
+
+        let score = vmaf.unwrap_or(f64::NEG_INFINITY);
+        if best_tried.is_none_or(|(_, best)| score > best) {
+            best_tried = Some((crf, score));
+        }
+        if score >= target.vmaf {
+            passing = Some((crf, score));
+            low = crf + 1;
+        } else {
+            high = crf.saturating_sub(1);
+            if crf == 0 {
+                break;
+            }
+        }
+    }
+    let _ = tokio::fs::remove_file(&sample_file).await;
+
+    let Some((crf, score)) = passing.or(best_tried) else {
+        return Err(ConversionError::QualitySearch(
+            "no sample could be scored".to_string(),
+        ));
+    };
+    let _ = task.sender.send(ConversionMessage::CrfSearch {
+        crf,
+        vmaf: score.is_finite().then_some(score),
+        chosen: true,
+    });
+    Ok(Some(crf))
+}
+
+/// Switches the video to constant quality at `crf`, dropping any bitrate or
+/// quality options that would fight it
+pub fn apply_crf(preset: &mut ConversionPreset, crf: u32) {
+    preset.video_bitrate = None;
+    preset.crf = Some(crf);
+
+    let mut kept = Vec::new();
+    let mut args = preset.extra_args.iter();
+    while let Some(arg) = args.next() {
+        if matches!(arg.as_str(), "-crf" | "-qp" | "-q:v" | "-b:v") {
+            args.next();
+        } else {
+            kept.push(arg.clone());
+        }
+    }
+    preset.extra_args = kept;
+}
+
+/// Runs a sample encode, passing on the job's pause and cancel requests so
+/// it stops mid-sample. Returns `true` when the search was cancelled.
+async fn run_sample(task: &mut ConversionTask, sample_task: ConversionTask) -> bool {
+    let (forward, sample_control) = unbounded_channel();
+    // The sample has no quality target, so this never recurses further
+    let mut run = Box::pin(sample_task.with_control(sample_control).execute());
+    let mut cancelled = false;
+    loop {
+        tokio::select! {
+            () = &mut run => return cancelled,
+            control = next_control(&mut task.control) => {
+                cancelled |= control == ConversionControl::Cancel;
+                let _ = forward.send(control);
+            }
+        }
+    }
+}
+
+// Never resolves without a control channel, or once it is closed
+async fn next_control(
+    control: &mut Option<UnboundedReceiver<ConversionControl>>,
+) -> ConversionControl {
+    if let Some(receiver) = control {
+        match receiver.recv().await {
+            Some(request) => return request,
+            None => *control = None,
+        }
+    }
+    std::future::pending().await
+}
+
+// Pause requests between samples are dropped; only a cancel stops the search
+fn search_cancelled(task: &mut ConversionTask) -> bool {
+    let Some(control) = &mut task.control else {
+        return false;
+    };
+    loop {
+        match control.try_recv() {
+            Ok(ConversionControl::Cancel) => return true,
+            Ok(_) => {}
+            Err(TryRecvError::Empty) => return false,
+            Err(TryRecvError::Disconnected) => {
+                task.control = None;
+                return false;
+            }
+        }
+    }
+}
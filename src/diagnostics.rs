@@ -0,0 +1,187 @@
+// Checking that FFmpeg, the settings folder and the disks are usable
+
+use crate::capabilities::{list_encoders, retest_hardware_encoders};
+use crate::config::{config_location, ffprobe_binary};
+use crate::conversion::{DISK_SPACE_THRESHOLD_MB, check_ffmpeg_installation};
+use crate::presets::{HardwareAccel, VideoCodec};
+use crate::utils::available_disk_space;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::process::Command as AsyncCommand;
+
+// Less free space than this is worth a warning; a long encode easily
+// writes several gigabytes
+const LOW_SPACE_WARNING_MB: u64 = 5 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl CheckStatus {
+    pub fn label(&self) -> &'static str {
+        match self {
+            CheckStatus::Pass => "PASS",
+            CheckStatus::Warn => "WARN",
+            CheckStatus::Fail => "FAIL",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DiagnosticCheck {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+impl DiagnosticCheck {
+    fn new(name: impl Into<String>, status: CheckStatus, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Runs every check, reporting free space for each of `output_folders` that
+/// exists plus the temp dir
+pub async fn run_diagnostics(output_folders: Vec<PathBuf>) -> Vec<DiagnosticCheck> {
+    let mut checks = Vec::new();
+
+    let ffmpeg = tokio::task::spawn_blocking(check_ffmpeg_installation)
+        .await
+        .unwrap_or_else(|e| Err(format!("FFmpeg check failed: {}", e)));
+    checks.push(match ffmpeg {
+        Ok(version) => DiagnosticCheck::new("FFmpeg", CheckStatus::Pass, version),
+        Err(error) => DiagnosticCheck::new("FFmpeg", CheckStatus::Fail, error),
+    });
+    checks.push(check_ffprobe().await);
+    checks.push(check_hardware_encoders().await);
+
+    checks.push(check_writable("Settings folder", config_location()).await);
+    let temp_dir = std::env::temp_dir();
+    checks.push(check_writable("Temp folder", &temp_dir).await);
+
+    let mut folders: Vec<PathBuf> = Vec::new();
+    for folder in output_folders.into_iter().chain([temp_dir]) {
+        if folder.is_dir() && !folders.contains(&folder) {
+            folders.push(folder);
+        }
+    }
+    checks.extend(folders.iter().map(|folder| check_disk_space(folder)));
+    checks
+}
+
+async fn check_ffprobe() -> DiagnosticCheck {
+    let output = AsyncCommand::new(ffprobe_binary())
+        .arg("-version")
+        .stdin(Stdio::null())
+        .output()
+        .await;
+    match output {
+        Ok(output) if output.status.success() => {
+            let version = String::from_utf8_lossy(&output.stdout);
+            DiagnosticCheck::new(
+                "ffprobe",
+                CheckStatus::Pass,
+                version.lines().next().unwrap_or("Installed"),
+            )
+        }
+        Ok(_) => DiagnosticCheck::new("ffprobe", CheckStatus::Fail, "ffprobe command failed"),
+        Err(_) => DiagnosticCheck::new(
+            "ffprobe",
+            CheckStatus::Fail,
+            "ffprobe not found; media info and trimming won't work",
+        ),
+    }
+}
+
+/// Hardware encoders that pass the same test encode the encoder list uses
+async fn check_hardware_encoders() -> DiagnosticCheck {
+    let mut encoders = match list_encoders().await {
+        Ok(encoders) => encoders,
+        Err(error) => {
+            return DiagnosticCheck::new("Hardware encoders", CheckStatus::Fail, error);
+        }
+    };
+    retest_hardware_encoders(&mut encoders).await;
+
+    let found: Vec<&str> = HardwareAccel::all()
+        .iter()
+        .filter(|accel| **accel != HardwareAccel::None)
+        .flat_map(|accel| VideoCodec::encoded().map(|codec| codec.encoder_name(*accel)))
+        .filter(|encoder| encoders.contains(*encoder))
+        .collect();
+
+    if found.is_empty() {
+        DiagnosticCheck::new(
+            "Hardware encoders",
+            CheckStatus::Warn,
+            "None usable; encoding runs on the CPU",
+        )
+    } else {
+        DiagnosticCheck::new("Hardware encoders", CheckStatus::Pass, found.join(", "))
+    }
+}
+
+/// Creates the folder if needed, then writes and removes a small file in it
+async fn check_writable(name: &str, folder: &Path) -> DiagnosticCheck {
+    let probe = folder.join(format!(".write_test_{}", std::process::id()));
+    let result = match tokio::fs::create_dir_all(folder).await {
+        Ok(()) => tokio::fs::write(&probe, b"ok").await,
+        Err(e) => Err(e),
+    };
+    let _ = tokio::fs::remove_file(&probe).await;
+
+    match result {
+        Ok(()) => DiagnosticCheck::new(name, CheckStatus::Pass, folder.display().to_string()),
+        Err(e) => DiagnosticCheck::new(
+            name,
+            CheckStatus::Fail,
+            format!("{} is not writable: {}", folder.display(), e),
+        ),
+    }
+}
+
+fn check_disk_space(folder: &Path) -> DiagnosticCheck {
+    let name = format!("Free space on {}", folder.display());
+    let Some(available) = available_disk_space(folder) else {
+        return DiagnosticCheck::new(name, CheckStatus::Warn, "Couldn't be read");
+    };
+
+    let available_mb = available / (1024 * 1024);
+    let status = if available_mb < DISK_SPACE_THRESHOLD_MB {
+        CheckStatus::Fail
+    } else if available_mb < LOW_SPACE_WARNING_MB {
+        CheckStatus::Warn
+    } else {
+        CheckStatus::Pass
+    };
+    DiagnosticCheck::new(
+        name,
+        status,
+        format!("{:.1} GB available", available as f64 / 1_073_741_824.0),
+    )
+}
+
+/// Plain-text version of the results, for pasting into a bug report
+pub fn diagnostics_report(checks: &[DiagnosticCheck]) -> String {
+    let mut report = format!(
+        "FFmpeg Rust {} on {}\n",
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS
+    );
+    for check in checks {
+        report.push_str(&format!(
+            "[{}] {}: {}\n",
+            check.status.label(),
+            check.name,
+            check.detail
+        ));
+    }
+    report
+}
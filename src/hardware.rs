@@ -0,0 +1,194 @@
+use crate::config::ffmpeg_binary;
+use crate::presets::HardwareAccel;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::Command as AsyncCommand;
+
+// Upper bound for the CUDA device probe; nobody has more GPUs than this
+const MAX_CUDA_DEVICES: u32 = 8;
+
+// A one-frame encode takes well under a second when the device works; a
+// hanging driver shouldn't stall the encoder list
+const TEST_ENCODE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Lower-cased stderr fragments of driver, device and GPU memory failures.
+// Input errors never contain these, so only they justify a software retry;
+// bare encoder names are left out because FFmpeg prints them on every run.
+const HARDWARE_FAILURE_PATTERNS: &[&str] = &[
+    "cuda_error",
+    "cannot load libcuda",
+    "cannot load libnvidia-encode",
+    "openencodesessionex failed",
+    "no capable devices found",
+    "driver does not support the required nvenc api version",
+    "out of memory",
+    "failed to create hardware device",
+    "device creation failed",
+    "hwaccel initialisation returned error",
+    "failed to initialise vaapi",
+    "vaentrypoint",
+    "error creating a mfx session",
+    "mfx_err",
+    "vtcompressionsession",
+];
+
+#[derive(Debug, Clone)]
+pub struct HardwareDevice {
+    pub index: u32,
+    pub label: String,
+}
+
+/// Best-effort list of the devices available to a hardware backend
+pub async fn list_hardware_devices(accel: HardwareAccel) -> Vec<HardwareDevice> {
+    match accel {
+        HardwareAccel::Nvenc => list_cuda_devices().await,
+        HardwareAccel::Qsv | HardwareAccel::Vaapi => list_render_nodes()
+            .into_iter()
+            .enumerate()
+            .map(|(index, path)| HardwareDevice {
+                index: index as u32,
+                label: path.display().to_string(),
+            })
+            .collect(),
+        HardwareAccel::None | HardwareAccel::VideoToolbox => Vec::new(),
+    }
+}
+
+/// Whether a failed encode's stderr points at the hardware encoder or its
+/// driver rather than at the input
+pub fn is_hardware_failure(stderr: &[String]) -> bool {
+    stderr.iter().any(|line| {
+        let line = line.to_lowercase();
+        HARDWARE_FAILURE_PATTERNS
+            .iter()
+            .any(|pattern| line.contains(pattern))
+    })
+}
+
+/// Options that must appear before `-i` to pin the encode to a device
+pub fn input_device_args(accel: HardwareAccel, device_index: Option<u32>) -> Vec<String> {
+    let render_node =
+        device_index.and_then(|index| list_render_nodes().get(index as usize).cloned());
+
+    match (accel, render_node) {
+        (HardwareAccel::Vaapi, Some(node)) => {
+            vec!["-vaapi_device".to_string(), node.display().to_string()]
+        }
+        (HardwareAccel::Vaapi, None) => {
+            vec![
+                "-vaapi_device".to_string(),
+                "/dev/dri/renderD128".to_string(),
+            ]
+        }
+        (HardwareAccel::Qsv, Some(node)) => {
+            vec!["-qsv_device".to_string(), node.display().to_string()]
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Options that follow the video encoder selection
+pub fn encoder_device_args(accel: HardwareAccel, device_index: Option<u32>) -> Vec<String> {
+    match (accel, device_index) {
+        (HardwareAccel::Nvenc, Some(index)) => vec!["-gpu".to_string(), index.to_string()],
+        _ => Vec::new(),
+    }
+}
+
+/// Filters that must end the video filter chain for `accel`. VAAPI encoders
+/// only accept frames that already live on the GPU, so every other filter
+/// has to run before the upload.
+pub fn hardware_upload_filter(accel: HardwareAccel) -> Option<&'static str> {
+    (accel == HardwareAccel::Vaapi).then_some("format=nv12,hwupload")
+}
+
+/// Encodes a single generated frame with `encoder` on the default device of
+/// `accel`, to tell a usable encoder from one that is merely compiled in
+pub async fn test_hardware_encode(encoder: &str, accel: HardwareAccel) -> bool {
+    let status = AsyncCommand::new(ffmpeg_binary())
+        .arg("-hide_banner")
+        .arg("-v")
+        .arg("error")
+        .args(input_device_args(accel, None))
+        .arg("-f")
+        .arg("lavfi")
+        .arg("-i")
+        .arg("testsrc=size=256x256:rate=1")
+        .arg("-frames:v")
+        .arg("1")
+        .arg("-c:v")
+        .arg(encoder)
+        .args(encoder_device_args(accel, None))
+        .args(
+            hardware_upload_filter(accel)
+                .into_iter()
+                .flat_map(|filter| ["-vf", filter]),
+        )
+        .arg("-f")
+        .arg("null")
+        .arg("-")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .status();
+
+    matches!(
+        tokio::time::timeout(TEST_ENCODE_TIMEOUT, status).await,
+        Ok(Ok(status)) if status.success()
+    )
+}
+
+async fn list_cuda_devices() -> Vec<HardwareDevice> {
+    let mut devices = Vec::new();
+
+    for index in 0..MAX_CUDA_DEVICES {
+        let status = AsyncCommand::new(ffmpeg_binary())
+            .arg("-hide_banner")
+            .arg("-v")
+            .arg("error")
+            .arg("-init_hw_device")
+            .arg(format!("cuda=gpu:{index}"))
+            .arg("-f")
+            .arg("lavfi")
+            .arg("-i")
+            .arg("nullsrc=s=16x16:d=0.04")
+            .arg("-f")
+            .arg("null")
+            .arg("-")
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .await;
+
+        match status {
+            Ok(status) if status.success() => devices.push(HardwareDevice {
+                index,
+                label: format!("GPU {index}"),
+            }),
+            _ => break,
+        }
+    }
+
+    devices
+}
+
+pub fn list_render_nodes() -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir("/dev/dri") else {
+        return Vec::new();
+    };
+
+    let mut nodes: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("renderD"))
+        })
+        .collect();
+    nodes.sort();
+    nodes
+}
@@ -0,0 +1,204 @@
+// Probing a whole media folder for a codec and size report
+
+use crate::probe::{MediaInfo, probe_media};
+use crate::recommend::recommend_settings;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::mpsc::Sender;
+use tokio::sync::Semaphore;
+
+/// Extensions treated as video when picking or scanning files
+pub const VIDEO_EXTENSIONS: &[&str] = &[
+    "mp4", "mkv", "mov", "avi", "webm", "flv", "wmv", "m4v", "3gp", "ts", "mts", "m2ts", "vob",
+    "mpg", "mpeg", "ogv",
+];
+// Beyond this a scan stops collecting; the report says it was cut short
+const MAX_LIBRARY_FILES: usize = 5000;
+const MAX_CONCURRENT_PROBES: usize = 8;
+
+#[derive(Debug, Clone)]
+pub struct LibraryEntry {
+    pub path: PathBuf,
+    pub size: u64,
+    pub info: Result<MediaInfo, String>,
+    /// Title of the settings `recommend_settings` suggests for the file
+    pub suggestion: Option<String>,
+}
+
+impl LibraryEntry {
+    pub fn container(&self) -> &str {
+        self.info.as_ref().map_or("", |info| {
+            info.format_name.split(',').next().unwrap_or_default()
+        })
+    }
+
+    pub fn video_codec(&self) -> &str {
+        self.info
+            .as_ref()
+            .ok()
+            .and_then(MediaInfo::video_stream)
+            .map_or("", |video| video.codec_name.as_str())
+    }
+
+    pub fn audio_codec(&self) -> &str {
+        self.info
+            .as_ref()
+            .ok()
+            .and_then(|info| info.audio_streams().first().copied())
+            .map_or("", |audio| audio.codec_name.as_str())
+    }
+
+    /// Picture size of the first video stream
+    pub fn resolution(&self) -> Option<(u32, u32)> {
+        let video = self.info.as_ref().ok()?.video_stream()?;
+        Some((video.width?, video.height?))
+    }
+
+    pub fn bit_rate(&self) -> Option<u64> {
+        self.info.as_ref().ok()?.bit_rate
+    }
+}
+
+#[derive(Debug)]
+pub enum LibraryMessage {
+    /// How many files the probes will cover, and whether the limit cut the
+    /// list short
+    Found {
+        files: usize,
+        truncated: bool,
+    },
+    Entry(LibraryEntry),
+    Finished,
+    Failed(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LibrarySort {
+    Name,
+    Container,
+    Video,
+    Resolution,
+    Bitrate,
+    Size,
+}
+
+pub fn is_video_file(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| {
+        let extension = extension.to_string_lossy().to_ascii_lowercase();
+        VIDEO_EXTENSIONS.contains(&extension.as_str())
+    })
+}
+
+/// Video files in `dir`, sorted, and whether `MAX_LIBRARY_FILES` cut the
+/// list short. Hidden entries and symlinked folders are skipped, so a scan
+/// can't loop.
+pub fn library_files(dir: &Path, recursive: bool) -> Result<(Vec<PathBuf>, bool), String> {
+    let mut files = Vec::new();
+    let mut folders = vec![dir.to_path_buf()];
+    while let Some(folder) = folders.pop() {
+        let entries = match std::fs::read_dir(&folder) {
+            Ok(entries) => entries,
+            // Only the folder the user picked has to be readable
+            Err(e) if folder == dir => return Err(format!("Failed to read folder: {}", e)),
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let path = entry.path();
+            if file_type.is_dir() {
+                if recursive {
+                    folders.push(path);
+                }
+            } else if is_video_file(&path) {
+                files.push(path);
+                if files.len() >= MAX_LIBRARY_FILES {
+                    files.sort();
+                    return Ok((files, true));
+                }
+            }
+        }
+    }
+    files.sort();
+    Ok((files, false))
+}
+
+/// Probes every video file in `dir` a few at a time, reporting each as soon
+/// as it is done, then `Finished`
+pub async fn scan_library(dir: PathBuf, recursive: bool, sender: Sender<LibraryMessage>) {
+    let listing = tokio::task::spawn_blocking(move || library_files(&dir, recursive)).await;
+    let (files, truncated) = match listing {
+        Ok(Ok(listing)) => listing,
+        Ok(Err(error)) => {
+            let _ = sender.send(LibraryMessage::Failed(error));
+            return;
+        }
+        Err(e) => {
+            let _ = sender.send(LibraryMessage::Failed(format!("Scan failed: {}", e)));
+            return;
+        }
+    };
+    let _ = sender.send(LibraryMessage::Found {
+        files: files.len(),
+        truncated,
+    });
+
+    let cores = std::thread::available_parallelism().map_or(1, |cores| cores.get());
+    let limit = Arc::new(Semaphore::new(cores.clamp(2, MAX_CONCURRENT_PROBES)));
+    let mut jobs = Vec::new();
+    for path in files {
+        let limit = limit.clone();
+        let sender = sender.clone();
+        jobs.push(tokio::spawn(async move {
+            let Ok(_permit) = limit.acquire().await else {
+                return;
+            };
+            let size = tokio::fs::metadata(&path)
+                .await
+                .map_or(0, |metadata| metadata.len());
+            let info = probe_media(&path).await;
+            let suggestion = info
+                .as_ref()
+                .ok()
+                .and_then(recommend_settings)
+                .map(|recommendation| recommendation.title);
+            let _ = sender.send(LibraryMessage::Entry(LibraryEntry {
+                path,
+                size,
+                info,
+                suggestion,
+            }));
+        }));
+    }
+
+    for job in jobs {
+        let _ = job.await;
+    }
+    let _ = sender.send(LibraryMessage::Finished);
+}
+
+/// Orders entries by one column; files that couldn't be probed go last
+pub fn sort_entries(entries: &mut [LibraryEntry], sort: LibrarySort, ascending: bool) {
+    entries.sort_by(|a, b| {
+        let order = match sort {
+            LibrarySort::Name => a.path.file_name().cmp(&b.path.file_name()),
+            LibrarySort::Container => a.container().cmp(b.container()),
+            LibrarySort::Video => a.video_codec().cmp(b.video_codec()),
+            LibrarySort::Resolution => a
+                .resolution()
+                .map(|(width, height)| u64::from(width) * u64::from(height))
+                .cmp(
+                    &b.resolution()
+                        .map(|(width, height)| u64::from(width) * u64::from(height)),
+                ),
+            LibrarySort::Bitrate => a.bit_rate().cmp(&b.bit_rate()),
+            LibrarySort::Size => a.size.cmp(&b.size),
+        };
+        let order = if ascending { order } else { order.reverse() };
+        a.info.is_err().cmp(&b.info.is_err()).then(order)
+    });
+}
@@ -2,21 +2,69 @@ use std::sync::Arc;
 use tokio::runtime::Runtime;
 
 mod app;
+mod batch;
+mod benchmark;
+mod capabilities;
+mod compare;
 mod config;
 mod conversion;
+mod crf_search;
+mod diagnostics;
+mod hardware;
+mod library;
+mod palette;
 mod presets;
+mod probe;
+mod process_control;
+mod recommend;
+mod segmented;
+mod segments;
+mod sequence;
+#[cfg(feature = "server")]
+mod server;
+mod subtitles;
 mod updater;
 mod utils;
+mod validation;
 
 use app::FFmpegApp;
+use config::Config;
+
+const MIN_WINDOW_SIZE: [f32; 2] = [800.0, 500.0];
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let runtime = Arc::new(Runtime::new()?);
+    let runtime = match Runtime::new() {
+        Ok(runtime) => Arc::new(runtime),
+        Err(e) => {
+            let message = format!("FFmpeg Rust couldn't start its background worker: {e}");
+            report_startup_error(&message);
+            return Err(message.into());
+        }
+    };
+
+    // Loaded once, so environment overrides are only reported once
+    let config = Config::load();
+    config::use_tool_paths(&config);
 
+    #[cfg(feature = "server")]
+    {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        if args.first().map(String::as_str) == Some("serve") {
+            let options = server::ServeOptions::from_args(&args[1..])?
+                .with_max_concurrent(config.max_concurrent);
+            return runtime.block_on(server::serve(options)).map_err(Into::into);
+        }
+    }
+
+    // Reopen at the size the window had when the app last closed
+    let size = [
+        config.window_width.max(MIN_WINDOW_SIZE[0]),
+        config.window_height.max(MIN_WINDOW_SIZE[1]),
+    ];
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
-            .with_inner_size([1000.0, 600.0])
-            .with_min_inner_size([800.0, 500.0])
+            .with_inner_size(size)
+            .with_min_inner_size(MIN_WINDOW_SIZE)
             .with_title("FFmpeg Rust")
             .with_resizable(true),
         centered: true,
@@ -26,7 +74,22 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     eframe::run_native(
         "FFmpeg Rust",
         options,
-        Box::new(move |_cc| Ok(Box::new(FFmpegApp::new(runtime.clone())))),
+        Box::new(move |_cc| Ok(Box::new(FFmpegApp::new(runtime.clone(), config)))),
     )
     .map_err(|e| Box::new(e) as Box<dyn std::error::Error>)
 }
+
+/// Shows `message` in a native dialog, since an app started from a desktop
+/// shortcut has nowhere to print it; `serve` runs headless and only prints
+fn report_startup_error(message: &str) {
+    eprintln!("{message}");
+    if std::env::args().nth(1).as_deref() == Some("serve") {
+        return;
+    }
+    rfd::MessageDialog::new()
+        .set_level(rfd::MessageLevel::Error)
+        .set_title("FFmpeg Rust")
+        .set_description(message)
+        .set_buttons(rfd::MessageButtons::Ok)
+        .show();
+}
@@ -0,0 +1,91 @@
+// Command palette: fuzzy search over presets and app actions
+
+use crate::presets::{ConversionMode, PresetManager};
+use crate::utils::fuzzy_score;
+
+/// Longest list the palette shows at once
+pub const MAX_PALETTE_ENTRIES: usize = 12;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PaletteAction {
+    ApplyPreset(String),
+    SetMode(ConversionMode),
+    SelectInput,
+    SelectOutput,
+    StartConversion,
+    TestConversion,
+    StopConversion,
+    Benchmark,
+    AddBatchFiles,
+    Compare,
+    ShowHelp,
+    CheckUpdates,
+}
+
+#[derive(Debug, Clone)]
+pub struct PaletteEntry {
+    pub label: String,
+    /// Shown dimmed after the label, e.g. "Preset" or "Action"
+    pub kind: &'static str,
+    pub action: PaletteAction,
+}
+
+// Every action the palette can run besides applying a preset
+const ACTIONS: &[(&str, PaletteAction)] = &[
+    ("Start conversion", PaletteAction::StartConversion),
+    ("Test conversion", PaletteAction::TestConversion),
+    ("Stop conversion", PaletteAction::StopConversion),
+    (
+        "Mode: Convert",
+        PaletteAction::SetMode(ConversionMode::Convert),
+    ),
+    ("Mode: Remux", PaletteAction::SetMode(ConversionMode::Remux)),
+    ("Select input file", PaletteAction::SelectInput),
+    ("Select output folder", PaletteAction::SelectOutput),
+    ("Add files to batch", PaletteAction::AddBatchFiles),
+    ("Benchmark encoder presets", PaletteAction::Benchmark),
+    ("Compare two files", PaletteAction::Compare),
+    ("Help and diagnostics", PaletteAction::ShowHelp),
+    ("Check for updates", PaletteAction::CheckUpdates),
+];
+
+/// Presets and actions matching `query`, best first. Presets come from
+/// `search_presets`, so they rank by name and tags; an empty query lists
+/// the actions before the presets.
+pub fn palette_entries(presets: &PresetManager, query: &str) -> Vec<PaletteEntry> {
+    let mut scored: Vec<(u32, PaletteEntry)> = ACTIONS
+        .iter()
+        .filter_map(|(label, action)| {
+            let score = fuzzy_score(query, label)?;
+            Some((
+                score,
+                PaletteEntry {
+                    label: label.to_string(),
+                    kind: "Action",
+                    action: action.clone(),
+                },
+            ))
+        })
+        .collect();
+
+    for preset in presets.search_presets(query) {
+        // Presets matched only by a tag rank with the weakest name matches
+        let score = fuzzy_score(query, &preset.name).unwrap_or(0);
+        scored.push((
+            score,
+            PaletteEntry {
+                label: preset.name.clone(),
+                kind: "Preset",
+                action: PaletteAction::ApplyPreset(preset.name.clone()),
+            },
+        ));
+    }
+
+    // Stable, so equal scores keep actions ahead of presets
+    scored.sort_by(|(a, _), (b, _)| b.cmp(a));
+    scored
+        .into_iter()
+        .map(|(_, entry)| entry)
+        .take(MAX_PALETTE_ENTRIES)
+        .collect()
+}
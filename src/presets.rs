@@ -1,9 +1,17 @@
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use crate::config::config_location;
+use crate::utils::fuzzy_score;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::{BTreeSet, HashMap};
+
+/// `(width, height, x, y)` of the part of the picture to keep
+pub type CropRect = (u32, u32, u32, u32);
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ConversionPreset {
     pub name: String,
+    /// Labels added by the user, on top of the ones derived from the settings
+    pub tags: Vec<String>,
     pub mode: ConversionMode,
     pub video_format: VideoFormat,
     pub video_codec: VideoCodec,
@@ -12,10 +20,165 @@ pub struct ConversionPreset {
     pub audio_bitrate: Option<String>,
     pub resolution: Option<String>,
     pub frame_rate: Option<String>,
+    pub hardware_accel: HardwareAccel,
+    pub hw_device_index: Option<u32>,
+    /// `-threads` value; `None` leaves FFmpeg's default, `Some(0)` is auto
+    pub threads: Option<u32>,
+    /// Regenerate missing timestamps (`-fflags +genpts`) for broken captures
+    pub fix_timestamps: bool,
+    /// Move the MP4/MOV index to the front (`-movflags +faststart`) so web
+    /// playback can start before the whole file is downloaded
+    pub web_optimized: bool,
+    /// Leave all video (`-vn`) or all audio (`-an`) out of the output,
+    /// whatever the codec selection says
+    pub disable_video: bool,
+    pub disable_audio: bool,
+    /// Remux every stream (`-map 0`) instead of FFmpeg's pick of one video,
+    /// audio and subtitle track; data streams are still left out
+    pub keep_all_streams: bool,
+    /// Further audio tracks after the main one, e.g. a 5.1 AC3 next to a
+    /// stereo AAC
+    pub extra_audio: Vec<AudioEncode>,
+    /// Delay (positive) or advance (negative) the audio against the video
+    pub audio_offset_ms: Option<i32>,
+    /// Turns interlaced fields into whole frames; applied before the crop
+    pub deinterlace: Option<DeinterlaceMode>,
+    /// Applied with the `crop` filter before any scaling
+    pub crop: Option<CropRect>,
+    /// Constant quality for software encoders; `None` uses the codec's
+    /// `default_crf`, so switching codecs never keeps a CRF meant for another
+    pub crf: Option<u32>,
+    /// Encode at the highest CRF whose sample still reaches this quality,
+    /// instead of at a fixed bitrate
+    pub quality_target: Option<QualityTarget>,
+    /// How trimmed copies handle a start time between keyframes
+    pub trim_accuracy: TrimAccuracy,
+    /// Write the output as several parts with FFmpeg's segment muxer
+    pub split_output: Option<OutputSplit>,
+    /// `-x264-params`/`-x265-params` string, checked by `parse_codec_params`
+    pub codec_params: Option<String>,
+    /// Advanced options appended before the output, checked by `parse_extra_args`
+    pub extra_args: Vec<String>,
     pub metadata_options: MetadataOptions,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// ISO 639-2 code written to the output, its ISO 639-1 code if it has one,
+/// and the English name
+const LANGUAGES: &[(&str, &str, &str)] = &[
+    ("und", "", "Undetermined"),
+    ("mul", "", "Multiple languages"),
+    ("zxx", "", "No linguistic content"),
+    ("afr", "af", "Afrikaans"),
+    ("alb", "sq", "Albanian"),
+    ("amh", "am", "Amharic"),
+    ("ara", "ar", "Arabic"),
+    ("arm", "hy", "Armenian"),
+    ("aze", "az", "Azerbaijani"),
+    ("baq", "eu", "Basque"),
+    ("bel", "be", "Belarusian"),
+    ("ben", "bn", "Bengali"),
+    ("bos", "bs", "Bosnian"),
+    ("bul", "bg", "Bulgarian"),
+    ("bur", "my", "Burmese"),
+    ("cat", "ca", "Catalan"),
+    ("chi", "zh", "Chinese"),
+    ("hrv", "hr", "Croatian"),
+    ("cze", "cs", "Czech"),
+    ("dan", "da", "Danish"),
+    ("nld", "nl", "Dutch"),
+    ("eng", "en", "English"),
+    ("epo", "eo", "Esperanto"),
+    ("est", "et", "Estonian"),
+    ("fil", "", "Filipino"),
+    ("fin", "fi", "Finnish"),
+    ("fra", "fr", "French"),
+    ("glg", "gl", "Galician"),
+    ("geo", "ka", "Georgian"),
+    ("deu", "de", "German"),
+    ("gre", "el", "Greek"),
+    ("guj", "gu", "Gujarati"),
+    ("heb", "he", "Hebrew"),
+    ("hin", "hi", "Hindi"),
+    ("hun", "hu", "Hungarian"),
+    ("ice", "is", "Icelandic"),
+    ("ind", "id", "Indonesian"),
+    ("gle", "ga", "Irish"),
+    ("ita", "it", "Italian"),
+    ("jpn", "ja", "Japanese"),
+    ("kan", "kn", "Kannada"),
+    ("kaz", "kk", "Kazakh"),
+    ("khm", "km", "Khmer"),
+    ("kor", "ko", "Korean"),
+    ("kur", "ku", "Kurdish"),
+    ("lao", "lo", "Lao"),
+    ("lat", "la", "Latin"),
+    ("lav", "lv", "Latvian"),
+    ("lit", "lt", "Lithuanian"),
+    ("mac", "mk", "Macedonian"),
+    ("may", "ms", "Malay"),
+    ("mal", "ml", "Malayalam"),
+    ("mlt", "mt", "Maltese"),
+    ("mar", "mr", "Marathi"),
+    ("mon", "mn", "Mongolian"),
+    ("nep", "ne", "Nepali"),
+    ("nor", "no", "Norwegian"),
+    ("nob", "nb", "Norwegian Bokmål"),
+    ("nno", "nn", "Norwegian Nynorsk"),
+    ("per", "fa", "Persian"),
+    ("pol", "pl", "Polish"),
+    ("por", "pt", "Portuguese"),
+    ("pan", "pa", "Punjabi"),
+    ("rum", "ro", "Romanian"),
+    ("rus", "ru", "Russian"),
+    ("srp", "sr", "Serbian"),
+    ("sin", "si", "Sinhala"),
+    ("slo", "sk", "Slovak"),
+    ("slv", "sl", "Slovenian"),
+    ("som", "so", "Somali"),
+    ("spa", "es", "Spanish"),
+    ("swa", "sw", "Swahili"),
+    ("swe", "sv", "Swedish"),
+    ("tgl", "tl", "Tagalog"),
+    ("tam", "ta", "Tamil"),
+    ("tel", "te", "Telugu"),
+    ("tha", "th", "Thai"),
+    ("tib", "bo", "Tibetan"),
+    ("tur", "tr", "Turkish"),
+    ("ukr", "uk", "Ukrainian"),
+    ("urd", "ur", "Urdu"),
+    ("uzb", "uz", "Uzbek"),
+    ("vie", "vi", "Vietnamese"),
+    ("wel", "cy", "Welsh"),
+    ("yid", "yi", "Yiddish"),
+    ("zul", "zu", "Zulu"),
+];
+
+// ISO 639-2 has a second code for some languages; both are accepted and the
+// one in `LANGUAGES` is written
+const LANGUAGE_ALIASES: &[(&str, &str)] = &[
+    ("sqi", "alb"),
+    ("hye", "arm"),
+    ("eus", "baq"),
+    ("mya", "bur"),
+    ("zho", "chi"),
+    ("ces", "cze"),
+    ("dut", "nld"),
+    ("fre", "fra"),
+    ("kat", "geo"),
+    ("ger", "deu"),
+    ("ell", "gre"),
+    ("isl", "ice"),
+    ("mkd", "mac"),
+    ("msa", "may"),
+    ("fas", "per"),
+    ("ron", "rum"),
+    ("slk", "slo"),
+    ("bod", "tib"),
+    ("cym", "wel"),
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
 pub struct MetadataOptions {
     pub copy_file_metadata: bool,
     pub copy_chapters: bool,
@@ -26,6 +189,115 @@ pub struct MetadataOptions {
     pub video_title: String,
     pub audio_title: String,
     pub subtitle_title: String,
+    /// File-level `title` and `comment` tags, set in either mode and taking
+    /// the place of copied ones; empty leaves them alone
+    pub file_title: String,
+    pub file_comment: String,
+    /// When non-empty, only these file-level keys are copied
+    pub metadata_allowlist: Vec<String>,
+    /// File-level keys that are never copied
+    pub metadata_blocklist: Vec<String>,
+    /// Flags like "default" or "forced" for individual output tracks
+    pub dispositions: Vec<StreamDisposition>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct QualityTarget {
+    /// VMAF score (0–100) a sample encode has to reach
+    pub vmaf: f64,
+}
+
+/// Cuts the output into numbered parts, e.g. for upload limits
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum OutputSplit {
+    /// Minutes per part
+    Duration(u32),
+    /// Megabytes per part; the part length is estimated from the bitrate
+    Size(u64),
+}
+
+/// Copied video can only start at a keyframe, so a trim starting between
+/// two either moves back to the earlier one or needs the video re-encoded
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+pub enum TrimAccuracy {
+    /// Start at the keyframe before the requested time, keeping the copy
+    #[default]
+    KeyframeFast,
+    /// Re-encode the video so the cut lands on the requested frame
+    FrameAccurate,
+}
+
+impl TrimAccuracy {
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            TrimAccuracy::KeyframeFast => "Fast (keyframes)",
+            TrimAccuracy::FrameAccurate => "Frame accurate",
+        }
+    }
+}
+
+/// Filter for interlaced sources such as broadcast captures and DVDs, which
+/// look combed on progressive displays
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum DeinterlaceMode {
+    /// Fast and widely used
+    Yadif,
+    /// Slower, with less flicker on fine detail
+    Bwdif,
+}
+
+impl DeinterlaceMode {
+    pub fn all() -> &'static [DeinterlaceMode] {
+        &[DeinterlaceMode::Yadif, DeinterlaceMode::Bwdif]
+    }
+
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            DeinterlaceMode::Yadif => "Yadif (fast)",
+            DeinterlaceMode::Bwdif => "Bwdif (better)",
+        }
+    }
+
+    /// FFmpeg filter, outputting one frame per frame so the rate is kept
+    pub fn filter_name(&self) -> &'static str {
+        match self {
+            DeinterlaceMode::Yadif => "yadif",
+            DeinterlaceMode::Bwdif => "bwdif",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum DispositionTarget {
+    Audio,
+    Subtitle,
+}
+
+impl DispositionTarget {
+    /// Stream specifier letter, as in `-disposition:a:0`
+    pub fn specifier(&self) -> &'static str {
+        match self {
+            DispositionTarget::Audio => "a",
+            DispositionTarget::Subtitle => "s",
+        }
+    }
+
+    pub fn from_codec_type(codec_type: &str) -> Option<Self> {
+        match codec_type {
+            "audio" => Some(DispositionTarget::Audio),
+            "subtitle" => Some(DispositionTarget::Subtitle),
+            _ => None,
+        }
+    }
+}
+
+/// Disposition flags of one output track; `index` counts only the output
+/// tracks of the same type, like FFmpeg's `a:N` and `s:N` specifiers
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StreamDisposition {
+    pub target: DispositionTarget,
+    pub index: usize,
+    pub flags: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -34,7 +306,9 @@ pub enum ConversionMode {
     Remux,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+// Formats and codecs are saved by variant name, but read with `TryFrom<&str>`
+// so presets written as e.g. "mkv" and "libx265" load too
+#[derive(Debug, Clone, Serialize, PartialEq)]
 pub enum VideoFormat {
     Mp4,
     Mkv,
@@ -43,23 +317,120 @@ pub enum VideoFormat {
     Webm,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, PartialEq)]
 pub enum VideoCodec {
     H264,
     H265,
     VP9,
+    AV1,
     Copy,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+pub enum HardwareAccel {
+    #[default]
+    None,
+    Nvenc,
+    Qsv,
+    Vaapi,
+    VideoToolbox,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
 pub enum AudioCodec {
     Aac,
     Mp3,
     Flac,
     Pcm16,
+    Opus,
+    Vorbis,
+    Ac3,
+    Eac3,
     Copy,
 }
 
+/// An extra output audio track, encoded from the same source track as the
+/// main one
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AudioEncode {
+    pub codec: AudioCodec,
+    pub bitrate: Option<String>,
+    /// Mix to this many channels (`-ac`); `None` keeps the source layout
+    pub channels: Option<u32>,
+}
+
+/// Codecs each container can hold, most widely supported first
+pub struct ContainerCodecs {
+    pub format: VideoFormat,
+    pub video: &'static [VideoCodec],
+    pub audio: &'static [AudioCodec],
+}
+
+pub const CONTAINER_CODEC_RECOMMENDATIONS: &[ContainerCodecs] = &[
+    ContainerCodecs {
+        format: VideoFormat::Mp4,
+        video: &[
+            VideoCodec::H264,
+            VideoCodec::H265,
+            VideoCodec::AV1,
+            VideoCodec::VP9,
+        ],
+        audio: &[
+            AudioCodec::Aac,
+            AudioCodec::Mp3,
+            AudioCodec::Opus,
+            AudioCodec::Flac,
+            AudioCodec::Ac3,
+            AudioCodec::Eac3,
+        ],
+    },
+    ContainerCodecs {
+        format: VideoFormat::Mkv,
+        video: &[
+            VideoCodec::H264,
+            VideoCodec::H265,
+            VideoCodec::AV1,
+            VideoCodec::VP9,
+        ],
+        audio: &[
+            AudioCodec::Aac,
+            AudioCodec::Opus,
+            AudioCodec::Vorbis,
+            AudioCodec::Flac,
+            AudioCodec::Mp3,
+            AudioCodec::Pcm16,
+            AudioCodec::Ac3,
+            AudioCodec::Eac3,
+        ],
+    },
+    ContainerCodecs {
+        format: VideoFormat::Mov,
+        video: &[VideoCodec::H264, VideoCodec::H265],
+        audio: &[
+            AudioCodec::Aac,
+            AudioCodec::Pcm16,
+            AudioCodec::Mp3,
+            AudioCodec::Ac3,
+            AudioCodec::Eac3,
+        ],
+    },
+    ContainerCodecs {
+        format: VideoFormat::Avi,
+        video: &[VideoCodec::H264, VideoCodec::H265],
+        audio: &[
+            AudioCodec::Mp3,
+            AudioCodec::Pcm16,
+            AudioCodec::Aac,
+            AudioCodec::Ac3,
+        ],
+    },
+    ContainerCodecs {
+        format: VideoFormat::Webm,
+        video: &[VideoCodec::VP9, VideoCodec::AV1],
+        audio: &[AudioCodec::Opus, AudioCodec::Vorbis],
+    },
+];
+
 impl VideoFormat {
     pub fn extension(&self) -> &'static str {
         match self {
@@ -80,6 +451,73 @@ impl VideoFormat {
             VideoFormat::Webm => "WebM",
         }
     }
+
+    pub fn from_extension(extension: &str) -> Option<VideoFormat> {
+        match extension.to_ascii_lowercase().as_str() {
+            "mp4" | "m4v" => Some(VideoFormat::Mp4),
+            "mkv" => Some(VideoFormat::Mkv),
+            "mov" => Some(VideoFormat::Mov),
+            "avi" => Some(VideoFormat::Avi),
+            "webm" => Some(VideoFormat::Webm),
+            _ => None,
+        }
+    }
+
+    fn codecs(&self) -> Option<&'static ContainerCodecs> {
+        CONTAINER_CODEC_RECOMMENDATIONS
+            .iter()
+            .find(|entry| entry.format == *self)
+    }
+
+    fn audio_codecs(&self) -> &'static [AudioCodec] {
+        self.codecs().map(|entry| entry.audio).unwrap_or(&[])
+    }
+
+    pub fn recommended_video_codec(&self) -> VideoCodec {
+        self.codecs()
+            .and_then(|entry| entry.video.first())
+            .cloned()
+            .unwrap_or(VideoCodec::H264)
+    }
+
+    pub fn recommended_audio_codec(&self) -> AudioCodec {
+        self.audio_codecs()
+            .first()
+            .cloned()
+            .unwrap_or(AudioCodec::Aac)
+    }
+
+    /// Stream copy is always allowed here; FFmpeg reports it if the source codec does not fit
+    pub fn supports_audio_codec(&self, codec: &AudioCodec) -> bool {
+        *codec == AudioCodec::Copy || self.audio_codecs().contains(codec)
+    }
+
+    /// MP4/MOV carry cover art as an attached picture, MKV as an attachment
+    pub fn supports_cover_art(&self) -> bool {
+        matches!(self, VideoFormat::Mp4 | VideoFormat::Mov | VideoFormat::Mkv)
+    }
+
+    /// Containers whose index (the `moov` atom) can be placed before the media
+    pub fn supports_faststart(&self) -> bool {
+        matches!(self, VideoFormat::Mp4 | VideoFormat::Mov)
+    }
+
+    /// Containers that players reliably read several audio tracks from
+    pub fn supports_multiple_audio(&self) -> bool {
+        matches!(self, VideoFormat::Mp4 | VideoFormat::Mkv | VideoFormat::Mov)
+    }
+
+    /// Containers that store per-track flags such as default and forced
+    pub fn supports_dispositions(&self) -> bool {
+        matches!(self, VideoFormat::Mp4 | VideoFormat::Mkv | VideoFormat::Mov)
+    }
+
+    pub fn supports_video_codec(&self, codec: &VideoCodec) -> bool {
+        *codec == VideoCodec::Copy
+            || self
+                .codecs()
+                .is_some_and(|entry| entry.video.contains(codec))
+    }
 }
 
 impl VideoCodec {
@@ -88,6 +526,7 @@ impl VideoCodec {
             VideoCodec::H264 => "H.264",
             VideoCodec::H265 => "H.265",
             VideoCodec::VP9 => "VP9",
+            VideoCodec::AV1 => "AV1",
             VideoCodec::Copy => "Copy",
         }
     }
@@ -97,9 +536,118 @@ impl VideoCodec {
             VideoCodec::H264 => "libx264",
             VideoCodec::H265 => "libx265",
             VideoCodec::VP9 => "libvpx-vp9",
+            VideoCodec::AV1 => "libaom-av1",
             VideoCodec::Copy => "copy",
         }
     }
+
+    /// Encoder to use with the given hardware backend, falling back to the
+    /// software encoder when the backend has none for this codec
+    pub fn encoder_name(&self, accel: HardwareAccel) -> &'static str {
+        match (self, accel) {
+            (VideoCodec::H264, HardwareAccel::Nvenc) => "h264_nvenc",
+            (VideoCodec::H264, HardwareAccel::Qsv) => "h264_qsv",
+            (VideoCodec::H264, HardwareAccel::Vaapi) => "h264_vaapi",
+            (VideoCodec::H264, HardwareAccel::VideoToolbox) => "h264_videotoolbox",
+            (VideoCodec::H265, HardwareAccel::Nvenc) => "hevc_nvenc",
+            (VideoCodec::H265, HardwareAccel::Qsv) => "hevc_qsv",
+            (VideoCodec::H265, HardwareAccel::Vaapi) => "hevc_vaapi",
+            (VideoCodec::H265, HardwareAccel::VideoToolbox) => "hevc_videotoolbox",
+            (VideoCodec::VP9, HardwareAccel::Qsv) => "vp9_qsv",
+            (VideoCodec::VP9, HardwareAccel::Vaapi) => "vp9_vaapi",
+            (VideoCodec::AV1, HardwareAccel::Nvenc) => "av1_nvenc",
+            (VideoCodec::AV1, HardwareAccel::Qsv) => "av1_qsv",
+            (VideoCodec::AV1, HardwareAccel::Vaapi) => "av1_vaapi",
+            _ => self.ffmpeg_name(),
+        }
+    }
+
+    /// Range of `-crf` values the software encoder accepts, `None` for
+    /// `Copy`. Higher values mean smaller, lower quality output.
+    pub fn crf_range(&self) -> Option<(u32, u32)> {
+        match self {
+            VideoCodec::H264 | VideoCodec::H265 => Some((0, 51)),
+            VideoCodec::VP9 | VideoCodec::AV1 => Some((0, 63)),
+            VideoCodec::Copy => None,
+        }
+    }
+
+    /// The codec matching an ffprobe `codec_name`, if it is one we encode
+    pub fn from_codec_name(name: &str) -> Option<Self> {
+        match name {
+            "h264" => Some(VideoCodec::H264),
+            "hevc" => Some(VideoCodec::H265),
+            "vp9" => Some(VideoCodec::VP9),
+            "av1" => Some(VideoCodec::AV1),
+            _ => None,
+        }
+    }
+
+    /// CRF used when none is set: the encoder's own default for x264 and
+    /// x265, and values of similar quality for libvpx and libaom, which
+    /// otherwise fall back to a low fixed bitrate
+    pub fn default_crf(&self) -> Option<u32> {
+        match self {
+            VideoCodec::H264 => Some(23),
+            VideoCodec::H265 => Some(28),
+            VideoCodec::VP9 => Some(31),
+            VideoCodec::AV1 => Some(30),
+            VideoCodec::Copy => None,
+        }
+    }
+
+    /// CRFs that usually look good without wasting space; outside it the
+    /// output is visibly worse or needlessly large
+    pub fn recommended_crf_range(&self) -> Option<(u32, u32)> {
+        match self {
+            VideoCodec::H264 => Some((18, 28)),
+            VideoCodec::H265 => Some((22, 32)),
+            VideoCodec::VP9 => Some((24, 37)),
+            VideoCodec::AV1 => Some((23, 38)),
+            VideoCodec::Copy => None,
+        }
+    }
+
+    /// Every codec that is actually encoded, i.e. all but `Copy`
+    pub fn encoded() -> [VideoCodec; 4] {
+        [
+            VideoCodec::H264,
+            VideoCodec::H265,
+            VideoCodec::VP9,
+            VideoCodec::AV1,
+        ]
+    }
+
+    /// Option taking a raw parameter string for the chosen encoder, if any
+    pub fn params_option(&self, accel: HardwareAccel) -> Option<&'static str> {
+        match self.encoder_name(accel) {
+            "libx264" => Some("-x264-params"),
+            "libx265" => Some("-x265-params"),
+            _ => None,
+        }
+    }
+}
+
+impl HardwareAccel {
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            HardwareAccel::None => "None (software)",
+            HardwareAccel::Nvenc => "NVIDIA NVENC",
+            HardwareAccel::Qsv => "Intel Quick Sync",
+            HardwareAccel::Vaapi => "VAAPI",
+            HardwareAccel::VideoToolbox => "VideoToolbox",
+        }
+    }
+
+    pub fn all() -> [HardwareAccel; 5] {
+        [
+            HardwareAccel::None,
+            HardwareAccel::Nvenc,
+            HardwareAccel::Qsv,
+            HardwareAccel::Vaapi,
+            HardwareAccel::VideoToolbox,
+        ]
+    }
 }
 
 impl AudioCodec {
@@ -109,6 +657,10 @@ impl AudioCodec {
             AudioCodec::Mp3 => "MP3",
             AudioCodec::Flac => "FLAC",
             AudioCodec::Pcm16 => "PCM (16-bit)",
+            AudioCodec::Opus => "Opus",
+            AudioCodec::Vorbis => "Vorbis",
+            AudioCodec::Ac3 => "AC3",
+            AudioCodec::Eac3 => "E-AC3",
             AudioCodec::Copy => "Copy",
         }
     }
@@ -119,14 +671,134 @@ impl AudioCodec {
             AudioCodec::Mp3 => "libmp3lame",
             AudioCodec::Flac => "flac",
             AudioCodec::Pcm16 => "pcm_s16le",
+            AudioCodec::Opus => "libopus",
+            AudioCodec::Vorbis => "libvorbis",
+            AudioCodec::Ac3 => "ac3",
+            AudioCodec::Eac3 => "eac3",
             AudioCodec::Copy => "copy",
         }
     }
+
+    /// Every codec that is actually encoded, i.e. all but `Copy`
+    pub fn encoded() -> [AudioCodec; 8] {
+        [
+            AudioCodec::Aac,
+            AudioCodec::Mp3,
+            AudioCodec::Opus,
+            AudioCodec::Vorbis,
+            AudioCodec::Ac3,
+            AudioCodec::Eac3,
+            AudioCodec::Flac,
+            AudioCodec::Pcm16,
+        ]
+    }
+
+    /// The codec matching an ffprobe `codec_name`, if it is one we encode
+    pub fn from_codec_name(name: &str) -> Option<Self> {
+        match name {
+            "aac" => Some(AudioCodec::Aac),
+            "mp3" => Some(AudioCodec::Mp3),
+            "flac" => Some(AudioCodec::Flac),
+            "pcm_s16le" => Some(AudioCodec::Pcm16),
+            "opus" => Some(AudioCodec::Opus),
+            "vorbis" => Some(AudioCodec::Vorbis),
+            "ac3" => Some(AudioCodec::Ac3),
+            "eac3" => Some(AudioCodec::Eac3),
+            _ => None,
+        }
+    }
+}
+
+impl TryFrom<&str> for VideoFormat {
+    type Error = String;
+
+    /// The saved variant name or a file extension, in any case
+    fn try_from(name: &str) -> Result<Self, Self::Error> {
+        VideoFormat::from_extension(name).ok_or_else(|| format!("unknown video format \"{name}\""))
+    }
+}
+
+impl TryFrom<&str> for VideoCodec {
+    type Error = String;
+
+    /// The saved variant name in any case, a software or hardware encoder
+    /// name, or an ffprobe codec name
+    fn try_from(name: &str) -> Result<Self, Self::Error> {
+        std::iter::once(VideoCodec::Copy)
+            .chain(VideoCodec::encoded())
+            .find(|codec| {
+                format!("{codec:?}").eq_ignore_ascii_case(name)
+                    || HardwareAccel::all()
+                        .into_iter()
+                        .any(|accel| codec.encoder_name(accel) == name)
+            })
+            .or_else(|| VideoCodec::from_codec_name(name))
+            .ok_or_else(|| format!("unknown video codec \"{name}\""))
+    }
+}
+
+impl TryFrom<&str> for AudioCodec {
+    type Error = String;
+
+    /// The saved variant name in any case, an encoder name, or an ffprobe
+    /// codec name
+    fn try_from(name: &str) -> Result<Self, Self::Error> {
+        std::iter::once(AudioCodec::Copy)
+            .chain(AudioCodec::encoded())
+            .find(|codec| {
+                format!("{codec:?}").eq_ignore_ascii_case(name) || codec.ffmpeg_name() == name
+            })
+            .or_else(|| AudioCodec::from_codec_name(name))
+            .ok_or_else(|| format!("unknown audio codec \"{name}\""))
+    }
+}
+
+/// Reads a format or codec through its `TryFrom<&str>`
+fn deserialize_named<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: for<'a> TryFrom<&'a str, Error = String>,
+{
+    let name = String::deserialize(deserializer)?;
+    T::try_from(name.as_str()).map_err(serde::de::Error::custom)
+}
+
+impl<'de> Deserialize<'de> for VideoFormat {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_named(deserializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for VideoCodec {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_named(deserializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for AudioCodec {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_named(deserializer)
+    }
+}
+
+/// Settings that were in the window when the app last autosaved or closed
+pub fn load_session() -> Option<ConversionPreset> {
+    let content = std::fs::read_to_string(config_location().join("session.json")).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// Writes settings serialized by the caller to `session.json`
+pub fn save_session(json: &str) {
+    if std::fs::create_dir_all(config_location()).is_ok() {
+        let _ = std::fs::write(config_location().join("session.json"), json);
+    }
 }
 
 #[derive(Debug, Default)]
 pub struct PresetManager {
     presets: HashMap<String, ConversionPreset>,
+    /// Set when presets.json was damaged, for the app to show once
+    load_warning: Option<String>,
 }
 
 impl PresetManager {
@@ -136,32 +808,76 @@ impl PresetManager {
         manager
     }
 
+    /// Loads every preset that still parses. A damaged file is copied to
+    /// presets.json.<unix time>.corrupt and rewritten with the presets that
+    /// survived, so one bad entry doesn't hide the rest.
     pub fn load_presets(&mut self) {
-        if let Some(config_dir) = dirs::config_dir() {
-            let presets_path = config_dir.join("ffmpegrust").join("presets.json");
-
-            if presets_path.exists() {
-                if let Ok(content) = std::fs::read_to_string(&presets_path) {
-                    if let Ok(presets) =
-                        serde_json::from_str::<HashMap<String, ConversionPreset>>(&content)
-                    {
-                        self.presets = presets;
-                    }
+        let presets_path = config_location().join("presets.json");
+        let Ok(content) = std::fs::read_to_string(&presets_path) else {
+            return;
+        };
+
+        let problem =
+            match serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(&content) {
+                Ok(entries) => {
+                    let total = entries.len();
+                    self.presets = entries
+                        .into_iter()
+                        .filter_map(|(name, value)| {
+                            serde_json::from_value::<ConversionPreset>(value)
+                                .ok()
+                                .map(|preset| (name, preset))
+                        })
+                        .collect();
+                    let skipped = total - self.presets.len();
+                    (skipped > 0).then(|| match skipped {
+                        1 => "1 preset couldn't be read and was skipped".to_string(),
+                        _ => format!("{skipped} presets couldn't be read and were skipped"),
+                    })
                 }
-            }
+                Err(e) => Some(format!("The presets file couldn't be read ({e})")),
+            };
+        let Some(problem) = problem else {
+            return;
+        };
+
+        // Timestamped so a later corruption doesn't replace an earlier backup
+        let corrupted_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|since| since.as_secs())
+            .unwrap_or_default();
+        let mut backup_path = presets_path.with_extension(format!("json.{corrupted_at}.corrupt"));
+        let mut n = 2;
+        while backup_path.exists() {
+            backup_path = presets_path.with_extension(format!("json.{corrupted_at}_{n}.corrupt"));
+            n += 1;
         }
+        self.load_warning = Some(match std::fs::copy(&presets_path, &backup_path) {
+            Ok(_) => {
+                self.save_presets();
+                format!(
+                    "{problem}; the original was kept as {}",
+                    backup_path.display()
+                )
+            }
+            // Without a backup the file is left as it is for the user to fix
+            Err(e) => format!("{problem}; it wasn't changed because a backup failed: {e}"),
+        });
+    }
+
+    /// The warning from loading a damaged presets file, once
+    pub fn take_load_warning(&mut self) -> Option<String> {
+        self.load_warning.take()
     }
 
     pub fn save_presets(&self) {
-        if let Some(config_dir) = dirs::config_dir() {
-            let app_config_dir = config_dir.join("ffmpegrust");
+        let app_config_dir = config_location();
 
-            if let Ok(()) = std::fs::create_dir_all(&app_config_dir) {
-                let presets_path = app_config_dir.join("presets.json");
+        if let Ok(()) = std::fs::create_dir_all(app_config_dir) {
+            let presets_path = app_config_dir.join("presets.json");
 
-                if let Ok(content) = serde_json::to_string_pretty(&self.presets) {
-                    let _ = std::fs::write(&presets_path, content);
-                }
+            if let Ok(content) = serde_json::to_string_pretty(&self.presets) {
+                let _ = std::fs::write(&presets_path, content);
             }
         }
     }
@@ -176,6 +892,22 @@ impl PresetManager {
         self.save_presets();
     }
 
+    /// Saves a copy of `name` as "<name> (copy)", numbering it if that name
+    /// is taken, and returns the new preset
+    pub fn duplicate_preset(&mut self, name: &str) -> Option<ConversionPreset> {
+        let mut copy = self.presets.get(name)?.clone();
+
+        copy.name = format!("{name} (copy)");
+        let mut counter = 2;
+        while self.presets.contains_key(&copy.name) {
+            copy.name = format!("{name} (copy {counter})");
+            counter += 1;
+        }
+
+        self.add_preset(copy.clone());
+        Some(copy)
+    }
+
     pub fn get_preset(&self, name: &str) -> Option<&ConversionPreset> {
         self.presets.get(name)
     }
@@ -183,12 +915,122 @@ impl PresetManager {
     pub fn list_presets(&self) -> Vec<&ConversionPreset> {
         self.presets.values().collect()
     }
+
+    /// Every tag used by a saved preset, sorted and without duplicates
+    pub fn get_all_tags(&self) -> Vec<String> {
+        self.presets
+            .values()
+            .flat_map(ConversionPreset::all_tags)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    /// Presets whose name or one of their tags fuzzy-matches `query`, best
+    /// match first; all presets by name for an empty query
+    pub fn search_presets(&self, query: &str) -> Vec<&ConversionPreset> {
+        let mut matches: Vec<(u32, &ConversionPreset)> = self
+            .presets
+            .values()
+            .filter_map(|preset| {
+                let name_score = fuzzy_score(query, &preset.name);
+                // A tag hit ranks below an equally good name hit
+                let tag_score = preset
+                    .all_tags()
+                    .iter()
+                    .filter_map(|tag| fuzzy_score(query, tag))
+                    .max()
+                    .map(|score| score / 2);
+                Some((name_score.max(tag_score)?, preset))
+            })
+            .collect();
+        matches.sort_by(|(a_score, a), (b_score, b)| {
+            b_score.cmp(a_score).then_with(|| a.name.cmp(&b.name))
+        });
+        matches.into_iter().map(|(_, preset)| preset).collect()
+    }
+
+    /// Presets carrying all (`match_all`) or any of `tags`; everything when
+    /// no tag is selected
+    pub fn filter_by_tags(
+        &self,
+        tags: &BTreeSet<String>,
+        match_all: bool,
+    ) -> Vec<&ConversionPreset> {
+        self.list_presets()
+            .into_iter()
+            .filter(|preset| {
+                if tags.is_empty() {
+                    return true;
+                }
+                let preset_tags = preset.all_tags();
+                if match_all {
+                    tags.is_subset(&preset_tags)
+                } else {
+                    !tags.is_disjoint(&preset_tags)
+                }
+            })
+            .collect()
+    }
+}
+
+impl ConversionPreset {
+    /// The extra audio tracks that are actually encoded: none when remuxing
+    /// or without audio
+    pub fn extra_audio_encodes(&self) -> &[AudioEncode] {
+        if self.mode == ConversionMode::Remux || self.disable_audio {
+            &[]
+        } else {
+            &self.extra_audio
+        }
+    }
+
+    /// Tags that follow from the settings, like `h265`, `mkv`, `web` or `archive`
+    pub fn builtin_tags(&self) -> Vec<String> {
+        let mut tags = vec![self.video_format.extension().to_string()];
+
+        if self.mode == ConversionMode::Remux {
+            tags.push("remux".to_string());
+        } else if !self.disable_video {
+            tags.push(match self.video_codec {
+                VideoCodec::H264 => "h264".to_string(),
+                VideoCodec::H265 => "h265".to_string(),
+                VideoCodec::VP9 => "vp9".to_string(),
+                VideoCodec::AV1 => "av1".to_string(),
+                VideoCodec::Copy => "copy".to_string(),
+            });
+        }
+        if self.web_optimized || self.video_format == VideoFormat::Webm {
+            tags.push("web".to_string());
+        }
+        if matches!(self.audio_codec, AudioCodec::Flac | AudioCodec::Pcm16) {
+            tags.push("archive".to_string());
+        }
+        if self.hardware_accel != HardwareAccel::None {
+            tags.push("hardware".to_string());
+        }
+        if self.disable_video {
+            tags.push("audio-only".to_string());
+        }
+
+        tags
+    }
+
+    /// Built-in and user tags, lowercased so "Web" and "web" are one tag
+    pub fn all_tags(&self) -> BTreeSet<String> {
+        self.builtin_tags()
+            .into_iter()
+            .chain(self.tags.iter().map(|tag| tag.trim().to_lowercase()))
+            .filter(|tag| !tag.is_empty())
+            .collect()
+    }
 }
 
 impl Default for ConversionPreset {
     fn default() -> Self {
         Self {
             name: "Default".to_string(),
+            tags: Vec::new(),
             mode: ConversionMode::Convert,
             video_format: VideoFormat::Mp4,
             video_codec: VideoCodec::H264,
@@ -197,6 +1039,24 @@ impl Default for ConversionPreset {
             audio_bitrate: None,
             resolution: None,
             frame_rate: None,
+            hardware_accel: HardwareAccel::None,
+            hw_device_index: None,
+            threads: None,
+            fix_timestamps: false,
+            web_optimized: false,
+            disable_video: false,
+            disable_audio: false,
+            keep_all_streams: false,
+            extra_audio: Vec::new(),
+            audio_offset_ms: None,
+            deinterlace: None,
+            crop: None,
+            crf: None,
+            quality_target: None,
+            trim_accuracy: TrimAccuracy::KeyframeFast,
+            split_output: None,
+            codec_params: None,
+            extra_args: Vec::new(),
             metadata_options: MetadataOptions::default(),
         }
     }
@@ -214,11 +1074,138 @@ impl Default for MetadataOptions {
             video_title: String::new(),
             audio_title: String::new(),
             subtitle_title: String::new(),
+            file_title: String::new(),
+            file_comment: String::new(),
+            metadata_allowlist: Vec::new(),
+            metadata_blocklist: Vec::new(),
+            dispositions: Vec::new(),
         }
     }
 }
 
 impl MetadataOptions {
+    /// The file-level tags to set, as `(key, value)`
+    pub fn file_tags(&self) -> Vec<(&'static str, &str)> {
+        [("title", &self.file_title), ("comment", &self.file_comment)]
+            .into_iter()
+            .map(|(key, value)| (key, value.trim()))
+            .filter(|(_, value)| !value.is_empty())
+            .collect()
+    }
+
+    pub fn filters_file_metadata(&self) -> bool {
+        !self.metadata_allowlist.is_empty() || !self.metadata_blocklist.is_empty()
+    }
+
+    pub fn keeps_key(&self, key: &str) -> bool {
+        if !self.metadata_allowlist.is_empty() {
+            return self.metadata_allowlist.iter().any(|k| k == key);
+        }
+
+        !self.metadata_blocklist.iter().any(|k| k == key)
+    }
+
+    pub fn set_key_kept(&mut self, key: &str, keep: bool) {
+        if !self.metadata_allowlist.is_empty() {
+            self.metadata_allowlist.retain(|k| k != key);
+            if keep {
+                self.metadata_allowlist.push(key.to_string());
+            }
+            return;
+        }
+
+        self.metadata_blocklist.retain(|k| k != key);
+        if !keep {
+            self.metadata_blocklist.push(key.to_string());
+        }
+    }
+
+    pub fn has_disposition(&self, target: DispositionTarget, index: usize, flag: &str) -> bool {
+        self.dispositions.iter().any(|disposition| {
+            disposition.target == target
+                && disposition.index == index
+                && disposition.flags.iter().any(|f| f == flag)
+        })
+    }
+
+    /// Turns one flag of a track on or off. Only one track of each type can
+    /// be the default, so setting "default" clears it from the others.
+    pub fn set_disposition(
+        &mut self,
+        target: DispositionTarget,
+        index: usize,
+        flag: &str,
+        enabled: bool,
+    ) {
+        for disposition in &mut self.dispositions {
+            if disposition.target == target
+                && (disposition.index == index || (enabled && flag == "default"))
+            {
+                disposition.flags.retain(|f| f != flag);
+            }
+        }
+
+        if enabled {
+            match self
+                .dispositions
+                .iter_mut()
+                .find(|disposition| disposition.target == target && disposition.index == index)
+            {
+                Some(disposition) => disposition.flags.push(flag.to_string()),
+                None => self.dispositions.push(StreamDisposition {
+                    target,
+                    index,
+                    flags: vec![flag.to_string()],
+                }),
+            }
+        }
+        self.dispositions
+            .retain(|disposition| !disposition.flags.is_empty());
+    }
+
+    /// The code to write for `value`, which may be any ISO 639-1 or 639-2
+    /// code in any case; empty means undetermined. `None` for anything else,
+    /// so arbitrary text never reaches the `language=` argument.
+    pub fn normalize_language(value: &str) -> Option<&'static str> {
+        let value = value.trim().to_ascii_lowercase();
+        if value.is_empty() {
+            return Some("und");
+        }
+        if !matches!(value.len(), 2 | 3) || !value.chars().all(|c| c.is_ascii_lowercase()) {
+            return None;
+        }
+
+        let value = LANGUAGE_ALIASES
+            .iter()
+            .find(|(alias, _)| *alias == value)
+            .map_or(value.as_str(), |(_, code)| code);
+        LANGUAGES
+            .iter()
+            .find(|(code, short, _)| *code == value || (!short.is_empty() && *short == value))
+            .map(|(code, _, _)| *code)
+    }
+
+    pub fn language_name(code: &str) -> Option<&'static str> {
+        let code = Self::normalize_language(code)?;
+        LANGUAGES
+            .iter()
+            .find(|(known, _, _)| *known == code)
+            .map(|(_, _, name)| *name)
+    }
+
+    /// The video, audio and subtitle languages that aren't known codes
+    pub fn invalid_languages(&self) -> Vec<(&'static str, &str)> {
+        [
+            ("Video", &self.video_language),
+            ("Audio", &self.audio_language),
+            ("Subtitle", &self.subtitle_language),
+        ]
+        .into_iter()
+        .filter(|(_, language)| Self::normalize_language(language).is_none())
+        .map(|(track, language)| (track, language.as_str()))
+        .collect()
+    }
+
     pub fn get_common_languages() -> Vec<(&'static str, &'static str)> {
         vec![
             ("und", "Undetermined"),
@@ -250,3 +1237,54 @@ impl MetadataOptions {
         ]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_saved_with_ffmpeg_names_load() {
+        let preset: ConversionPreset = serde_json::from_str(
+            r#"{"video_codec": "libx265", "video_format": "mkv", "audio_codec": "libopus"}"#,
+        )
+        .expect("preset should load");
+        assert_eq!(preset.video_codec, VideoCodec::H265);
+        assert_eq!(preset.video_format, VideoFormat::Mkv);
+        assert_eq!(preset.audio_codec, AudioCodec::Opus);
+
+        assert_eq!(VideoCodec::try_from("hevc_qsv"), Ok(VideoCodec::H265));
+        assert_eq!(VideoCodec::try_from("h264_nvenc"), Ok(VideoCodec::H264));
+        assert_eq!(AudioCodec::try_from("pcm_s16le"), Ok(AudioCodec::Pcm16));
+        assert!(VideoFormat::try_from("flv").is_err());
+
+        // Variant names, as presets are saved, still round-trip
+        let saved = serde_json::to_string(&preset).expect("preset should save");
+        let loaded: ConversionPreset = serde_json::from_str(&saved).expect("preset should load");
+        assert_eq!(loaded.video_codec, VideoCodec::H265);
+        assert_eq!(loaded.audio_codec, AudioCodec::Opus);
+    }
+
+    #[test]
+    fn language_codes_are_normalized() {
+        assert_eq!(MetadataOptions::normalize_language(" EN "), Some("eng"));
+        assert_eq!(MetadataOptions::normalize_language("ger"), Some("deu"));
+        assert_eq!(MetadataOptions::normalize_language(""), Some("und"));
+    }
+
+    #[test]
+    fn bogus_language_codes_are_rejected() {
+        for value in ["xx1", "english", "xyz", "e", "en;rm", "eng language=fra"] {
+            assert_eq!(MetadataOptions::normalize_language(value), None, "{value}");
+        }
+
+        let options = MetadataOptions {
+            audio_language: "xx1".to_string(),
+            subtitle_language: "english".to_string(),
+            ..MetadataOptions::default()
+        };
+        assert_eq!(
+            options.invalid_languages(),
+            [("Audio", "xx1"), ("Subtitle", "english")]
+        );
+    }
+}
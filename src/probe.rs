@@ -0,0 +1,693 @@
+use crate::config::{ffmpeg_binary, ffprobe_binary};
+use crate::conversion::format_duration;
+use crate::presets::CropRect;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::{Mutex, MutexGuard};
+use std::time::SystemTime;
+use tokio::process::Command as AsyncCommand;
+
+/// A container ffprobe can detect, with the extensions its files usually
+/// have. MP4 and MOV (or MKV and WebM) share a demuxer, so their extensions
+/// are interchangeable.
+struct KnownContainer {
+    /// First entry of ffprobe's `format_name`
+    demuxer: &'static str,
+    label: &'static str,
+    extensions: &'static [&'static str],
+}
+
+const KNOWN_CONTAINERS: &[KnownContainer] = &[
+    KnownContainer {
+        demuxer: "mov",
+        label: "MP4/MOV",
+        extensions: &["mp4", "m4v", "mov", "3gp"],
+    },
+    KnownContainer {
+        demuxer: "matroska",
+        label: "Matroska/WebM",
+        extensions: &["mkv", "webm"],
+    },
+    KnownContainer {
+        demuxer: "avi",
+        label: "AVI",
+        extensions: &["avi"],
+    },
+    KnownContainer {
+        demuxer: "flv",
+        label: "FLV",
+        extensions: &["flv"],
+    },
+    KnownContainer {
+        demuxer: "asf",
+        label: "ASF/WMV",
+        extensions: &["wmv"],
+    },
+    KnownContainer {
+        demuxer: "mpegts",
+        label: "MPEG-TS",
+        extensions: &["ts", "mts", "m2ts"],
+    },
+    KnownContainer {
+        demuxer: "mpeg",
+        label: "MPEG-PS",
+        extensions: &["vob", "mpg", "mpeg"],
+    },
+    KnownContainer {
+        demuxer: "ogg",
+        label: "Ogg",
+        extensions: &["ogv"],
+    },
+];
+
+#[derive(Debug, Clone, Default)]
+pub struct MediaInfo {
+    pub format_name: String,
+    pub duration: Option<f64>,
+    /// Overall bitrate in bits per second
+    pub bit_rate: Option<u64>,
+    pub tags: BTreeMap<String, String>,
+    pub streams: Vec<StreamInfo>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct StreamInfo {
+    pub codec_type: String,
+    pub codec_name: String,
+    pub channels: Option<u32>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// e.g. "yuv420p" or "yuv420p10le"
+    pub pix_fmt: Option<String>,
+    /// Matrix coefficients, e.g. "bt709" or "bt2020nc"
+    pub color_space: Option<String>,
+    /// e.g. "smpte2084" (PQ) or "arib-std-b67" (HLG) for HDR video
+    pub color_transfer: Option<String>,
+    /// Frame rates as ffprobe reports them, e.g. "30000/1001"
+    pub r_frame_rate: Option<String>,
+    pub avg_frame_rate: Option<String>,
+    /// "progressive", or "tt"/"bb"/"tb"/"bt" for interlaced video
+    pub field_order: Option<String>,
+    pub start_time: Option<f64>,
+    pub language: Option<String>,
+    pub title: Option<String>,
+    pub is_default: bool,
+}
+
+impl MediaInfo {
+    pub fn summary(&self) -> String {
+        let mut parts = vec![
+            self.container_label()
+                .map_or(self.format_name.clone(), str::to_string),
+        ];
+
+        if let Some(duration) = self.duration {
+            parts.push(format_duration(duration));
+        }
+
+        for (codec_type, label) in [("video", "video"), ("audio", "audio"), ("subtitle", "subs")] {
+            let count = self.streams_of_type(codec_type).len();
+            if count > 0 {
+                parts.push(format!("{count} {label}"));
+            }
+        }
+
+        parts.join(" · ")
+    }
+
+    /// Readable name of the container FFmpeg detected, if it is a known one
+    pub fn container_label(&self) -> Option<&'static str> {
+        self.container().map(|container| container.label)
+    }
+
+    /// Whether `path`'s extension fits the detected container; `None` when
+    /// either is one we don't know
+    pub fn container_matches_extension(&self, path: &Path) -> Option<bool> {
+        let extension = path.extension()?.to_string_lossy().to_ascii_lowercase();
+        let detected = self.container()?;
+        let known = KNOWN_CONTAINERS
+            .iter()
+            .any(|container| container.extensions.contains(&extension.as_str()));
+        known.then(|| detected.extensions.contains(&extension.as_str()))
+    }
+
+    fn container(&self) -> Option<&'static KnownContainer> {
+        let demuxer = self.format_name.split(',').next()?;
+        KNOWN_CONTAINERS
+            .iter()
+            .find(|container| container.demuxer == demuxer)
+    }
+
+    pub fn video_stream(&self) -> Option<&StreamInfo> {
+        self.streams_of_type("video").into_iter().next()
+    }
+
+    /// Signs of variable frame rate or broken timestamps: a stream starting
+    /// before zero, or a video whose nominal and average rates disagree
+    pub fn has_irregular_timestamps(&self) -> bool {
+        let negative_start = self
+            .streams
+            .iter()
+            .any(|stream| stream.start_time.is_some_and(|start| start < 0.0));
+        let variable_rate = self.video_stream().is_some_and(|video| {
+            match (
+                video.r_frame_rate.as_deref().and_then(parse_frame_rate),
+                video.avg_frame_rate.as_deref().and_then(parse_frame_rate),
+            ) {
+                (Some(nominal), Some(average)) => (nominal - average).abs() / nominal > 0.01,
+                _ => false,
+            }
+        });
+
+        negative_start || variable_rate
+    }
+
+    pub fn audio_streams(&self) -> Vec<&StreamInfo> {
+        self.streams_of_type("audio")
+    }
+
+    pub fn streams_of_type(&self, codec_type: &str) -> Vec<&StreamInfo> {
+        self.streams
+            .iter()
+            .filter(|stream| stream.codec_type == codec_type)
+            .collect()
+    }
+
+    /// Index (relative to the audio streams) of the track FFmpeg would pick
+    /// by default: the one flagged as default, otherwise the first.
+    pub fn default_audio_track(&self) -> Option<usize> {
+        let audio = self.audio_streams();
+        if audio.is_empty() {
+            return None;
+        }
+
+        Some(
+            audio
+                .iter()
+                .position(|stream| stream.is_default)
+                .unwrap_or(0),
+        )
+    }
+}
+
+impl StreamInfo {
+    pub fn is_hdr(&self) -> bool {
+        matches!(
+            self.color_transfer.as_deref(),
+            Some("smpte2084" | "arib-std-b67")
+        )
+    }
+
+    /// Whether the container flags the video as interlaced. Many files are
+    /// flagged wrongly or not at all, which `detect_interlacing` can check.
+    pub fn is_interlaced(&self) -> bool {
+        matches!(self.field_order.as_deref(), Some("tt" | "bb" | "tb" | "bt"))
+    }
+
+    pub fn describe(&self) -> String {
+        let mut parts = vec![self.codec_name.clone()];
+
+        if let Some(language) = &self.language {
+            parts.push(language.clone());
+        }
+
+        if let (Some(width), Some(height)) = (self.width, self.height) {
+            parts.push(format!("{width}x{height}"));
+        }
+
+        if let Some(channels) = self.channels {
+            parts.push(format!("{channels}ch"));
+        }
+
+        if let Some(title) = &self.title {
+            parts.push(format!("\"{title}\""));
+        }
+
+        if self.is_default {
+            parts.push("default".to_string());
+        }
+
+        parts.join(" · ")
+    }
+}
+
+/// Parses an ffprobe rate fraction like "30000/1001"; "0/0" means unknown
+pub fn parse_frame_rate(rate: &str) -> Option<f64> {
+    let (num, den) = rate.split_once('/')?;
+    let (num, den) = (num.parse::<f64>().ok()?, den.parse::<f64>().ok()?);
+    (num > 0.0 && den > 0.0).then(|| num / den)
+}
+
+/// Whether an MP4/MOV file has its `moov` atom ahead of the media data, so
+/// players can start before the whole file is downloaded. Only the top-level
+/// atom headers are read. Unreadable or truncated files count as not
+/// streamable.
+pub fn is_faststart(path: &Path) -> bool {
+    let Ok(file) = File::open(path) else {
+        return false;
+    };
+    let mut reader = BufReader::new(file);
+
+    loop {
+        let mut header = [0u8; 8];
+        if reader.read_exact(&mut header).is_err() {
+            return false;
+        }
+        let size = u64::from(u32::from_be_bytes([
+            header[0], header[1], header[2], header[3],
+        ]));
+        match &header[4..] {
+            b"moov" => return true,
+            b"mdat" => return false,
+            _ => {}
+        }
+
+        let skip = match size {
+            // The atom runs to the end of the file
+            0 => return false,
+            // A 64-bit size follows the type
+            1 => {
+                let mut large = [0u8; 8];
+                if reader.read_exact(&mut large).is_err() {
+                    return false;
+                }
+                u64::from_be_bytes(large).checked_sub(16)
+            }
+            size => size.checked_sub(8),
+        };
+        let Some(skip) = skip.and_then(|skip| i64::try_from(skip).ok()) else {
+            return false;
+        };
+        if reader.seek(SeekFrom::Current(skip)).is_err() {
+            return false;
+        }
+    }
+}
+
+// Portion of the input sampled for black bar detection
+const CROP_SAMPLE_SECS: f64 = 20.0;
+
+/// Runs FFmpeg's `cropdetect` over a sample from the first tenth of the
+/// input and returns the suggested `(width, height, x, y)`. The filter keeps
+/// the largest area seen over the sample, so brief dark scenes don't shrink
+/// the result.
+pub async fn detect_crop(input_file: &Path, duration: Option<f64>) -> Result<CropRect, String> {
+    let start = duration.map_or(0.0, |duration| {
+        (duration * 0.1).min((duration - CROP_SAMPLE_SECS).max(0.0))
+    });
+    let output = AsyncCommand::new(ffmpeg_binary())
+        .arg("-hide_banner")
+        .arg("-nostats")
+        .arg("-ss")
+        .arg(format!("{start:.3}"))
+        .arg("-i")
+        .arg(input_file)
+        .arg("-t")
+        .arg(CROP_SAMPLE_SECS.to_string())
+        .arg("-map")
+        .arg("0:v:0")
+        .arg("-vf")
+        .arg("cropdetect=limit=24:round=2:reset=0")
+        .arg("-an")
+        .arg("-f")
+        .arg("null")
+        .arg("-")
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| format!("Failed to run FFmpeg: {}", e))?;
+
+    String::from_utf8_lossy(&output.stderr)
+        .lines()
+        .filter_map(|line| parse_crop(line.rsplit_once("crop=")?.1))
+        .next_back()
+        .ok_or_else(|| "No black bars could be measured in the input".to_string())
+}
+
+// Frames idet has to classify before its verdict counts
+const MIN_IDET_FRAMES: u64 = 50;
+
+/// Runs FFmpeg's `idet` over the same sample as `detect_crop` and reports
+/// whether most of the frames it could classify are interlaced
+pub async fn detect_interlacing(input_file: &Path, duration: Option<f64>) -> Result<bool, String> {
+    let start = duration.map_or(0.0, |duration| {
+        (duration * 0.1).min((duration - CROP_SAMPLE_SECS).max(0.0))
+    });
+    let output = AsyncCommand::new(ffmpeg_binary())
+        .arg("-hide_banner")
+        .arg("-nostats")
+        .arg("-ss")
+        .arg(format!("{start:.3}"))
+        .arg("-i")
+        .arg(input_file)
+        .arg("-t")
+        .arg(CROP_SAMPLE_SECS.to_string())
+        .arg("-map")
+        .arg("0:v:0")
+        .arg("-vf")
+        .arg("idet")
+        .arg("-an")
+        .arg("-f")
+        .arg("null")
+        .arg("-")
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| format!("Failed to run FFmpeg: {}", e))?;
+
+    let (interlaced, progressive) = String::from_utf8_lossy(&output.stderr)
+        .lines()
+        .filter_map(|line| parse_idet(line.split_once("Multi frame detection:")?.1))
+        .next_back()
+        .ok_or_else(|| "The input's frames couldn't be analysed".to_string())?;
+    if interlaced + progressive < MIN_IDET_FRAMES {
+        return Err("Too few frames could be classified as interlaced or not".to_string());
+    }
+    Ok(interlaced > progressive)
+}
+
+/// Interlaced (top or bottom field first) and progressive frame counts from
+/// an idet summary like "TFF: 12 BFF: 0 Progressive: 340 Undetermined: 3"
+fn parse_idet(summary: &str) -> Option<(u64, u64)> {
+    let counts: HashMap<&str, u64> = summary
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .chunks(2)
+        .filter_map(|pair| {
+            Some((
+                pair.first()?.trim_end_matches(':'),
+                pair.get(1)?.parse().ok()?,
+            ))
+        })
+        .collect();
+    Some((
+        counts.get("TFF")? + counts.get("BFF")?,
+        *counts.get("Progressive")?,
+    ))
+}
+
+fn parse_crop(value: &str) -> Option<CropRect> {
+    let mut parts = value.trim().split(':').map(|part| part.parse::<u32>().ok());
+    let crop = (
+        parts.next()??,
+        parts.next()??,
+        parts.next()??,
+        parts.next()??,
+    );
+    (crop.0 > 0 && crop.1 > 0).then_some(crop)
+}
+
+// How far before a trim start to look for the keyframe a copy starts at
+const KEYFRAME_SEARCH_SECS: f64 = 30.0;
+
+/// Keyframe timestamps of the first video stream, in seconds, optionally
+/// only those read between `interval`'s start and end
+pub async fn probe_keyframes(
+    input_file: &Path,
+    interval: Option<(f64, f64)>,
+) -> Result<Vec<f64>, String> {
+    let mut cmd = AsyncCommand::new(ffprobe_binary());
+    cmd.arg("-v")
+        .arg("error")
+        .arg("-select_streams")
+        .arg("v:0")
+        .arg("-show_entries")
+        .arg("packet=pts_time,flags")
+        .arg("-of")
+        .arg("csv=p=0");
+    if let Some((start, end)) = interval {
+        cmd.arg("-read_intervals")
+            .arg(format!("{start:.3}%{end:.3}"));
+    }
+    let output = cmd
+        .arg(input_file)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| format!("Failed to run ffprobe: {}", e))?;
+
+    let mut keyframes: Vec<f64> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let (time, flags) = line.split_once(',')?;
+            flags.starts_with('K').then(|| time.parse::<f64>().ok())?
+        })
+        .collect();
+    keyframes.sort_by(f64::total_cmp);
+    Ok(keyframes)
+}
+
+/// The last keyframe at or before `time`, where copied video has to start
+pub async fn keyframe_before(input_file: &Path, time: f64) -> Result<Option<f64>, String> {
+    let start = (time - KEYFRAME_SEARCH_SECS).max(0.0);
+    let keyframes = probe_keyframes(input_file, Some((start, time + 0.001))).await?;
+    Ok(keyframes
+        .into_iter()
+        .rfind(|keyframe| *keyframe <= time + 0.001))
+}
+
+// Plenty for a library scan; beyond it the least recently probed file goes
+const MAX_CACHED_PROBES: usize = 256;
+
+static PROBE_CACHE: Mutex<ProbeCache> = Mutex::new(ProbeCache::new());
+
+/// One version of a file on disk. `None` for anything that isn't a regular
+/// file, such as an image sequence pattern, which is never cached.
+struct FileVersion {
+    path: PathBuf,
+    modified: SystemTime,
+    size: u64,
+}
+
+impl FileVersion {
+    fn of(path: &Path) -> Option<Self> {
+        let path = std::fs::canonicalize(path).ok()?;
+        let metadata = std::fs::metadata(&path).ok()?;
+        if !metadata.is_file() {
+            return None;
+        }
+        Some(Self {
+            path,
+            modified: metadata.modified().ok()?,
+            size: metadata.len(),
+        })
+    }
+}
+
+struct CachedProbe {
+    modified: SystemTime,
+    size: u64,
+    // Order of use, for evicting the stalest entry
+    used: u64,
+    info: MediaInfo,
+    frame_count: Option<u64>,
+}
+
+/// Probe results of files that haven't changed since. Entries are keyed on
+/// the canonical path and only match while the size and modification time
+/// do, so a file rewritten in place is probed again.
+struct ProbeCache {
+    entries: BTreeMap<PathBuf, CachedProbe>,
+    uses: u64,
+}
+
+impl ProbeCache {
+    const fn new() -> Self {
+        Self {
+            entries: BTreeMap::new(),
+            uses: 0,
+        }
+    }
+
+    fn lock() -> MutexGuard<'static, ProbeCache> {
+        PROBE_CACHE.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn get(&mut self, version: &FileVersion) -> Option<&mut CachedProbe> {
+        self.uses += 1;
+        let uses = self.uses;
+        let entry = self
+            .entries
+            .get_mut(&version.path)
+            .filter(|entry| entry.modified == version.modified && entry.size == version.size)?;
+        entry.used = uses;
+        Some(entry)
+    }
+
+    fn insert(&mut self, version: FileVersion, info: MediaInfo) {
+        if self.entries.len() >= MAX_CACHED_PROBES
+            && !self.entries.contains_key(&version.path)
+            && let Some(stalest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.used)
+                .map(|(path, _)| path.clone())
+        {
+            self.entries.remove(&stalest);
+        }
+        self.uses += 1;
+        self.entries.insert(
+            version.path,
+            CachedProbe {
+                modified: version.modified,
+                size: version.size,
+                used: self.uses,
+                info,
+                frame_count: None,
+            },
+        );
+    }
+}
+
+/// Exact frame total of `input_file` counted earlier, if it hasn't changed
+pub fn cached_frame_count(input_file: &Path) -> Option<u64> {
+    let version = FileVersion::of(input_file)?;
+    ProbeCache::lock().get(&version)?.frame_count
+}
+
+/// Keeps an exact frame total next to the probe of `input_file`; dropped if
+/// the file hasn't been probed
+pub fn remember_frame_count(input_file: &Path, frames: u64) {
+    if let Some(version) = FileVersion::of(input_file)
+        && let Some(entry) = ProbeCache::lock().get(&version)
+    {
+        entry.frame_count = Some(frames);
+    }
+}
+
+/// Streams and container details of `input_file`. Unchanged files are only
+/// run through ffprobe once; failures aren't cached.
+pub async fn probe_media(input_file: &Path) -> Result<MediaInfo, String> {
+    // Taken before probing, so a file that changes meanwhile is probed again
+    let version = FileVersion::of(input_file);
+    if let Some(version) = &version
+        && let Some(entry) = ProbeCache::lock().get(version)
+    {
+        return Ok(entry.info.clone());
+    }
+
+    let info = run_ffprobe(input_file).await?;
+    if let Some(version) = version {
+        ProbeCache::lock().insert(version, info.clone());
+    }
+    Ok(info)
+}
+
+async fn run_ffprobe(input_file: &Path) -> Result<MediaInfo, String> {
+    let output = AsyncCommand::new(ffprobe_binary())
+        .arg("-v")
+        .arg("quiet")
+        .arg("-print_format")
+        .arg("json")
+        .arg("-show_format")
+        .arg("-show_streams")
+        .arg(input_file)
+        .output()
+        .await
+        .map_err(|e| format!("Failed to run ffprobe: {}", e))?;
+
+    if !output.status.success() {
+        return Err("ffprobe could not read the input file".to_string());
+    }
+
+    let probe: ProbeOutput = serde_json::from_slice(&output.stdout)
+        .map_err(|e| format!("Failed to parse ffprobe output: {}", e))?;
+
+    Ok(probe.into())
+}
+
+#[derive(Debug, Deserialize)]
+struct ProbeOutput {
+    #[serde(default)]
+    streams: Vec<ProbeStream>,
+    format: Option<ProbeFormat>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProbeStream {
+    #[serde(default)]
+    codec_type: String,
+    #[serde(default)]
+    codec_name: String,
+    channels: Option<u32>,
+    width: Option<u32>,
+    height: Option<u32>,
+    pix_fmt: Option<String>,
+    color_space: Option<String>,
+    color_transfer: Option<String>,
+    r_frame_rate: Option<String>,
+    avg_frame_rate: Option<String>,
+    field_order: Option<String>,
+    start_time: Option<String>,
+    #[serde(default)]
+    disposition: HashMap<String, u8>,
+    #[serde(default)]
+    tags: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProbeFormat {
+    #[serde(default)]
+    format_name: String,
+    duration: Option<String>,
+    bit_rate: Option<String>,
+    #[serde(default)]
+    tags: BTreeMap<String, String>,
+}
+
+impl From<ProbeOutput> for MediaInfo {
+    fn from(probe: ProbeOutput) -> Self {
+        let (format_name, duration, bit_rate, tags) = probe
+            .format
+            .map(|format| {
+                (
+                    format.format_name,
+                    format.duration.and_then(|d| d.parse::<f64>().ok()),
+                    format.bit_rate.and_then(|b| b.parse::<u64>().ok()),
+                    format.tags,
+                )
+            })
+            .unwrap_or_default();
+
+        let streams = probe
+            .streams
+            .into_iter()
+            .map(|stream| StreamInfo {
+                codec_type: stream.codec_type,
+                codec_name: stream.codec_name,
+                channels: stream.channels,
+                width: stream.width,
+                height: stream.height,
+                pix_fmt: stream.pix_fmt,
+                color_space: stream.color_space,
+                color_transfer: stream.color_transfer,
+                r_frame_rate: stream.r_frame_rate,
+                avg_frame_rate: stream.avg_frame_rate,
+                field_order: stream.field_order,
+                start_time: stream.start_time.and_then(|t| t.parse::<f64>().ok()),
+                language: stream
+                    .tags
+                    .get("language")
+                    .filter(|lang| !lang.is_empty() && lang.as_str() != "und")
+                    .cloned(),
+                title: stream.tags.get("title").cloned(),
+                is_default: stream.disposition.get("default").copied() == Some(1),
+            })
+            .collect();
+
+        Self {
+            format_name,
+            duration,
+            bit_rate,
+            tags,
+            streams,
+        }
+    }
+}
@@ -0,0 +1,138 @@
+// Platform helpers for pausing and resuming a running FFmpeg process, and
+// for telling why one ended
+
+use std::process::ExitStatus;
+
+/// Signal that ended a process which didn't exit by itself. Cancels and
+/// timeouts return before FFmpeg's status is read, so this is always the
+/// OS or another program.
+#[cfg(unix)]
+pub fn termination_signal(status: &ExitStatus) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+    status.signal()
+}
+
+// Windows has no signals; a process that runs out of memory ends with one of
+// these NTSTATUS codes instead (STATUS_NO_MEMORY, STATUS_COMMITMENT_LIMIT)
+#[cfg(windows)]
+const OUT_OF_MEMORY_STATUSES: [u32; 2] = [0xC000_0017, 0xC000_012D];
+
+#[cfg(windows)]
+pub fn termination_signal(status: &ExitStatus) -> Option<i32> {
+    status
+        .code()
+        .filter(|code| OUT_OF_MEMORY_STATUSES.contains(&(*code as u32)))
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn termination_signal(_status: &ExitStatus) -> Option<i32> {
+    None
+}
+
+/// Whether a `termination_signal` points at memory running out: the Linux
+/// OOM killer sends SIGKILL, which the app itself never does
+#[cfg(unix)]
+pub fn is_out_of_memory(signal: i32) -> bool {
+    signal == libc::SIGKILL
+}
+
+// Only the out of memory statuses are reported on Windows
+#[cfg(not(unix))]
+pub fn is_out_of_memory(_signal: i32) -> bool {
+    true
+}
+
+/// "signal 9" or "status 0xC0000017", for error messages
+pub fn describe_termination(signal: i32) -> String {
+    if cfg!(windows) {
+        format!("status {:#010X}", signal as u32)
+    } else {
+        format!("signal {signal}")
+    }
+}
+
+#[cfg(unix)]
+pub fn suspend_process(pid: u32) -> Result<(), String> {
+    send_signal(pid, libc::SIGSTOP)
+}
+
+#[cfg(unix)]
+pub fn resume_process(pid: u32) -> Result<(), String> {
+    send_signal(pid, libc::SIGCONT)
+}
+
+#[cfg(unix)]
+fn send_signal(pid: u32, signal: libc::c_int) -> Result<(), String> {
+    // SAFETY: kill has no memory-safety preconditions; an invalid pid just errors
+    let result = unsafe { libc::kill(pid as libc::pid_t, signal) };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(format!(
+            "Failed to signal FFmpeg process: {}",
+            std::io::Error::last_os_error()
+        ))
+    }
+}
+
+// Windows has no SIGSTOP, so suspend every thread owned by the process instead
+#[cfg(windows)]
+pub fn suspend_process(pid: u32) -> Result<(), String> {
+    use winapi::um::processthreadsapi::SuspendThread;
+    // SAFETY: the handle comes from OpenThread and is valid for the call
+    for_each_thread(pid, |thread| unsafe { SuspendThread(thread) } != u32::MAX)
+}
+
+#[cfg(windows)]
+pub fn resume_process(pid: u32) -> Result<(), String> {
+    use winapi::um::processthreadsapi::ResumeThread;
+    // SAFETY: the handle comes from OpenThread and is valid for the call
+    for_each_thread(pid, |thread| unsafe { ResumeThread(thread) } != u32::MAX)
+}
+
+#[cfg(windows)]
+fn for_each_thread(
+    pid: u32,
+    action: impl Fn(winapi::um::winnt::HANDLE) -> bool,
+) -> Result<(), String> {
+    use winapi::um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE};
+    use winapi::um::processthreadsapi::OpenThread;
+    use winapi::um::tlhelp32::{
+        CreateToolhelp32Snapshot, TH32CS_SNAPTHREAD, THREADENTRY32, Thread32First, Thread32Next,
+    };
+    use winapi::um::winnt::THREAD_SUSPEND_RESUME;
+
+    // SAFETY: all handles are checked before use and closed exactly once
+    unsafe {
+        let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPTHREAD, 0);
+        if snapshot == INVALID_HANDLE_VALUE {
+            return Err("Failed to enumerate FFmpeg threads".to_string());
+        }
+
+        let mut entry: THREADENTRY32 = std::mem::zeroed();
+        entry.dwSize = std::mem::size_of::<THREADENTRY32>() as u32;
+
+        let mut all_succeeded = true;
+        let mut has_entry = Thread32First(snapshot, &mut entry) != 0;
+        while has_entry {
+            if entry.th32OwnerProcessID == pid {
+                let thread = OpenThread(THREAD_SUSPEND_RESUME, 0, entry.th32ThreadID);
+                if thread.is_null() {
+                    all_succeeded = false;
+                } else {
+                    all_succeeded &= action(thread);
+                    CloseHandle(thread);
+                }
+            }
+            has_entry = Thread32Next(snapshot, &mut entry) != 0;
+        }
+
+        CloseHandle(snapshot);
+
+        if all_succeeded {
+            Ok(())
+        } else {
+            Err("Failed to change the state of some FFmpeg threads".to_string())
+        }
+    }
+}
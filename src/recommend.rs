@@ -0,0 +1,208 @@
+// Suggesting conversion settings from what the probe found in the input
+
+use crate::presets::{AudioCodec, ConversionMode, ConversionPreset, VideoCodec, VideoFormat};
+use crate::probe::{MediaInfo, is_faststart};
+use std::path::Path;
+
+// Codecs old or obscure enough that re-encoding mostly buys compatibility
+const LEGACY_VIDEO_CODECS: &[&str] = &[
+    "mpeg1video",
+    "mpeg2video",
+    "mpeg4",
+    "msmpeg4v2",
+    "msmpeg4v3",
+    "wmv1",
+    "wmv2",
+    "wmv3",
+    "vc1",
+    "flv1",
+    "h263",
+    "theora",
+    "rv40",
+];
+
+// Codecs that are already about as compact as a re-encode would make them
+const EFFICIENT_VIDEO_CODECS: &[&str] = &["hevc", "av1", "vp9"];
+
+#[derive(Debug, Clone)]
+pub struct Recommendation {
+    pub title: String,
+    /// Why these settings fit the input, shown under the title
+    pub reasons: Vec<String>,
+    pub preset: ConversionPreset,
+}
+
+/// Picks settings for the probed input, or `None` when there is no video
+/// stream to base a suggestion on
+pub fn recommend_settings(info: &MediaInfo) -> Option<Recommendation> {
+    let video = info.video_stream()?;
+    let codec = video.codec_name.as_str();
+    let height = video.height.unwrap_or(0);
+    let mut reasons = Vec::new();
+
+    let mut preset = ConversionPreset {
+        name: "Recommended".to_string(),
+        ..ConversionPreset::default()
+    };
+
+    let title = if video.is_hdr() {
+        preset.video_format = VideoFormat::Mkv;
+        preset.video_codec = VideoCodec::H265;
+        reasons.push(
+            "HDR source: H.265 in MKV keeps the 10-bit HDR signal that H.264 would drop"
+                .to_string(),
+        );
+        "Keep HDR with H.265"
+    } else if EFFICIENT_VIDEO_CODECS.contains(&codec) {
+        preset.mode = ConversionMode::Remux;
+        preset.video_format = VideoFormat::Mkv;
+        preset.video_codec = VideoCodec::Copy;
+        preset.audio_codec = AudioCodec::Copy;
+        reasons.push(format!(
+            "{codec} is already efficient; re-encoding would lose quality for little gain"
+        ));
+        "Remux without re-encoding"
+    } else if LEGACY_VIDEO_CODECS.contains(&codec) {
+        reasons.push(format!(
+            "{codec} is poorly supported by modern players; H.264 plays almost everywhere"
+        ));
+        "Modernize to H.264 MP4"
+    } else {
+        preset.video_format = VideoFormat::Mkv;
+        preset.video_codec = VideoCodec::H265;
+        reasons.push(format!(
+            "{codec} can usually be shrunk by a third or more with H.265 at similar quality"
+        ));
+        "Shrink with H.265"
+    };
+
+    if preset.mode == ConversionMode::Convert {
+        if let Some(target) = target_video_bitrate(&preset.video_codec, height)
+            && info.bit_rate.is_some_and(|rate| rate > target)
+        {
+            let rate = format_bitrate(target);
+            reasons.push(format!(
+                "{}p at {} is more than needed; {rate} keeps it visually clean",
+                height,
+                format_bitrate(info.bit_rate.unwrap_or(0))
+            ));
+            preset.video_bitrate = Some(rate);
+        }
+
+        preset.audio_codec = recommended_audio(info, &preset.video_format);
+        if preset.audio_codec == AudioCodec::Copy {
+            reasons.push("The existing audio already fits the container and is copied".to_string());
+        }
+    }
+
+    Some(Recommendation {
+        title: title.to_string(),
+        reasons,
+        preset,
+    })
+}
+
+// Rough targets for a clean encode; H.264 needs about half again as much
+fn target_video_bitrate(codec: &VideoCodec, height: u32) -> Option<u64> {
+    let base = match height {
+        0 => return None,
+        2160.. => 16_000_000,
+        1440.. => 8_000_000,
+        1080.. => 5_000_000,
+        720.. => 3_000_000,
+        _ => 1_500_000,
+    };
+
+    Some(match codec {
+        VideoCodec::H264 => base * 3 / 2,
+        _ => base,
+    })
+}
+
+fn recommended_audio(info: &MediaInfo, format: &VideoFormat) -> AudioCodec {
+    let fits = info.audio_streams().first().is_some_and(|stream| {
+        AudioCodec::from_codec_name(&stream.codec_name)
+            .is_some_and(|codec| format.supports_audio_codec(&codec))
+    });
+
+    if fits {
+        AudioCodec::Copy
+    } else {
+        format.recommended_audio_codec()
+    }
+}
+
+fn format_bitrate(bits_per_second: u64) -> String {
+    if bits_per_second >= 1_000_000 && bits_per_second.is_multiple_of(100_000) {
+        let megabits = bits_per_second as f64 / 1_000_000.0;
+        format!("{}M", megabits)
+    } else {
+        format!("{}k", bits_per_second / 1000)
+    }
+}
+
+/// Remux settings that fix common container problems without re-encoding:
+/// every stream copied into a fresh container of the same kind, which
+/// rewrites its index, plus only the fixes the probe and file show a need
+/// for
+pub fn repair_settings(info: &MediaInfo, input_file: &Path) -> Recommendation {
+    let mut reasons = Vec::new();
+    let mut preset = ConversionPreset {
+        name: "Repair".to_string(),
+        mode: ConversionMode::Remux,
+        video_codec: VideoCodec::Copy,
+        audio_codec: AudioCodec::Copy,
+        keep_all_streams: true,
+        ..ConversionPreset::default()
+    };
+
+    let detected = info.container_label().unwrap_or("another format");
+    preset.video_format = match input_file
+        .extension()
+        .and_then(|extension| VideoFormat::from_extension(&extension.to_string_lossy()))
+    {
+        Some(_) if info.container_matches_extension(input_file) == Some(false) => {
+            reasons.push(format!(
+                "The extension doesn't match the content ({detected}); MKV holds any kind of stream"
+            ));
+            VideoFormat::Mkv
+        }
+        Some(format) => format,
+        None => {
+            reasons.push("Unknown container; MKV holds any kind of stream".to_string());
+            VideoFormat::Mkv
+        }
+    };
+    reasons.push(format!(
+        "Every stream is copied into a new {}, which rebuilds its seek index",
+        preset.video_format.display_name()
+    ));
+
+    if info.has_irregular_timestamps() {
+        preset.fix_timestamps = true;
+        reasons.push(if preset.video_format.supports_faststart() {
+            "Irregular timestamps: missing ones are regenerated and a negative start that \
+             confuses edit lists is moved to zero"
+                .to_string()
+        } else {
+            "Irregular timestamps: missing ones are regenerated".to_string()
+        });
+    }
+    if preset.video_format.supports_faststart() && !is_faststart(input_file) {
+        preset.web_optimized = true;
+        reasons.push("The index is moved to the front, so playback can start sooner".to_string());
+    }
+
+    let data_streams = info.streams_of_type("data").len();
+    if data_streams > 0 {
+        reasons.push(format!(
+            "{data_streams} data stream(s) such as timecodes are left out"
+        ));
+    }
+
+    Recommendation {
+        title: "Repair without re-encoding".to_string(),
+        reasons,
+        preset,
+    }
+}
@@ -0,0 +1,487 @@
+// Encoding one input as several time segments at once, then joining them
+
+use crate::config::ffmpeg_binary;
+use crate::conversion::{
+    ConversionControl, ConversionError, ConversionMessage, ConversionProgress, ConversionTask,
+    format_duration, next_control, sleep_until_deadline, stop_gracefully, verify_output,
+};
+use crate::presets::{AudioCodec, ConversionMode, MetadataOptions, VideoCodec};
+use crate::probe::{probe_keyframes, probe_media};
+use crate::process_control::{resume_process, suspend_process};
+use crate::segments::Segment;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncReadExt;
+use tokio::process::Command as AsyncCommand;
+use tokio::sync::mpsc::{UnboundedSender, unbounded_channel};
+
+// Segments shorter than this aren't worth a process of their own
+const MIN_SEGMENT_SECS: f64 = 60.0;
+const MAX_SEGMENTS: usize = 8;
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How many segments to encode at once on this machine, or `None` when it
+/// has too few cores for splitting to beat a single multi-threaded encoder
+pub fn segment_count() -> Option<usize> {
+    let cores = std::thread::available_parallelism().map_or(1, |cores| cores.get());
+    let count = (cores / 4).min(MAX_SEGMENTS);
+    (count >= 2).then_some(count)
+}
+
+/// Whether the settings allow splitting: only re-encoded video can be cut
+/// at arbitrary keyframes and joined back without a visible seam. The join
+/// only adds audio, so tags, dispositions and extra arguments rule it out
+/// too; inputs with subtitles fall back in `execute_segmented`.
+pub fn can_encode_segmented(task: &ConversionTask) -> bool {
+    !task.input_source.is_image_sequence()
+        && task.preset.mode == ConversionMode::Convert
+        && task.preset.video_codec != VideoCodec::Copy
+        && !task.preset.disable_video
+        && task.preset.audio_offset_ms.is_none_or(|offset| offset == 0)
+        && task.trim.is_none()
+        && task.test_duration.is_none()
+        && task.cover_art.is_none()
+        && task.preset.extra_audio_encodes().is_empty()
+        && task.preset.split_output.is_none()
+        && task.preset.extra_args.is_empty()
+        && task.preset.metadata_options == MetadataOptions::default()
+}
+
+/// Runs `task` as `parts` concurrent encodes of keyframe-aligned pieces of
+/// the input, joined losslessly at the end. The audio is encoded once from
+/// the whole input while joining, so there are no gaps at the seams. Falls
+/// back to a normal conversion when the input can't be split.
+pub async fn execute_segmented(mut task: ConversionTask, parts: usize) {
+    // Searched once on the whole input, so every segment gets the same CRF
+    if !task.resolve_quality_target().await {
+        return;
+    }
+
+    // Subtitles can't be encoded per segment and joined
+    let duration = probe_media(&task.input_file)
+        .await
+        .ok()
+        .filter(|info| info.streams_of_type("subtitle").is_empty())
+        .and_then(|info| info.duration);
+    let segments = match duration {
+        Some(duration) => match probe_keyframes(&task.input_file, None).await {
+            Ok(keyframes) => plan_segments(duration, &keyframes, parts),
+            Err(_) => Vec::new(),
+        },
+        None => Vec::new(),
+    };
+    if segments.len() < 2 {
+        task.execute().await;
+        return;
+    }
+
+    match run_segments(task, &segments).await {
+        (task, Ok(Some(output_path))) => task.report_success(output_path).await,
+        (task, Ok(None)) => {
+            let _ = task.sender.send(ConversionMessage::Cancelled);
+        }
+        (task, Err(error)) => {
+            let _ = task.sender.send(ConversionMessage::Error(error));
+        }
+    }
+}
+
+/// Splits `duration` into up to `parts` segments of similar length, moving
+/// each cut to the nearest keyframe
+fn plan_segments(duration: f64, keyframes: &[f64], parts: usize) -> Vec<Segment> {
+    let mut cuts = vec![0.0];
+    for i in 1..parts {
+        let target = duration * i as f64 / parts as f64;
+        let Some(nearest) = keyframes
+            .iter()
+            .copied()
+            .min_by(|a, b| (a - target).abs().total_cmp(&(b - target).abs()))
+        else {
+            break;
+        };
+        let previous = cuts.last().copied().unwrap_or_default();
+        if nearest - previous >= MIN_SEGMENT_SECS && duration - nearest >= MIN_SEGMENT_SECS {
+            cuts.push(nearest);
+        }
+    }
+    cuts.push(duration);
+
+    cuts.windows(2)
+        .map(|window| Segment {
+            start: window[0],
+            end: window[1],
+            label: None,
+        })
+        .collect()
+}
+
+struct SegmentJob {
+    clip: PathBuf,
+    duration: f64,
+    receiver: Receiver<ConversionMessage>,
+    control: UnboundedSender<ConversionControl>,
+    percentage: f32,
+    done: bool,
+}
+
+async fn run_segments(
+    mut task: ConversionTask,
+    segments: &[Segment],
+) -> (ConversionTask, Result<Option<PathBuf>, ConversionError>) {
+    let stem = task
+        .output_file
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| "output".to_string());
+    let extension = task
+        .output_file
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_string())
+        .unwrap_or_else(|| task.preset.video_format.extension().to_string());
+
+    // Every segment uses identical video settings, so their streams can be
+    // concatenated as they are; audio is added once when joining
+    let mut preset = task.preset.clone();
+    preset.name = format!("{} (segment)", preset.name);
+    preset.disable_audio = true;
+    preset.web_optimized = false;
+    if preset.threads.is_none() {
+        let cores = std::thread::available_parallelism().map_or(1, |cores| cores.get());
+        preset.threads = Some((cores / segments.len()).max(1) as u32);
+    }
+
+    let mut jobs = Vec::new();
+    for (i, segment) in segments.iter().enumerate() {
+        let clip = task
+            .output_file
+            .with_file_name(format!("{stem}.part{:02}.{extension}", i + 1));
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let (control_tx, control_rx) = unbounded_channel();
+        let job = ConversionTask::new(
+            task.input_file.clone(),
+            clip.clone(),
+            preset.clone(),
+            sender,
+        )
+        .with_trim(Some(segment.clone()))
+        .with_control(control_rx)
+        .with_log(task.log.clone())
+        .with_grace_period(task.grace_period)
+        .with_timeout(task.timeout);
+        tokio::spawn(job.execute());
+
+        jobs.push(SegmentJob {
+            clip,
+            duration: segment.duration(),
+            receiver,
+            control: control_tx,
+            percentage: 0.0,
+            done: false,
+        });
+    }
+
+    let total_duration: f64 = jobs.iter().map(|job| job.duration).sum();
+    let started = Instant::now();
+    let mut cancelled = false;
+    let mut failure = None;
+
+    while jobs.iter().any(|job| !job.done) {
+        tokio::select! {
+            _ = tokio::time::sleep(POLL_INTERVAL) => {}
+            command = next_control(&mut task.control) => {
+                let Some(command) = command else {
+                    task.control = None;
+                    continue;
+                };
+                cancelled |= command == ConversionControl::Cancel;
+                for job in jobs.iter().filter(|job| !job.done) {
+                    let _ = job.control.send(command);
+                }
+                if command != ConversionControl::Cancel {
+                    let _ = task
+                        .sender
+                        .send(ConversionMessage::Paused(command == ConversionControl::Pause));
+                }
+            }
+        }
+
+        for job in jobs.iter_mut().filter(|job| !job.done) {
+            loop {
+                match job.receiver.try_recv() {
+                    Ok(ConversionMessage::Progress(progress)) => {
+                        job.percentage = progress.percentage;
+                    }
+                    Ok(ConversionMessage::Completed(_)) => {
+                        job.percentage = 100.0;
+                        job.done = true;
+                    }
+                    Ok(ConversionMessage::Cancelled) => job.done = true,
+                    Ok(ConversionMessage::Error(error)) => {
+                        job.done = true;
+                        failure.get_or_insert(error);
+                    }
+                    Ok(_) => {}
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        job.done = true;
+                        break;
+                    }
+                }
+            }
+        }
+
+        // One failed segment spoils the whole output, so stop the rest
+        if failure.is_some() && !cancelled {
+            cancelled = true;
+            for job in jobs.iter().filter(|job| !job.done) {
+                let _ = job.control.send(ConversionControl::Cancel);
+            }
+        }
+
+        if !cancelled {
+            let encoded: f64 = jobs
+                .iter()
+                .map(|job| job.duration * f64::from(job.percentage) / 100.0)
+                .sum();
+            task.send_progress(overall_progress(encoded, total_duration, started.elapsed()));
+        }
+    }
+
+    let clips: Vec<PathBuf> = jobs.into_iter().map(|job| job.clip).collect();
+    if cancelled {
+        remove_clips(&clips).await;
+        return (task, failure.map_or(Ok(None), Err));
+    }
+
+    let result = join_segments(&mut task, &clips, total_duration).await;
+    remove_clips(&clips).await;
+    if !matches!(result, Ok(Some(_))) {
+        let _ = tokio::fs::remove_file(&task.output_file).await;
+    }
+    (task, result)
+}
+
+fn overall_progress(encoded: f64, total: f64, elapsed: Duration) -> ConversionProgress {
+    let percentage = if total > 0.0 {
+        (encoded / total * 100.0).min(100.0) as f32
+    } else {
+        0.0
+    };
+    let time_remaining = (percentage > 1.0 && percentage < 100.0)
+        .then(|| {
+            let elapsed = elapsed.as_secs_f64();
+            Duration::try_from_secs_f64(elapsed * 100.0 / f64::from(percentage) - elapsed).ok()
+        })
+        .flatten();
+
+    ConversionProgress {
+        percentage,
+        time_remaining,
+        current_time: format_duration(encoded),
+        total_time: format_duration(total),
+        fps: None,
+        bitrate_kbps: None,
+        speed: (elapsed.as_secs_f64() > 0.0).then(|| (encoded / elapsed.as_secs_f64()) as f32),
+        estimated: false,
+    }
+}
+
+/// Concatenates the video of every clip and adds the audio, chapters and
+/// tags of the whole input, then checks the result is as long as the input.
+/// Re-encoding the audio takes a while, so the join can be paused, cancelled
+/// and timed out like the segments. `None` when it was cancelled.
+async fn join_segments(
+    task: &mut ConversionTask,
+    clips: &[PathBuf],
+    duration: f64,
+) -> Result<Option<PathBuf>, ConversionError> {
+    let failed = |message: String| ConversionError::SegmentedEncode(message);
+
+    let list_file = task.output_file.with_extension("segments.txt");
+    let list: String = clips
+        .iter()
+        .map(|clip| {
+            let path = clip.display().to_string().replace('\'', "'\\''");
+            format!("file '{path}'\n")
+        })
+        .collect();
+    tokio::fs::write(&list_file, list)
+        .await
+        .map_err(|e| failed(format!("Failed to write segment list: {}", e)))?;
+
+    let preset = &task.preset;
+    let mut cmd = AsyncCommand::new(ffmpeg_binary());
+    cmd.arg("-y")
+        .arg("-v")
+        .arg("error")
+        .arg("-f")
+        .arg("concat")
+        .arg("-safe")
+        .arg("0")
+        .arg("-i")
+        .arg(&list_file)
+        .arg("-i")
+        .arg(&task.input_file)
+        .arg("-map")
+        .arg("0:v")
+        .arg("-map_metadata")
+        .arg("1")
+        .arg("-map_chapters")
+        .arg("1");
+
+    if !preset.disable_audio {
+        let audio = match task.audio_track {
+            Some(track) => format!("1:a:{track}"),
+            None => "1:a:0?".to_string(),
+        };
+        cmd.arg("-map").arg(audio).arg("-c:v").arg("copy");
+        if preset.audio_codec == AudioCodec::Copy {
+            cmd.arg("-c:a").arg("copy");
+        } else {
+            cmd.arg("-c:a").arg(preset.audio_codec.ffmpeg_name());
+            if let Some(bitrate) = preset.audio_bitrate.as_deref().filter(|b| !b.is_empty()) {
+                cmd.arg("-b:a").arg(bitrate);
+            }
+        }
+    } else {
+        cmd.arg("-c").arg("copy");
+    }
+
+    if preset.web_optimized && preset.video_format.supports_faststart() {
+        cmd.arg("-movflags").arg("+faststart");
+    }
+
+    let child = cmd
+        .arg(&task.output_file)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            let _ = tokio::fs::remove_file(&list_file).await;
+            return Err(failed(format!("Failed to start FFmpeg: {}", e)));
+        }
+    };
+
+    let mut stdin = child.stdin.take();
+    let stderr_pipe = child.stderr.take();
+    let stderr_read = tokio::spawn(async move {
+        let mut stderr = String::new();
+        if let Some(mut pipe) = stderr_pipe {
+            let mut bytes = Vec::new();
+            let _ = pipe.read_to_end(&mut bytes).await;
+            stderr = String::from_utf8_lossy(&bytes).into_owned();
+        }
+        stderr
+    });
+
+    let timeout = task.timeout.limit(duration);
+    let mut deadline = timeout.map(|limit| tokio::time::Instant::now() + limit);
+    let mut paused_at = None;
+    let status = loop {
+        tokio::select! {
+            status = child.wait() => break status,
+            _ = sleep_until_deadline(deadline), if paused_at.is_none() => {
+                stop_gracefully(&mut child, stdin.take(), task.grace_period).await;
+                let _ = tokio::fs::remove_file(&list_file).await;
+                return Err(ConversionError::TimedOut(timeout.unwrap_or_default()));
+            }
+            command = next_control(&mut task.control) => {
+                let Some(command) = command else {
+                    task.control = None;
+                    continue;
+                };
+                let Some(pid) = child.id() else {
+                    continue;
+                };
+
+                match command {
+                    ConversionControl::Pause if paused_at.is_none() && suspend_process(pid).is_ok() => {
+                        paused_at = Some(Instant::now());
+                        let _ = task.sender.send(ConversionMessage::Paused(true));
+                    }
+                    ConversionControl::Resume if paused_at.is_some() && resume_process(pid).is_ok() => {
+                        if let (Some(deadline), Some(paused_at)) = (deadline.as_mut(), paused_at.take()) {
+                            *deadline += paused_at.elapsed();
+                        }
+                        let _ = task.sender.send(ConversionMessage::Paused(false));
+                    }
+                    ConversionControl::Cancel => {
+                        if paused_at.is_some() {
+                            let _ = resume_process(pid);
+                        }
+                        stop_gracefully(&mut child, stdin.take(), task.grace_period).await;
+                        let _ = tokio::fs::remove_file(&list_file).await;
+                        return Ok(None);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    };
+    let _ = tokio::fs::remove_file(&list_file).await;
+
+    let status = status.map_err(ConversionError::WaitFailed)?;
+    let stderr = stderr_read.await.unwrap_or_default();
+    if !status.success() {
+        return Err(failed(format!(
+            "Failed to join segments: {}",
+            stderr.lines().next_back().unwrap_or("FFmpeg failed")
+        )));
+    }
+
+    verify_output(&task.input_file, &task.output_file)
+        .await
+        .map_err(|err| failed(format!("The joined output doesn't match the input: {err}")))?;
+    Ok(Some(task.output_file.clone()))
+}
+
+async fn remove_clips(clips: &[PathBuf]) {
+    for clip in clips {
+        let _ = tokio::fs::remove_file(clip).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task() -> ConversionTask {
+        let (sender, _receiver) = std::sync::mpsc::channel();
+        ConversionTask::new(
+            PathBuf::from("input.mkv"),
+            PathBuf::from("output.mp4"),
+            Default::default(),
+            sender,
+        )
+    }
+
+    #[test]
+    fn plain_encodes_can_be_segmented() {
+        assert!(can_encode_segmented(&task()));
+    }
+
+    #[test]
+    fn settings_the_join_drops_rule_out_segments() {
+        let mut extra_args = task();
+        extra_args.preset.extra_args = vec!["-tune".to_string(), "film".to_string()];
+        assert!(!can_encode_segmented(&extra_args));
+
+        let mut tagged = task();
+        tagged.preset.metadata_options.file_title = "Holiday".to_string();
+        assert!(!can_encode_segmented(&tagged));
+    }
+
+    #[test]
+    fn cuts_land_on_keyframes() {
+        let keyframes: Vec<f64> = (0..60).map(|i| f64::from(i) * 10.0).collect();
+        let segments = plan_segments(600.0, &keyframes, 3);
+        let cuts: Vec<(f64, f64)> = segments.iter().map(|s| (s.start, s.end)).collect();
+        assert_eq!(cuts, vec![(0.0, 200.0), (200.0, 400.0), (400.0, 600.0)]);
+    }
+}
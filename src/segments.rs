@@ -0,0 +1,173 @@
+// Cutting highlight segments out of an input and optionally joining them
+
+use crate::config::ffmpeg_binary;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::process::Command as AsyncCommand;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Segment {
+    /// Seconds from the start of the input
+    pub start: f64,
+    pub end: f64,
+    pub label: Option<String>,
+}
+
+impl Segment {
+    pub fn duration(&self) -> f64 {
+        self.end - self.start
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SegmentOutput {
+    /// One file per segment
+    SeparateClips,
+    /// Clips are joined into one file, then removed
+    Joined,
+}
+
+/// Parses a segment list with one `in,out[,label]` entry per line.
+///
+/// Fields may also be separated by whitespace. Timestamps are seconds,
+/// `MM:SS` or `HH:MM:SS(.fff)`. Blank lines, `#` comments and a leading
+/// header row are ignored. `duration` (when known) bounds every segment.
+pub fn parse_segment_list(text: &str, duration: Option<f64>) -> Result<Vec<Segment>, String> {
+    let mut segments = Vec::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields: Vec<&str> = if line.contains(',') {
+            line.splitn(3, ',').map(str::trim).collect()
+        } else {
+            line.splitn(3, char::is_whitespace).map(str::trim).collect()
+        };
+
+        let line_number = number + 1;
+        let (Some(start), Some(end)) = (fields.first(), fields.get(1)) else {
+            return Err(format!(
+                "Line {line_number}: expected an in and an out time"
+            ));
+        };
+
+        let start = match parse_timestamp(start) {
+            Some(start) => start,
+            // Header rows like "in,out,label"
+            None if segments.is_empty() && start.chars().all(char::is_alphabetic) => continue,
+            None => return Err(format!("Line {line_number}: invalid in time \"{start}\"")),
+        };
+        let end = parse_timestamp(end)
+            .ok_or_else(|| format!("Line {line_number}: invalid out time \"{end}\""))?;
+
+        if end <= start {
+            return Err(format!(
+                "Line {line_number}: out time must be after in time"
+            ));
+        }
+        if let Some(duration) = duration
+            && start >= duration
+        {
+            return Err(format!(
+                "Line {line_number}: starts after the end of the input"
+            ));
+        }
+
+        segments.push(Segment {
+            start,
+            end: duration.map_or(end, |duration| end.min(duration)),
+            label: fields
+                .get(2)
+                .filter(|label| !label.is_empty())
+                .map(|label| label.to_string()),
+        });
+    }
+
+    if segments.is_empty() {
+        return Err("No segments listed".to_string());
+    }
+
+    Ok(segments)
+}
+
+/// Seconds for `SS(.fff)`, `MM:SS(.fff)` or `HH:MM:SS(.fff)`
+pub fn parse_timestamp(value: &str) -> Option<f64> {
+    let parts: Vec<&str> = value.trim().split(':').collect();
+    if parts.len() > 3 || parts.iter().any(|part| part.is_empty()) {
+        return None;
+    }
+
+    let (seconds, whole) = parts.split_last()?;
+    let seconds = seconds
+        .parse::<f64>()
+        .ok()
+        .filter(|s| s.is_finite() && *s >= 0.0)?;
+    if !whole.is_empty() && seconds >= 60.0 {
+        return None;
+    }
+
+    let mut total = 0.0;
+    for (i, part) in whole.iter().enumerate() {
+        let value = part.parse::<u32>().ok()?;
+        // Minutes must stay below 60 when hours are given
+        if i == 1 && value >= 60 {
+            return None;
+        }
+        total = total * 60.0 + f64::from(value);
+    }
+
+    Some(total * 60.0 + seconds)
+}
+
+/// Appends every clip into `output_file` with the concat demuxer. The clips
+/// come from one input with one set of settings, so their streams match and
+/// can be copied without re-encoding.
+pub async fn join_clips(clips: &[PathBuf], output_file: &Path) -> Result<(), String> {
+    let list_file = output_file.with_extension("segments.txt");
+    let list: String = clips
+        .iter()
+        .map(|clip| {
+            let path = clip.display().to_string().replace('\'', "'\\''");
+            format!("file '{path}'\n")
+        })
+        .collect();
+    tokio::fs::write(&list_file, list)
+        .await
+        .map_err(|e| format!("Failed to write segment list: {}", e))?;
+
+    let output = AsyncCommand::new(ffmpeg_binary())
+        .arg("-y")
+        .arg("-v")
+        .arg("error")
+        .arg("-f")
+        .arg("concat")
+        .arg("-safe")
+        .arg("0")
+        .arg("-i")
+        .arg(&list_file)
+        .arg("-c")
+        .arg("copy")
+        .arg(output_file)
+        .stdin(Stdio::null())
+        .output()
+        .await;
+    let _ = tokio::fs::remove_file(&list_file).await;
+
+    let output = output.map_err(|e| format!("Failed to start FFmpeg: {}", e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!(
+            "Failed to join segments: {}",
+            stderr.lines().next_back().unwrap_or("FFmpeg failed")
+        ));
+    }
+
+    for clip in clips {
+        let _ = tokio::fs::remove_file(clip).await;
+    }
+    Ok(())
+}
@@ -0,0 +1,250 @@
+// Numbered or wildcard image files read as the frames of one video
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_SEQUENCE_FRAMERATE: u32 = 25;
+pub const MAX_SEQUENCE_FRAMERATE: u32 = 240;
+pub const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "bmp", "tif", "tiff", "webp"];
+// Widest zero padding accepted in a `%0Nd` pattern
+const MAX_NUMBER_WIDTH: usize = 9;
+
+/// Where a conversion reads its frames from
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputSource {
+    File(PathBuf),
+    /// `pattern` is a path whose file name holds one `%0Nd` counter or `*`
+    /// wildcards, e.g. `photos/img%04d.jpg` or `photos/*.jpg`
+    ImageSequence {
+        pattern: PathBuf,
+        framerate: u32,
+    },
+}
+
+impl InputSource {
+    pub fn path(&self) -> &Path {
+        match self {
+            InputSource::File(path) => path,
+            InputSource::ImageSequence { pattern, .. } => pattern,
+        }
+    }
+
+    pub fn is_image_sequence(&self) -> bool {
+        matches!(self, InputSource::ImageSequence { .. })
+    }
+
+    /// The arguments that open this input, ending with `-i <path>`. A
+    /// sequence is checked again here, since its files may have changed
+    /// since it was picked.
+    pub fn input_args(&self) -> Result<Vec<OsString>, String> {
+        let mut args: Vec<OsString> = Vec::new();
+        if let InputSource::ImageSequence { pattern, framerate } = self {
+            validate_framerate(*framerate)?;
+            let scan = scan_image_sequence(pattern)?;
+
+            args.extend(["-framerate".into(), framerate.to_string().into()]);
+            match scan.start_number {
+                Some(start) => args.extend(["-start_number".into(), start.to_string().into()]),
+                None => args.extend(["-pattern_type".into(), "glob".into()]),
+            }
+        }
+        args.extend(["-i".into(), self.path().as_os_str().to_owned()]);
+        Ok(args)
+    }
+}
+
+/// What a sequence pattern matched on disk
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SequenceScan {
+    pub frames: usize,
+    /// Lowest number of a `%0Nd` sequence; `None` for wildcard patterns
+    pub start_number: Option<u64>,
+}
+
+#[derive(Debug, PartialEq)]
+enum NamePattern<'a> {
+    /// `width` is the zero padding, `None` for a plain `%d`
+    Numbered {
+        prefix: &'a str,
+        width: Option<usize>,
+        suffix: &'a str,
+    },
+    Glob(Vec<&'a str>),
+}
+
+pub fn is_image_file(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| {
+        let extension = extension.to_string_lossy().to_ascii_lowercase();
+        IMAGE_EXTENSIONS.contains(&extension.as_str())
+    })
+}
+
+pub fn validate_framerate(framerate: u32) -> Result<(), String> {
+    if (1..=MAX_SEQUENCE_FRAMERATE).contains(&framerate) {
+        Ok(())
+    } else {
+        Err(format!(
+            "Image sequence frame rate must be from 1 to {MAX_SEQUENCE_FRAMERATE}"
+        ))
+    }
+}
+
+/// The pattern for the sequence `image` belongs to: trailing digits in its
+/// name become a counter (`img0001.jpg` → `img%04d.jpg`); without digits,
+/// every image with the same extension in the folder is used
+pub fn sequence_pattern_for(image: &Path) -> Option<PathBuf> {
+    if !is_image_file(image) {
+        return None;
+    }
+    let stem = image.file_stem()?.to_str()?;
+    // FFmpeg would read a % in the name as a format code
+    if stem.contains('%') {
+        return None;
+    }
+    let extension = image.extension()?.to_str()?;
+
+    let digits = stem.len() - stem.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    let name = if digits == 0 || digits > MAX_NUMBER_WIDTH {
+        format!("*.{extension}")
+    } else {
+        format!("{}%0{digits}d.{extension}", &stem[..stem.len() - digits])
+    };
+    Some(image.with_file_name(name))
+}
+
+/// Checks the folder exists, the name is an image pattern FFmpeg reads
+/// literally apart from its one counter or wildcards, and counts the files
+/// it matches
+pub fn scan_image_sequence(pattern: &Path) -> Result<SequenceScan, String> {
+    let name = pattern
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| "The image sequence pattern needs a file name".to_string())?;
+    if !is_image_file(pattern) {
+        return Err(format!(
+            "Image sequences must end in one of: {}",
+            IMAGE_EXTENSIONS.join(", ")
+        ));
+    }
+    let name_pattern = parse_name_pattern(name)?;
+
+    let folder = pattern
+        .parent()
+        .filter(|folder| !folder.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    if !folder.is_dir() {
+        return Err(format!("Folder {} doesn't exist", folder.display()));
+    }
+    let entries =
+        std::fs::read_dir(folder).map_err(|e| format!("Failed to read image folder: {}", e))?;
+
+    let mut frames = 0;
+    let mut start_number: Option<u64> = None;
+    for entry in entries.flatten() {
+        let file_name = entry.file_name();
+        let Some(file_name) = file_name.to_str() else {
+            continue;
+        };
+        match &name_pattern {
+            NamePattern::Numbered {
+                prefix,
+                width,
+                suffix,
+            } => {
+                let Some(number) = file_name
+                    .strip_prefix(prefix)
+                    .and_then(|rest| rest.strip_suffix(suffix))
+                    .filter(|number| {
+                        !number.is_empty()
+                            && number.chars().all(|c| c.is_ascii_digit())
+                            && width.is_none_or(|width| number.len() == width)
+                    })
+                    .and_then(|number| number.parse::<u64>().ok())
+                else {
+                    continue;
+                };
+                frames += 1;
+                start_number = Some(start_number.map_or(number, |start| start.min(number)));
+            }
+            NamePattern::Glob(parts) => {
+                if glob_matches(parts, file_name) {
+                    frames += 1;
+                }
+            }
+        }
+    }
+
+    if frames == 0 {
+        return Err(format!("No images match {name}"));
+    }
+    Ok(SequenceScan {
+        frames,
+        start_number: match name_pattern {
+            NamePattern::Numbered { .. } => start_number,
+            NamePattern::Glob(_) => None,
+        },
+    })
+}
+
+// Accepts exactly one `%d`/`%0Nd` counter or `*` wildcards. Anything else
+// FFmpeg would expand, such as other format codes or `?`, `[` and `{`
+// globs, is rejected.
+fn parse_name_pattern(name: &str) -> Result<NamePattern<'_>, String> {
+    if name.contains('*') {
+        if name.contains(['%', '?', '[', ']', '{', '}', '\\']) {
+            return Err("Wildcard patterns may only use *".to_string());
+        }
+        return Ok(NamePattern::Glob(name.split('*').collect()));
+    }
+
+    if name.matches('%').count() != 1 {
+        return Err("The pattern needs exactly one counter like %04d, or a * wildcard".to_string());
+    }
+
+    let (prefix, rest) = name.split_once('%').unwrap_or((name, ""));
+    let end = rest
+        .find('d')
+        .ok_or_else(|| "The counter must look like %d or %04d".to_string())?;
+    let spec = &rest[..end];
+    let width = match spec {
+        "" => None,
+        spec if spec.len() >= 2 && spec.starts_with('0') => spec[1..]
+            .parse::<usize>()
+            .ok()
+            .filter(|width| (1..=MAX_NUMBER_WIDTH).contains(width))
+            .map(Some)
+            .ok_or_else(|| "The counter must look like %d or %04d".to_string())?,
+        _ => return Err("The counter must look like %d or %04d".to_string()),
+    };
+
+    Ok(NamePattern::Numbered {
+        prefix,
+        width,
+        suffix: &rest[end + 1..],
+    })
+}
+
+fn glob_matches(parts: &[&str], name: &str) -> bool {
+    let (Some(first), Some(last)) = (parts.first(), parts.last()) else {
+        return false;
+    };
+    if parts.len() == 1 {
+        return name == *first;
+    }
+    if name.starts_with('.') && !first.starts_with('.') {
+        return false;
+    }
+    let Some(mut rest) = name
+        .strip_prefix(first)
+        .and_then(|rest| rest.strip_suffix(last))
+    else {
+        return false;
+    };
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
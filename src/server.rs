@@ -0,0 +1,512 @@
+// Headless `serve` mode: submit and watch conversions over HTTP
+
+use crate::conversion::{
+    ConversionControl, ConversionMessage, ConversionProgress, ConversionTask,
+    DEFAULT_PROGRESS_MILESTONES, existing_split_parts, milestone_text,
+};
+use crate::presets::ConversionPreset;
+use crate::utils::is_same_file;
+use crate::validation::validate_conversion_settings;
+use futures_util::stream;
+use http_body_util::{BodyExt, Full, Limited, StreamBody, combinators::BoxBody};
+use hyper::body::{Bytes, Frame, Incoming};
+use hyper::header::{AUTHORIZATION, CACHE_CONTROL, CONTENT_TYPE, HeaderValue};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+use tokio::sync::{Semaphore, mpsc};
+
+const DEFAULT_BIND_ADDRESS: &str = "127.0.0.1:8750";
+const TOKEN_ENV_VAR: &str = "FFMPEGRUST_API_TOKEN";
+// How often the event stream checks a task for news
+const EVENT_INTERVAL: Duration = Duration::from_millis(500);
+// Largest request body accepted for a submission
+const MAX_BODY_BYTES: usize = 64 * 1024;
+// How long a finished task can still be looked up before it is forgotten
+const FINISHED_TASK_TTL: Duration = Duration::from_secs(60 * 60);
+
+type Body = BoxBody<Bytes, Infallible>;
+
+pub struct ServeOptions {
+    pub bind: SocketAddr,
+    pub token: String,
+    /// Conversions run at once, from the `max_concurrent` config key
+    pub max_concurrent: Option<NonZeroUsize>,
+}
+
+impl ServeOptions {
+    /// Parses `serve [--bind ADDR] [--token TOKEN]`; the token may also come
+    /// from `FFMPEGRUST_API_TOKEN` and is required either way
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut bind = DEFAULT_BIND_ADDRESS.to_string();
+        let mut token = std::env::var(TOKEN_ENV_VAR).ok();
+
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--bind" => bind = iter.next().ok_or("--bind needs an address")?.clone(),
+                "--token" => token = Some(iter.next().ok_or("--token needs a value")?.clone()),
+                other => return Err(format!("Unknown option for serve: {other}")),
+            }
+        }
+
+        let bind = bind
+            .parse()
+            .map_err(|_| format!("Invalid bind address: {bind}"))?;
+        let token = token
+            .filter(|token| !token.is_empty())
+            .ok_or_else(|| format!("An API token is required (--token or {TOKEN_ENV_VAR})"))?;
+
+        Ok(Self {
+            bind,
+            token,
+            max_concurrent: None,
+        })
+    }
+
+    pub fn with_max_concurrent(mut self, max_concurrent: Option<NonZeroUsize>) -> Self {
+        self.max_concurrent = max_concurrent;
+        self
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SubmitRequest {
+    input: PathBuf,
+    output: PathBuf,
+    #[serde(default)]
+    settings: ConversionPreset,
+    /// Percentages to report progress at; the default milestones if unset
+    milestones: Option<Vec<u32>>,
+    /// Replace an existing output instead of refusing the job
+    #[serde(default)]
+    overwrite: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TaskStatus {
+    /// Waiting for one of the `max_concurrent` slots
+    Queued,
+    Running,
+    Paused,
+    Completed(PathBuf),
+    Cancelled,
+    Failed(String),
+}
+
+impl TaskStatus {
+    fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            TaskStatus::Completed(_) | TaskStatus::Cancelled | TaskStatus::Failed(_)
+        )
+    }
+}
+
+struct TaskState {
+    input: PathBuf,
+    output: PathBuf,
+    status: TaskStatus,
+    progress: Option<ConversionProgress>,
+    /// The highest milestone passed so far
+    milestone: Option<u32>,
+    control: mpsc::UnboundedSender<ConversionControl>,
+    /// When the task reached a final status, for `FINISHED_TASK_TTL`
+    finished_at: Option<Instant>,
+}
+
+impl TaskState {
+    fn set_status(&mut self, status: TaskStatus) {
+        if status.is_finished() && self.finished_at.is_none() {
+            self.finished_at = Some(Instant::now());
+        }
+        self.status = status;
+    }
+
+    fn to_json(&self, id: u64) -> serde_json::Value {
+        let (status, detail) = match &self.status {
+            TaskStatus::Queued => ("queued", None),
+            TaskStatus::Running => ("running", None),
+            TaskStatus::Paused => ("paused", None),
+            TaskStatus::Completed(path) => ("completed", Some(path.display().to_string())),
+            TaskStatus::Cancelled => ("cancelled", None),
+            TaskStatus::Failed(error) => ("failed", Some(error.clone())),
+        };
+        let progress = self.progress.as_ref().map(|progress| {
+            json!({
+                "percentage": progress.percentage,
+                "estimated": progress.estimated,
+                "current_time": progress.current_time,
+                "total_time": progress.total_time,
+                "eta_secs": progress.time_remaining.map(|eta| eta.as_secs()),
+                "fps": progress.fps,
+                "speed": progress.speed,
+            })
+        });
+
+        json!({
+            "id": id,
+            "input": self.input,
+            "output": self.output,
+            "status": status,
+            "detail": detail,
+            "progress": progress,
+            "milestone": self.milestone,
+        })
+    }
+}
+
+#[derive(Default)]
+struct ServerState {
+    next_id: u64,
+    tasks: HashMap<u64, TaskState>,
+    /// Slots for running conversions; `None` runs every one right away
+    limit: Option<Arc<Semaphore>>,
+}
+
+impl ServerState {
+    /// Forgets tasks that finished more than `FINISHED_TASK_TTL` ago
+    fn prune_finished(&mut self) {
+        self.tasks.retain(|_, task| {
+            task.finished_at
+                .is_none_or(|finished| finished.elapsed() < FINISHED_TASK_TTL)
+        });
+    }
+}
+
+type SharedState = Arc<Mutex<ServerState>>;
+
+/// Accepts connections until the process is stopped
+pub async fn serve(options: ServeOptions) -> Result<(), String> {
+    let listener = TcpListener::bind(options.bind)
+        .await
+        .map_err(|e| format!("Failed to bind {}: {}", options.bind, e))?;
+    eprintln!("Listening on http://{}", options.bind);
+
+    let state = SharedState::new(Mutex::new(ServerState {
+        limit: options
+            .max_concurrent
+            .map(|limit| Arc::new(Semaphore::new(limit.get()))),
+        ..ServerState::default()
+    }));
+    let token = Arc::new(options.token);
+
+    loop {
+        let Ok((stream, _)) = listener.accept().await else {
+            continue;
+        };
+
+        let state = state.clone();
+        let token = token.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |request| {
+                let state = state.clone();
+                let token = token.clone();
+                async move { Ok::<_, Infallible>(handle(request, &state, &token).await) }
+            });
+            let _ = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await;
+        });
+    }
+}
+
+async fn handle(request: Request<Incoming>, state: &SharedState, token: &str) -> Response<Body> {
+    if !is_authorized(&request, token) {
+        return error_response(StatusCode::UNAUTHORIZED, "Missing or invalid API token");
+    }
+    state
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .prune_finished();
+
+    let segments: Vec<String> = request
+        .uri()
+        .path()
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(str::to_string)
+        .collect();
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+    let method = request.method().clone();
+
+    match (method, segments.as_slice()) {
+        (Method::POST, ["conversions"]) => submit(request, state).await,
+        (Method::GET, ["conversions"]) => {
+            let state = state.lock().unwrap_or_else(|e| e.into_inner());
+            let mut tasks: Vec<_> = state
+                .tasks
+                .iter()
+                .map(|(id, task)| task.to_json(*id))
+                .collect();
+            tasks.sort_by_key(|task| task["id"].as_u64());
+            json_response(StatusCode::OK, &json!(tasks))
+        }
+        (method, ["conversions", id, rest @ ..]) => {
+            let Ok(id) = id.parse::<u64>() else {
+                return error_response(StatusCode::NOT_FOUND, "Unknown conversion");
+            };
+            match (method, rest) {
+                (Method::GET, []) => status(state, id),
+                (Method::GET, ["events"]) => events(state.clone(), id),
+                (Method::DELETE, []) => cancel(state, id),
+                _ => error_response(StatusCode::NOT_FOUND, "Not found"),
+            }
+        }
+        _ => error_response(StatusCode::NOT_FOUND, "Not found"),
+    }
+}
+
+fn is_authorized(request: &Request<Incoming>, token: &str) -> bool {
+    let Some(provided) = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    else {
+        return false;
+    };
+
+    // Compare every byte so the response time doesn't reveal the prefix
+    provided.len() == token.len()
+        && provided
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+async fn submit(request: Request<Incoming>, state: &SharedState) -> Response<Body> {
+    let body = match Limited::new(request.into_body(), MAX_BODY_BYTES)
+        .collect()
+        .await
+    {
+        Ok(body) => body.to_bytes(),
+        Err(_) => {
+            return error_response(
+                StatusCode::BAD_REQUEST,
+                "Could not read request body (64 KiB at most)",
+            );
+        }
+    };
+
+    let submission: SubmitRequest = match serde_json::from_slice(&body) {
+        Ok(submission) => submission,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, &format!("Invalid JSON: {e}")),
+    };
+
+    if !submission.input.is_file() {
+        return error_response(StatusCode::BAD_REQUEST, "Input file not found");
+    }
+    if is_same_file(&submission.input, &submission.output) {
+        return error_response(StatusCode::BAD_REQUEST, "Output would overwrite the input");
+    }
+    // FFmpeg replaces outputs without asking, so the client has to
+    let output_exists = submission.output.exists()
+        || (submission.settings.split_output.is_some()
+            && !existing_split_parts(&submission.output).is_empty());
+    if output_exists && !submission.overwrite {
+        return error_response(
+            StatusCode::CONFLICT,
+            "Output already exists; set \"overwrite\": true to replace it",
+        );
+    }
+    let threads = submission
+        .settings
+        .threads
+        .map(|threads| threads.to_string())
+        .unwrap_or_default();
+    let errors: Vec<String> = validate_conversion_settings(
+        &submission.settings,
+        &threads,
+        &submission.settings.extra_args.join(" "),
+    )
+    .iter()
+    .filter(|issue| issue.is_error())
+    .map(|issue| issue.message().to_string())
+    .collect();
+    if !errors.is_empty() {
+        return error_response(StatusCode::BAD_REQUEST, &errors.join("; "));
+    }
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let (control_tx, control_rx) = mpsc::unbounded_channel();
+    let task = ConversionTask::new(
+        submission.input.clone(),
+        submission.output.clone(),
+        submission.settings,
+        sender,
+    )
+    .with_control(control_rx)
+    .with_milestones(
+        submission
+            .milestones
+            .unwrap_or_else(|| DEFAULT_PROGRESS_MILESTONES.to_vec()),
+    );
+
+    let (id, limit) = {
+        let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+        state.next_id += 1;
+        let id = state.next_id;
+        state.tasks.insert(
+            id,
+            TaskState {
+                input: submission.input,
+                output: submission.output,
+                status: TaskStatus::Queued,
+                progress: None,
+                milestone: None,
+                control: control_tx,
+                finished_at: None,
+            },
+        );
+        (id, state.limit.clone())
+    };
+
+    let run_state = state.clone();
+    tokio::spawn(async move {
+        let _slot = match limit {
+            Some(limit) => limit.acquire_owned().await.ok(),
+            None => None,
+        };
+        {
+            let mut state = run_state.lock().unwrap_or_else(|e| e.into_inner());
+            match state.tasks.get_mut(&id) {
+                Some(task) if task.status == TaskStatus::Queued => {
+                    task.set_status(TaskStatus::Running);
+                }
+                // Cancelled while it waited
+                _ => return,
+            }
+        }
+        task.execute().await;
+    });
+
+    // The task reports over a std channel, so drain it off the async threads
+    let state = state.clone();
+    tokio::task::spawn_blocking(move || {
+        for message in receiver {
+            let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+            let Some(task) = state.tasks.get_mut(&id) else {
+                return;
+            };
+            match message {
+                ConversionMessage::Progress(progress) => task.progress = Some(progress),
+                ConversionMessage::Milestone {
+                    percent,
+                    time_remaining,
+                } => {
+                    task.milestone = Some(percent);
+                    eprintln!(
+                        "Conversion {id}: {}",
+                        milestone_text(percent, time_remaining)
+                    );
+                }
+                ConversionMessage::Paused(true) => task.set_status(TaskStatus::Paused),
+                ConversionMessage::Paused(false) => task.set_status(TaskStatus::Running),
+                ConversionMessage::LowDiskSpace { .. }
+                | ConversionMessage::CrfSearch { .. }
+                | ConversionMessage::TrimAdjusted { .. }
+                | ConversionMessage::HardwareFallback { .. }
+                | ConversionMessage::OutputParts(_)
+                | ConversionMessage::SourceTrashed(_) => {}
+                ConversionMessage::Completed(path) => task.set_status(TaskStatus::Completed(path)),
+                ConversionMessage::Cancelled => task.set_status(TaskStatus::Cancelled),
+                ConversionMessage::Error(error) => {
+                    task.set_status(TaskStatus::Failed(error.to_string()))
+                }
+            }
+        }
+    });
+
+    json_response(StatusCode::CREATED, &json!({ "id": id }))
+}
+
+fn status(state: &SharedState, id: u64) -> Response<Body> {
+    let state = state.lock().unwrap_or_else(|e| e.into_inner());
+    match state.tasks.get(&id) {
+        Some(task) => json_response(StatusCode::OK, &task.to_json(id)),
+        None => error_response(StatusCode::NOT_FOUND, "Unknown conversion"),
+    }
+}
+
+fn cancel(state: &SharedState, id: u64) -> Response<Body> {
+    let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+    match state.tasks.get_mut(&id) {
+        Some(task) if task.status == TaskStatus::Queued => {
+            task.set_status(TaskStatus::Cancelled);
+            json_response(StatusCode::OK, &task.to_json(id))
+        }
+        Some(task) if !task.status.is_finished() => {
+            let _ = task.control.send(ConversionControl::Cancel);
+            json_response(StatusCode::ACCEPTED, &task.to_json(id))
+        }
+        Some(task) => json_response(StatusCode::CONFLICT, &task.to_json(id)),
+        None => error_response(StatusCode::NOT_FOUND, "Unknown conversion"),
+    }
+}
+
+/// Server-sent events with the task's state, sent whenever it changes and
+/// ending once the task has finished
+fn events(state: SharedState, id: u64) -> Response<Body> {
+    if !state
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .tasks
+        .contains_key(&id)
+    {
+        return error_response(StatusCode::NOT_FOUND, "Unknown conversion");
+    }
+
+    let events = stream::unfold(Some(String::new()), move |last| {
+        let state = state.clone();
+        async move {
+            let last = last?;
+            loop {
+                let (snapshot, finished) = {
+                    let state = state.lock().unwrap_or_else(|e| e.into_inner());
+                    let task = state.tasks.get(&id)?;
+                    (task.to_json(id).to_string(), task.status.is_finished())
+                };
+
+                if snapshot != last {
+                    let frame = Frame::data(Bytes::from(format!("data: {snapshot}\n\n")));
+                    let next = (!finished).then_some(snapshot);
+                    return Some((Ok::<_, Infallible>(frame), next));
+                }
+                tokio::time::sleep(EVENT_INTERVAL).await;
+            }
+        }
+    });
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "text/event-stream")
+        .header(CACHE_CONTROL, "no-cache")
+        .body(BodyExt::boxed(StreamBody::new(events)))
+        .unwrap_or_else(|_| error_response(StatusCode::INTERNAL_SERVER_ERROR, "Stream failed"))
+}
+
+fn json_response(status: StatusCode, value: &serde_json::Value) -> Response<Body> {
+    let mut response = Response::new(Full::new(Bytes::from(value.to_string())).boxed());
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    response
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response<Body> {
+    json_response(status, &json!({ "error": message }))
+}
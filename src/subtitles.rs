@@ -0,0 +1,190 @@
+// Extracting subtitle streams into standalone text files
+
+use crate::config::ffmpeg_binary;
+use crate::conversion::next_free_name;
+use crate::presets::MetadataOptions;
+use crate::probe::MediaInfo;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::process::Command as AsyncCommand;
+
+// Bitmap subtitle codecs; turning these into text would need OCR
+pub const IMAGE_SUBTITLE_CODECS: &[&str] =
+    &["hdmv_pgs_subtitle", "dvd_subtitle", "dvb_subtitle", "xsub"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SubtitleFormat {
+    Srt,
+    WebVtt,
+}
+
+impl SubtitleFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            SubtitleFormat::Srt => "srt",
+            SubtitleFormat::WebVtt => "vtt",
+        }
+    }
+
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            SubtitleFormat::Srt => "SRT",
+            SubtitleFormat::WebVtt => "WebVTT",
+        }
+    }
+
+    fn ffmpeg_name(&self) -> &'static str {
+        match self {
+            SubtitleFormat::Srt => "srt",
+            SubtitleFormat::WebVtt => "webvtt",
+        }
+    }
+}
+
+/// Writes each selected subtitle stream (indices relative to the input's
+/// subtitle streams) next to `output_base`, named `<stem>.<language>.<ext>`.
+///
+/// Returns one entry per selected stream; image-based streams are skipped
+/// with an explanatory error instead of failing the whole extraction.
+pub async fn extract_subtitles(
+    input_file: &Path,
+    info: &MediaInfo,
+    selected: &[usize],
+    format: SubtitleFormat,
+    output_base: &Path,
+) -> Vec<Result<PathBuf, String>> {
+    let subtitles = info.streams_of_type("subtitle");
+    let mut results = Vec::new();
+    let mut used_names = Vec::new();
+
+    for &index in selected {
+        let Some(stream) = subtitles.get(index) else {
+            results.push(Err(format!("Subtitle {} does not exist", index + 1)));
+            continue;
+        };
+
+        if IMAGE_SUBTITLE_CODECS.contains(&stream.codec_name.as_str()) {
+            results.push(Err(format!(
+                "Skipped subtitle {} ({}): image-based subtitles can't be converted to {}",
+                index + 1,
+                stream.codec_name,
+                format.display_name()
+            )));
+            continue;
+        }
+
+        let label = subtitle_label(stream.language.as_deref(), index);
+        let label = if used_names.contains(&label) {
+            format!("{label}.{}", index + 1)
+        } else {
+            label
+        };
+        used_names.push(label.clone());
+
+        let mut output_file = subtitle_output_path(output_base, &label, format);
+        if output_file.exists() {
+            output_file = next_free_name(&output_file);
+        }
+        let result = extract_stream(input_file, index, format, &output_file).await;
+        results.push(result.map(|()| output_file));
+    }
+
+    results
+}
+
+/// The language tag comes straight from the container, so only a known
+/// language code or a plain `[A-Za-z0-9_-]` word is allowed into the file
+/// name; anything else falls back to `track<n>`.
+fn subtitle_label(language: Option<&str>, index: usize) -> String {
+    let fallback = || format!("track{}", index + 1);
+    let Some(language) = language
+        .map(str::trim)
+        .filter(|language| !language.is_empty())
+    else {
+        return fallback();
+    };
+
+    if let Some(code) = MetadataOptions::normalize_language(language) {
+        return code.to_string();
+    }
+    if language.len() <= 32
+        && language
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        language.to_string()
+    } else {
+        fallback()
+    }
+}
+
+fn subtitle_output_path(output_base: &Path, label: &str, format: SubtitleFormat) -> PathBuf {
+    let stem = output_base
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| "subtitles".to_string());
+    output_base.with_file_name(format!("{stem}.{label}.{}", format.extension()))
+}
+
+async fn extract_stream(
+    input_file: &Path,
+    index: usize,
+    format: SubtitleFormat,
+    output_file: &Path,
+) -> Result<(), String> {
+    let output = AsyncCommand::new(ffmpeg_binary())
+        .arg("-n")
+        .arg("-v")
+        .arg("error")
+        .arg("-i")
+        .arg(input_file)
+        .arg("-map")
+        .arg(format!("0:s:{index}"))
+        .arg("-c:s")
+        .arg(format.ffmpeg_name())
+        .arg(output_file)
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .map_err(|e| format!("Failed to start FFmpeg: {}", e))?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Err(format!(
+            "Failed to extract subtitle {}: {}",
+            index + 1,
+            stderr.lines().next_back().unwrap_or("FFmpeg failed")
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn label_keeps_language_codes() {
+        assert_eq!(subtitle_label(Some("eng"), 0), "eng");
+        assert_eq!(subtitle_label(Some("EN"), 0), "eng");
+        assert_eq!(subtitle_label(Some("forced-1"), 0), "forced-1");
+        assert_eq!(subtitle_label(None, 2), "track3");
+        assert_eq!(subtitle_label(Some("  "), 0), "track1");
+    }
+
+    #[test]
+    fn label_rejects_path_characters() {
+        for tag in ["../../x", "a/b", "a\\b", "..", "c:x", "x y"] {
+            assert_eq!(subtitle_label(Some(tag), 0), "track1", "{tag}");
+        }
+    }
+
+    #[test]
+    fn output_path_stays_next_to_the_output() {
+        let base = Path::new("/videos/out/movie.mp4");
+        let label = subtitle_label(Some("../../x"), 0);
+        let path = subtitle_output_path(base, &label, SubtitleFormat::Srt);
+        assert_eq!(path, Path::new("/videos/out/movie.track1.srt"));
+    }
+}
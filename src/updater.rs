@@ -1,10 +1,19 @@
 use futures_util::StreamExt;
-use reqwest;
 use semver::Version;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use tokio::fs;
 use tokio::io::AsyncWriteExt;
+use tokio::sync::watch;
+
+// Limits for reaching GitHub and for a download that stops sending data. A
+// throttled download can take any time overall, so only the API check gets
+// a limit on the whole request.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+const API_TIMEOUT: Duration = Duration::from_secs(30);
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UpdateInfo {
@@ -12,6 +21,8 @@ pub struct UpdateInfo {
     pub download_url: String,
     pub release_notes: String,
     pub published_at: String,
+    #[serde(default)]
+    pub sha256: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -21,41 +32,83 @@ pub enum UpdateStatus {
     NoUpdateAvailable,
     DownloadingUpdate(f32), // percentage
     InstallingUpdate,
+    DownloadCancelled,
     Error(String),
 }
 
+#[derive(Debug, thiserror::Error)]
+pub enum UpdateError {
+    #[error("Invalid current version: {0}")]
+    InvalidVersion(#[source] semver::Error),
+    #[error("Failed to create HTTP client: {0}")]
+    HttpClient(#[source] reqwest::Error),
+    #[error("Failed to start download: {0}")]
+    DownloadStart(#[source] reqwest::Error),
+    #[error("Download failed: {0}")]
+    DownloadStatus(reqwest::StatusCode),
+    #[error("Failed to read chunk: {0}")]
+    ReadChunk(#[source] reqwest::Error),
+    #[error("Downloaded update does not match the published checksum")]
+    ChecksumMismatch,
+    #[error("Downloaded update is {actual} bytes, expected {expected}")]
+    SizeMismatch { expected: u64, actual: u64 },
+    #[error("Download cancelled")]
+    Cancelled,
+    /// Any filesystem or process step; `context` says which one
+    #[error("{context}: {source}")]
+    Io {
+        context: &'static str,
+        source: std::io::Error,
+    },
+}
+
+impl UpdateError {
+    fn io(context: &'static str) -> impl FnOnce(std::io::Error) -> Self {
+        move |source| UpdateError::Io { context, source }
+    }
+}
+
 #[derive(Clone)]
 pub struct Updater {
     current_version: Version,
     github_repo: String,
     client: reqwest::Client,
+    download_rate_limit: Option<u64>,
 }
 
 impl Updater {
-    pub fn new(current_version: &str, github_repo: &str) -> Result<Self, String> {
-        let current_version = Version::parse(current_version)
-            .map_err(|e| format!("Invalid current version: {}", e))?;
+    pub fn new(current_version: &str, github_repo: &str) -> Result<Self, UpdateError> {
+        let current_version =
+            Version::parse(current_version).map_err(UpdateError::InvalidVersion)?;
 
         let client = reqwest::Client::builder()
             .user_agent("FFmpegRust-Updater/1.0")
-            .timeout(std::time::Duration::from_secs(30))
+            .connect_timeout(CONNECT_TIMEOUT)
+            .read_timeout(READ_TIMEOUT)
             .build()
-            .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+            .map_err(UpdateError::HttpClient)?;
 
         Ok(Self {
             current_version,
             github_repo: github_repo.to_string(),
             client,
+            download_rate_limit: None,
         })
     }
 
+    /// Cap update downloads at `bytes_per_second`; `None` downloads at full speed
+    pub fn with_download_rate_limit(mut self, bytes_per_second: Option<u64>) -> Self {
+        self.download_rate_limit = bytes_per_second.filter(|rate| *rate > 0);
+        self
+    }
+
     pub async fn check_for_updates(&self) -> UpdateStatus {
         let url = format!(
             "https://api.github.com/repos/{}/releases/latest",
             self.github_repo
         );
 
-        match self.client.get(&url).send().await {
+        match self.client.get(&url).timeout(API_TIMEOUT).send().await {
             Ok(response) => {
                 if response.status().is_success() {
                     match response.json::<GitHubRelease>().await {
@@ -83,12 +136,17 @@ impl Updater {
             Ok(remote_version) => {
                 if remote_version > self.current_version {
                     // Find the appropriate download URL
-                    if let Some(download_url) = self.find_download_url(&release.assets) {
+                    if let Some(asset) = self.find_download_asset(&release.assets) {
                         let update_info = UpdateInfo {
                             version: remote_version.to_string(),
-                            download_url,
-                            release_notes: release.body.unwrap_or_default(),
-                            published_at: release.published_at.unwrap_or_default(),
+                            download_url: asset.browser_download_url.clone(),
+                            release_notes: release.body.clone().unwrap_or_default(),
+                            published_at: release.published_at.clone().unwrap_or_default(),
+                            sha256: asset
+                                .digest
+                                .as_deref()
+                                .and_then(|digest| digest.strip_prefix("sha256:"))
+                                .map(str::to_string),
                         };
                         UpdateStatus::UpdateAvailable(update_info)
                     } else {
@@ -102,7 +160,7 @@ impl Updater {
         }
     }
 
-    fn find_download_url(&self, assets: &[GitHubAsset]) -> Option<String> {
+    fn find_download_asset<'a>(&self, assets: &'a [GitHubAsset]) -> Option<&'a GitHubAsset> {
         // Look for platform-specific executable
         let platform_suffix = if cfg!(target_os = "windows") {
             ".exe"
@@ -112,107 +170,197 @@ impl Updater {
             "-linux"
         };
 
-        // Try to find a platform-specific asset
-        for asset in assets {
-            if asset.name.contains(platform_suffix) {
-                return Some(asset.browser_download_url.clone());
-            }
-        }
-
-        // Fallback to any executable file
-        for asset in assets {
-            if asset.name.ends_with(".exe") || !asset.name.contains('.') {
-                return Some(asset.browser_download_url.clone());
-            }
-        }
-
-        None
+        // Try to find a platform-specific asset, then fall back to any executable file
+        assets
+            .iter()
+            .find(|asset| asset.name.contains(platform_suffix))
+            .or_else(|| {
+                assets
+                    .iter()
+                    .find(|asset| asset.name.ends_with(".exe") || !asset.name.contains('.'))
+            })
     }
 
     pub async fn download_update(
         &self,
         update_info: &UpdateInfo,
         sender: Option<tokio::sync::mpsc::UnboundedSender<f32>>,
-    ) -> Result<PathBuf, String> {
-        let response = self
-            .client
-            .get(&update_info.download_url)
-            .send()
-            .await
-            .map_err(|e| format!("Failed to start download: {}", e))?;
-
-        if !response.status().is_success() {
-            return Err(format!("Download failed: {}", response.status()));
-        }
-
-        let total_size = response.content_length().unwrap_or(0);
-
+        cancel: Option<watch::Receiver<bool>>,
+    ) -> Result<PathBuf, UpdateError> {
         // Get the file name from the URL
         let file_name = update_info
             .download_url
             .split('/')
-            .last()
+            .next_back()
             .unwrap_or("ffmpegrust_update");
 
         // Create temporary directory for download
         let temp_dir = std::env::temp_dir().join("ffmpegrust_updates");
-        if let Err(e) = fs::create_dir_all(&temp_dir).await {
-            return Err(format!("Failed to create temp directory: {}", e));
-        }
+        fs::create_dir_all(&temp_dir)
+            .await
+            .map_err(UpdateError::io("Failed to create temp directory"))?;
 
         let file_path = temp_dir.join(file_name);
-
-        // Download the file with progress reporting
-        let mut file = fs::File::create(&file_path)
+        // Assets keep their name across releases, so the version keeps a
+        // download of one release from being resumed with another's bytes
+        let partial_name = partial_file_name(file_name, &update_info.version);
+        let partial_path = temp_dir.join(&partial_name);
+        remove_stale_partials(&temp_dir, file_name, &partial_name).await;
+
+        // Continue an interrupted download where it stopped
+        let mut existing = fs::metadata(&partial_path)
             .await
-            .map_err(|e| format!("Failed to create download file: {}", e))?;
+            .map(|metadata| metadata.len())
+            .unwrap_or(0);
 
-        let mut stream = response.bytes_stream();
-        let mut downloaded = 0u64;
+        let mut request = self.client.get(&update_info.download_url);
+        if existing > 0 {
+            request = request.header(reqwest::header::RANGE, format!("bytes={existing}-"));
+        }
+        let mut response = request.send().await.map_err(UpdateError::DownloadStart)?;
+
+        if response.status() == reqwest::StatusCode::PARTIAL_CONTENT
+            && content_range(&response).map(|(start, _)| start) != Some(existing)
+        {
+            // A range that doesn't continue the partial file can't be
+            // appended to it, so the download starts over
+            let _ = fs::remove_file(&partial_path).await;
+            existing = 0;
+            response = self
+                .client
+                .get(&update_info.download_url)
+                .send()
+                .await
+                .map_err(UpdateError::DownloadStart)?;
+        }
+        if response.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+            // The partial file is larger than the asset, so it can't be a prefix of it
+            let _ = fs::remove_file(&partial_path).await;
+        }
+        if !response.status().is_success() {
+            return Err(UpdateError::DownloadStatus(response.status()));
+        }
 
-        while let Some(chunk) = stream.next().await {
-            let chunk = chunk.map_err(|e| format!("Failed to read chunk: {}", e))?;
+        let partial = response.status() == reqwest::StatusCode::PARTIAL_CONTENT;
+        if partial && content_range(&response).map(|(start, _)| start) != Some(existing) {
+            // Still not the part that follows what is on disk
+            return Err(UpdateError::DownloadStatus(response.status()));
+        }
+        // Servers that ignore the range send the whole file again
+        let resumed = partial && existing > 0;
+        let mut downloaded = if resumed { existing } else { 0 };
+        let expected_size = if partial {
+            content_range(&response)
+                .and_then(|(_, total)| total)
+                .or(response.content_length().map(|length| length + existing))
+        } else {
+            response.content_length()
+        };
+        let total_size = expected_size.unwrap_or(0);
+
+        let mut file = if resumed {
+            fs::OpenOptions::new()
+                .append(true)
+                .open(&partial_path)
+                .await
+                .map_err(UpdateError::io("Failed to create download file"))?
+        } else {
+            fs::File::create(&partial_path)
+                .await
+                .map_err(UpdateError::io("Failed to create download file"))?
+        };
+
+        let mut stream = response.bytes_stream();
+        let mut throttle = self.download_rate_limit.map(Throttle::new);
+
+        loop {
+            let next = tokio::select! {
+                next = stream.next() => next,
+                () = cancelled(cancel.clone()) => {
+                    drop(file);
+                    let _ = fs::remove_file(&partial_path).await;
+                    return Err(UpdateError::Cancelled);
+                }
+            };
+            let Some(chunk) = next else {
+                break;
+            };
+            let chunk = chunk.map_err(UpdateError::ReadChunk)?;
 
             file.write_all(&chunk)
                 .await
-                .map_err(|e| format!("Failed to write chunk: {}", e))?;
+                .map_err(UpdateError::io("Failed to write chunk"))?;
 
             downloaded += chunk.len() as u64;
 
+            if let Some(throttle) = throttle.as_mut() {
+                throttle.consume(chunk.len() as u64).await;
+            }
+
             // Report progress
-            if let Some(ref sender) = sender {
-                if total_size > 0 {
-                    let progress = (downloaded as f32 / total_size as f32) * 100.0;
-                    let _ = sender.send(progress);
-                }
+            if let Some(ref sender) = sender
+                && total_size > 0
+            {
+                let progress = (downloaded as f32 / total_size as f32) * 100.0;
+                let _ = sender.send(progress);
             }
         }
 
         file.flush()
             .await
-            .map_err(|e| format!("Failed to flush download file: {}", e))?;
+            .map_err(UpdateError::io("Failed to flush download file"))?;
+        drop(file);
+
+        if update_info.sha256.is_none()
+            && let Some(expected) = expected_size
+            && downloaded != expected
+        {
+            // Too short can still be resumed, too long never becomes the asset
+            if downloaded > expected {
+                let _ = fs::remove_file(&partial_path).await;
+            }
+            return Err(UpdateError::SizeMismatch {
+                expected,
+                actual: downloaded,
+            });
+        }
+
+        if let Some(expected) = &update_info.sha256 {
+            let content = fs::read(&partial_path)
+                .await
+                .map_err(UpdateError::io("Failed to read download file"))?;
+            let actual = format!("{:x}", Sha256::digest(&content));
+            if !actual.eq_ignore_ascii_case(expected) {
+                // Resuming this file again would only reproduce the mismatch
+                let _ = fs::remove_file(&partial_path).await;
+                return Err(UpdateError::ChecksumMismatch);
+            }
+        }
+
+        fs::rename(&partial_path, &file_path)
+            .await
+            .map_err(UpdateError::io("Failed to finalize download file"))?;
 
         // Make executable on Unix systems
         #[cfg(unix)]
         {
             use std::os::unix::fs::PermissionsExt;
-            let mut perms = file
-                .metadata()
+            let mut perms = fs::metadata(&file_path)
                 .await
-                .map_err(|e| format!("Failed to get file metadata: {}", e))?
+                .map_err(UpdateError::io("Failed to get file metadata"))?
                 .permissions();
             perms.set_mode(0o755);
             fs::set_permissions(&file_path, perms)
                 .await
-                .map_err(|e| format!("Failed to set file permissions: {}", e))?;
+                .map_err(UpdateError::io("Failed to set file permissions"))?;
         }
 
         Ok(file_path)
     }
 
-    pub async fn apply_update(&self, update_file: &PathBuf) -> Result<(), String> {
+    pub async fn apply_update(&self, update_file: &PathBuf) -> Result<(), UpdateError> {
         let current_exe = std::env::current_exe()
-            .map_err(|e| format!("Failed to get current executable path: {}", e))?;
+            .map_err(UpdateError::io("Failed to get current executable path"))?;
 
         #[cfg(windows)]
         {
@@ -221,26 +369,26 @@ impl Updater {
 
             fs::rename(&current_exe, &backup_path)
                 .await
-                .map_err(|e| format!("Failed to backup current executable: {}", e))?;
+                .map_err(UpdateError::io("Failed to backup current executable"))?;
 
             fs::copy(update_file, &current_exe)
                 .await
-                .map_err(|e| format!("Failed to replace executable: {}", e))?;
+                .map_err(UpdateError::io("Failed to replace executable"))?;
         }
 
         #[cfg(not(windows))]
         {
             fs::copy(update_file, &current_exe)
                 .await
-                .map_err(|e| format!("Failed to replace executable: {}", e))?;
+                .map_err(UpdateError::io("Failed to replace executable"))?;
         }
 
         Ok(())
     }
 
-    pub async fn restart_application(&self) -> Result<(), String> {
+    pub async fn restart_application(&self) -> Result<(), UpdateError> {
         let current_exe = std::env::current_exe()
-            .map_err(|e| format!("Failed to get current executable path: {}", e))?;
+            .map_err(UpdateError::io("Failed to get current executable path"))?;
 
         // Give a moment for the current process to finish cleanly
         tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
@@ -257,18 +405,18 @@ impl Updater {
                     &current_exe.to_string_lossy(),
                 ])
                 .spawn()
-                .map_err(|e| format!("Failed to restart application: {}", e))?;
+                .map_err(UpdateError::io("Failed to restart application"))?;
         }
 
         #[cfg(not(windows))]
         {
             std::process::Command::new("sh")
-                .args(&[
+                .args([
                     "-c",
                     &format!("sleep 1 && exec '{}'", current_exe.display()),
                 ])
                 .spawn()
-                .map_err(|e| format!("Failed to restart application: {}", e))?;
+                .map_err(UpdateError::io("Failed to restart application"))?;
         }
 
         std::process::exit(0);
@@ -287,4 +435,130 @@ struct GitHubRelease {
 struct GitHubAsset {
     name: String,
     browser_download_url: String,
+    /// e.g. "sha256:<hex>", only present on newer releases
+    digest: Option<String>,
+}
+
+/// Resolves once `cancel` is set. Without a signal, or once its sender is
+/// dropped, nothing can cancel anymore and it never resolves.
+async fn cancelled(cancel: Option<watch::Receiver<bool>>) {
+    if let Some(mut cancel) = cancel
+        && cancel.wait_for(|cancelled| *cancelled).await.is_ok()
+    {
+        return;
+    }
+    std::future::pending::<()>().await;
+}
+
+/// Name of the unfinished download of `file_name` from release `version`
+fn partial_file_name(file_name: &str, version: &str) -> String {
+    format!("{file_name}.{version}.part")
+}
+
+/// Removes unfinished downloads of `file_name` other than `keep`, left by
+/// earlier releases or by versions before the partial name had one
+async fn remove_stale_partials(dir: &Path, file_name: &str, keep: &str) {
+    let Ok(mut entries) = fs::read_dir(dir).await else {
+        return;
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name != keep && name.starts_with(file_name) && name.ends_with(".part") {
+            let _ = fs::remove_file(entry.path()).await;
+        }
+    }
+}
+
+/// First byte offset of a `Content-Range: bytes <start>-<end>/<total>` reply
+/// Start offset and, when known, total size from a 206 reply's
+/// `Content-Range`
+fn content_range(response: &reqwest::Response) -> Option<(u64, Option<u64>)> {
+    parse_content_range(
+        response
+            .headers()
+            .get(reqwest::header::CONTENT_RANGE)?
+            .to_str()
+            .ok()?,
+    )
+}
+
+/// Parses `bytes 100-199/200`; a total of `*` means the size is unknown
+fn parse_content_range(value: &str) -> Option<(u64, Option<u64>)> {
+    let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let start = range.split_once('-')?.0.trim().parse().ok()?;
+    Some((start, total.trim().parse().ok()))
+}
+
+/// Token bucket holding at most one second of transfer
+struct Throttle {
+    rate: u64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl Throttle {
+    fn new(rate: u64) -> Self {
+        let rate = rate.max(1);
+        Self {
+            rate,
+            tokens: rate as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    async fn consume(&mut self, bytes: u64) {
+        let now = Instant::now();
+        let refill = now.duration_since(self.last_refill).as_secs_f64() * self.rate as f64;
+        self.tokens = (self.tokens + refill).min(self.rate as f64) - bytes as f64;
+        self.last_refill = now;
+
+        if self.tokens < 0.0 {
+            tokio::time::sleep(Duration::from_secs_f64(-self.tokens / self.rate as f64)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_range_gives_the_start_and_total() {
+        assert_eq!(
+            parse_content_range("bytes 100-199/200"),
+            Some((100, Some(200)))
+        );
+        assert_eq!(parse_content_range("bytes 0-99/*"), Some((0, None)));
+        assert_eq!(parse_content_range("bytes */200"), None);
+        assert_eq!(parse_content_range("100-199/200"), None);
+    }
+
+    #[tokio::test]
+    async fn partial_downloads_of_other_releases_are_removed() {
+        let dir = std::env::temp_dir().join(format!("ffmpegrust_partials_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let keep = partial_file_name("ffmpegrust-linux", "1.2.0");
+        for name in [
+            "ffmpegrust-linux.part",
+            "ffmpegrust-linux.1.1.0.part",
+            keep.as_str(),
+            "other-linux.1.1.0.part",
+        ] {
+            std::fs::write(dir.join(name), b"partial").unwrap();
+        }
+
+        remove_stale_partials(&dir, "ffmpegrust-linux", &keep).await;
+
+        let mut left: Vec<String> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        left.sort();
+        assert_eq!(
+            left,
+            ["ffmpegrust-linux.1.2.0.part", "other-linux.1.1.0.part"]
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
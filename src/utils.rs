@@ -1,2 +1,283 @@
 // Utility functions for FFmpeg Rust application
-// Currently empty - all utility functions removed to clean up unused code
+use std::fs::{File, FileTimes};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Open a file with the platform's default application
+pub fn open_in_default_app(path: &Path) -> Result<(), String> {
+    #[cfg(target_os = "windows")]
+    let mut cmd = {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C").arg("start").arg("");
+        cmd
+    };
+
+    #[cfg(target_os = "macos")]
+    let mut cmd = Command::new("open");
+
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    let mut cmd = Command::new("xdg-open");
+
+    cmd.arg(path)
+        .spawn()
+        .map(|_| ())
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))
+}
+
+/// Gives `target` the modification and access times of `source`, plus its
+/// creation time on platforms where that can be set (Windows and macOS)
+pub fn copy_file_times(source: &Path, target: &Path) -> Result<(), String> {
+    let metadata = std::fs::metadata(source)
+        .map_err(|e| format!("Failed to read times of {}: {}", source.display(), e))?;
+
+    let mut times = FileTimes::new();
+    if let Ok(modified) = metadata.modified() {
+        times = times.set_modified(modified);
+    }
+    if let Ok(accessed) = metadata.accessed() {
+        times = times.set_accessed(accessed);
+    }
+
+    #[cfg(target_os = "windows")]
+    if let Ok(created) = metadata.created() {
+        use std::os::windows::fs::FileTimesExt;
+        times = times.set_created(created);
+    }
+
+    #[cfg(target_os = "macos")]
+    if let Ok(created) = metadata.created() {
+        use std::os::macos::fs::FileTimesExt;
+        times = times.set_created(created);
+    }
+
+    File::options()
+        .write(true)
+        .open(target)
+        .and_then(|file| file.set_times(times))
+        .map_err(|e| format!("Failed to set times of {}: {}", target.display(), e))
+}
+
+/// Whether two paths name the same file. When one doesn't exist yet, e.g.
+/// an output, they match if their folders are the same and the names differ
+/// at most in case on platforms whose filesystems usually ignore it.
+pub fn is_same_file(a: &Path, b: &Path) -> bool {
+    if a == b {
+        return true;
+    }
+    if let Some(same) = same_existing_file(a, b) {
+        return same;
+    }
+
+    let (Some(a_name), Some(b_name)) = (a.file_name(), b.file_name()) else {
+        return false;
+    };
+    let names_match = if cfg!(any(target_os = "macos", target_os = "windows")) {
+        a_name.to_string_lossy().to_lowercase() == b_name.to_string_lossy().to_lowercase()
+    } else {
+        a_name == b_name
+    };
+    names_match && same_existing_file(&parent_folder(a), &parent_folder(b)).unwrap_or(false)
+}
+
+// "movie.mp4" has the empty path as its parent, which can't be read
+fn parent_folder(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// Whether two existing paths are the same file, comparing device and inode
+/// so hardlinks, symlinks and case variants all match. `None` when either
+/// path can't be read.
+#[cfg(unix)]
+fn same_existing_file(a: &Path, b: &Path) -> Option<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    let (a, b) = (std::fs::metadata(a).ok()?, std::fs::metadata(b).ok()?);
+    Some(a.dev() == b.dev() && a.ino() == b.ino())
+}
+
+/// Whether two existing paths are the same file. Canonical paths resolve
+/// links and the real case of every component. `None` when either path
+/// can't be resolved.
+#[cfg(not(unix))]
+fn same_existing_file(a: &Path, b: &Path) -> Option<bool> {
+    Some(a.canonicalize().ok()? == b.canonicalize().ok()?)
+}
+
+/// Whether another program has `path` open, e.g. a player, so it can't be
+/// replaced or moved to the trash. Windows is asked for exclusive access to
+/// find out; a file that doesn't exist isn't in use.
+#[cfg(windows)]
+pub fn file_in_use(path: &Path) -> bool {
+    use std::os::windows::fs::OpenOptionsExt;
+    // ERROR_SHARING_VIOLATION and ERROR_LOCK_VIOLATION
+    const IN_USE_ERRORS: [i32; 2] = [32, 33];
+
+    match File::options().read(true).share_mode(0).open(path) {
+        Ok(_) => false,
+        Err(e) => e
+            .raw_os_error()
+            .is_some_and(|code| IN_USE_ERRORS.contains(&code)),
+    }
+}
+
+/// Whether another program has `path` open. Locks elsewhere are advisory
+/// and don't stop a file being replaced or moved, so this is always false.
+#[cfg(not(windows))]
+pub fn file_in_use(_path: &Path) -> bool {
+    false
+}
+
+/// Free bytes available to this user on the volume holding `path`
+#[cfg(unix)]
+pub fn available_disk_space(path: &Path) -> Option<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stats = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `path` is NUL-terminated and `stats` is only read after success
+    let result = unsafe { libc::statvfs(path.as_ptr(), stats.as_mut_ptr()) };
+    if result != 0 {
+        return None;
+    }
+    // SAFETY: statvfs succeeded, so it filled in `stats`
+    let stats = unsafe { stats.assume_init() };
+    // The field types are u64 on Linux but narrower on some other Unixes
+    #[allow(clippy::unnecessary_cast)]
+    Some((stats.f_bavail as u64).saturating_mul(stats.f_frsize as u64))
+}
+
+/// Free bytes available to this user on the volume holding `path`
+#[cfg(windows)]
+pub fn available_disk_space(path: &Path) -> Option<u64> {
+    use std::os::windows::ffi::OsStrExt;
+    use winapi::um::fileapi::GetDiskFreeSpaceExW;
+    use winapi::um::winnt::ULARGE_INTEGER;
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    // SAFETY: ULARGE_INTEGER is plain data, so all zeroes is a valid value
+    let mut available: ULARGE_INTEGER = unsafe { std::mem::zeroed() };
+    // SAFETY: `wide` is NUL-terminated and the unused outputs may be null
+    let result = unsafe {
+        GetDiskFreeSpaceExW(
+            wide.as_ptr(),
+            &mut available,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        )
+    };
+    // SAFETY: QuadPart is the whole 64-bit value
+    (result != 0).then(|| unsafe { *available.QuadPart() })
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn available_disk_space(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Scores `text` against a fuzzy `query` whose characters must all appear
+/// in order, ignoring case. Higher is better; runs of consecutive characters
+/// and matches at word starts count extra. `None` when it doesn't match.
+pub fn fuzzy_score(query: &str, text: &str) -> Option<u32> {
+    let mut score = 0;
+    let mut previous: Option<char> = None;
+    let mut run = 0;
+    let mut text_chars = text.chars();
+
+    for wanted in query.chars().filter(|c| !c.is_whitespace()) {
+        let wanted = wanted.to_lowercase();
+        loop {
+            let c = text_chars.next()?;
+            let word_start = previous.is_none_or(|p| !p.is_alphanumeric());
+            previous = Some(c);
+            if c.to_lowercase().eq(wanted.clone()) {
+                run += 1;
+                score += run + if word_start { 3 } else { 0 };
+                break;
+            }
+            run = 0;
+        }
+    }
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ffmpegrust_{name}_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn dot_prefixed_paths_are_the_same_file() {
+        assert!(is_same_file(
+            Path::new("Cargo.toml"),
+            Path::new("./Cargo.toml")
+        ));
+        assert!(is_same_file(
+            Path::new("missing_output.mkv"),
+            Path::new("./missing_output.mkv")
+        ));
+        assert!(!is_same_file(
+            Path::new("Cargo.toml"),
+            Path::new("Cargo.lock")
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_are_the_same_file_as_their_target() {
+        let dir = scratch_dir("symlink");
+        let target = dir.join("clip.mkv");
+        let link = dir.join("link.mkv");
+        std::fs::write(&target, b"").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        assert!(is_same_file(&target, &link));
+        assert!(!is_same_file(&target, &dir.join("other.mkv")));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hardlinks_are_the_same_file() {
+        let dir = scratch_dir("hardlink");
+        let original = dir.join("clip.mkv");
+        let link = dir.join("clip_link.mkv");
+        let copy = dir.join("clip_copy.mkv");
+        std::fs::write(&original, b"clip").unwrap();
+        std::fs::hard_link(&original, &link).unwrap();
+        std::fs::copy(&original, &copy).unwrap();
+
+        assert!(is_same_file(&original, &link));
+        assert!(!is_same_file(&original, &copy));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "windows"))]
+    #[test]
+    fn case_variants_are_the_same_file() {
+        let dir = scratch_dir("case");
+        let existing = dir.join("Clip.mkv");
+        std::fs::write(&existing, b"").unwrap();
+
+        assert!(is_same_file(&existing, &dir.join("clip.MKV")));
+        assert!(is_same_file(&dir.join("New.mp4"), &dir.join("new.mp4")));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    #[test]
+    fn case_variants_are_different_files() {
+        let dir = scratch_dir("case");
+        assert!(!is_same_file(&dir.join("New.mp4"), &dir.join("new.mp4")));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,962 @@
+// Checks for user- and file-provided values that end up in FFmpeg arguments
+
+use crate::conversion::{StreamAction, plan_streams};
+use crate::hardware::hardware_upload_filter;
+use crate::presets::{
+    AudioCodec, ConversionMode, ConversionPreset, CropRect, DeinterlaceMode, HardwareAccel,
+    OutputSplit, QualityTarget, VideoCodec, VideoFormat,
+};
+use crate::probe::MediaInfo;
+use std::path::Path;
+
+const MAX_METADATA_KEY_LENGTH: usize = 64;
+const MAX_THREADS: u32 = 256;
+/// Largest audio shift accepted either way, in milliseconds
+pub const MAX_AUDIO_OFFSET_MS: i32 = 60_000;
+/// VMAF targets offered for the CRF search; below the minimum the search
+/// just runs into the codec's highest CRF
+pub const MIN_TARGET_VMAF: f64 = 50.0;
+pub const MAX_TARGET_VMAF: f64 = 99.0;
+pub const DEFAULT_TARGET_VMAF: f64 = 93.0;
+/// Extra audio tracks allowed besides the main one
+pub const MAX_EXTRA_AUDIO_TRACKS: usize = 4;
+/// Most channels an extra track can be mixed to (7.1)
+pub const MAX_AUDIO_CHANNELS: u32 = 8;
+/// Bounds of split output parts; a part can't be longer than a day
+pub const MAX_SPLIT_MINUTES: u32 = 24 * 60;
+pub const MIN_SPLIT_MB: u64 = 10;
+pub const MAX_SPLIT_MB: u64 = 1024 * 1024;
+/// Track dispositions FFmpeg accepts that are worth setting on an output
+pub const DISPOSITION_FLAGS: &[&str] = &[
+    "default",
+    "forced",
+    "original",
+    "dub",
+    "comment",
+    "hearing_impaired",
+    "visual_impaired",
+    "captions",
+    "descriptions",
+];
+const COVER_ART_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg"];
+
+// Options the app sets itself, or that read, write or execute things beyond
+// the chosen input and output. Never accepted as extra arguments.
+const BANNED_EXTRA_OPTIONS: &[&str] = &[
+    "-i",
+    "-y",
+    "-n",
+    "-f",
+    "-map",
+    "-attach",
+    "-dump_attachment",
+    "-filter_script",
+    "-filter_complex_script",
+    "-filter_complex",
+    "-lavfi",
+    "-progress",
+    "-report",
+    "-vstats_file",
+    "-passlogfile",
+    "-stats_enc_pre",
+    "-stats_enc_post",
+    "-stats_mux_pre",
+    "-protocol_whitelist",
+];
+
+// Extra options power users may add, and whether each takes a value
+const ALLOWED_EXTRA_OPTIONS: &[(&str, bool)] = &[
+    ("-tune", true),
+    ("-preset", true),
+    ("-profile:v", true),
+    ("-profile:a", true),
+    ("-level", true),
+    ("-x264-params", true),
+    ("-x265-params", true),
+    ("-svtav1-params", true),
+    ("-crf", true),
+    ("-qp", true),
+    ("-q:v", true),
+    ("-q:a", true),
+    ("-g", true),
+    ("-bf", true),
+    ("-refs", true),
+    ("-keyint_min", true),
+    ("-sc_threshold", true),
+    ("-maxrate", true),
+    ("-minrate", true),
+    ("-bufsize", true),
+    ("-pix_fmt", true),
+    ("-color_primaries", true),
+    ("-color_trc", true),
+    ("-colorspace", true),
+    ("-tag:v", true),
+    ("-ac", true),
+    ("-ar", true),
+    ("-movflags", true),
+    ("-fps_mode", true),
+    ("-shortest", false),
+];
+
+// Extra options whose value is an encoder parameter string
+const CODEC_PARAMS_OPTIONS: &[&str] = &["-x264-params", "-x265-params", "-svtav1-params"];
+
+/// Metadata keys are plain identifiers like `creation_time` or `com.apple.quicktime.make`
+pub fn is_valid_metadata_key(key: &str) -> bool {
+    !key.is_empty()
+        && key.len() <= MAX_METADATA_KEY_LENGTH
+        && !key.starts_with('-')
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | ':'))
+}
+
+/// Values are passed as a single argument, so only control characters
+/// (which FFmpeg would write verbatim into the container) are rejected
+pub fn is_safe_metadata_value(value: &str) -> bool {
+    !value.chars().any(|c| c.is_control())
+}
+
+pub fn is_valid_disposition(flag: &str) -> bool {
+    DISPOSITION_FLAGS.contains(&flag)
+}
+
+/// Parses a `-threads` value: empty means FFmpeg's default, `0` means auto
+pub fn parse_thread_count(value: &str) -> Result<Option<u32>, String> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(None);
+    }
+
+    match value.parse::<u32>() {
+        Ok(threads) if threads <= MAX_THREADS => Ok(Some(threads)),
+        _ => Err(format!(
+            "Threads must be 0 (auto) or a number from 1 to {MAX_THREADS}"
+        )),
+    }
+}
+
+/// Parses progress milestones like "25, 50, 75, 100" into ascending
+/// percentages; empty means none
+pub fn parse_milestones(value: &str) -> Result<Vec<u32>, String> {
+    let mut milestones = value
+        .split([',', ' '])
+        .map(|part| part.trim().trim_end_matches('%'))
+        .filter(|part| !part.is_empty())
+        .map(|part| match part.parse::<u32>() {
+            Ok(percent) if (1..=100).contains(&percent) => Ok(percent),
+            _ => Err(format!(
+                "Milestone \"{part}\" should be a percentage from 1 to 100"
+            )),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    milestones.sort_unstable();
+    milestones.dedup();
+    Ok(milestones)
+}
+
+/// Converts an audio offset to the seconds `-itsoffset` takes
+pub fn audio_offset_seconds(offset_ms: i32) -> Result<f64, String> {
+    if offset_ms.abs() > MAX_AUDIO_OFFSET_MS {
+        return Err(format!(
+            "Audio offset must be within ±{MAX_AUDIO_OFFSET_MS} ms"
+        ));
+    }
+    Ok(f64::from(offset_ms) / 1000.0)
+}
+
+/// The only video filters the app builds itself: a deinterlacer from
+/// `DeinterlaceMode`, then `crop=w:h:x:y`, then the GPU upload `accel`
+/// needs. Extra arguments can't add filters, so this is the whole filter
+/// allowlist. FFmpeg keeps only the last filter option per stream, so this
+/// is the one chain to give it. `None` when there is nothing to filter.
+pub fn video_filter(
+    deinterlace: Option<DeinterlaceMode>,
+    crop: Option<CropRect>,
+    accel: HardwareAccel,
+) -> Option<String> {
+    let crop = crop
+        .filter(|crop| validate_crop(*crop, None).is_ok())
+        .map(|(width, height, x, y)| format!("crop={width}:{height}:{x}:{y}"));
+    let filters: Vec<String> = deinterlace
+        .map(|mode| mode.filter_name().to_string())
+        .into_iter()
+        .chain(crop)
+        .chain(hardware_upload_filter(accel).map(str::to_string))
+        .collect();
+    (!filters.is_empty()).then(|| filters.join(","))
+}
+
+/// Checks a crop rectangle is non-empty and, when the source size is known,
+/// lies within the picture
+pub fn validate_crop(crop: CropRect, source_size: Option<(u32, u32)>) -> Result<(), String> {
+    let (width, height, x, y) = crop;
+    if width == 0 || height == 0 {
+        return Err("Crop width and height must be greater than 0".to_string());
+    }
+    if let Some((source_width, source_height)) = source_size
+        && (u64::from(x) + u64::from(width) > u64::from(source_width)
+            || u64::from(y) + u64::from(height) > u64::from(source_height))
+    {
+        return Err(format!(
+            "Crop {width}x{height} at {x},{y} doesn't fit the {source_width}x{source_height} source"
+        ));
+    }
+    Ok(())
+}
+
+pub fn validate_quality_target(target: QualityTarget) -> Result<(), String> {
+    if (MIN_TARGET_VMAF..=MAX_TARGET_VMAF).contains(&target.vmaf) {
+        Ok(())
+    } else {
+        Err(format!(
+            "Target VMAF must be from {MIN_TARGET_VMAF} to {MAX_TARGET_VMAF}"
+        ))
+    }
+}
+
+/// Replaces characters that are invalid in file names on any supported
+/// platform, so a generated name never escapes its output folder
+pub fn sanitize_filename(name: &str) -> String {
+    let replaced: String = name
+        .chars()
+        .map(|c| {
+            if c.is_control() || matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|') {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+
+    // Windows silently strips trailing dots and spaces
+    replaced
+        .trim_matches(|c: char| c == '.' || c.is_whitespace())
+        .to_string()
+}
+
+/// Source streams the settings copy into a container that can't hold them,
+/// or only with a caveat. Copies into MP4, MOV and WebM fail in FFmpeg's
+/// muxer, so those are errors; the other containers usually manage.
+pub fn validate_stream_copy(
+    preset: &ConversionPreset,
+    info: &MediaInfo,
+    audio_track: Option<usize>,
+    cover_art: Option<&Path>,
+) -> Vec<SettingsIssue> {
+    let format = &preset.video_format;
+    let container = format.display_name();
+    let strict = matches!(
+        format,
+        VideoFormat::Mp4 | VideoFormat::Mov | VideoFormat::Webm
+    );
+    let mut issues = Vec::new();
+
+    // `plan_streams` has one plan per input stream, in order, before any
+    // extra audio tracks or cover art, and checks each stream the mapping
+    // actually writes
+    let plans = plan_streams(preset, info, audio_track, cover_art);
+    for (stream, plan) in info.streams.iter().zip(&plans) {
+        if plan.action != StreamAction::Copy {
+            continue;
+        }
+        let codec = stream.codec_name.as_str();
+        let issue = match stream.codec_type.as_str() {
+            "video" => match VideoCodec::from_codec_name(codec) {
+                Some(video) if !format.supports_video_codec(&video) => {
+                    Some(SettingsIssue::Error(format!(
+                        "{container} can't hold the source's {} video; choose a video codec \
+                         to re-encode it",
+                        video.display_name()
+                    )))
+                }
+                Some(video) => video_codec_caveat(format, &video).map(|caveat| {
+                    SettingsIssue::Warning(format!("Copying the source video: {caveat}"))
+                }),
+                None if *format == VideoFormat::Webm && codec != "vp8" => {
+                    Some(SettingsIssue::Error(format!(
+                        "WebM can't hold the source's {codec} video; choose a video codec to \
+                         re-encode it"
+                    )))
+                }
+                None => None,
+            },
+            "audio" => match AudioCodec::from_codec_name(codec) {
+                Some(audio) if !format.supports_audio_codec(&audio) => {
+                    let message = format!(
+                        "{container} can't reliably hold the source's {} audio; choose an \
+                         audio codec to re-encode it",
+                        audio.display_name()
+                    );
+                    Some(if strict {
+                        SettingsIssue::Error(message)
+                    } else {
+                        SettingsIssue::Warning(message)
+                    })
+                }
+                Some(audio) => audio_codec_caveat(format, &audio).map(|caveat| {
+                    SettingsIssue::Warning(format!("Copying the source audio: {caveat}"))
+                }),
+                None if *format == VideoFormat::Webm => Some(SettingsIssue::Error(format!(
+                    "WebM can't hold the source's {codec} audio; choose an audio codec to \
+                     re-encode it"
+                ))),
+                None => None,
+            },
+            // Only MKV takes every kind of subtitle as it is
+            "subtitle" => {
+                let fits = match format {
+                    VideoFormat::Mp4 | VideoFormat::Mov => codec == "mov_text",
+                    VideoFormat::Webm => codec == "webvtt",
+                    VideoFormat::Mkv | VideoFormat::Avi => true,
+                };
+                (!fits).then(|| {
+                    SettingsIssue::Error(format!(
+                        "{container} can't hold the source's {codec} subtitles as they are; \
+                         remux into MKV, or convert to turn them into a supported format"
+                    ))
+                })
+            }
+            _ => None,
+        };
+        if let Some(issue) = issue
+            && !issues.contains(&issue)
+        {
+            issues.push(issue);
+        }
+    }
+    issues
+}
+
+/// Warns when the input's extension names a different container than the
+/// one FFmpeg actually found in it
+pub fn validate_input_format(path: &Path, info: &MediaInfo) -> Option<SettingsIssue> {
+    if info.container_matches_extension(path)? {
+        return None;
+    }
+    let extension = path.extension()?.to_string_lossy();
+    Some(SettingsIssue::Warning(format!(
+        "This .{extension} file is actually {}; remuxing may pick the wrong container \
+         for its streams",
+        info.container_label()?
+    )))
+}
+
+pub fn validate_cover_art(path: &Path, format: &VideoFormat) -> Result<(), String> {
+    if !format.supports_cover_art() {
+        return Err(format!("{} can't hold cover art", format.display_name()));
+    }
+
+    let is_image = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            COVER_ART_EXTENSIONS
+                .iter()
+                .any(|known| known.eq_ignore_ascii_case(extension))
+        });
+    if !is_image {
+        return Err("Cover art must be a PNG or JPEG image".to_string());
+    }
+
+    if !path.is_file() {
+        return Err(format!("Cover art not found: {}", path.display()));
+    }
+
+    Ok(())
+}
+
+/// Splits and checks the advanced "extra FFmpeg arguments" field.
+///
+/// Only allowlisted options are accepted, and their values must be plain
+/// numbers or identifiers such as `slow`, `yuv420p` or `keyint=240:ref=4`.
+pub fn parse_extra_args(value: &str) -> Result<Vec<String>, String> {
+    let tokens: Vec<String> = value.split_whitespace().map(str::to_string).collect();
+    let mut iter = tokens.iter();
+
+    while let Some(option) = iter.next() {
+        if !option.starts_with('-') {
+            return Err(format!(
+                "Expected an option starting with '-', found \"{option}\""
+            ));
+        }
+        if BANNED_EXTRA_OPTIONS.contains(&option.as_str()) {
+            return Err(format!("{option} is managed by the app and can't be added"));
+        }
+        let Some(&(_, takes_value)) = ALLOWED_EXTRA_OPTIONS
+            .iter()
+            .find(|(allowed, _)| *allowed == option)
+        else {
+            return Err(format!(
+                "{option} is not in the list of supported extra options"
+            ));
+        };
+
+        if takes_value {
+            match iter.next() {
+                // Encoder parameter strings get the codec parameter checks,
+                // which keep them from naming files
+                Some(value) if CODEC_PARAMS_OPTIONS.contains(&option.as_str()) => {
+                    parse_codec_params(value)
+                        .map_err(|error| format!("Invalid value for {option}: {error}"))?;
+                }
+                Some(value) if is_safe_extra_value(value) => {}
+                Some(value) => return Err(format!("Invalid value for {option}: \"{value}\"")),
+                None => return Err(format!("{option} needs a value")),
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn is_safe_extra_value(value: &str) -> bool {
+    let numeric = value.parse::<f64>().is_ok();
+    numeric
+        || (!value.starts_with('-')
+            && value.chars().all(|c| {
+                c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | ':' | '=' | '+' | '-' | ',')
+            }))
+}
+
+// Encoder parameters that read or write files of their own
+const FILE_CODEC_PARAMS: &[&str] = &[
+    "stats",
+    "csv",
+    "dump-yuv",
+    "recon",
+    "analysis-save",
+    "analysis-load",
+    "analysis-reuse-file",
+    "qpfile",
+    "zonefile",
+    "cqmfile",
+    "lambda-file",
+    "scenecut-aware-qp-file",
+    "vbv-end-file",
+];
+
+/// Checks an x264/x265 parameter string like `aq-mode=3:psy-rd=1.0,0.15`.
+///
+/// Each `:`-separated entry must be `key=value` built from plain characters;
+/// spaces, quotes and shell metacharacters are rejected outright. A `/` is
+/// only allowed in ratios like `30000/1001`, and parameters naming files
+/// are refused, so the encoder can't be made to write anywhere.
+pub fn parse_codec_params(value: &str) -> Result<String, String> {
+    let value = value.trim();
+    if let Some(c) = value
+        .chars()
+        .find(|c| c.is_whitespace() || matches!(c, ';' | '`' | '$' | '|' | '&' | '\'' | '"'))
+    {
+        return Err(format!("Codec parameters can't contain '{c}'"));
+    }
+
+    let mut entries = Vec::new();
+    for entry in value.split(':').filter(|entry| !entry.is_empty()) {
+        let valid = entry.split_once('=').is_some_and(|(key, value)| {
+            !key.is_empty()
+                && !value.is_empty()
+                && key
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'))
+                && value.chars().all(|c| {
+                    c.is_ascii_alphanumeric() || matches!(c, '.' | ',' | '-' | '+' | '_' | '/')
+                })
+                && !value.contains("..")
+                && value.split(',').all(|part| {
+                    part.split_once('/').is_none_or(|(num, den)| {
+                        let digits =
+                            |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+                        digits(num) && digits(den)
+                    })
+                })
+        });
+        if let Some((key, _)) = entry.split_once('=')
+            && FILE_CODEC_PARAMS
+                .iter()
+                .any(|param| param.eq_ignore_ascii_case(key))
+        {
+            return Err(format!("Codec parameter \"{key}\" reads or writes files"));
+        }
+        if !valid {
+            return Err(format!(
+                "Invalid codec parameter \"{entry}\", expected key=value"
+            ));
+        }
+        entries.push(entry);
+    }
+
+    Ok(entries.join(":"))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SettingsIssue {
+    /// Works, but with a caveat worth knowing about
+    Warning(String),
+    /// FFmpeg will reject it, so the conversion can't start
+    Error(String),
+}
+
+impl SettingsIssue {
+    pub fn is_error(&self) -> bool {
+        matches!(self, SettingsIssue::Error(_))
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            SettingsIssue::Warning(message) | SettingsIssue::Error(message) => message,
+        }
+    }
+}
+
+/// Problems with the conversion settings that can be spotted before FFmpeg
+/// runs. `threads` and `extra_args` are the raw text of their fields.
+pub fn validate_conversion_settings(
+    preset: &ConversionPreset,
+    threads: &str,
+    extra_args: &str,
+) -> Vec<SettingsIssue> {
+    let mut issues = Vec::new();
+
+    if let Err(error) = parse_thread_count(threads) {
+        issues.push(SettingsIssue::Error(error));
+    }
+    if let Err(error) = parse_extra_args(extra_args) {
+        issues.push(SettingsIssue::Error(error));
+    }
+    if let Some(offset_ms) = preset.audio_offset_ms
+        && let Err(error) = audio_offset_seconds(offset_ms)
+    {
+        issues.push(SettingsIssue::Error(error));
+    }
+    if preset.disable_video && preset.disable_audio {
+        issues.push(SettingsIssue::Error(
+            "Video and audio are both disabled, so the output would be empty".to_string(),
+        ));
+    }
+    let dispositions = &preset.metadata_options.dispositions;
+    for flag in dispositions
+        .iter()
+        .flat_map(|disposition| &disposition.flags)
+    {
+        if !is_valid_disposition(flag) {
+            issues.push(SettingsIssue::Error(format!(
+                "Unknown track disposition \"{flag}\""
+            )));
+        }
+    }
+    for (key, value) in preset.metadata_options.file_tags() {
+        if !is_safe_metadata_value(value) {
+            issues.push(SettingsIssue::Error(format!(
+                "The file {key} can't contain line breaks or control characters"
+            )));
+        }
+    }
+    for (track, language) in preset.metadata_options.invalid_languages() {
+        issues.push(SettingsIssue::Error(format!(
+            "{track} language \"{language}\" isn't an ISO 639 code like eng or en"
+        )));
+    }
+    if !dispositions.is_empty() && !preset.video_format.supports_dispositions() {
+        issues.push(SettingsIssue::Warning(format!(
+            "{} doesn't store default or forced track flags; they are ignored",
+            preset.video_format.display_name()
+        )));
+    }
+
+    if let Some(crop) = preset.crop {
+        if let Err(error) = validate_crop(crop, None) {
+            issues.push(SettingsIssue::Error(error));
+        } else if preset.mode == ConversionMode::Remux || preset.video_codec == VideoCodec::Copy {
+            issues.push(SettingsIssue::Warning(
+                "Cropping needs the video re-encoded and is ignored when it is copied".to_string(),
+            ));
+        }
+    }
+
+    if preset.deinterlace.is_some()
+        && (preset.mode == ConversionMode::Remux || preset.video_codec == VideoCodec::Copy)
+    {
+        issues.push(SettingsIssue::Warning(
+            "Deinterlacing needs the video re-encoded and is ignored when it is copied".to_string(),
+        ));
+    }
+
+    if let Some(target) = preset.quality_target {
+        if let Err(error) = validate_quality_target(target) {
+            issues.push(SettingsIssue::Error(error));
+        } else if preset.mode == ConversionMode::Remux
+            || preset.video_codec == VideoCodec::Copy
+            || preset.disable_video
+        {
+            issues.push(SettingsIssue::Warning(
+                "A VMAF target needs the video re-encoded and is ignored when it is copied"
+                    .to_string(),
+            ));
+        } else if preset.hardware_accel != HardwareAccel::None {
+            issues.push(SettingsIssue::Error(
+                "A VMAF target only works with software encoders".to_string(),
+            ));
+        } else if preset.video_bitrate.is_some() {
+            issues.push(SettingsIssue::Warning(
+                "The video bitrate is ignored while a VMAF target is set".to_string(),
+            ));
+        }
+    }
+
+    if let Some(split) = preset.split_output {
+        if let Err(error) = validate_output_split(split) {
+            issues.push(SettingsIssue::Error(error));
+        } else if matches!(split, OutputSplit::Size(_))
+            && preset.mode == ConversionMode::Convert
+            && preset.video_codec != VideoCodec::Copy
+            && preset.video_bitrate.is_none()
+        {
+            issues.push(SettingsIssue::Warning(
+                "Without a video bitrate the part length is guessed from the input's bitrate, \
+                 so parts may come out larger or smaller than the limit"
+                    .to_string(),
+            ));
+        }
+    }
+
+    if preset.keep_all_streams && preset.mode == ConversionMode::Convert {
+        issues.push(SettingsIssue::Warning(
+            "Keeping all streams only applies when remuxing".to_string(),
+        ));
+    }
+
+    if !preset.extra_audio.is_empty() && preset.extra_audio_encodes().is_empty() {
+        issues.push(SettingsIssue::Warning(
+            "Extra audio tracks are ignored when remuxing or without audio".to_string(),
+        ));
+    }
+
+    // Remux copies every stream, so codec choices don't apply
+    if preset.mode == ConversionMode::Remux {
+        return issues;
+    }
+
+    let format = &preset.video_format;
+    if !preset.disable_video && !format.supports_video_codec(&preset.video_codec) {
+        issues.push(SettingsIssue::Error(format!(
+            "{} can't hold {} video",
+            format.display_name(),
+            preset.video_codec.display_name()
+        )));
+    }
+    if !preset.disable_audio && !format.supports_audio_codec(&preset.audio_codec) {
+        issues.push(SettingsIssue::Error(format!(
+            "{} can't hold {} audio",
+            format.display_name(),
+            preset.audio_codec.display_name()
+        )));
+    }
+
+    let extra_audio = preset.extra_audio_encodes();
+    if !extra_audio.is_empty() && !format.supports_multiple_audio() {
+        issues.push(SettingsIssue::Error(format!(
+            "{} can't reliably hold more than one audio track",
+            format.display_name()
+        )));
+    } else if extra_audio.len() > MAX_EXTRA_AUDIO_TRACKS {
+        issues.push(SettingsIssue::Error(format!(
+            "At most {MAX_EXTRA_AUDIO_TRACKS} extra audio tracks are supported"
+        )));
+    }
+    for encode in extra_audio {
+        if encode.codec == AudioCodec::Copy {
+            issues.push(SettingsIssue::Error(
+                "Extra audio tracks need a codec to encode with".to_string(),
+            ));
+        } else if !format.supports_audio_codec(&encode.codec) {
+            issues.push(SettingsIssue::Error(format!(
+                "{} can't hold {} audio",
+                format.display_name(),
+                encode.codec.display_name()
+            )));
+        }
+        if let Some(bitrate) = encode.bitrate.as_deref()
+            && !is_valid_bitrate(bitrate)
+        {
+            issues.push(SettingsIssue::Error(format!(
+                "Invalid bitrate \"{bitrate}\" for the extra {} track",
+                encode.codec.display_name()
+            )));
+        }
+        if encode
+            .channels
+            .is_some_and(|channels| !(1..=MAX_AUDIO_CHANNELS).contains(&channels))
+        {
+            issues.push(SettingsIssue::Error(format!(
+                "Extra audio tracks can have 1 to {MAX_AUDIO_CHANNELS} channels"
+            )));
+        }
+    }
+
+    if let Some(params) = &preset.codec_params {
+        if let Err(error) = parse_codec_params(params) {
+            issues.push(SettingsIssue::Error(error));
+        } else if preset
+            .video_codec
+            .params_option(preset.hardware_accel)
+            .is_none()
+        {
+            issues.push(SettingsIssue::Warning(format!(
+                "Codec parameters only apply to software H.264/H.265 and are ignored for {}",
+                preset.video_codec.encoder_name(preset.hardware_accel)
+            )));
+        }
+    }
+
+    for (label, value) in [
+        ("Video bitrate", &preset.video_bitrate),
+        ("Audio bitrate", &preset.audio_bitrate),
+    ] {
+        if let Some(value) = value
+            && !is_valid_bitrate(value)
+        {
+            issues.push(SettingsIssue::Error(format!(
+                "{label} \"{value}\" should look like 2500k or 5M"
+            )));
+        }
+    }
+    if let Some(resolution) = &preset.resolution
+        && !is_valid_resolution(resolution)
+    {
+        issues.push(SettingsIssue::Error(format!(
+            "Resolution \"{resolution}\" should look like 1920x1080"
+        )));
+    }
+    if let Some(frame_rate) = &preset.frame_rate
+        && !is_valid_frame_rate(frame_rate)
+    {
+        issues.push(SettingsIssue::Error(format!(
+            "Frame rate \"{frame_rate}\" should look like 30 or 30000/1001"
+        )));
+    }
+
+    if preset.video_codec != VideoCodec::Copy
+        && preset.hardware_accel != HardwareAccel::None
+        && preset.video_codec.encoder_name(preset.hardware_accel)
+            == preset.video_codec.ffmpeg_name()
+    {
+        issues.push(SettingsIssue::Warning(format!(
+            "{} has no {} encoder; the software encoder will be used",
+            preset.hardware_accel.display_name(),
+            preset.video_codec.display_name()
+        )));
+    }
+    if let Some(caveat) = audio_codec_caveat(format, &preset.audio_codec) {
+        issues.push(SettingsIssue::Warning(caveat));
+    }
+    if let Some(caveat) = video_codec_caveat(format, &preset.video_codec) {
+        issues.push(SettingsIssue::Warning(caveat));
+    }
+    if preset.audio_bitrate.is_some()
+        && matches!(preset.audio_codec, AudioCodec::Flac | AudioCodec::Pcm16)
+    {
+        issues.push(SettingsIssue::Warning(format!(
+            "The audio bitrate is ignored for lossless {}",
+            preset.audio_codec.display_name()
+        )));
+    }
+
+    issues
+}
+
+fn is_valid_bitrate(value: &str) -> bool {
+    parse_bitrate(value).is_some()
+}
+
+/// Bits per second of an FFmpeg bitrate like "5M", "192k" or "800000"
+pub fn parse_bitrate(value: &str) -> Option<u64> {
+    let digits = value.trim_end_matches(['k', 'K', 'm', 'M']);
+    if digits.len() + 1 < value.len() {
+        return None;
+    }
+    let multiplier = match value.chars().last()? {
+        'k' | 'K' => 1e3,
+        'm' | 'M' => 1e6,
+        _ => 1.0,
+    };
+    let rate = digits.parse::<f64>().ok().filter(|rate| *rate > 0.0)?;
+    Some((rate * multiplier) as u64)
+}
+
+/// Part lengths and sizes FFmpeg's segment muxer is given
+pub fn validate_output_split(split: OutputSplit) -> Result<(), String> {
+    match split {
+        OutputSplit::Duration(minutes) if !(1..=MAX_SPLIT_MINUTES).contains(&minutes) => Err(
+            format!("Split parts must be 1 to {MAX_SPLIT_MINUTES} minutes long"),
+        ),
+        OutputSplit::Size(megabytes) if !(MIN_SPLIT_MB..=MAX_SPLIT_MB).contains(&megabytes) => Err(
+            format!("Split parts must be {MIN_SPLIT_MB} to {MAX_SPLIT_MB} MB"),
+        ),
+        _ => Ok(()),
+    }
+}
+
+fn is_valid_resolution(value: &str) -> bool {
+    value.split_once(['x', 'X']).is_some_and(|(width, height)| {
+        width.parse::<u32>().is_ok_and(|w| w > 0) && height.parse::<u32>().is_ok_and(|h| h > 0)
+    })
+}
+
+fn is_valid_frame_rate(value: &str) -> bool {
+    match value.split_once('/') {
+        Some((num, den)) => {
+            num.parse::<u32>().is_ok_and(|n| n > 0) && den.parse::<u32>().is_ok_and(|d| d > 0)
+        }
+        None => value.parse::<f64>().is_ok_and(|rate| rate > 0.0),
+    }
+}
+
+/// Why an allowed video codec may still not play back from this container
+pub fn video_codec_caveat(format: &VideoFormat, codec: &VideoCodec) -> Option<String> {
+    (*format == VideoFormat::Avi && *codec == VideoCodec::H265)
+        .then(|| "H.265 in AVI isn't supported by most players".to_string())
+}
+
+/// Why an allowed audio codec may still not play back from this container
+pub fn audio_codec_caveat(format: &VideoFormat, codec: &AudioCodec) -> Option<String> {
+    matches!(
+        (format, codec),
+        (VideoFormat::Mp4, AudioCodec::Opus | AudioCodec::Flac)
+    )
+    .then(|| {
+        format!(
+            "{} audio in MP4 isn't supported by some players",
+            codec.display_name()
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::probe::StreamInfo;
+
+    #[test]
+    fn codec_params_accept_plain_settings() {
+        assert_eq!(
+            parse_codec_params("aq-mode=3:psy-rd=1.0,0.15:fps=30000/1001"),
+            Ok("aq-mode=3:psy-rd=1.0,0.15:fps=30000/1001".to_string())
+        );
+    }
+
+    #[test]
+    fn codec_params_reject_file_paths() {
+        for params in [
+            "stats=/home/u/.bashrc",
+            "csv=../x",
+            "STATS=x264.log",
+            "dump-yuv=out.yuv",
+            "analysis-save=a",
+            "qpfile=q",
+            "zonefile=z",
+            "aq-mode=../3",
+            "aq-mode=a/b",
+            "aq-mode=1/",
+            "aq-mode=a\\b",
+        ] {
+            assert!(parse_codec_params(params).is_err(), "{params}");
+        }
+    }
+
+    fn hevc_aac() -> MediaInfo {
+        media(&[("video", "hevc"), ("audio", "aac")])
+    }
+
+    fn media(codecs: &[(&str, &str)]) -> MediaInfo {
+        MediaInfo {
+            streams: codecs
+                .iter()
+                .map(|(codec_type, codec_name)| StreamInfo {
+                    codec_type: codec_type.to_string(),
+                    codec_name: codec_name.to_string(),
+                    ..StreamInfo::default()
+                })
+                .collect(),
+            ..MediaInfo::default()
+        }
+    }
+
+    fn remux(format: VideoFormat) -> ConversionPreset {
+        ConversionPreset {
+            mode: ConversionMode::Remux,
+            video_format: format,
+            ..ConversionPreset::default()
+        }
+    }
+
+    #[test]
+    fn copying_hevc_into_avi_is_flagged() {
+        let issues = validate_stream_copy(&remux(VideoFormat::Avi), &hevc_aac(), None, None);
+        assert!(
+            matches!(issues.as_slice(), [SettingsIssue::Warning(message)] if message.contains("H.265")),
+            "{issues:?}"
+        );
+    }
+
+    #[test]
+    fn copying_hevc_into_mp4_is_allowed() {
+        assert_eq!(
+            validate_stream_copy(&remux(VideoFormat::Mp4), &hevc_aac(), None, None),
+            Vec::new()
+        );
+    }
+
+    #[test]
+    fn every_copied_subtitle_is_checked() {
+        let info = media(&[
+            ("video", "h264"),
+            ("audio", "aac"),
+            ("subtitle", "mov_text"),
+            ("subtitle", "hdmv_pgs_subtitle"),
+        ]);
+        assert_eq!(
+            validate_stream_copy(&remux(VideoFormat::Mp4), &info, None, None),
+            Vec::new()
+        );
+
+        let keep_all = ConversionPreset {
+            keep_all_streams: true,
+            ..remux(VideoFormat::Mp4)
+        };
+        let issues = validate_stream_copy(&keep_all, &info, None, None);
+        assert!(
+            matches!(issues.as_slice(), [SettingsIssue::Error(message)] if message.contains("hdmv_pgs_subtitle")),
+            "{issues:?}"
+        );
+    }
+
+    #[test]
+    fn vaapi_upload_ends_the_one_filter_chain() {
+        assert_eq!(
+            video_filter(
+                Some(DeinterlaceMode::Yadif),
+                Some((1280, 720, 0, 40)),
+                HardwareAccel::Vaapi
+            ),
+            Some("yadif,crop=1280:720:0:40,format=nv12,hwupload".to_string())
+        );
+        assert_eq!(
+            video_filter(None, None, HardwareAccel::Vaapi),
+            Some("format=nv12,hwupload".to_string())
+        );
+        assert_eq!(
+            video_filter(Some(DeinterlaceMode::Bwdif), None, HardwareAccel::Nvenc),
+            Some("bwdif".to_string())
+        );
+        assert_eq!(video_filter(None, None, HardwareAccel::None), None);
+    }
+
+    #[test]
+    fn extra_codec_params_cant_name_files() {
+        assert!(parse_extra_args("-x264-params aq-mode=3:psy-rd=1.0,0.15").is_ok());
+        assert!(parse_extra_args("-x264-params stats=x.log").is_err());
+        assert!(parse_extra_args("-x265-params csv=out.csv:qpfile=q").is_err());
+        assert!(parse_extra_args("-svtav1-params tune=0:film-grain=8").is_ok());
+    }
+}